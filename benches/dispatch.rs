@@ -0,0 +1,43 @@
+// Dispatch::Match against Dispatch::Table, run with
+//   cargo bench --no-default-features --features std --bench dispatch
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rusty_chip8::cpu::CPU;
+use rusty_chip8::dispatch::Dispatch;
+
+// Loads, arithmetic, a skip, an index add and a store, the mix of a typical game loop. I is
+// set every pass so the stores never reach the program.
+const LOOP: [u16; 11] = [
+    0x6005, 0x7101, 0x8014, 0x8206, 0x3200, 0x7301, 0xA300, 0xF01E, 0xF033, 0x8120, 0x1200,
+];
+
+fn execute(c: &mut Criterion) {
+    let rom: Vec<u8> = LOOP.iter().flat_map(|op| op.to_be_bytes()).collect();
+    let mut group = c.benchmark_group("10000 instructions");
+    for (name, backend) in [("match", Dispatch::Match), ("table", Dispatch::Table)] {
+        let mut cpu = CPU::builder().dispatch(backend).build();
+        cpu.load_rom_bytes(&rom).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(cpu.exec_cycles(10_000).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+fn brix(c: &mut Criterion) {
+    let mut group = c.benchmark_group("BRIX 60 frames");
+    for (name, backend) in [("match", Dispatch::Match), ("table", Dispatch::Table)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut cpu = CPU::builder().dispatch(backend).seed(1).build();
+                cpu.load_rom_bytes(include_bytes!("../roms/BRIX")).unwrap();
+                for _ in 0..60 {
+                    black_box(cpu.run_frame(500).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, execute, brix);
+criterion_main!(benches);
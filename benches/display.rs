@@ -0,0 +1,72 @@
+// Redrawing the whole screen with draw_sprite against the old pixel by pixel drawing, run with
+//   cargo bench --no-default-features --features std --bench display
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rusty_chip8::display::{Display, Resolution};
+
+// The old way: read and write every sprite pixel through get_pixel and set_pixel
+fn draw_per_pixel(display: &mut Display, x: usize, y: usize, sprite: &[u8], bytes: usize) -> bool {
+    let mut collided = false;
+    for (j, row) in sprite.chunks(bytes).enumerate() {
+        let py = (y + j) % display.height();
+        for (i, &byte) in row.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & 0x80 >> bit == 0 {
+                    continue;
+                }
+                let px = (x + 8 * i + bit) % display.width();
+                let old = display.get_pixel(px, py);
+                collided |= old == 1;
+                display.set_pixel(px, py, old ^ 1);
+            }
+        }
+    }
+    collided
+}
+
+fn redraw(c: &mut Criterion) {
+    let sprite8 = [0xAA; 8];
+    let sprite16 = [0x5A; 32];
+    let mut group = c.benchmark_group("full screen redraw");
+    let mut display = Display::new();
+    group.bench_function("lores draw_sprite", |b| {
+        b.iter(|| {
+            for y in (0..32).step_by(8) {
+                for x in (0..64).step_by(8) {
+                    black_box(display.draw_sprite(x, y, &sprite8, false));
+                }
+            }
+        })
+    });
+    group.bench_function("lores per pixel", |b| {
+        b.iter(|| {
+            for y in (0..32).step_by(8) {
+                for x in (0..64).step_by(8) {
+                    black_box(draw_per_pixel(&mut display, x, y, &sprite8, 1));
+                }
+            }
+        })
+    });
+    display.set_resolution(Resolution::High, false);
+    group.bench_function("hires draw_sprite16", |b| {
+        b.iter(|| {
+            for y in (0..64).step_by(16) {
+                for x in (0..128).step_by(16) {
+                    black_box(display.draw_sprite16(x, y, &sprite16, false));
+                }
+            }
+        })
+    });
+    group.bench_function("hires per pixel", |b| {
+        b.iter(|| {
+            for y in (0..64).step_by(16) {
+                for x in (0..128).step_by(16) {
+                    black_box(draw_per_pixel(&mut display, x, y, &sprite16, 2));
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, redraw);
+criterion_main!(benches);
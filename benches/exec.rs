@@ -0,0 +1,33 @@
+// exec_cycles(10_000) against 10,000 exec_cycle calls, run with
+//   cargo bench --no-default-features --features std --bench exec
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rusty_chip8::cpu::CPU;
+
+// ADD V0, 1 / JP 0x200, never halts or waits
+const LOOP: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+fn cpu() -> CPU {
+    let mut cpu = CPU::builder().use_rom_database(false).build();
+    cpu.load_rom_bytes(&LOOP).unwrap();
+    cpu
+}
+
+fn exec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("10000 instructions");
+    let mut batch = cpu();
+    group.bench_function("exec_cycles", |b| {
+        b.iter(|| black_box(batch.exec_cycles(10_000).unwrap()))
+    });
+    let mut single = cpu();
+    group.bench_function("exec_cycle", |b| {
+        b.iter(|| {
+            for _ in 0..10_000 {
+                black_box(single.exec_cycle().unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, exec);
+criterion_main!(benches);
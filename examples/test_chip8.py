@@ -0,0 +1,42 @@
+# Driving the emulator core from Python, run with pytest after `maturin develop`.
+import pytest
+
+import rusty_chip8
+
+# LD V0, 0xA / LD F, V0 / DRW V1, V1, 5 / JP 0x206
+DRAW_A = bytes([0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06])
+
+# LD V2, K / JP 0x202
+WAIT_KEY = bytes([0xF2, 0x0A, 0x12, 0x02])
+
+
+def test_draws_the_glyph_a():
+    chip8 = rusty_chip8.Chip8()
+    assert chip8.load_rom(DRAW_A) == len(DRAW_A)
+    assert chip8.step(3) == 3
+    assert chip8.registers()[0] == 0xA
+    width, height = chip8.size()
+    screen = chip8.framebuffer()
+    assert len(screen) == width * height
+    assert screen[:5] == bytes([1, 1, 1, 1, 0])
+    assert chip8.screenshot_ascii().splitlines()[0].startswith("####.")
+
+
+def test_keys_reach_fx0a():
+    chip8 = rusty_chip8.Chip8()
+    chip8.load_rom(WAIT_KEY)
+    assert chip8.run_frame() == 0
+    chip8.press_key(7)
+    chip8.run_frame()
+    chip8.release_key(7)
+    chip8.run_frame()
+    assert chip8.registers()[2] == 7
+
+
+def test_errors_raise_chip8_error():
+    chip8 = rusty_chip8.Chip8()
+    with pytest.raises(rusty_chip8.Chip8Error, match="keys are 0 to F"):
+        chip8.press_key(16)
+    chip8.load_rom(bytes([0xE0, 0x00]))
+    with pytest.raises(rusty_chip8.Chip8Error, match="unknown opcode 0xE000"):
+        chip8.run_frame()
@@ -0,0 +1,57 @@
+/* C interface to the rusty_chip8 core, see src/ffi.rs.
+ * Functions returning int return 0 or a positive result on success and a negative
+ * CHIP8_ERR_ code on failure. */
+
+#ifndef RUSTY_CHIP8_H
+#define RUSTY_CHIP8_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define CHIP8_OK 0
+#define CHIP8_ERR_NULL -1
+#define CHIP8_ERR_PANIC -2
+#define CHIP8_ERR_BUFFER_TOO_SMALL -3
+#define CHIP8_ERR_MEMORY_OUT_OF_BOUNDS -10
+#define CHIP8_ERR_PC_OUT_OF_BOUNDS -11
+#define CHIP8_ERR_STACK_UNDERFLOW -12
+#define CHIP8_ERR_STACK_OVERFLOW -13
+#define CHIP8_ERR_UNKNOWN_OPCODE -14
+#define CHIP8_ERR_SYS_CALL -15
+#define CHIP8_ERR_WRITE_PROTECTED -16
+#define CHIP8_ERR_UNINITIALIZED_READ -17
+#define CHIP8_ERR_NO_ROM_LOADED -18
+#define CHIP8_ERR_INVALID_KEY -19
+#define CHIP8_ERR_INVALID_REGISTER -20
+#define CHIP8_ERR_ADDRESS_OUT_OF_RANGE -21
+#define CHIP8_ERR_ROM_LOAD -22
+
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+Chip8 *chip8_new(void);
+
+void chip8_free(Chip8 *handle);
+
+int chip8_load_rom(Chip8 *handle, const uint8_t *data, size_t len);
+
+int chip8_run_frame(Chip8 *handle, uint32_t cycles);
+
+int chip8_set_keys(Chip8 *handle, uint16_t keys);
+
+int chip8_framebuffer(Chip8 *handle, uint8_t *out, size_t out_len);
+
+int chip8_width(Chip8 *handle);
+
+int chip8_height(Chip8 *handle);
+
+int chip8_beeping(Chip8 *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
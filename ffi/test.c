@@ -0,0 +1,50 @@
+/* Checks the C interface against the static library. From the repository root:
+ *   cargo rustc --lib --release --no-default-features --features ffi --crate-type staticlib
+ *   cc -Iffi ffi/test.c target/release/librusty_chip8.a -lpthread -ldl -lm -o target/ffi_test
+ *   target/ffi_test */
+
+#include <assert.h>
+#include <stdio.h>
+
+#include "rusty_chip8.h"
+
+int main(void) {
+    /* Draw the 0 glyph at (0, 0), start the sound timer, wait for key 5, then count in V0 */
+    const uint8_t rom[] = {
+        0xA0, 0x00, /* LD I, 0 */
+        0xD0, 0x05, /* DRW V0, V0, 5 */
+        0x60, 0x10, /* LD V0, 0x10 */
+        0xF0, 0x18, /* LD ST, V0 */
+        0xF1, 0x0A, /* LD V1, K */
+        0x70, 0x01, /* ADD V0, 1 */
+        0x12, 0x0A, /* JP 0x20A */
+    };
+    uint8_t screen[64 * 32];
+    uint8_t small[10];
+
+    assert(chip8_run_frame(NULL, 10) == CHIP8_ERR_NULL);
+
+    Chip8 *chip8 = chip8_new();
+    assert(chip8 != NULL);
+    assert(chip8_load_rom(chip8, NULL, 0) == CHIP8_ERR_NULL);
+    assert(chip8_load_rom(chip8, rom, 0) == CHIP8_ERR_ROM_LOAD);
+    assert(chip8_load_rom(chip8, rom, sizeof rom) == (int)sizeof rom);
+
+    assert(chip8_run_frame(chip8, 10) == 4);
+    assert(chip8_beeping(chip8) == 1);
+    assert(chip8_width(chip8) == 64 && chip8_height(chip8) == 32);
+    assert(chip8_framebuffer(chip8, small, sizeof small) == CHIP8_ERR_BUFFER_TOO_SMALL);
+    assert(chip8_framebuffer(chip8, screen, sizeof screen) == 64 * 32);
+    /* Top row of the 0 glyph is 0xF0 */
+    assert(screen[0] == 1 && screen[3] == 1 && screen[4] == 0);
+
+    assert(chip8_set_keys(chip8, 1 << 5) == CHIP8_OK);
+    chip8_run_frame(chip8, 10);
+    assert(chip8_set_keys(chip8, 0) == CHIP8_OK);
+    assert(chip8_run_frame(chip8, 10) == 10);
+
+    chip8_free(chip8);
+    chip8_free(NULL);
+    printf("ok\n");
+    return 0;
+}
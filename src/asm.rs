@@ -0,0 +1,488 @@
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::error::{AsmError, AsmErrorKind};
+use crate::instruction::Instruction;
+
+// A two-pass assembler for the mnemonics the disassembler prints. The first pass finds where
+// every line goes and so the address of each label, the second encodes.
+//
+//   ; comments run to the end of the line
+//   start:  LD V0, 0x1F      ; numbers are decimal, 0x hex or Cowgod's #hex
+//           LD I, sprite     ; labels work wherever a number does
+//           DRW V0, V1, 5
+//           JP start
+//   sprite: db 0x3C, 0x42, 0x81
+//           dw 0x1234        ; big-endian words
+//
+// Mnemonics, registers and directives are case-insensitive, labels aren't.
+
+// Every instruction and directive
+const MNEMONICS: [&str; 30] = [
+    "DB", "DW", "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SCD", "SCU", "PLANE", "SYS",
+    "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND",
+    "DRW", "SKP", "SKNP",
+];
+
+// One instruction or directive, with where it goes
+struct Statement<'a> {
+    line: usize,
+    addr: u16,
+    mnemonic: &'a str,
+    operands: Vec<&'a str>,
+}
+
+// Assemble source into a ROM to be loaded at base
+pub fn assemble(source: &str, base: u16) -> Result<Vec<u8>, AsmError> {
+    let mut labels = BTreeMap::new();
+    let mut statements = Vec::new();
+    let mut addr = base as usize;
+    for (k, text) in source.lines().enumerate() {
+        let line = k + 1;
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+        while let Some((label, rest)) = split_label(text) {
+            if labels.insert(label, addr).is_some() {
+                return Err(error(line, label, AsmErrorKind::DuplicateLabel));
+            }
+            text = rest;
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands: Vec<&str> = match rest.trim() {
+            "" => Vec::new(),
+            rest => rest.split(',').map(str::trim).collect(),
+        };
+        let size = match mnemonic.to_ascii_uppercase().as_str() {
+            "DB" => operands.len(),
+            "DW" => 2 * operands.len(),
+            "LD" if is_long(&operands) => 4,
+            _ => 2,
+        };
+        statements.push(Statement {
+            line,
+            addr: addr as u16,
+            mnemonic,
+            operands,
+        });
+        addr += size;
+        if addr > 0x10000 {
+            return Err(error(
+                line,
+                mnemonic,
+                AsmErrorKind::OutOfRange { max: 0xFFFF },
+            ));
+        }
+    }
+
+    let mut rom = Vec::new();
+    for statement in &statements {
+        debug_assert_eq!(statement.addr as usize, base as usize + rom.len());
+        encode(statement, &labels, &mut rom)?;
+    }
+    Ok(rom)
+}
+
+// "name: rest" to (name, rest) when name is a label
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (label, rest) = text.split_once(':')?;
+    let label = label.trim();
+    is_identifier(label).then(|| (label, rest.trim()))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// LD I, LONG addr, XO-CHIP's two word F000 NNNN
+fn is_long(operands: &[&str]) -> bool {
+    operands.len() == 2
+        && operands[0].eq_ignore_ascii_case("I")
+        && long_operand(operands[1]).is_some()
+}
+
+fn long_operand(operand: &str) -> Option<&str> {
+    let (keyword, addr) = operand.split_once(char::is_whitespace)?;
+    keyword.eq_ignore_ascii_case("LONG").then(|| addr.trim())
+}
+
+fn error(line: usize, token: &str, kind: AsmErrorKind) -> AsmError {
+    AsmError {
+        line,
+        token: token.to_string(),
+        kind,
+    }
+}
+
+fn encode(
+    statement: &Statement,
+    labels: &BTreeMap<&str, usize>,
+    rom: &mut Vec<u8>,
+) -> Result<(), AsmError> {
+    let line = statement.line;
+    let ops = &statement.operands;
+    let bad_operands = || error(line, &ops.join(", "), AsmErrorKind::BadOperands);
+    // A number or label no bigger than max
+    let value = |operand: &str, max: u16| -> Result<u16, AsmError> {
+        let n = match parse_number(operand) {
+            Some(n) => n,
+            None if is_identifier(operand) => match labels.get(operand) {
+                Some(&addr) => addr,
+                None => return Err(error(line, operand, AsmErrorKind::UndefinedLabel)),
+            },
+            None => return Err(bad_operands()),
+        };
+        match u16::try_from(n) {
+            Ok(n) if n <= max => Ok(n),
+            _ => Err(error(line, operand, AsmErrorKind::OutOfRange { max })),
+        }
+    };
+    let addr = |operand: &str| value(operand, 0xFFF);
+    let byte = |operand: &str| value(operand, 0xFF).map(|n| n as u8);
+    let nibble = |operand: &str| value(operand, 0xF).map(|n| n as u8);
+    let reg = |operand: &str| register(operand).ok_or_else(bad_operands);
+
+    let mnemonic = statement.mnemonic.to_ascii_uppercase();
+    if !MNEMONICS.contains(&mnemonic.as_str()) {
+        let kind = AsmErrorKind::UnknownMnemonic;
+        return Err(error(line, statement.mnemonic, kind));
+    }
+    let instruction = match (mnemonic.as_str(), ops.len()) {
+        ("DB", n) if n > 0 => {
+            for operand in ops {
+                rom.push(byte(operand)?);
+            }
+            return Ok(());
+        }
+        ("DW", n) if n > 0 => {
+            for operand in ops {
+                rom.extend_from_slice(&value(operand, 0xFFFF)?.to_be_bytes());
+            }
+            return Ok(());
+        }
+        ("CLS", 0) => Instruction::Cls,
+        ("RET", 0) => Instruction::Ret,
+        ("SCR", 0) => Instruction::ScrollRight,
+        ("SCL", 0) => Instruction::ScrollLeft,
+        ("EXIT", 0) => Instruction::Exit,
+        ("LOW", 0) => Instruction::Lores,
+        ("HIGH", 0) => Instruction::Hires,
+        ("SCD", 1) => Instruction::ScrollDown(nibble(ops[0])?),
+        ("SCU", 1) => Instruction::ScrollUp(nibble(ops[0])?),
+        ("PLANE", 1) => Instruction::Plane(nibble(ops[0])?),
+        ("SYS", 1) => Instruction::Sys(addr(ops[0])?),
+        ("JP", 1) => Instruction::Jump(addr(ops[0])?),
+        // JP Vx, addr is BXNN with the jump quirk, so X has to match
+        ("JP", 2) => {
+            let x = reg(ops[0])?;
+            let nnn = addr(ops[1])?;
+            if x != 0 && (nnn >> 8) as usize != x {
+                return Err(bad_operands());
+            }
+            Instruction::JumpOffset { x, nnn }
+        }
+        ("CALL", 1) => Instruction::Call(addr(ops[0])?),
+        ("SE", 2) | ("SNE", 2) => {
+            let x = reg(ops[0])?;
+            match (mnemonic.as_str(), register(ops[1])) {
+                ("SE", Some(y)) => Instruction::SkipEqReg { x, y },
+                ("SNE", Some(y)) => Instruction::SkipNeReg { x, y },
+                ("SE", None) => Instruction::SkipEqByte {
+                    x,
+                    kk: byte(ops[1])?,
+                },
+                _ => Instruction::SkipNeByte {
+                    x,
+                    kk: byte(ops[1])?,
+                },
+            }
+        }
+        ("LD", 2) => load(ops[0], ops[1], &value, &bad_operands)?,
+        ("ADD", 2) if ops[0].eq_ignore_ascii_case("I") => Instruction::AddI { x: reg(ops[1])? },
+        ("ADD", 2) => {
+            let x = reg(ops[0])?;
+            match register(ops[1]) {
+                Some(y) => Instruction::AddReg { x, y },
+                None => Instruction::AddVxByte {
+                    x,
+                    kk: byte(ops[1])?,
+                },
+            }
+        }
+        ("OR", 2) | ("AND", 2) | ("XOR", 2) | ("SUB", 2) | ("SUBN", 2) => {
+            let (x, y) = (reg(ops[0])?, reg(ops[1])?);
+            match mnemonic.as_str() {
+                "OR" => Instruction::Or { x, y },
+                "AND" => Instruction::And { x, y },
+                "XOR" => Instruction::Xor { x, y },
+                "SUB" => Instruction::Sub { x, y },
+                _ => Instruction::SubN { x, y },
+            }
+        }
+        // SHR Vx alone shifts Vx whichever register the shift quirk reads
+        ("SHR", 1) | ("SHR", 2) | ("SHL", 1) | ("SHL", 2) => {
+            let x = reg(ops[0])?;
+            let y = match ops.get(1) {
+                Some(op) => reg(op)?,
+                None => x,
+            };
+            match mnemonic.as_str() {
+                "SHR" => Instruction::Shr { x, y },
+                _ => Instruction::Shl { x, y },
+            }
+        }
+        ("RND", 2) => Instruction::Rnd {
+            x: reg(ops[0])?,
+            kk: byte(ops[1])?,
+        },
+        ("DRW", 3) => Instruction::Draw {
+            x: reg(ops[0])?,
+            y: reg(ops[1])?,
+            n: nibble(ops[2])?,
+        },
+        ("SKP", 1) => Instruction::SkipKeyPressed { x: reg(ops[0])? },
+        ("SKNP", 1) => Instruction::SkipKeyNotPressed { x: reg(ops[0])? },
+        _ => return Err(bad_operands()),
+    };
+    rom.extend_from_slice(&instruction.encode().to_be_bytes());
+    if instruction == Instruction::LoadLongI {
+        let long = long_operand(ops[1]).unwrap_or_default();
+        rom.extend_from_slice(&value(long, 0xFFFF)?.to_be_bytes());
+    }
+    Ok(())
+}
+
+// The many forms of LD
+fn load<V, E>(dst: &str, src: &str, value: &V, bad_operands: &E) -> Result<Instruction, AsmError>
+where
+    V: Fn(&str, u16) -> Result<u16, AsmError>,
+    E: Fn() -> AsmError,
+{
+    let keyword = |operand: &str| operand.to_ascii_uppercase();
+    let instruction = match (register(dst), register(src)) {
+        (Some(x), Some(y)) => Instruction::LoadReg { x, y },
+        (Some(x), None) => match keyword(src).as_str() {
+            "DT" => Instruction::LoadDelay { x },
+            "K" => Instruction::WaitKey { x },
+            "[I]" => Instruction::LoadRegs { x },
+            "R" => Instruction::LoadFlags { x },
+            _ => Instruction::LoadByte {
+                x,
+                kk: value(src, 0xFF)? as u8,
+            },
+        },
+        (None, Some(x)) => match keyword(dst).as_str() {
+            "DT" => Instruction::SetDelay { x },
+            "ST" => Instruction::SetSound { x },
+            "F" => Instruction::LoadFont { x },
+            "HF" => Instruction::LoadBigFont { x },
+            "B" => Instruction::StoreBcd { x },
+            "[I]" => Instruction::StoreRegs { x },
+            "R" => Instruction::StoreFlags { x },
+            "PITCH" => Instruction::SetPitch { x },
+            _ => return Err(bad_operands()),
+        },
+        (None, None) => match (keyword(dst).as_str(), keyword(src).as_str()) {
+            ("AUDIO", "[I]") => Instruction::LoadAudio,
+            ("I", _) if long_operand(src).is_some() => Instruction::LoadLongI,
+            ("I", _) => Instruction::LoadI(value(src, 0xFFF)?),
+            ("[I]", _) => match register_range(src) {
+                Some((x, y)) => Instruction::StoreRange { x, y },
+                None => return Err(bad_operands()),
+            },
+            (_, "[I]") => match register_range(dst) {
+                Some((x, y)) => Instruction::LoadRange { x, y },
+                None => return Err(bad_operands()),
+            },
+            _ => return Err(bad_operands()),
+        },
+    };
+    Ok(instruction)
+}
+
+// V0 to VF
+fn register(operand: &str) -> Option<usize> {
+    let digit = operand.strip_prefix(['V', 'v'])?;
+    match digit.len() {
+        1 => usize::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+// Vx-Vy
+fn register_range(operand: &str) -> Option<(usize, usize)> {
+    let (x, y) = operand.split_once('-')?;
+    Some((register(x.trim())?, register(y.trim())?))
+}
+
+// Decimal, 0x hex or #hex, as wide as it comes so range errors can show it
+fn parse_number(text: &str) -> Option<usize> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('#')) {
+        return usize::from_str_radix(hex, 16).ok();
+    }
+    match text.starts_with(|c: char| c.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm;
+    use alloc::string::String;
+    use alloc::vec;
+
+    fn encodes(line: &str) -> Vec<u8> {
+        assemble(line, 0x200).unwrap_or_else(|e| panic!("{}: {}", line, e))
+    }
+
+    #[test]
+    fn each_mnemonic_encodes() {
+        for (line, opcode) in [
+            ("CLS", 0x00E0),
+            ("RET", 0x00EE),
+            ("SCR", 0x00FB),
+            ("SCL", 0x00FC),
+            ("EXIT", 0x00FD),
+            ("LOW", 0x00FE),
+            ("HIGH", 0x00FF),
+            ("SCD 4", 0x00C4),
+            ("SCU 4", 0x00D4),
+            ("PLANE 3", 0xF301),
+            ("SYS 0x123", 0x0123),
+            ("JP 0x2F0", 0x12F0),
+            ("JP V0, 0x300", 0xB300),
+            ("JP V3, 0x300", 0xB300),
+            ("CALL 0x2F0", 0x22F0),
+            ("SE V3, 0x1F", 0x331F),
+            ("SE V3, V4", 0x5340),
+            ("SNE V3, 31", 0x431F),
+            ("SNE V3, V4", 0x9340),
+            ("LD V3, #1F", 0x631F),
+            ("LD V3, V4", 0x8340),
+            ("LD V3, DT", 0xF307),
+            ("LD V3, K", 0xF30A),
+            ("LD DT, V3", 0xF315),
+            ("LD ST, V3", 0xF318),
+            ("LD I, 0x300", 0xA300),
+            ("LD F, V3", 0xF329),
+            ("LD HF, V3", 0xF330),
+            ("LD B, V3", 0xF333),
+            ("LD [I], V3", 0xF355),
+            ("LD V3, [I]", 0xF365),
+            ("LD R, V3", 0xF375),
+            ("LD V3, R", 0xF385),
+            ("LD [I], V2-V5", 0x5252),
+            ("LD V2-V5, [I]", 0x5253),
+            ("LD AUDIO, [I]", 0xF002),
+            ("LD PITCH, V3", 0xF33A),
+            ("ADD V3, 1", 0x7301),
+            ("ADD V3, V4", 0x8344),
+            ("ADD I, V3", 0xF31E),
+            ("OR V3, V4", 0x8341),
+            ("AND V3, V4", 0x8342),
+            ("XOR V3, V4", 0x8343),
+            ("SUB V3, V4", 0x8345),
+            ("SHR V3", 0x8336),
+            ("SHR V3, V4", 0x8346),
+            ("SUBN V3, V4", 0x8347),
+            ("SHL V3, V4", 0x834E),
+            ("RND V3, 0x0F", 0xC30F),
+            ("drw v0, v1, 5", 0xD015),
+            ("SKP V3", 0xE39E),
+            ("SKNP V3", 0xE3A1),
+        ] {
+            assert_eq!(encodes(line), u16::to_be_bytes(opcode), "{}", line);
+        }
+        assert_eq!(encodes("LD I, LONG 0x1234"), [0xF0, 0x00, 0x12, 0x34]);
+        assert_eq!(encodes("db 1, 0x02, #3\ndw 0x1234"), [1, 2, 3, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let source = "
+            start:  LD I, sprite  ; comment
+                    DRW V0, V1, 1
+            loop:   JP loop
+            sprite: db 0xFF
+                    dw start
+        ";
+        assert_eq!(
+            encodes(source),
+            [0xA2, 0x06, 0xD0, 0x11, 0x12, 0x04, 0xFF, 0x02, 0x00]
+        );
+    }
+
+    #[test]
+    fn disassembly_assembles_back_to_the_same_bytes() {
+        let source = "
+                CLS
+                LD V0, 0
+                LD I, LONG data
+            top:
+                DRW V0, V1, 4
+                ADD V0, 8
+                SE V0, 64
+                JP top
+                CALL done
+                LD [I], V0-V3
+            done:
+                RET
+            data:
+                dw 0xF0FF, 0x5AB1
+        ";
+        let rom = encodes(source);
+        let listing: Vec<String> = disasm::disassemble_range(&rom, 0, 100)
+            .into_iter()
+            .map(|(_, _, text)| text)
+            .collect();
+        assert_eq!(assemble(&listing.join("\n"), 0x200), Ok(rom));
+    }
+
+    #[test]
+    fn errors_name_the_line_and_token() {
+        let fails = |source| assemble(source, 0x200).unwrap_err();
+        assert_eq!(
+            fails("CLS\nJP nowhere"),
+            error(2, "nowhere", AsmErrorKind::UndefinedLabel)
+        );
+        assert_eq!(
+            fails("JP 0x1000"),
+            error(1, "0x1000", AsmErrorKind::OutOfRange { max: 0xFFF })
+        );
+        assert_eq!(
+            fails("LD V0, 256"),
+            error(1, "256", AsmErrorKind::OutOfRange { max: 0xFF })
+        );
+        assert_eq!(
+            fails("a: CLS\na: RET"),
+            error(2, "a", AsmErrorKind::DuplicateLabel)
+        );
+        assert_eq!(
+            fails("MOV V0, V1"),
+            error(1, "MOV", AsmErrorKind::UnknownMnemonic)
+        );
+        assert_eq!(
+            fails("DRW V0, V1"),
+            error(1, "V0, V1", AsmErrorKind::BadOperands)
+        );
+        // The ROM can't run past the end of 64K memory
+        let source = vec!["dw 0"; 0x8000].join("\n");
+        assert_eq!(
+            assemble(&source, 0x200).unwrap_err(),
+            error(0x7F01, "dw", AsmErrorKind::OutOfRange { max: 0xFFFF })
+        );
+        assert_eq!(
+            fails("JP nowhere").to_string(),
+            "line 1: undefined label nowhere"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+// XO-CHIP sound: instead of a fixed tone, the buzzer loops over a 128 bit pattern loaded with
+// F002, at a bit rate set with FX3A. The pattern plays while ST is non-zero, like the buzzer.
+
+pub const PATTERN_LEN: usize = 16;
+const PATTERN_BITS: f32 = (PATTERN_LEN * 8) as f32;
+// Pitch register value that plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+// Pattern that plays as the plain CHIP-8 buzzer: half on, half off, looped at 440Hz
+const BUZZER: [u8; PATTERN_LEN] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+const BUZZER_HZ: f32 = 440.0;
+
+// Sound state of the CPU
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Audio {
+    // Pattern loaded with F002, None until the ROM loads one
+    pub pattern: Option<[u8; PATTERN_LEN]>,
+    // Pitch register set with FX3A
+    pub pitch: u8,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        Audio {
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+        }
+    }
+
+    // Pattern bits played per second at the current pitch
+    pub fn bit_rate(&self) -> f32 {
+        pitch_to_bit_rate(self.pitch)
+    }
+}
+
+// 4000 * 2^((pitch - 64) / 48) bits per second, as XO-CHIP defines it
+pub fn pitch_to_bit_rate(pitch: u8) -> f32 {
+    4000.0 * exp2((pitch as f32 - 64.0) / 48.0)
+}
+
+#[cfg(feature = "std")]
+fn exp2(x: f32) -> f32 {
+    x.exp2()
+}
+
+// core has no exp2. Whole powers of two are exact, the fraction comes from the series for
+// e^(f ln 2), which is within a few ulps after 8 terms for f below 1.
+#[cfg(not(feature = "std"))]
+fn exp2(x: f32) -> f32 {
+    let mut whole = x as i32;
+    if (whole as f32) > x {
+        whole -= 1;
+    }
+    let t = (x - whole as f32) * core::f32::consts::LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..8 {
+        term *= t / k as f32;
+        sum += term;
+    }
+    match whole {
+        0.. => sum * (1u64 << whole) as f32,
+        _ => sum / (1u64 << -whole) as f32,
+    }
+}
+
+// Turns a pattern into samples at the output sample rate. Starts out as the CHIP-8 buzzer.
+pub struct PatternWave {
+    pattern: [u8; PATTERN_LEN],
+    // pattern bits to advance per output sample
+    step: f32,
+    // current bit in the pattern, fractional
+    position: f32,
+    sample_rate: f32,
+    volume: f32,
+}
+
+impl PatternWave {
+    pub fn new(sample_rate: f32, volume: f32) -> Self {
+        PatternWave {
+            pattern: BUZZER,
+            step: BUZZER_HZ * PATTERN_BITS / sample_rate,
+            position: 0.0,
+            sample_rate,
+            volume,
+        }
+    }
+
+    // Play what the CPU asks for. Keeps the position so switching patterns doesn't click.
+    pub fn set_audio(&mut self, audio: &Audio) {
+        match audio.pattern {
+            Some(pattern) => {
+                self.pattern = pattern;
+                self.step = audio.bit_rate() / self.sample_rate;
+            }
+            None => {
+                self.pattern = BUZZER;
+                self.step = BUZZER_HZ * PATTERN_BITS / self.sample_rate;
+            }
+        }
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            let bit = self.position as usize;
+            let on = self.pattern[bit / 8] >> (7 - bit % 8) & 1 == 1;
+            *x = if on { self.volume } else { -self.volume };
+            self.position = (self.position + self.step) % PATTERN_BITS;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn pitch_doubles_every_48_steps() {
+        for (pitch, rate) in [(64, 4000.0), (112, 8000.0), (16, 2000.0), (88, 5656.854)] {
+            assert!(
+                (pitch_to_bit_rate(pitch) - rate).abs() < 0.01,
+                "pitch {}",
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn wave_plays_the_pattern_bits() {
+        let mut pattern = [0; PATTERN_LEN];
+        pattern[0] = 0b1011_0001;
+        pattern[15] = 0x01;
+        // One pattern bit per sample at the default pitch
+        let mut wave = PatternWave::new(4000.0, 0.5);
+        wave.set_audio(&Audio {
+            pattern: Some(pattern),
+            pitch: DEFAULT_PITCH,
+        });
+        let mut out = vec![0.0; 130];
+        wave.fill(&mut out);
+        let bits: Vec<bool> = out.iter().map(|&x| x > 0.0).collect();
+        let on = |k: usize| pattern[k % 128 / 8] >> (7 - k % 8) & 1 == 1;
+        assert_eq!(bits, (0..130).map(on).collect::<Vec<_>>());
+        assert!(out.iter().all(|&x| x == 0.5 || x == -0.5));
+
+        // Twice the pitch skips every other bit, carrying on from bit 2
+        wave.set_audio(&Audio {
+            pattern: Some(pattern),
+            pitch: DEFAULT_PITCH + 48,
+        });
+        let mut out = [0.0; 4];
+        wave.fill(&mut out);
+        assert_eq!(out, [0.5, -0.5, -0.5, -0.5]);
+    }
+
+    // Runs the series version of exp2 when built without std
+    #[test]
+    fn exp2_matches_known_powers() {
+        for (x, expected) in [
+            (0.0, 1.0),
+            (3.0, 8.0),
+            (-2.0, 0.25),
+            (0.5, core::f32::consts::SQRT_2),
+            (-1.25, 0.420_448_2),
+            (1.0 / 3.0, 1.259_921),
+        ] {
+            let got = exp2(x);
+            assert!(
+                (got - expected).abs() <= expected * 1e-6,
+                "2^{} = {}",
+                x,
+                got
+            );
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use alloc::boxed::Box;
+use alloc::vec;
+
+// What the CPU's memory accesses go through. Every read and write a program does, including
+// fetching opcodes, calls read or write, so a bus can put registers or devices at some addresses
+// and RAM everywhere else. addr is always below ram().len().
+// Loading ROMs and fonts, resets, snapshots and the debugger accessors work on ram() directly.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+    // The memory behind the bus, its length is the size of the address space
+    fn ram(&self) -> &[u8];
+    fn ram_mut(&mut self) -> &mut [u8];
+    // Replace the memory with len zero bytes, see CPU::set_memory_size
+    fn resize(&mut self, len: usize);
+}
+
+// Plain memory and nothing else, the bus of a real interpreter
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ram {
+    bytes: Box<[u8]>,
+}
+
+impl Ram {
+    pub fn new(len: usize) -> Self {
+        Ram {
+            bytes: vec![0; len].into_boxed_slice(),
+        }
+    }
+}
+
+impl Bus for Ram {
+    #[inline]
+    fn read(&mut self, addr: u16) -> u8 {
+        self.bytes[addr as usize]
+    }
+
+    #[inline]
+    fn write(&mut self, addr: u16, val: u8) {
+        self.bytes[addr as usize] = val;
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    fn resize(&mut self, len: usize) {
+        self.bytes = vec![0; len].into_boxed_slice();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use alloc::vec::Vec;
+
+    // RAM with a counter register that counts its reads at 0xFF0 and a serial port at 0xFF1
+    struct Devices {
+        ram: Ram,
+        counter: u8,
+        serial: Vec<u8>,
+    }
+
+    impl Bus for Devices {
+        fn read(&mut self, addr: u16) -> u8 {
+            match addr {
+                0xFF0 => {
+                    self.counter += 1;
+                    self.counter
+                }
+                _ => self.ram.read(addr),
+            }
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            match addr {
+                0xFF1 => self.serial.push(val),
+                _ => self.ram.write(addr, val),
+            }
+        }
+
+        fn ram(&self) -> &[u8] {
+            self.ram.ram()
+        }
+
+        fn ram_mut(&mut self) -> &mut [u8] {
+            self.ram.ram_mut()
+        }
+
+        fn resize(&mut self, len: usize) {
+            self.ram.resize(len)
+        }
+    }
+
+    #[test]
+    fn programs_see_memory_mapped_devices() {
+        let devices = Devices {
+            ram: Ram::new(0x1000),
+            counter: 0,
+            serial: Vec::new(),
+        };
+        let mut cpu = CPU::builder().build_with_bus(devices);
+        // LD I, 0xFF0 / LD V0, [I] / LD V0, [I] / LD I, 0xFF1 / LD [I], V0 / LD [I], V0
+        cpu.load_rom_bytes(&[
+            0xAF, 0xF0, 0xF0, 0x65, 0xF0, 0x65, 0xAF, 0xF1, 0xF0, 0x55, 0xF0, 0x55,
+        ])
+        .unwrap();
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(cpu.v[0], 1);
+        cpu.exec_cycle().unwrap();
+        assert_eq!(cpu.v[0], 2);
+        cpu.exec_cycles(3).unwrap();
+        assert_eq!(cpu.bus.serial, [2, 2]);
+        // The register isn't memory, nothing was stored there
+        assert_eq!(cpu.read_mem(0xFF0..0xFF2).unwrap(), [0, 0]);
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::error::ConditionError;
+
+// A value a condition looks at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    // V0 to VF
+    Register(u8),
+    Index,
+    DelayTimer,
+    SoundTimer,
+    // the byte at an address, 0 past the end of memory
+    Memory(u16),
+    Literal(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// Two-character operators first, so "<=" isn't taken for "<"
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    ("<=", Comparison::Le),
+    (">=", Comparison::Ge),
+    ("<", Comparison::Lt),
+    (">", Comparison::Gt),
+];
+
+// A test of the CPU state for conditional breakpoints, such as "v3 == 0x1f && i > 0x300".
+// Comparisons are joined with && and ||, && binding tighter, without parentheses.
+// Numbers are decimal or 0x hex.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    // true when all comparisons of any group are
+    any_of: Vec<Vec<(Operand, Comparison, Operand)>>,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, ConditionError> {
+        let any_of = text
+            .split("||")
+            .map(|group| group.split("&&").map(parse_comparison).collect())
+            .collect::<Result<_, _>>()?;
+        Ok(Condition { any_of })
+    }
+
+    pub fn eval<B: Bus>(&self, cpu: &CPU<B>) -> bool {
+        self.any_of.iter().any(|all_of| {
+            all_of.iter().all(|&(left, comparison, right)| {
+                let (left, right) = (value(cpu, left), value(cpu, right));
+                match comparison {
+                    Comparison::Eq => left == right,
+                    Comparison::Ne => left != right,
+                    Comparison::Lt => left < right,
+                    Comparison::Le => left <= right,
+                    Comparison::Gt => left > right,
+                    Comparison::Ge => left >= right,
+                }
+            })
+        })
+    }
+}
+
+fn parse_comparison(term: &str) -> Result<(Operand, Comparison, Operand), ConditionError> {
+    let term = term.trim();
+    if term.is_empty() {
+        return Err(ConditionError::Empty);
+    }
+    for &(symbol, comparison) in COMPARISONS.iter() {
+        if let Some(at) = term.find(symbol) {
+            let left = parse_operand(&term[..at])?;
+            let right = parse_operand(&term[at + symbol.len()..])?;
+            return Ok((left, comparison, right));
+        }
+    }
+    Err(ConditionError::MissingComparison {
+        term: term.to_string(),
+    })
+}
+
+fn parse_operand(text: &str) -> Result<Operand, ConditionError> {
+    let text = text.trim();
+    let lower = text.to_ascii_lowercase();
+    let operand = match lower.as_str() {
+        "i" => Some(Operand::Index),
+        "dt" => Some(Operand::DelayTimer),
+        "st" => Some(Operand::SoundTimer),
+        _ if lower.starts_with("mem[") && lower.ends_with(']') => {
+            parse_number(&lower[4..lower.len() - 1]).map(Operand::Memory)
+        }
+        _ if lower.len() == 2 && lower.starts_with('v') => u8::from_str_radix(&lower[1..], 16)
+            .ok()
+            .map(Operand::Register),
+        _ => parse_number(&lower).map(Operand::Literal),
+    };
+    operand.ok_or_else(|| ConditionError::BadOperand {
+        operand: text.to_string(),
+    })
+}
+
+// Decimal, or hex with 0x
+pub(crate) fn parse_number(text: &str) -> Option<u16> {
+    let text = text.trim();
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn value<B: Bus>(cpu: &CPU<B>, operand: Operand) -> u16 {
+    match operand {
+        Operand::Register(x) => cpu.v[x as usize] as u16,
+        Operand::Index => cpu.i,
+        Operand::DelayTimer => cpu.dt as u16,
+        Operand::SoundTimer => cpu.st as u16,
+        Operand::Memory(addr) => cpu.bus.ram().get(addr as usize).copied().unwrap_or(0) as u16,
+        Operand::Literal(n) => n,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn parse_builds_groups_of_comparisons() {
+        let condition = Condition::parse("v3 == 0x1f && i > 0x300 || mem[0x2F0]<=DT").unwrap();
+        assert_eq!(
+            condition.any_of,
+            vec![
+                vec![
+                    (Operand::Register(3), Comparison::Eq, Operand::Literal(0x1F)),
+                    (Operand::Index, Comparison::Gt, Operand::Literal(0x300)),
+                ],
+                vec![(Operand::Memory(0x2F0), Comparison::Le, Operand::DelayTimer)],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_what_it_cant_read() {
+        assert_eq!(Condition::parse("v3 == 1 &&"), Err(ConditionError::Empty));
+        assert_eq!(
+            Condition::parse("v3"),
+            Err(ConditionError::MissingComparison {
+                term: "v3".to_string()
+            })
+        );
+        assert_eq!(
+            Condition::parse("vg == 1"),
+            Err(ConditionError::BadOperand {
+                operand: "vg".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn breakpoint_waits_for_the_condition() {
+        // ADD V3, 1 / ADD I, V3 / JP 0x200
+        let program = [0x73, 0x01, 0xF3, 0x1E, 0x12, 0x00];
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(&program).unwrap();
+        let condition = Condition::parse("v3 == 5 && i >= 10").unwrap();
+        cpu.add_conditional_breakpoint(0x202, condition);
+        // Passes 1 to 4 go through 0x202 without stopping
+        assert_eq!(cpu.exec_cycles(100), Ok(13));
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!((cpu.v[3], cpu.i), (5, 10));
+    }
+}
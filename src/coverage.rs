@@ -0,0 +1,174 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::ops::Range;
+
+use crate::disasm::{self, ListingItem};
+
+// Runs of addresses in range that are all executed or all not, as (first, last, executed)
+fn runs(range: Range<usize>, executed: impl Fn(usize) -> bool) -> Vec<(usize, usize, bool)> {
+    let mut runs: Vec<(usize, usize, bool)> = Vec::new();
+    for addr in range {
+        let executed = executed(addr);
+        match runs.last_mut() {
+            Some((_, last, run_executed)) if *run_executed == executed => *last = addr,
+            _ => runs.push((addr, addr, executed)),
+        }
+    }
+    runs
+}
+
+// The (first, last) addresses of each run of ROM bytes that never executed, for a ROM of len
+// bytes loaded at base. See CPU::coverage.
+pub fn unexecuted_ranges(base: u16, len: usize, coverage: &[bool]) -> Vec<(u16, u16)> {
+    let start = base as usize;
+    runs(start..start + len, |addr| is_executed(coverage, addr))
+        .into_iter()
+        .filter(|&(_, _, executed)| !executed)
+        .map(|(first, last, _)| (first as u16, last as u16))
+        .collect()
+}
+
+fn is_executed(coverage: &[bool], addr: usize) -> bool {
+    coverage.get(addr).copied().unwrap_or(false)
+}
+
+// How much of rom, loaded at base, executed according to coverage: the share of its bytes, the
+// ranges that never did and those that did but disasm::analyze took for data. Then the
+// analyzed listing marked + where executed, - where not and ~ for data lines partly executed.
+pub fn report(rom: &[u8], base: u16, coverage: &[bool]) -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = write_report(&mut out, rom, base, coverage);
+    out
+}
+
+fn write_report(out: &mut String, rom: &[u8], base: u16, coverage: &[bool]) -> fmt::Result {
+    let executed = |addr: usize| is_executed(coverage, addr);
+    let start = base as usize;
+    let covered = (start..start + rom.len())
+        .filter(|&addr| executed(addr))
+        .count();
+    let percent = match rom.len() {
+        0 => 0.0,
+        len => covered as f64 * 100.0 / len as f64,
+    };
+    writeln!(
+        out,
+        "Executed {} of {} ROM bytes, {:.2}%",
+        covered,
+        rom.len(),
+        percent
+    )?;
+
+    writeln!(out, "Never executed:")?;
+    for (first, last) in unexecuted_ranges(base, rom.len(), coverage) {
+        write_range(out, first as usize, last as usize)?;
+    }
+
+    let listing = disasm::analyze(rom, base);
+    let data = |item: &ListingItem| match item {
+        ListingItem::Data { addr, bytes } => Some(*addr as usize..*addr as usize + bytes.len()),
+        ListingItem::Code { .. } => None,
+    };
+    // Merged across data lines
+    let mut executed_data: Vec<(usize, usize)> = Vec::new();
+    for range in listing.items.iter().filter_map(data) {
+        for (first, last, _) in runs(range, executed).into_iter().filter(|run| run.2) {
+            match executed_data.last_mut() {
+                Some((_, prev_last)) if *prev_last + 1 == first => *prev_last = last,
+                _ => executed_data.push((first, last)),
+            }
+        }
+    }
+    if !executed_data.is_empty() {
+        writeln!(out, "Executed but analyzed as data:")?;
+        for &(first, last) in &executed_data {
+            write_range(out, first, last)?;
+        }
+    }
+
+    writeln!(out, "Listing:")?;
+    for item in &listing.items {
+        let marker = match (item, data(item)) {
+            (_, Some(range)) => {
+                let len = range.len();
+                match range.filter(|&addr| executed(addr)).count() {
+                    0 => "-   ",
+                    n if n == len => "+   ",
+                    _ => "~   ",
+                }
+            }
+            (ListingItem::Code { addr, .. }, None) if executed(*addr as usize) => "+   ",
+            _ => "-   ",
+        };
+        listing.write_item(out, item, marker)?;
+    }
+    Ok(())
+}
+
+fn write_range(out: &mut String, first: usize, last: usize) -> fmt::Result {
+    writeln!(
+        out,
+        "    {:#05X}-{:#05X}  {} bytes",
+        first,
+        last,
+        last - first + 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    fn run(rom: &[u8]) -> CPU {
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(rom).unwrap();
+        cpu.exec_cycles(100).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn never_taken_branch_stays_uncovered() {
+        let rom = [
+            0x60, 0x01, // LD V0, 1
+            0x30, 0x01, // SE V0, 1
+            0x12, 0x0A, // JP 0x20A, always skipped
+            0x12, 0x06, // JP 0x206
+            0x60, 0x05, // never reached
+            0x00, 0xE0, // never reached
+        ];
+        let cpu = run(&rom);
+        let coverage = cpu.coverage();
+        assert!(coverage[0x200..0x204].iter().all(|&executed| executed));
+        assert!(!coverage[0x204] && !coverage[0x205]);
+        assert_eq!(
+            unexecuted_ranges(0x200, rom.len(), coverage),
+            [(0x204, 0x205), (0x208, 0x20B)]
+        );
+
+        let report = report(&rom, 0x200, coverage);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "Executed 6 of 12 ROM bytes, 50.00%");
+        assert_eq!(lines[2], "    0x204-0x205  2 bytes");
+        assert_eq!(lines[3], "    0x208-0x20B  4 bytes");
+        assert!(report.contains("-   JP L_20A"));
+        assert!(report.contains("+   SE V0, 0x01"));
+    }
+
+    #[test]
+    fn report_lists_executed_data() {
+        let rom = [
+            0x60, 0x02, // LD V0, 2
+            0xB2, 0x06, // JP V0, 0x206, lands on 0x208
+            0x00, 0x00, // data
+            0x00, 0xFD, // EXIT, where analysis follows the jump
+            0x12, 0x08, // JP 0x208, where the program goes
+        ];
+        let cpu = run(&rom);
+        let report = report(&rom, 0x200, cpu.coverage());
+        assert!(report.contains("Executed but analyzed as data:\n    0x208-0x209  2 bytes\n"));
+        assert!(report.contains("+   db 0x12, 0x08"));
+    }
+}
@@ -1,354 +1,4505 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Range, RangeInclusive};
+#[cfg(feature = "std")]
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::display::Display;
+use crate::audio::{Audio, PATTERN_LEN};
+use crate::bus::{Bus, Ram};
+use crate::condition::Condition;
+use crate::disasm;
+use crate::dispatch::{self, Dispatch};
+use crate::display::{Display, Resolution, PLANES};
+use crate::error::{Chip8Error, RomLoadError};
+use crate::fonts::{self, FontSet};
+use crate::history::History;
+use crate::instruction::Instruction;
 use crate::keyboard::Keyboard;
+use crate::profile::ProfileData;
+use crate::quirks::{Platform, Quirk, Quirks};
+use crate::rng::{RandomSource, Rng};
+use crate::rom_db;
+use crate::user_flags::USER_FLAGS;
 
-pub struct CPU {
+// I always holds an address within memory: 12 bits with the standard 4K, 16 bits with 64K.
+// Instructions that compute it mask it with CPU::address_mask, and instructions that read or
+// write memory starting at I check each byte of the access.
+// Where programs are loaded and start executing on most interpreters
+const DEFAULT_PROGRAM_START: u16 = 0x200;
+// Where ETI-660 programs are loaded and start
+const ETI_660_PROGRAM_START: u16 = 0x600;
+// HiRes CHIP-8 programs start with a jump here, which switches to the 64x64 screen and
+// continues at HIRES_START, past the interpreter's second page
+const HIRES_ENTRY: u16 = 0x260;
+const HIRES_START: u16 = 0x2C0;
+// The interpreter and font live below this address
+const INTERPRETER_AREA_END: u16 = 0x200;
+// Longest loop body, not counting the jump back, that idle detection looks at
+const MAX_IDLE_LOOP_LEN: u16 = 4;
+// Call stack depth of the original interpreter
+const DEFAULT_STACK_DEPTH: usize = 16;
+// Address of the built-in hex font in the interpreter area
+const FONT_BASE: u16 = 0x000;
+// Each glyph of the built-in hex font is 5 bytes tall
+const FONT_GLYPH_SIZE: u16 = 5;
+// The SUPER-CHIP large font follows right after the hex font
+const BIG_FONT_BASE: u16 = FONT_BASE + 80;
+// Each glyph of the large font is 10 bytes tall
+const BIG_FONT_GLYPH_SIZE: u16 = 10;
+// Both fonts, including the A to F slots FX30 can point at, must fit below the program
+const _: () = assert!(BIG_FONT_BASE + 16 * BIG_FONT_GLYPH_SIZE <= INTERPRETER_AREA_END);
+
+// What the last executed instruction did, so the frontend can react to it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CycleState {
+    // The instruction executed with no notable side effect
+    Normal,
+    // DXYN drew a sprite to the display
+    DrewSprite,
+    // DXYN is stalled until the next vertical blank (display wait quirk)
+    WaitingForVblank,
+    // FX0A is blocked until a key is pressed
+    WaitingForKey,
+    // The program has finished and won't execute any further
+    Halted,
+    // A loop that only polls the delay timer jumped back, nothing changes until the next timer tick
+    IdleUntilTimer,
+    // The pre-execution hook asked to pause, the instruction at pc wasn't executed
+    Paused,
+    // pc reached a breakpoint, the instruction at addr wasn't executed
+    BreakpointHit { addr: u16 },
+    // The instruction executed and accessed memory under a watchpoint, see add_watchpoint
+    WatchpointHit(WatchpointHit),
+}
+
+// Which accesses a watchpoint catches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn catches(self, access: WatchKind) -> bool {
+        self == WatchKind::ReadWrite || self == access
+    }
+}
+
+// The first access an instruction made under a watchpoint. kind is Read or Write, and
+// a read has the value read in both old and new.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchpointHit {
+    pub addr: u16,
+    pub kind: WatchKind,
+    // the instruction that made the access
+    pub pc: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            WatchKind::Write => write!(
+                f,
+                "write to {:#05X}: {:#04X} -> {:#04X} by PC {:#05X}",
+                self.addr, self.old, self.new, self.pc
+            ),
+            _ => write!(
+                f,
+                "read of {:#05X}: {:#04X} by PC {:#05X}",
+                self.addr, self.new, self.pc
+            ),
+        }
+    }
+}
+
+// What the pre-execution hook wants done with the instruction about to execute
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HookAction {
+    Continue,
+    Pause,
+}
+
+// Called with the CPU and opcode before an instruction executes, while pc still points at it
+pub type PreExecHook<B = Ram> = Box<dyn FnMut(&CPU<B>, u16) -> HookAction>;
+// Called with the CPU and opcode after an instruction executed successfully
+pub type PostExecHook<B = Ram> = Box<dyn FnMut(&CPU<B>, u16)>;
+
+// How writes by the program into the interpreter area (0x000 to 0x1FF) are treated
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteProtection {
+    // The write lands, as on the original interpreter
+    Off,
+    // The write is dropped with a warning
+    Skip,
+    // The write is reported as a WriteProtected error
+    Error,
+}
+
+// What memory above the interpreter area holds after a reset, before a ROM is loaded.
+// Real hardware doesn't clear RAM, so non-zero contents expose ROMs that read memory they never wrote.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryInit {
+    Zero,
+    // Pseudo random bytes, the same for every reset with the same seed
+    Random(u64),
+    // Every byte set to the same value, e.g. 0xAA
+    Pattern(u8),
+}
+
+// Size of the address space
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MemorySize {
+    // 4K, as on every interpreter before XO-CHIP
+    #[default]
+    Standard,
+    // 64K, for XO-CHIP programs
+    Large,
+}
+
+impl MemorySize {
+    pub fn bytes(self) -> usize {
+        match self {
+            MemorySize::Standard => 0x1000,
+            MemorySize::Large => 0x10000,
+        }
+    }
+}
+
+// Settings for a new CPU, applied on top of the platform preset by build().
+// Anything left unset keeps the value CPU::with_platform or CPU::new gives it.
+#[derive(Clone, Debug, Default)]
+pub struct CpuBuilder {
+    platform: Option<Platform>,
+    quirks: Vec<(Quirk, bool)>,
+    memory_size: Option<MemorySize>,
+    memory_init: Option<MemoryInit>,
+    font: Option<FontSet>,
+    custom_font: Option<[u8; 80]>,
+    load_address: Option<u16>,
+    seed: Option<u64>,
+    use_rom_database: Option<bool>,
+    strict_sys_calls: Option<bool>,
+    dispatch: Option<Dispatch>,
+    protect_interpreter_area: Option<WriteProtection>,
+    uninitialized_read_check: bool,
+}
+
+impl CpuBuilder {
+    // Interpreter family and its quirks. When not set the CPU is the same as CPU::new().
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+
+    // Override one quirk of the platform preset
+    pub fn quirk(mut self, quirk: Quirk, on: bool) -> Self {
+        self.quirks.push((quirk, on));
+        self
+    }
+
+    pub fn memory_size(mut self, size: MemorySize) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+
+    pub fn memory_init(mut self, init: MemoryInit) -> Self {
+        self.memory_init = Some(init);
+        self
+    }
+
+    pub fn font(mut self, font: FontSet) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    // Custom glyphs, 5 rows for each of 0 to F. Wins over font().
+    pub fn custom_font(mut self, font: [u8; 80]) -> Self {
+        self.custom_font = Some(font);
+        self
+    }
+
+    // Where ROMs are loaded and start executing
+    pub fn load_address(mut self, addr: u16) -> Self {
+        self.load_address = Some(addr);
+        self
+    }
+
+    // Seed for RND, see CPU::seed_rng
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn use_rom_database(mut self, on: bool) -> Self {
+        self.use_rom_database = Some(on);
+        self
+    }
+
+    pub fn strict_sys_calls(mut self, on: bool) -> Self {
+        self.strict_sys_calls = Some(on);
+        self
+    }
+
+    pub fn dispatch(mut self, dispatch: Dispatch) -> Self {
+        self.dispatch = Some(dispatch);
+        self
+    }
+
+    pub fn protect_interpreter_area(mut self, protection: WriteProtection) -> Self {
+        self.protect_interpreter_area = Some(protection);
+        self
+    }
+
+    pub fn uninitialized_read_check(mut self, on: bool) -> Self {
+        self.uninitialized_read_check = on;
+        self
+    }
+
+    // Create the CPU and reset it, so the font is loaded and it's ready for a ROM
+    pub fn build(self) -> CPU {
+        self.build_with_bus(Ram::new(MemorySize::Standard.bytes()))
+    }
+
+    // Same as build, with the CPU's memory accesses going through `bus`
+    pub fn build_with_bus<B: Bus>(self, bus: B) -> CPU<B> {
+        let mut cpu = CPU::with_bus(bus);
+        if let Some(platform) = self.platform {
+            cpu.use_platform(platform);
+        }
+        for (quirk, on) in self.quirks {
+            cpu.quirks.set(quirk, on);
+            cpu.explicit_quirks.push(quirk);
+        }
+        if let Some(size) = self.memory_size {
+            cpu.set_memory_size(size);
+        }
+        if let Some(init) = self.memory_init {
+            cpu.memory_init = init;
+        }
+        if let Some(font) = self.font {
+            cpu.set_font(font);
+        }
+        if let Some(font) = &self.custom_font {
+            cpu.set_custom_font(font);
+        }
+        if let Some(addr) = self.load_address {
+            cpu.program_start = addr;
+        }
+        if let Some(seed) = self.seed {
+            cpu.seed_rng(seed);
+        }
+        if let Some(on) = self.use_rom_database {
+            cpu.use_rom_database = on;
+        }
+        if let Some(on) = self.strict_sys_calls {
+            cpu.strict_sys_calls = on;
+        }
+        if let Some(dispatch) = self.dispatch {
+            cpu.dispatch = dispatch;
+        }
+        if let Some(protection) = self.protect_interpreter_area {
+            cpu.protect_interpreter_area = protection;
+        }
+        cpu.set_uninitialized_read_check(self.uninitialized_read_check);
+        cpu.reset();
+        cpu
+    }
+}
+
+// Callback receiving (addr, old, new) for each byte written, see CPU::set_memory_observer
+pub type MemoryObserver = Box<dyn FnMut(u16, u8, u8)>;
+// Callback receiving the screen after an instruction changed it, see CPU::on_display_updated
+pub type DisplayCallback = Box<dyn FnMut(&Display)>;
+// Callback receiving whether the buzzer should sound, see CPU::on_sound
+pub type SoundCallback = Box<dyn FnMut(bool)>;
+
+// What happened during one 60Hz frame, see CPU::run_frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameResult {
+    // instructions executed, fewer than asked for when the program blocked or finished
+    pub instructions: u32,
+    // the display changed and needs redrawing
+    pub drew: bool,
+    // the sound timer is running, the buzzer should sound
+    pub beep: bool,
+    // the program has finished, see CycleState::Halted
+    pub halted: bool,
+    // what ended the frame before it ran all its instructions
+    pub ended_by: Option<CycleState>,
+}
+
+// Counters for profiling, updated as instructions execute and timers tick
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CpuStats {
+    // instructions executed successfully
+    pub instructions: u64,
+    // instructions executed, indexed by the high nibble of the opcode
+    pub instructions_by_family: [u64; 16],
+    // sprites drawn by DXYN
+    pub draws: u64,
+    // 60Hz timer updates
+    pub timer_ticks: u64,
+}
+
+pub struct CPU<B: Bus = Ram> {
     // program counter
     pub pc: u16,
-    // stack
-    pub stack: [u16; 16],
-    // stack pointer
-    pub sp: u8,
-    // index register
+    // stack of return addresses, its length is the stack pointer
+    pub stack: Vec<u16>,
+    // nesting limit of CALL, 16 on the original interpreter
+    pub max_stack_depth: usize,
+    // index register, always within address_mask
     pub i: u16,
+    // SUPER-CHIP RPL user flags, kept across resets so games can save high scores
+    pub rpl_flags: [u8; USER_FLAGS],
     // delay timer
     pub dt: u8,
     // sound timer
     pub st: u8,
+    // XO-CHIP sound pattern and pitch, played while ST is non-zero
+    pub audio: Audio,
     // registers
     pub v: [u8; 16],
-    // memory
-    pub memory: [u8; 4096],
+    // memory, MemorySize::bytes long, and whatever else the bus maps into the address space
+    pub bus: B,
+    // hex font copied to FONT_BASE on reset
+    font: [u8; 80],
+    // contents of memory from 0x200 up after a reset
+    pub memory_init: MemoryInit,
+    // address the program is loaded at and where reset puts the program counter,
+    // 0x200 on most interpreters, 0x600 on the ETI-660
+    pub program_start: u16,
     // keyboard
     pub keyboard: Keyboard,
     // display
     pub display: Display,
+    // interpreter family, decides which extended instructions exist
+    pub platform: Platform,
+    // interpreter-specific behavior toggles
+    pub quirks: Quirks,
+    // whether memory holds a ROM that soft_reset can restart
+    rom_loaded: bool,
+    // CPU::rom_hash of the last ROM loaded
+    rom_crc: Option<u32>,
+    // pick quirks from the ROM database when a known ROM is loaded
+    pub use_rom_database: bool,
+    // quirks set with CpuBuilder::quirk, the ROM database leaves them alone
+    explicit_quirks: Vec<Quirk>,
+    // the values the ROM database replaced, put back before the next ROM is loaded
+    rom_db_undo: Vec<(Quirk, bool)>,
+    // wrap the program counter around the end of memory instead of reporting an error
+    pub pc_wraps: bool,
+    // report 0NNN SYS calls as errors instead of ignoring them
+    pub strict_sys_calls: bool,
+    // how an opcode gets to the code that runs it, both ways behave identically
+    pub dispatch: Dispatch,
+    // keep the program from overwriting the font and the rest of the interpreter area
+    pub protect_interpreter_area: WriteProtection,
+    // random number generator backing RND
+    rng: Box<dyn RandomSource>,
+    // called with (pc, opcode) after each instruction executes, used for tracing
+    on_instruction: Option<Box<dyn FnMut(u16, u16)>>,
+    // called with (addr, old, new) for every byte the program writes, used by tooling
+    memory_observer: Option<MemoryObserver>,
+    // instruction hooks for external tooling, see set_pre_exec_hook and set_post_exec_hook
+    pre_exec_hook: Option<PreExecHook<B>>,
+    post_exec_hook: Option<PostExecHook<B>>,
+    // frontend notifications, see on_display_updated and on_sound
+    display_callback: Option<DisplayCallback>,
+    sound_callback: Option<SoundCallback>,
+    // FX0A is waiting for a key to be released
+    waiting_for_key: bool,
+    // whether ST was non-zero when last checked, to report only changes to the sound callback
+    sound_on: bool,
+    // addresses exec_cycle stops at, when their condition if any holds, see add_breakpoint
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    // breakpoint that was just hit, passed over by the next exec_cycle if pc is still there
+    stepping_off: Option<u16>,
+    // memory ranges whose accesses exec_cycle reports, see add_watchpoint
+    watchpoints: Vec<(RangeInclusive<u16>, WatchKind)>,
+    // first watched access of the instruction being executed
+    watchpoint_hit: Option<WatchpointHit>,
+    // profiling counters
+    stats: CpuStats,
+    // executions per address and opcode family
+    profile: ProfileData,
+    // which bytes belong to an instruction that executed since reset, see coverage
+    coverage: Box<[bool]>,
+    // the last instructions that ran or failed, see set_history
+    history: Option<History>,
+    // COSMAC VIP machine cycles spent by all executed instructions
+    machine_cycles: u64,
+    // set by the 60Hz timer tick, consumed by DXYN when the display wait quirk is on
+    vblank: bool,
+    // address and opcode of the instruction being executed, reported by memory access errors
+    current_pc: u16,
+    current_opcode: u16,
+    // which bytes have been written since reset, only tracked while the uninitialized read
+    // check is enabled
+    written: Option<Box<[bool]>>,
+    // first reads of bytes that were never written, see set_uninitialized_read_check
+    uninitialized_reads: Vec<Chip8Error>,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Hooks, observers and callbacks belong to whoever set them, so the copy starts without any.
+// RND keeps its sequence when the source can save its state, see RandomSource::state,
+// and otherwise starts over from an Rng seeded with 0.
+impl<B: Bus + Clone> Clone for CPU<B> {
+    fn clone(&self) -> Self {
+        CPU {
+            pc: self.pc,
+            stack: self.stack.clone(),
+            max_stack_depth: self.max_stack_depth,
+            i: self.i,
+            rpl_flags: self.rpl_flags,
+            dt: self.dt,
+            st: self.st,
+            audio: self.audio,
+            v: self.v,
+            bus: self.bus.clone(),
+            font: self.font,
+            memory_init: self.memory_init,
+            program_start: self.program_start,
+            keyboard: self.keyboard.clone(),
+            display: self.display.clone(),
+            platform: self.platform,
+            quirks: self.quirks,
+            rom_loaded: self.rom_loaded,
+            rom_crc: self.rom_crc,
+            use_rom_database: self.use_rom_database,
+            explicit_quirks: self.explicit_quirks.clone(),
+            rom_db_undo: self.rom_db_undo.clone(),
+            pc_wraps: self.pc_wraps,
+            strict_sys_calls: self.strict_sys_calls,
+            dispatch: self.dispatch,
+            protect_interpreter_area: self.protect_interpreter_area,
+            rng: rng_from_state(self.rng.state()),
+            on_instruction: None,
+            memory_observer: None,
+            pre_exec_hook: None,
+            post_exec_hook: None,
+            display_callback: None,
+            sound_callback: None,
+            waiting_for_key: self.waiting_for_key,
+            sound_on: self.sound_on,
+            breakpoints: self.breakpoints.clone(),
+            stepping_off: self.stepping_off,
+            watchpoints: self.watchpoints.clone(),
+            watchpoint_hit: self.watchpoint_hit,
+            stats: self.stats.clone(),
+            profile: self.profile.clone(),
+            coverage: self.coverage.clone(),
+            history: self.history.clone(),
+            machine_cycles: self.machine_cycles,
+            vblank: self.vblank,
+            current_pc: self.current_pc,
+            current_opcode: self.current_opcode,
+            written: self.written.clone(),
+            uninitialized_reads: self.uninitialized_reads.clone(),
+        }
+    }
+}
+
+fn rng_from_state(state: Option<u64>) -> Box<dyn RandomSource> {
+    let mut rng = Rng::new(0);
+    if let Some(state) = state {
+        rng.set_state(state);
+    }
+    Box::new(rng)
+}
+
+// Everything a running program can change, to go back to later with CPU::restore.
+// Settings such as quirks, platform and callbacks aren't part of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuSnapshot {
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub dt: u8,
+    pub st: u8,
+    pub memory: Vec<u8>,
+    pub display: Display,
+    pub keyboard: Keyboard,
+    // FX0A is waiting for a key to be released
+    pub waiting_for_key: bool,
+    pub audio: Audio,
+    pub rpl_flags: [u8; USER_FLAGS],
+    // see RandomSource::state
+    pub rng_state: Option<u64>,
+    pub vblank: bool,
+    pub machine_cycles: u64,
 }
 
 impl CPU {
     pub fn new() -> Self {
+        CPU::with_quirks(Quirks::default())
+    }
+
+    // Configure a CPU step by step, see CpuBuilder
+    pub fn builder() -> CpuBuilder {
+        CpuBuilder::default()
+    }
+
+    // Create a CPU whose RND instruction draws from `source`
+    pub fn with_random_source(source: Box<dyn RandomSource>) -> Self {
+        let mut cpu = CPU::new();
+        cpu.rng = source;
+        cpu
+    }
+
+    // Create a CPU emulating a specific interpreter family, including its quirks
+    pub fn with_platform(platform: Platform) -> Self {
+        let mut cpu = CPU::new();
+        cpu.use_platform(platform);
+        cpu
+    }
+
+    // Create a CPU behaving like a specific interpreter, usually one of the Quirks presets
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        let mut cpu = CPU::with_bus(Ram::new(MemorySize::Standard.bytes()));
+        cpu.quirks = quirks;
+        cpu
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    // Create a CPU whose memory accesses go through `bus`, otherwise the same as CPU::new.
+    // The size of the bus's RAM is the size of the address space.
+    pub fn with_bus(bus: B) -> Self {
+        let memory_len = bus.ram().len();
         CPU {
             pc: 0x200,
-            stack: [0; 16],
-            sp: 0,
+            stack: Vec::with_capacity(DEFAULT_STACK_DEPTH),
+            max_stack_depth: DEFAULT_STACK_DEPTH,
             i: 0,
+            rpl_flags: [0; USER_FLAGS],
+            audio: Audio::new(),
             dt: 0,
             st: 0,
             v: [0; 16],
-            memory: [0; 4096],
+            bus,
+            font: *FontSet::default().glyphs(),
+            memory_init: MemoryInit::Zero,
+            program_start: DEFAULT_PROGRAM_START,
             keyboard: Keyboard::new(),
             display: Display::new(),
+            platform: Platform::Chip8,
+            quirks: Quirks::default(),
+            rom_loaded: false,
+            rom_crc: None,
+            use_rom_database: true,
+            explicit_quirks: Vec::new(),
+            rom_db_undo: Vec::new(),
+            pc_wraps: false,
+            strict_sys_calls: false,
+            dispatch: Dispatch::default(),
+            protect_interpreter_area: WriteProtection::Off,
+            rng: Box::new(Rng::new(0)),
+            on_instruction: None,
+            memory_observer: None,
+            pre_exec_hook: None,
+            post_exec_hook: None,
+            display_callback: None,
+            sound_callback: None,
+            waiting_for_key: false,
+            sound_on: false,
+            breakpoints: BTreeMap::new(),
+            stepping_off: None,
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            stats: CpuStats::default(),
+            profile: ProfileData::default(),
+            coverage: vec![false; memory_len].into_boxed_slice(),
+            history: None,
+            machine_cycles: 0,
+            vblank: false,
+            current_pc: 0,
+            current_opcode: 0,
+            written: None,
+            uninitialized_reads: Vec::new(),
+        }
+    }
+
+    // Switch to the quirks and memory layout of an interpreter family
+    fn use_platform(&mut self, platform: Platform) {
+        self.quirks = platform.quirks();
+        self.rom_db_undo.clear();
+        self.platform = platform;
+        match platform {
+            Platform::XoChip => self.set_memory_size(MemorySize::Large),
+            Platform::Eti660 => {
+                self.program_start = ETI_660_PROGRAM_START;
+                self.set_font(FontSet::Eti660);
+            }
+            _ => {}
         }
     }
 
+    // Power cycle: clear everything, including any loaded ROM, and fill memory per memory_init
     pub fn reset(&mut self) {
-        self.pc = 0x200;
-        self.stack = [0; 16];
-        self.sp = 0;
+        let memory = self.bus.ram_mut();
+        memory.fill(0);
+        let program_area = &mut memory[INTERPRETER_AREA_END as usize..];
+        match self.memory_init {
+            MemoryInit::Zero => {}
+            MemoryInit::Random(seed) => {
+                let mut rng = Rng::new(seed);
+                for byte in program_area.iter_mut() {
+                    *byte = rng.next_u8();
+                }
+            }
+            MemoryInit::Pattern(val) => {
+                program_area.fill(val);
+            }
+        }
+        self.rom_loaded = false;
+        if let Some(written) = self.written.as_mut() {
+            written.fill(false);
+        }
+        self.coverage.fill(false);
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        self.uninitialized_reads.clear();
+        self.reset_state();
+    }
+
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            pc: self.pc,
+            stack: self.stack.clone(),
+            i: self.i,
+            v: self.v,
+            dt: self.dt,
+            st: self.st,
+            memory: self.bus.ram().to_vec(),
+            display: self.display.clone(),
+            keyboard: self.keyboard.clone(),
+            waiting_for_key: self.waiting_for_key,
+            audio: self.audio,
+            rpl_flags: self.rpl_flags,
+            rng_state: self.rng.state(),
+            vblank: self.vblank,
+            machine_cycles: self.machine_cycles,
+        }
+    }
+
+    // Go back to the state of a snapshot. The whole screen is redrawn, and the sound callback
+    // hears about ST starting or stopping.
+    pub fn restore(&mut self, snapshot: &CpuSnapshot) {
+        self.pc = snapshot.pc;
+        self.stack.clone_from(&snapshot.stack);
+        self.i = snapshot.i;
+        self.v = snapshot.v;
+        self.dt = snapshot.dt;
+        self.st = snapshot.st;
+        if self.bus.ram().len() != snapshot.memory.len() {
+            self.bus.resize(snapshot.memory.len());
+            if self.written.is_some() {
+                self.set_uninitialized_read_check(true);
+            }
+        }
+        self.bus.ram_mut().copy_from_slice(&snapshot.memory);
+        self.display.clone_from(&snapshot.display);
+        self.display.invalidate();
+        self.display.updated = true;
+        self.keyboard.clone_from(&snapshot.keyboard);
+        self.waiting_for_key = snapshot.waiting_for_key;
+        self.audio = snapshot.audio;
+        self.rpl_flags = snapshot.rpl_flags;
+        if let Some(state) = snapshot.rng_state {
+            self.rng.set_state(state);
+        }
+        self.vblank = snapshot.vblank;
+        self.machine_cycles = snapshot.machine_cycles;
+        self.notify();
+    }
+
+    // Resize memory. Its contents are lost, so reset before loading a ROM.
+    pub fn set_memory_size(&mut self, size: MemorySize) {
+        self.bus.resize(size.bytes());
+        if self.written.is_some() {
+            self.written = Some(vec![false; size.bytes()].into_boxed_slice());
+        }
+        self.rom_loaded = false;
+    }
+
+    pub fn memory_size(&self) -> MemorySize {
+        match self.bus.ram().len() {
+            len if len > MemorySize::Standard.bytes() => MemorySize::Large,
+            _ => MemorySize::Standard,
+        }
+    }
+
+    // Highest address, and the mask for addresses I computes: 0xFFF or 0xFFFF
+    pub fn address_mask(&self) -> u16 {
+        (self.bus.ram().len() - 1) as u16
+    }
+
+    // Restart the loaded ROM without reading it again. Memory from 0x200 up is left as is,
+    // so a ROM that modified itself restarts in its modified form.
+    pub fn soft_reset(&mut self) -> Result<(), Chip8Error> {
+        if !self.rom_loaded {
+            return Err(Chip8Error::NoRomLoaded);
+        }
+        self.reset_state();
+        Ok(())
+    }
+
+    // Clear registers, stack, timers, keyboard and display and reload the font
+    fn reset_state(&mut self) {
+        self.pc = self.program_start;
+        self.stepping_off = None;
+        self.stack.clear();
         self.i = 0;
         self.dt = 0;
         self.st = 0;
+        self.audio = Audio::new();
         self.v = [0; 16];
-        self.memory = [0; 4096];
+        self.vblank = false;
         self.keyboard.clear();
-        self.display.clear();
+        self.waiting_for_key = false;
+        let resolution = match self.platform {
+            Platform::HiresChip8 => Resolution::Tall,
+            Platform::Eti660 => Resolution::Eti660,
+            _ => Resolution::Low,
+        };
+        self.display.set_resolution(resolution, false);
+        self.display.select_planes(1);
         self.load_font();
+        self.notify();
     }
 
-    fn load_font(&mut self) {
-        // Font data should be stored in the interpreter area of Chip-8 memory (0x000 to 0x1FF).
-        let font: [u8; 80] = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
+    // Call `callback` with the address and opcode of every instruction once it executed or
+    // failed. Instructions stalled on a key or the vertical blank are reported once they go on.
+    pub fn on_instruction<F: FnMut(u16, u16) + 'static>(&mut self, callback: F) {
+        self.on_instruction = Some(Box::new(callback));
+    }
 
-        for i in 0..font.len() {
-            self.memory[i] = font[i];
-        }
+    // Call `hook` before every instruction. Returning HookAction::Pause stops exec_cycle from
+    // executing it and makes it report CycleState::Paused instead.
+    pub fn set_pre_exec_hook<F: FnMut(&CPU<B>, u16) -> HookAction + 'static>(&mut self, hook: F) {
+        self.pre_exec_hook = Some(Box::new(hook));
     }
 
-    // Most Chip-8 programs start at location 0x200 in memory
-    pub fn load_rom(&mut self, filename: &str) {
-        let contents: Vec<u8> = fs::read(filename).ok().unwrap();
-        if contents.len() < 0xFFF - 0x200 {
-            for i in 0..contents.len() {
-                self.memory[0x200 + i] = contents[i];
-            }
-        } else {
-            panic!();
-        }
+    // Stop exec_cycle before it executes the instruction at addr, see CycleState::BreakpointHit.
+    // Continuing from there executes that instruction instead of stopping again.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr, None);
     }
 
-    fn fetch_opcode(&mut self) -> u16 {
-        // All instructions are 2 bytes long and are stored most-significant-byte first.
-        println!("PC: {:#X}", self.pc);
-        ((self.memory[self.pc as usize] as u16) << 8) | (self.memory[(self.pc + 1) as usize] as u16)
+    // Like add_breakpoint, but only stop when `condition` holds as pc reaches addr.
+    // Replaces any other breakpoint at addr.
+    pub fn add_conditional_breakpoint(&mut self, addr: u16, condition: Condition) {
+        self.breakpoints.insert(addr, Some(condition));
     }
 
-    // This function expects to be executed at 500HZ, since that is the clock speed of the CHIP8 CPU
-    // Fetch, decode, execute
-    pub fn exec_cycle(&mut self) {
-        let opcode: u16 = self.fetch_opcode();
-        println!("Opcode at PC: {:#X}", opcode);
-        self.pc += 2;
-        self.process_opcode(opcode);
-    }
-
-    fn process_opcode(&mut self, opcode: u16) {
-        // Break apart opcode for decoding
-        let op_4 = (opcode & 0xF000) >> 12;
-        let op_3 = (opcode & 0x0F00) >> 8;
-        let op_2 = (opcode & 0x00F0) >> 4;
-        let op_1 = opcode & 0x000F;
-
-        let nnn = opcode & 0x0FFF;
-        let x = op_3 as usize;
-        let y = op_2 as usize;
-        let n = op_1;
-        let kk = (opcode & 0x00FF) as u8;
-
-        match (op_4, op_3, op_2, op_1) {
-            // CLS - Clear the display
-            (0x0, 0x0, 0xE, 0x0) => self.display.clear(),
-            // RET
-            (0x0, 0x0, 0xE, 0xE) => {
-                self.sp -= 1;
-                self.pc = self.stack[self.sp as usize];
-            }
-            // JP addr
-            (0x1, _, _, _) => {
-                self.pc = nnn;
-            }
-            // CALL addr
-            (0x2, _, _, _) => {
-                self.stack[self.sp as usize] = self.pc;
-                self.sp += 1;
-                self.pc = nnn;
-            }
-            // SE Vx, byte
-            (0x3, _, _, _) => {
-                if self.v[x] == kk {
-                    self.pc += 2;
-                }
-            }
-            // SNE Vx, byte
-            (0x4, _, _, _) => {
-                if self.v[x] != kk {
-                    self.pc += 2;
-                }
-            }
-            // SE Vx, Vy
-            (0x5, _, _, _) => {
-                if self.v[x] == self.v[y] {
-                    self.pc += 2;
-                }
-            }
-            // LD Vx, byte
-            (0x6, _, _, _) => {
-                self.v[x] = kk;
-            }
-            // ADD Vx, byte
-            (0x7, _, _, _) => {
-                self.v[x] = self.v[x].wrapping_add(kk);
-            }
-            // LD Vx, Vy
-            (0x8, _, _, 0x0) => {
-                self.v[x] = self.v[y];
-            }
-            // OR Vx, Vy
-            (0x8, _, _, 0x1) => {
-                self.v[x] = self.v[x] | self.v[y];
-            }
-            // AND Vx, Vy
-            (0x8, _, _, 0x2) => {
-                self.v[x] = self.v[x] & self.v[y];
-            }
-            // XOR Vx, Vy
-            (0x8, _, _, 0x3) => {
-                self.v[x] = self.v[x] ^ self.v[y];
-            }
-            // ADD Vx, Vy
-            (0x8, _, _, 0x4) => {
-                let (res, overflow) = self.v[x].overflowing_add(self.v[y]);
-                self.v[x] = res;
-                match overflow {
-                    true => self.v[0xF] = 1,
-                    false => self.v[0xF] = 0,
-                }
-            }
-            // SUB Vx, Vy
-            (0x8, _, _, 0x5) => {
-                let (res, overflow) = self.v[x].overflowing_sub(self.v[y]);
-                self.v[x] = res;
-                match overflow {
-                    true => self.v[0xF] = 0,
-                    false => self.v[0xF] = 1,
-                }
-            }
-            // SHR Vx {, Vy}
-            (0x8, _, _, 0x6) => {
-                if (self.v[x] & 0b1) == 1 {
-                    self.v[0xF] = 1;
-                } else {
-                    self.v[0xF] = 0;
-                }
-                self.v[x] = self.v[x] >> 1;
-            }
-            // SUBN Vx, Vy
-            (0x8, _, _, 0x7) => {
-                let (res, overflow) = self.v[y].overflowing_sub(self.v[x]);
-                self.v[x] = res;
-                match overflow {
-                    true => self.v[0xF] = 0,
-                    false => self.v[0xF] = 1,
-                }
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    // Report reads, writes or both of the bytes in range, both ends included so the last byte
+    // of 64K memory can be watched. exec_cycle finishes the instruction that made the access,
+    // then returns CycleState::WatchpointHit for the first one it made. Opcode fetches don't
+    // count as reads. Ranges may overlap.
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, kind: WatchKind) {
+        self.watchpoints.push((range, kind));
+    }
+
+    // Remove every watchpoint over exactly this range
+    pub fn remove_watchpoint(&mut self, range: RangeInclusive<u16>) {
+        self.watchpoints.retain(|(watched, _)| *watched != range);
+    }
+
+    // Call `hook` after every successfully executed instruction. Cycles where DXYN or FX0A
+    // stall don't count, see CycleState::WaitingForVblank.
+    pub fn set_post_exec_hook<F: FnMut(&CPU<B>, u16) + 'static>(&mut self, hook: F) {
+        self.post_exec_hook = Some(Box::new(hook));
+    }
+
+    // Remove the post-execution hook and hand it back, to chain another one in front of it
+    pub fn take_post_exec_hook(&mut self) -> Option<PostExecHook<B>> {
+        self.post_exec_hook.take()
+    }
+
+    // Call `observer` with the address, old and new value of every byte written by an instruction.
+    // Loading the ROM and font and resetting memory don't count as writes.
+    pub fn set_memory_observer(&mut self, observer: MemoryObserver) {
+        self.memory_observer = Some(observer);
+    }
+
+    // Remove the memory observer and hand it back, to chain another one in front of it
+    pub fn take_memory_observer(&mut self) -> Option<MemoryObserver> {
+        self.memory_observer.take()
+    }
+
+    // Call `callback` with the screen after every instruction that drew, cleared or scrolled it
+    pub fn on_display_updated<F: FnMut(&Display) + 'static>(&mut self, callback: F) {
+        self.display_callback = Some(Box::new(callback));
+    }
+
+    // Call `callback` with true when ST becomes non-zero and false when it gets back to zero,
+    // so a frontend can start and stop the buzzer exactly as long as ST runs
+    pub fn on_sound<F: FnMut(bool) + 'static>(&mut self, callback: F) {
+        self.sound_callback = Some(Box::new(callback));
+    }
+
+    // Tell the callbacks about display and sound changes since the last check
+    fn notify(&mut self) {
+        if self.display.updated {
+            self.display.updated = false;
+            if let Some(callback) = self.display_callback.as_mut() {
+                callback(&self.display);
             }
-            // SHL Vx {, Vy}
-            (0x8, _, _, 0xE) => {
-                if (self.v[x] & 0x80) > 1 {
-                    self.v[0xF] = 1;
-                } else {
-                    self.v[0xF] = 0;
-                }
-                self.v[x] = self.v[x] << 1;
+        }
+        let sound_on = self.st > 0;
+        if sound_on != self.sound_on {
+            self.sound_on = sound_on;
+            if let Some(callback) = self.sound_callback.as_mut() {
+                callback(sound_on);
             }
-            // SNE Vx, Vy
-            (0x9, _, _, 0x0) => {
-                if self.v[x] != self.v[y] {
-                    self.pc += 2;
-                }
+        }
+    }
+
+    // Log the first read of every byte the program never wrote, neither the ROM nor the font
+    // count. Enable before reset() so the ROM load is seen.
+    pub fn set_uninitialized_read_check(&mut self, enabled: bool) {
+        self.written = match enabled {
+            true => Some(vec![false; self.bus.ram().len()].into_boxed_slice()),
+            false => None,
+        };
+    }
+
+    // Reads reported by the uninitialized read check since reset, as UninitializedRead errors
+    pub fn uninitialized_reads(&self) -> &[Chip8Error] {
+        &self.uninitialized_reads
+    }
+
+    // Make RND produce the same sequence on every run with the same seed.
+    // Without this RND uses seed 0, so frontends wanting a different game every run seed from the clock.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Box::new(Rng::new(seed));
+    }
+
+    // Use one of the built-in fonts from the next reset on
+    pub fn set_font(&mut self, font: FontSet) {
+        self.font = *font.glyphs();
+    }
+
+    // Use custom glyphs, 5 rows for each of 0 to F, from the next reset on
+    pub fn set_custom_font(&mut self, font: &[u8; 80]) {
+        self.font = *font;
+    }
+
+    // Font data should be stored in the interpreter area of Chip-8 memory (0x000 to 0x1FF).
+    fn load_font(&mut self) {
+        let font = self.font;
+        let base = FONT_BASE as usize;
+        self.bus.ram_mut()[base..base + font.len()].copy_from_slice(&font);
+        self.mark_written(base..base + font.len());
+
+        let big_base = BIG_FONT_BASE as usize;
+        self.bus.ram_mut()[big_base..big_base + fonts::BIG.len()].copy_from_slice(&fonts::BIG);
+        self.mark_written(big_base..big_base + fonts::BIG.len());
+    }
+
+    // Load a ROM file at program_start
+    // Returns the number of bytes loaded
+    #[cfg(feature = "std")]
+    pub fn load_rom(&mut self, filename: &str) -> Result<usize, Chip8Error> {
+        let contents: Vec<u8> = fs::read(filename).map_err(RomLoadError::Io)?;
+        self.load_rom_bytes(&contents)
+    }
+
+    // Load a ROM image that is already in memory, e.g. from include_bytes!, at program_start
+    // Returns the number of bytes loaded
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<usize, Chip8Error> {
+        self.load_rom_at(data, self.program_start)
+    }
+
+    // Load a ROM image at `base` and make that the program start, for interpreters that
+    // don't use 0x200 such as the ETI-660. The program counter is moved to `base` as well.
+    // Returns the number of bytes loaded
+    pub fn load_rom_at(&mut self, data: &[u8], base: u16) -> Result<usize, Chip8Error> {
+        if data.is_empty() {
+            return Err(RomLoadError::Empty.into());
+        }
+        // The ROM may fill memory right up to the last byte
+        let start = base as usize;
+        let max = self.bus.ram().len().saturating_sub(start);
+        if data.len() > max {
+            return Err(RomLoadError::TooLarge {
+                size: data.len(),
+                max,
             }
-            // LD I, addr
-            (0xA, _, _, _) => {
-                self.i = nnn;
-            }
-            // JP V0, addr
-            (0xB, _, _, _) => {
-                self.pc = nnn + (self.v[0] as u16);
-            }
-            // RND Vx, byte
-            (0xC, _, _, _) => {
-                let pseudo_random = (SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .subsec_nanos()
-                    % 256) as u8;
-                self.v[x] = pseudo_random & kk;
-            }
-            // DRW Vx, Vy, nibble
-            (0xD, _, _, _) => {
-                let collision = self.display.draw_sprite(
-                    self.v[x] as usize,
-                    self.v[y] as usize,
-                    &self.memory[self.i as usize..=(self.i + n) as usize],
+            .into());
+        }
+        self.bus.ram_mut()[start..start + data.len()].copy_from_slice(data);
+        self.mark_written(start..start + data.len());
+        self.program_start = base;
+        self.pc = base;
+        self.rom_loaded = true;
+
+        let hash = Self::rom_hash(data);
+        self.rom_crc = Some(hash);
+        for (quirk, on) in self.rom_db_undo.drain(..) {
+            self.quirks.set(quirk, on);
+        }
+        if self.use_rom_database {
+            if let Some(profile) = rom_db::lookup(hash) {
+                log!(
+                    "Recognized {} ({:08X}), using its quirks",
+                    profile.name,
+                    hash
                 );
-                match collision {
-                    true => self.v[0xF] = 1,
-                    false => self.v[0xF] = 0,
+                // On top of the platform preset, quirks set on the builder still win
+                for &(quirk, on) in profile.quirks {
+                    if !self.explicit_quirks.contains(&quirk) {
+                        self.rom_db_undo.push((quirk, self.quirks.get(quirk)));
+                        self.quirks.set(quirk, on);
+                    }
                 }
             }
-            // SKP Vx
-            (0xE, _, 0x9, 0xE) => {
-                if self.keyboard.is_pressed(self.v[x]) {
-                    self.pc += 2;
-                }
+        }
+        Ok(data.len())
+    }
+
+    // CRC-32 of the last ROM loaded, identifies it for the ROM database and saved user flags
+    pub fn rom_crc(&self) -> Option<u32> {
+        self.rom_crc
+    }
+
+    // CRC-32 (IEEE) of a ROM image, the key of the ROM quirk database
+    pub fn rom_hash(rom: &[u8]) -> u32 {
+        let mut crc = 0xFFFF_FFFFu32;
+        for &byte in rom {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
             }
-            // SKNP Vx
-            (0xE, _, 0xA, 0x1) => {
-                if !self.keyboard.is_pressed(self.v[x]) {
-                    self.pc += 2;
-                }
+        }
+        !crc
+    }
+
+    fn fetch_opcode(&mut self) -> Result<u16, Chip8Error> {
+        // All instructions are 2 bytes long and are stored most-significant-byte first.
+        let len = self.bus.ram().len();
+        let opcode = match self.pc_wraps {
+            true => {
+                self.pc &= self.address_mask();
+                let next = self.pc.wrapping_add(1) & self.address_mask();
+                self.read_byte(self.pc)
+                    .and_then(|hi| Ok(((hi as u16) << 8) | self.read_byte(next)? as u16))
             }
-            // LD Vx, DT
-            (0xF, _, 0x0, 0x7) => {
-                self.v[x] = self.dt;
+            false => self.read_word(self.pc),
+        };
+        // Report the first byte of the opcode that lies outside memory
+        opcode.map_err(|_| Chip8Error::ProgramCounterOutOfBounds {
+            addr: (self.pc as usize).max(len),
+            pc: self.pc,
+        })
+    }
+
+    // Read the byte at addr
+    pub fn read_byte(&mut self, addr: u16) -> Result<u8, Chip8Error> {
+        if addr as usize >= self.bus.ram().len() {
+            return Err(self.out_of_bounds(addr));
+        }
+        let val = self.bus.read(addr);
+        if let Some(written) = self.written.as_mut() {
+            // Report each byte once by treating it as written from here on
+            if !written[addr as usize] {
+                written[addr as usize] = true;
+                let err = Chip8Error::UninitializedRead {
+                    addr,
+                    pc: self.current_pc,
+                };
+                log!("Warning: {}", err);
+                self.uninitialized_reads.push(err);
             }
-            // LD Vx, K
-            (0xF, _, 0x0, 0xA) => match self.keyboard.keys.iter().next() {
-                Some(key) => {
-                    self.v[x] = *key;
-                }
-                None => {
-                    self.pc -= 2;
-                }
-            },
-            // LD DT, Vx
-            (0xF, _, 0x1, 0x5) => {
-                self.dt = self.v[x];
-            }
-            // LD ST, Vx
-            (0xF, _, 0x1, 0x8) => {
-                self.st = self.v[x];
-            }
-            // ADD I, Vx
-            (0xF, _, 0x1, 0xE) => {
-                self.i = self.i + (self.v[x] as u16);
-            }
-            // LD F, Vx
-            (0xF, _, 0x2, 0x9) => {
-                self.i = (self.v[x] * 5).into();
-            }
-            // LD B, Vx
-            (0xF, _, 0x3, 0x3) => {
-                self.memory[self.i as usize] = self.v[x] / 100;
-                self.memory[(self.i + 1) as usize] = (self.v[x] / 10) % 10;
-                self.memory[(self.i + 2) as usize] = (self.v[x] % 100) % 10;
-            }
-            // LD [I], Vx
-            (0xF, _, 0x5, 0x5) => {
-                for idx in 0..=x {
-                    self.memory[(self.i + (idx as u16)) as usize] = self.v[idx as usize];
+        }
+        self.watch(addr, WatchKind::Read, val, val);
+        Ok(val)
+    }
+
+    // Write val to the byte at addr, subject to protect_interpreter_area
+    pub fn write_byte(&mut self, addr: u16, val: u8) -> Result<(), Chip8Error> {
+        if addr < INTERPRETER_AREA_END {
+            match self.protect_interpreter_area {
+                WriteProtection::Off => {}
+                WriteProtection::Skip => {
+                    log!(
+                        "Ignoring write to protected address {:#05X} at PC {:#05X}",
+                        addr,
+                        self.current_pc
+                    );
+                    return Ok(());
                 }
-            }
-            // LD Vx, [I]
-            (0xF, _, 0x6, 0x5) => {
-                for idx in 0..=x {
-                    self.v[idx as usize] = self.memory[(self.i + (idx as u16)) as usize];
+                WriteProtection::Error => {
+                    return Err(Chip8Error::WriteProtected {
+                        addr,
+                        pc: self.current_pc,
+                        opcode: self.current_opcode,
+                    })
                 }
             }
-            _ => {
-                println!("Invalid Opcode: {:#X}", opcode);
-                panic!();
-            }
         }
+        if addr as usize >= self.bus.ram().len() {
+            return Err(self.out_of_bounds(addr));
+        }
+        let old = self.bus.ram()[addr as usize];
+        self.bus.write(addr, val);
+        if let Some(observer) = self.memory_observer.as_mut() {
+            observer(addr, old, val);
+        }
+        self.watch(addr, WatchKind::Write, old, val);
+        if let Some(written) = self.written.as_mut() {
+            written[addr as usize] = true;
+        }
+        Ok(())
     }
 
-    // This function should be called at 60Hz
-    // Returns true if buzzer should sound
-    pub fn update_timers(&mut self) -> bool {
-        // The delay timer is active whenever the delay timer register (DT) is non-zero.
-        // This timer does nothing more than subtract 1 from the value of DT at a rate of 60Hz.
-        // When DT reaches 0, it deactivates.
-        if self.dt > 0 {
-            self.dt -= 1;
+    // Remember the access if it's the instruction's first under a watchpoint
+    fn watch(&mut self, addr: u16, access: WatchKind, old: u8, new: u8) {
+        if self.watchpoint_hit.is_some() {
+            return;
         }
-
-        //The sound timer is active whenever the sound timer register (ST) is non-zero.
-        // This timer also decrements at a rate of 60Hz, however, as long as ST's value is greater than zero,
-        // the Chip-8 buzzer will sound. When ST reaches zero, the sound timer deactivates.
-        if self.st > 0 {
-            self.st -= 1;
-            true
-        } else {
-            false
+        let watched = self
+            .watchpoints
+            .iter()
+            .any(|(range, kind)| range.contains(&addr) && kind.catches(access));
+        if watched {
+            self.watchpoint_hit = Some(WatchpointHit {
+                addr,
+                kind: access,
+                pc: self.current_pc,
+                old,
+                new,
+            });
         }
     }
 
-}
+    // Read the big-endian word at addr and addr + 1
+    pub fn read_word(&mut self, addr: u16) -> Result<u16, Chip8Error> {
+        let hi = self.read_byte(addr)?;
+        let lo = self.read_byte(addr.wrapping_add(1))?;
+        Ok(((hi as u16) << 8) | lo as u16)
+    }
+
+    // Write val big-endian to addr and addr + 1
+    pub fn write_word(&mut self, addr: u16, val: u16) -> Result<(), Chip8Error> {
+        self.write_byte(addr, (val >> 8) as u8)?;
+        self.write_byte(addr.wrapping_add(1), val as u8)
+    }
+
+    // Key input for scripts, tests and headless frontends, see Keyboard
+    pub fn key_down(&mut self, key: u8) -> Result<(), Chip8Error> {
+        self.keyboard.press(key)
+    }
 
+    pub fn key_up(&mut self, key: u8) -> Result<(), Chip8Error> {
+        self.keyboard.release(key)
+    }
+
+    // Access for debuggers and other tools. Unlike read_byte and write_byte these act
+    // like the hardware isn't involved: no write protection, memory observer or uninitialized
+    // read warnings.
+
+    pub fn register(&self, x: usize) -> Result<u8, Chip8Error> {
+        self.v
+            .get(x)
+            .copied()
+            .ok_or(Chip8Error::InvalidRegister { x })
+    }
+
+    pub fn set_register(&mut self, x: usize, val: u8) -> Result<(), Chip8Error> {
+        match self.v.get_mut(x) {
+            Some(v) => {
+                *v = val;
+                Ok(())
+            }
+            None => Err(Chip8Error::InvalidRegister { x }),
+        }
+    }
+
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+
+    // I must stay within memory, like the instructions that compute it keep it
+    pub fn set_index(&mut self, addr: u16) -> Result<(), Chip8Error> {
+        self.check_range(addr as usize, 1)?;
+        self.i = addr;
+        Ok(())
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.pc
+    }
+
+    // The whole opcode at addr has to be in memory
+    pub fn set_program_counter(&mut self, addr: u16) -> Result<(), Chip8Error> {
+        self.check_range(addr as usize, 2)?;
+        self.pc = addr;
+        Ok(())
+    }
+
+    pub fn read_mem(&self, range: Range<usize>) -> Result<&[u8], Chip8Error> {
+        let len = range.end.saturating_sub(range.start);
+        self.check_range(range.start, len)?;
+        Ok(&self.bus.ram()[range.start..range.start + len])
+    }
+
+    // Copy data into memory at addr. Nothing is written unless all of it fits.
+    pub fn write_mem(&mut self, addr: u16, data: &[u8]) -> Result<(), Chip8Error> {
+        let start = addr as usize;
+        self.check_range(start, data.len())?;
+        self.bus.ram_mut()[start..start + data.len()].copy_from_slice(data);
+        self.mark_written(start..start + data.len());
+        Ok(())
+    }
+
+    // Color index of every pixel, see Display::framebuffer
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.display.framebuffer()
+    }
+
+    fn check_range(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        match addr.checked_add(len) {
+            Some(end) if end <= self.bus.ram().len() => Ok(()),
+            _ => Err(Chip8Error::AddressOutOfRange { addr, len }),
+        }
+    }
+
+    // Word at addr without any checks or side effects, None past the end of memory
+    fn peek_word(&self, addr: u16) -> Option<u16> {
+        let memory = self.bus.ram();
+        let hi = *memory.get(addr as usize)?;
+        let lo = *memory.get(addr as usize + 1)?;
+        Some(((hi as u16) << 8) | lo as u16)
+    }
+
+    fn mark_executed(&mut self, pc: u16, opcode: u16) {
+        let len = self.bus.ram().len();
+        if self.coverage.len() != len {
+            // The bus was resized
+            self.coverage = vec![false; len].into_boxed_slice();
+        }
+        // F000 NNNN is one instruction, the bytes past the end wrap to 0 with pc_wraps
+        let bytes = if opcode == 0xF000 { 4 } else { 2 };
+        for k in 0..bytes {
+            self.coverage[(pc as usize + k) % len] = true;
+        }
+    }
+
+    fn mark_written(&mut self, range: Range<usize>) {
+        if let Some(written) = self.written.as_mut() {
+            written[range].fill(true);
+        }
+    }
+
+    // Address `offset` bytes past I, for instructions that access memory starting at I
+    fn index_address(&self, offset: usize) -> Result<u16, Chip8Error> {
+        let addr = self.i as usize + offset;
+        match addr < self.bus.ram().len() {
+            true => Ok(addr as u16),
+            false => Err(Chip8Error::MemoryOutOfBounds {
+                addr,
+                pc: self.current_pc,
+                opcode: self.current_opcode,
+            }),
+        }
+    }
+
+    fn out_of_bounds(&self, addr: u16) -> Chip8Error {
+        Chip8Error::MemoryOutOfBounds {
+            addr: addr as usize,
+            pc: self.current_pc,
+            opcode: self.current_opcode,
+        }
+    }
+
+    // This function expects to be executed at 500HZ, since that is the clock speed of the CHIP8 CPU
+    // Fetch, decode, execute
+    // On error the program counter is left pointing at the faulting instruction.
+    pub fn exec_cycle(&mut self) -> Result<CycleState, Chip8Error> {
+        if self.stepping_off.take() != Some(self.pc) && self.breakpoint_hit() {
+            self.stepping_off = Some(self.pc);
+            return Ok(CycleState::BreakpointHit { addr: self.pc });
+        }
+        let opcode: u16 = self.fetch_opcode()?;
+        let pc = self.pc;
+        self.watchpoint_hit = None;
+        // The hooks get to look at the whole CPU, so take them out while they run
+        if let Some(mut hook) = self.pre_exec_hook.take() {
+            let action = hook(self, opcode);
+            self.pre_exec_hook = Some(hook);
+            if action == HookAction::Pause {
+                return Ok(CycleState::Paused);
+            }
+        }
+        // The history wants the registers from before the instruction
+        let (v, i) = (self.v, self.i);
+        let executed = match self.dispatch {
+            Dispatch::Match => self.decode(opcode).map(|instruction| {
+                self.move_past(pc, opcode);
+                (self.execute(instruction), instruction.vip_cycles())
+            }),
+            Dispatch::Table => dispatch::lookup(opcode, self.platform).map(|(handler, cycles)| {
+                self.move_past(pc, opcode);
+                self.start_instruction(opcode);
+                (handler(self, opcode), cycles)
+            }),
+        };
+        let (result, cycles) = match executed {
+            Some(executed) => executed,
+            None => {
+                self.record_instruction(pc, opcode, &v, i);
+                return Err(Chip8Error::UnknownOpcode { opcode, pc });
+            }
+        };
+        match result {
+            // Stalled with pc back on the instruction, nothing executed. It runs again on the
+            // next cycle and is counted then.
+            Ok(CycleState::WaitingForKey) | Ok(CycleState::WaitingForVblank) => {}
+            Ok(state) => {
+                self.record_instruction(pc, opcode, &v, i);
+                self.machine_cycles += cycles as u64;
+                self.stats.instructions += 1;
+                self.stats.instructions_by_family[(opcode >> 12) as usize] += 1;
+                self.profile.record(pc, opcode);
+                if state == CycleState::DrewSprite {
+                    self.stats.draws += 1;
+                }
+                if let Some(mut hook) = self.post_exec_hook.take() {
+                    hook(self, opcode);
+                    self.post_exec_hook = Some(hook);
+                }
+                self.notify();
+                if let Some(hit) = self.watchpoint_hit.take() {
+                    return Ok(CycleState::WatchpointHit(hit));
+                }
+            }
+            Err(_) => {
+                self.record_instruction(pc, opcode, &v, i);
+                self.pc = pc;
+            }
+        }
+        result
+    }
+
+    // Decode for this platform, None when the opcode isn't an instruction on it
+    fn decode(&self, opcode: u16) -> Option<Instruction> {
+        match Instruction::decode(opcode) {
+            Some(instruction) if instruction.available_on(self.platform) => Some(instruction),
+            // Where they don't exist, the SUPER-CHIP 00NN instructions are machine code calls
+            Some(_) if opcode & 0xF000 == 0 => Some(Instruction::Sys(opcode)),
+            _ => None,
+        }
+    }
+
+    // Mark the instruction at pc as run and point pc past it
+    fn move_past(&mut self, pc: u16, opcode: u16) {
+        self.mark_executed(pc, opcode);
+        // Only wraps with 64K memory, where the last opcode sits at 0xFFFE
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    // Add an instruction that executed or failed to the history and the trace
+    fn record_instruction(&mut self, pc: u16, opcode: u16, v: &[u8; 16], i: u16) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(pc, opcode, v, i);
+        }
+        if let Some(callback) = self.on_instruction.as_mut() {
+            callback(pc, opcode);
+        }
+    }
+
+    // Whether a breakpoint stops the instruction at pc
+    fn breakpoint_hit(&self) -> bool {
+        match self.breakpoints.get(&self.pc) {
+            Some(Some(condition)) => condition.eval(self),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    // Execute up to n instructions and return how many executed. Stops early when the program
+    // finishes or blocks on FX0A or the vertical blank, which no amount of instructions can end.
+    pub fn exec_cycles(&mut self, n: u32) -> Result<u32, Chip8Error> {
+        for executed in 0..n {
+            match self.exec_cycle()? {
+                CycleState::Halted | CycleState::WatchpointHit(_) => return Ok(executed + 1),
+                CycleState::WaitingForKey
+                | CycleState::WaitingForVblank
+                | CycleState::Paused
+                | CycleState::BreakpointHit { .. } => return Ok(executed),
+                _ => {}
+            }
+        }
+        Ok(n)
+    }
+
+    // Run one 60Hz frame: up to `instructions_per_frame` instructions, then one timer tick.
+    // The frame ends early when the program waits for a key, the vertical blank or the timer,
+    // or finishes. A breakpoint, watchpoint or pause ends it without the timer tick.
+    pub fn run_frame(&mut self, instructions_per_frame: u32) -> Result<FrameResult, Chip8Error> {
+        self.run_frame_while(|_, frame| frame.instructions < instructions_per_frame)
+    }
+
+    // run_frame, going on with the frame while `more` agrees. Emulator uses it to measure
+    // frames in machine cycles.
+    pub(crate) fn run_frame_while(
+        &mut self,
+        mut more: impl FnMut(&Self, &FrameResult) -> bool,
+    ) -> Result<FrameResult, Chip8Error> {
+        let changes = self.display.changes();
+        let mut result = FrameResult::default();
+        while more(self, &result) {
+            let state = self.exec_cycle()?;
+            match state {
+                CycleState::Normal | CycleState::DrewSprite => {
+                    result.instructions += 1;
+                    continue;
+                }
+                CycleState::IdleUntilTimer | CycleState::WatchpointHit(_) => {
+                    result.instructions += 1
+                }
+                CycleState::Halted => {
+                    result.instructions += 1;
+                    result.halted = true;
+                }
+                // Nothing executed, the instruction runs again next frame
+                CycleState::WaitingForVblank
+                | CycleState::WaitingForKey
+                | CycleState::Paused
+                | CycleState::BreakpointHit { .. } => {}
+            }
+            result.ended_by = Some(state);
+            break;
+        }
+        result.beep = match result.ended_by {
+            // Stopped for the debugger, the timers wait with the rest of the machine
+            Some(CycleState::Paused)
+            | Some(CycleState::BreakpointHit { .. })
+            | Some(CycleState::WatchpointHit(_)) => self.st > 0,
+            _ => self.update_timers(),
+        };
+        result.drew = self.display.changes() != changes;
+        Ok(result)
+    }
+
+    // Number of subroutine calls currently nested
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn stats(&self) -> &CpuStats {
+        &self.stats
+    }
+
+    // Clears the profile too
+    pub fn reset_stats(&mut self) {
+        self.stats = CpuStats::default();
+        self.profile = ProfileData::default();
+    }
+
+    // Whether each byte of memory belongs to an instruction that executed since reset,
+    // including the address after F000. Opcodes that don't decode and instructions the
+    // pre-execution hook paused don't count, an instruction failing as it executes does.
+    // See coverage::report.
+    pub fn coverage(&self) -> &[bool] {
+        &self.coverage
+    }
+
+    // Keep the last instructions from now on, including one that fails, or stop with None, the
+    // default. Instructions the pre-execution hook paused aren't kept until they run.
+    pub fn set_history(&mut self, history: Option<History>) {
+        self.history = history;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    // Instructions executed per address and opcode family, for finding where a ROM spends its
+    // time. See ProfileData::report.
+    pub fn profile(&self) -> &ProfileData {
+        &self.profile
+    }
+
+    // Time spent so far according to the COSMAC VIP timing table, see Instruction::vip_cycles
+    pub fn machine_cycles(&self) -> u64 {
+        self.machine_cycles
+    }
+
+    // Execute an already decoded instruction. The program counter must already point past it.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<CycleState, Chip8Error> {
+        self.start_instruction(instruction.encode());
+        match instruction {
+            Instruction::Cls => self.op_cls(),
+            Instruction::ScrollDown(n) => self.op_scroll_down(n),
+            Instruction::ScrollUp(n) => self.op_scroll_up(n),
+            Instruction::ScrollRight => self.op_scroll_right(),
+            Instruction::ScrollLeft => self.op_scroll_left(),
+            Instruction::Exit => self.op_exit(),
+            Instruction::Plane(n) => self.op_plane(n),
+            Instruction::Lores => self.op_lores(),
+            Instruction::Hires => self.op_hires(),
+            Instruction::Ret => self.op_ret(),
+            Instruction::Sys(nnn) => self.op_sys(nnn),
+            Instruction::Jump(nnn) => self.op_jump(nnn),
+            Instruction::Call(nnn) => self.op_call(nnn),
+            Instruction::SkipEqByte { x, kk } => self.op_skip_eq_byte(x, kk),
+            Instruction::SkipNeByte { x, kk } => self.op_skip_ne_byte(x, kk),
+            Instruction::SkipEqReg { x, y } => self.op_skip_eq_reg(x, y),
+            Instruction::LoadByte { x, kk } => self.op_load_byte(x, kk),
+            Instruction::AddVxByte { x, kk } => self.op_add_vx_byte(x, kk),
+            Instruction::LoadReg { x, y } => self.op_load_reg(x, y),
+            Instruction::Or { x, y } => self.op_or(x, y),
+            Instruction::And { x, y } => self.op_and(x, y),
+            Instruction::Xor { x, y } => self.op_xor(x, y),
+            Instruction::AddReg { x, y } => self.op_add_reg(x, y),
+            Instruction::Sub { x, y } => self.op_sub(x, y),
+            Instruction::Shr { x, y } => self.op_shr(x, y),
+            Instruction::SubN { x, y } => self.op_subn(x, y),
+            Instruction::Shl { x, y } => self.op_shl(x, y),
+            Instruction::SkipNeReg { x, y } => self.op_skip_ne_reg(x, y),
+            Instruction::LoadI(nnn) => self.op_load_i(nnn),
+            Instruction::LoadLongI => self.op_load_long_i(),
+            Instruction::JumpOffset { x, nnn } => self.op_jump_offset(x, nnn),
+            Instruction::Rnd { x, kk } => self.op_rnd(x, kk),
+            Instruction::Draw { x, y, n } => self.op_draw(x, y, n),
+            Instruction::SkipKeyPressed { x } => self.op_skip_key_pressed(x),
+            Instruction::SkipKeyNotPressed { x } => self.op_skip_key_not_pressed(x),
+            Instruction::LoadDelay { x } => self.op_load_delay(x),
+            Instruction::WaitKey { x } => self.op_wait_key(x),
+            Instruction::SetDelay { x } => self.op_set_delay(x),
+            Instruction::SetSound { x } => self.op_set_sound(x),
+            Instruction::AddI { x } => self.op_add_i(x),
+            Instruction::LoadFont { x } => self.op_load_font(x),
+            Instruction::LoadBigFont { x } => self.op_load_big_font(x),
+            Instruction::SetPitch { x } => self.op_set_pitch(x),
+            Instruction::LoadAudio => self.op_load_audio(),
+            Instruction::StoreBcd { x } => self.op_store_bcd(x),
+            Instruction::StoreRange { x, y } => self.op_store_range(x, y),
+            Instruction::LoadRange { x, y } => self.op_load_range(x, y),
+            Instruction::StoreRegs { x } => self.op_store_regs(x),
+            Instruction::LoadRegs { x } => self.op_load_regs(x),
+            Instruction::StoreFlags { x } => self.op_store_flags(x),
+            Instruction::LoadFlags { x } => self.op_load_flags(x),
+        }
+    }
+
+    // Note the instruction about to execute, for errors. Its own address is used instead of
+    // pc - 2 throughout since pc wraps to 0 past an opcode at 0xFFFE.
+    pub(crate) fn start_instruction(&mut self, opcode: u16) {
+        self.current_pc = self.pc.wrapping_sub(2);
+        self.current_opcode = opcode;
+    }
+
+    // One method per instruction, shared by execute and the handlers of Dispatch::Table. They
+    // run with pc already past the instruction and report what happened like exec_cycle.
+    //
+    // Arithmetic ops compute their result and flag from the original operands, write the
+    // result and only then the flag, so VF always ends up holding the flag.
+
+    // CLS - Clear the display
+    pub(crate) fn op_cls(&mut self) -> Result<CycleState, Chip8Error> {
+        self.display.clear();
+        Ok(CycleState::Normal)
+    }
+
+    // SCD nibble
+    pub(crate) fn op_scroll_down(&mut self, n: u8) -> Result<CycleState, Chip8Error> {
+        self.display.scroll_down(self.scroll_distance(n as usize));
+        Ok(CycleState::Normal)
+    }
+
+    // SCU n
+    pub(crate) fn op_scroll_up(&mut self, n: u8) -> Result<CycleState, Chip8Error> {
+        self.display.scroll_up(self.scroll_distance(n as usize));
+        Ok(CycleState::Normal)
+    }
+
+    // SCR
+    pub(crate) fn op_scroll_right(&mut self) -> Result<CycleState, Chip8Error> {
+        self.display.scroll_right(self.scroll_distance(4));
+        Ok(CycleState::Normal)
+    }
+
+    // SCL
+    pub(crate) fn op_scroll_left(&mut self) -> Result<CycleState, Chip8Error> {
+        self.display.scroll_left(self.scroll_distance(4));
+        Ok(CycleState::Normal)
+    }
+
+    // EXIT - stay on this instruction so the program stays finished
+    pub(crate) fn op_exit(&mut self) -> Result<CycleState, Chip8Error> {
+        self.pc = self.current_pc;
+        Ok(CycleState::Halted)
+    }
+
+    // PLANE n
+    pub(crate) fn op_plane(&mut self, n: u8) -> Result<CycleState, Chip8Error> {
+        self.display.select_planes(n);
+        Ok(CycleState::Normal)
+    }
+
+    // LOW - back to the 64x32 screen
+    pub(crate) fn op_lores(&mut self) -> Result<CycleState, Chip8Error> {
+        self.display
+            .set_resolution(Resolution::Low, self.quirks.resolution_switch_keeps_display);
+        Ok(CycleState::Normal)
+    }
+
+    // HIGH - SUPER-CHIP 128x64 screen
+    pub(crate) fn op_hires(&mut self) -> Result<CycleState, Chip8Error> {
+        self.display.set_resolution(
+            Resolution::High,
+            self.quirks.resolution_switch_keeps_display,
+        );
+        Ok(CycleState::Normal)
+    }
+
+    // RET
+    pub(crate) fn op_ret(&mut self) -> Result<CycleState, Chip8Error> {
+        self.pc = match self.stack.pop() {
+            Some(addr) => addr,
+            None => {
+                return Err(Chip8Error::StackUnderflow {
+                    pc: self.current_pc,
+                    opcode: self.current_opcode,
+                })
+            }
+        };
+        Ok(CycleState::Normal)
+    }
+
+    // SYS addr - jump to a machine code routine, which modern interpreters ignore
+    pub(crate) fn op_sys(&mut self, nnn: u16) -> Result<CycleState, Chip8Error> {
+        if self.strict_sys_calls {
+            return Err(Chip8Error::SysCall {
+                opcode: self.current_opcode,
+                pc: self.current_pc,
+            });
+        }
+        log!("Ignoring SYS {:#05X} at PC {:#05X}", nnn, self.current_pc);
+        Ok(CycleState::Normal)
+    }
+
+    // JP addr
+    pub(crate) fn op_jump(&mut self, nnn: u16) -> Result<CycleState, Chip8Error> {
+        // An unconditional jump to itself is how most programs end, nothing can change
+        // the outcome so report the program as finished. Conditional loops such as
+        // DT polling always go through a skip first and are never flagged.
+        let mut state = CycleState::Normal;
+        if self.is_hires_entry(nnn) {
+            self.display.set_resolution(Resolution::Tall, false);
+            self.pc = HIRES_START;
+        } else {
+            let pc = self.current_pc;
+            if nnn == pc {
+                state = CycleState::Halted;
+            } else if nnn < pc && self.is_timer_poll_loop(nnn, pc) {
+                state = CycleState::IdleUntilTimer;
+            }
+            self.pc = nnn;
+        }
+        Ok(state)
+    }
+
+    // CALL addr
+    pub(crate) fn op_call(&mut self, nnn: u16) -> Result<CycleState, Chip8Error> {
+        if self.stack.len() >= self.max_stack_depth {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.current_pc,
+                opcode: self.current_opcode,
+            });
+        }
+        self.stack.push(self.pc);
+        self.pc = nnn;
+        Ok(CycleState::Normal)
+    }
+
+    // SE Vx, byte
+    pub(crate) fn op_skip_eq_byte(&mut self, x: usize, kk: u8) -> Result<CycleState, Chip8Error> {
+        if self.v[x] == kk {
+            self.skip_next();
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // SNE Vx, byte
+    pub(crate) fn op_skip_ne_byte(&mut self, x: usize, kk: u8) -> Result<CycleState, Chip8Error> {
+        if self.v[x] != kk {
+            self.skip_next();
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // SE Vx, Vy
+    pub(crate) fn op_skip_eq_reg(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        if self.v[x] == self.v[y] {
+            self.skip_next();
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD Vx, byte
+    pub(crate) fn op_load_byte(&mut self, x: usize, kk: u8) -> Result<CycleState, Chip8Error> {
+        self.v[x] = kk;
+        Ok(CycleState::Normal)
+    }
+
+    // ADD Vx, byte
+    pub(crate) fn op_add_vx_byte(&mut self, x: usize, kk: u8) -> Result<CycleState, Chip8Error> {
+        self.v[x] = self.v[x].wrapping_add(kk);
+        Ok(CycleState::Normal)
+    }
+
+    // LD Vx, Vy
+    pub(crate) fn op_load_reg(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        self.v[x] = self.v[y];
+        Ok(CycleState::Normal)
+    }
+
+    // OR Vx, Vy
+    pub(crate) fn op_or(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        self.v[x] |= self.v[y];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // AND Vx, Vy
+    pub(crate) fn op_and(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        self.v[x] &= self.v[y];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // XOR Vx, Vy
+    pub(crate) fn op_xor(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        self.v[x] ^= self.v[y];
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // ADD Vx, Vy
+    pub(crate) fn op_add_reg(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        let (res, overflow) = self.v[x].overflowing_add(self.v[y]);
+        self.v[x] = res;
+        match overflow {
+            true => self.v[0xF] = 1,
+            false => self.v[0xF] = 0,
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // SUB Vx, Vy
+    pub(crate) fn op_sub(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        let (res, overflow) = self.v[x].overflowing_sub(self.v[y]);
+        self.v[x] = res;
+        match overflow {
+            true => self.v[0xF] = 0,
+            false => self.v[0xF] = 1,
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // SHR Vx {, Vy}
+    pub(crate) fn op_shr(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        let src = self.shift_source(x, y);
+        self.v[x] = src >> 1;
+        self.v[0xF] = src & 0b1;
+        Ok(CycleState::Normal)
+    }
+
+    // SUBN Vx, Vy
+    pub(crate) fn op_subn(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        let (res, overflow) = self.v[y].overflowing_sub(self.v[x]);
+        self.v[x] = res;
+        match overflow {
+            true => self.v[0xF] = 0,
+            false => self.v[0xF] = 1,
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // SHL Vx {, Vy}
+    pub(crate) fn op_shl(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        let src = self.shift_source(x, y);
+        self.v[x] = src << 1;
+        self.v[0xF] = src >> 7;
+        Ok(CycleState::Normal)
+    }
+
+    // SNE Vx, Vy
+    pub(crate) fn op_skip_ne_reg(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        if self.v[x] != self.v[y] {
+            self.skip_next();
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD I, addr
+    pub(crate) fn op_load_i(&mut self, nnn: u16) -> Result<CycleState, Chip8Error> {
+        self.i = nnn & self.address_mask();
+        Ok(CycleState::Normal)
+    }
+
+    // LD I, long NNNN - the address is the word after the opcode
+    pub(crate) fn op_load_long_i(&mut self) -> Result<CycleState, Chip8Error> {
+        self.i = self.read_word(self.pc)? & self.address_mask();
+        self.pc = self.pc.wrapping_add(2);
+        Ok(CycleState::Normal)
+    }
+
+    // JP V0, addr (or JP Vx, addr with the jump quirk)
+    pub(crate) fn op_jump_offset(&mut self, x: usize, nnn: u16) -> Result<CycleState, Chip8Error> {
+        let offset = match self.quirks.jump_uses_vx {
+            true => self.v[x],
+            false => self.v[0],
+        };
+        let target = nnn as usize + offset as usize;
+        // Both bytes of the next opcode must be fetchable
+        if !self.pc_wraps && target + 1 >= self.bus.ram().len() {
+            return Err(Chip8Error::ProgramCounterOutOfBounds {
+                addr: target,
+                pc: self.current_pc,
+            });
+        }
+        self.pc = target as u16;
+        Ok(CycleState::Normal)
+    }
+
+    // RND Vx, byte
+    pub(crate) fn op_rnd(&mut self, x: usize, kk: u8) -> Result<CycleState, Chip8Error> {
+        self.v[x] = self.rng.next_u8() & kk;
+        Ok(CycleState::Normal)
+    }
+
+    // DRW Vx, Vy, nibble
+    pub(crate) fn op_draw(&mut self, x: usize, y: usize, n: u8) -> Result<CycleState, Chip8Error> {
+        // Stall on this instruction until the next vertical blank
+        if self.quirks.display_wait {
+            if !self.vblank {
+                self.pc = self.current_pc;
+                return Ok(CycleState::WaitingForVblank);
+            }
+            self.vblank = false;
+        }
+        // The sprite is n bytes long, starting at I. DXY0 is a 16x16 sprite of 32 bytes on
+        // SUPER-CHIP and XO-CHIP, except in SUPER-CHIP 1.1 low resolution, and 8x16 or
+        // nothing at all on CHIP-8. XO-CHIP draws a sprite of that length into each
+        // selected plane, one after the other.
+        let lores_8x16 =
+            self.quirks.lores_dxy0_draws_8x16 && self.display.resolution == Resolution::Low;
+        let wide = n == 0 && self.platform.has_superchip_opcodes() && !lores_8x16;
+        let len = match n {
+            0 if wide => 32,
+            0 if self.quirks.dxy0_draws_8x16 || lores_8x16 => 16,
+            _ => n as usize,
+        } * self.display.planes.count_ones() as usize;
+        let mut sprite = [0u8; 32 * PLANES];
+        for (k, row) in sprite[..len].iter_mut().enumerate() {
+            *row = self.read_byte(self.index_address(k)?)?;
+        }
+        let (vx, vy) = (self.v[x] as usize, self.v[y] as usize);
+        let result = match wide {
+            true => self
+                .display
+                .draw_sprite16(vx, vy, &sprite[..len], self.quirks.clipping),
+            false => self
+                .display
+                .draw_sprite(vx, vy, &sprite[..len], self.quirks.clipping),
+        };
+        self.v[0xF] = match self.display.resolution {
+            Resolution::High if self.quirks.hires_collision_counts_rows => {
+                (result.collided_rows + result.clipped_rows) as u8
+            }
+            _ => result.collision() as u8,
+        };
+        Ok(CycleState::DrewSprite)
+    }
+
+    // SKP Vx
+    pub(crate) fn op_skip_key_pressed(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        if self.keyboard.is_pressed(self.v[x]) {
+            self.skip_next();
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // SKNP Vx
+    pub(crate) fn op_skip_key_not_pressed(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        if !self.keyboard.is_pressed(self.v[x]) {
+            self.skip_next();
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD Vx, DT
+    pub(crate) fn op_load_delay(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        self.v[x] = self.dt;
+        Ok(CycleState::Normal)
+    }
+
+    // LD Vx, K
+    // Completes when a key is released, as on the original interpreter. Releases from
+    // before the wait started don't count.
+    pub(crate) fn op_wait_key(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        if !self.waiting_for_key {
+            self.waiting_for_key = true;
+            self.keyboard.take_released();
+        }
+        match self.keyboard.take_released() {
+            Some(key) => {
+                self.v[x] = key;
+                self.waiting_for_key = false;
+                Ok(CycleState::Normal)
+            }
+            None => {
+                self.pc = self.current_pc;
+                Ok(CycleState::WaitingForKey)
+            }
+        }
+    }
+
+    // LD DT, Vx
+    pub(crate) fn op_set_delay(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        self.dt = self.v[x];
+        Ok(CycleState::Normal)
+    }
+
+    // LD ST, Vx
+    pub(crate) fn op_set_sound(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        self.st = self.v[x];
+        Ok(CycleState::Normal)
+    }
+
+    // ADD I, Vx
+    pub(crate) fn op_add_i(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        // I wraps around within the address space
+        let mask = self.address_mask() as u32;
+        let sum = self.i as u32 + self.v[x] as u32;
+        self.i = (sum & mask) as u16;
+        if self.quirks.index_overflow_sets_vf {
+            self.v[0xF] = (sum > mask) as u8;
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD F, Vx
+    pub(crate) fn op_load_font(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        // Only the low nibble selects a glyph
+        let digit = (self.v[x] & 0x0F) as u16;
+        self.i = FONT_BASE + digit * FONT_GLYPH_SIZE;
+        Ok(CycleState::Normal)
+    }
+
+    // LD HF, Vx
+    pub(crate) fn op_load_big_font(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        // As on SUPER-CHIP 1.1, A to F point past the table but stay in the interpreter area
+        let digit = (self.v[x] & 0x0F) as u16;
+        self.i = BIG_FONT_BASE + digit * BIG_FONT_GLYPH_SIZE;
+        Ok(CycleState::Normal)
+    }
+
+    // LD PITCH, Vx
+    pub(crate) fn op_set_pitch(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        self.audio.pitch = self.v[x];
+        Ok(CycleState::Normal)
+    }
+
+    // LD AUDIO, [I]
+    pub(crate) fn op_load_audio(&mut self) -> Result<CycleState, Chip8Error> {
+        let mut pattern = [0; PATTERN_LEN];
+        for (k, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_byte(self.index_address(k)?)?;
+        }
+        self.audio.pattern = Some(pattern);
+        Ok(CycleState::Normal)
+    }
+
+    // LD B, Vx
+    pub(crate) fn op_store_bcd(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        // Bytes are stored one at a time, so those before a fault are already written
+        let bcd = [self.v[x] / 100, (self.v[x] / 10) % 10, self.v[x] % 10];
+        for (k, &digit) in bcd.iter().enumerate() {
+            self.write_byte(self.index_address(k)?, digit)?;
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD [I], Vx-Vy - I is left alone
+    pub(crate) fn op_store_range(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        for (k, reg) in register_range(x, y).enumerate() {
+            self.write_byte(self.index_address(k)?, self.v[reg])?;
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD Vx-Vy, [I]
+    pub(crate) fn op_load_range(&mut self, x: usize, y: usize) -> Result<CycleState, Chip8Error> {
+        // Read everything first so a fault leaves the registers untouched
+        let mut values = [0u8; 16];
+        let len = register_range(x, y).count();
+        for (k, value) in values[..len].iter_mut().enumerate() {
+            *value = self.read_byte(self.index_address(k)?)?;
+        }
+        for (reg, &value) in register_range(x, y).zip(values.iter()) {
+            self.v[reg] = value;
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD [I], Vx
+    pub(crate) fn op_store_regs(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        for k in 0..=x {
+            self.write_byte(self.index_address(k)?, self.v[k])?;
+        }
+        if self.quirks.load_store_increments_i {
+            self.increment_i_past(x);
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD Vx, [I]
+    pub(crate) fn op_load_regs(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        // Read everything first so a fault leaves the registers untouched
+        let mut regs = [0u8; 16];
+        for (k, reg) in regs[..=x].iter_mut().enumerate() {
+            *reg = self.read_byte(self.index_address(k)?)?;
+        }
+        self.v[..=x].copy_from_slice(&regs[..=x]);
+        if self.quirks.load_store_increments_i {
+            self.increment_i_past(x);
+        }
+        Ok(CycleState::Normal)
+    }
+
+    // LD R, Vx
+    pub(crate) fn op_store_flags(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        let last = self.last_user_flag(x);
+        self.rpl_flags[..=last].copy_from_slice(&self.v[..=last]);
+        Ok(CycleState::Normal)
+    }
+
+    // LD Vx, R
+    pub(crate) fn op_load_flags(&mut self, x: usize) -> Result<CycleState, Chip8Error> {
+        let last = self.last_user_flag(x);
+        self.v[..=last].copy_from_slice(&self.rpl_flags[..=last]);
+        Ok(CycleState::Normal)
+    }
+
+    // Skip over the next instruction. On XO-CHIP that may be the two word F000 NNNN.
+    fn skip_next(&mut self) {
+        let is_long = self.platform == Platform::XoChip && self.peek_word(self.pc) == Some(0xF000);
+        self.pc = self.pc.wrapping_add(if is_long { 4 } else { 2 });
+    }
+
+    // Whether the loop from `start` up to the jump back at `jump` does nothing but read DT and
+    // compare registers, e.g. FX07 / 3X00 / 1NNN. Anything that writes memory, draws, reads keys
+    // or changes a register other than through FX07 disqualifies it.
+    fn is_timer_poll_loop(&self, start: u16, jump: u16) -> bool {
+        if jump - start > MAX_IDLE_LOOP_LEN * 2 {
+            return false;
+        }
+        let mut reads_dt = false;
+        for addr in (start..jump).step_by(2) {
+            match self.peek_word(addr).and_then(Instruction::decode) {
+                Some(Instruction::LoadDelay { .. }) => reads_dt = true,
+                Some(Instruction::SkipEqByte { .. })
+                | Some(Instruction::SkipNeByte { .. })
+                | Some(Instruction::SkipEqReg { .. })
+                | Some(Instruction::SkipNeReg { .. }) => {}
+                _ => return false,
+            }
+        }
+        reads_dt
+    }
+
+    // Pixels a scroll opcode moves the picture by in the current resolution
+    fn scroll_distance(&self, n: usize) -> usize {
+        match self.display.resolution {
+            Resolution::Low if self.quirks.scroll_half_pixel_lores => n / 2,
+            _ => n,
+        }
+    }
+
+    // Whether this jump is the 1260 a HiRes CHIP-8 program starts with. Recognized on plain
+    // CHIP-8 too, since those programs don't run any other way.
+    fn is_hires_entry(&self, nnn: u16) -> bool {
+        nnn == HIRES_ENTRY
+            && self.current_pc == DEFAULT_PROGRAM_START
+            && matches!(self.platform, Platform::Chip8 | Platform::HiresChip8)
+    }
+
+    // SUPER-CHIP only has flags for V0 to V7, XO-CHIP has one per register
+    fn last_user_flag(&self, x: usize) -> usize {
+        match self.platform {
+            Platform::XoChip => x,
+            _ => x.min(7),
+        }
+    }
+
+    // Register value shifted by 8XY6/8XYE
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        match self.quirks.shift_uses_vy {
+            true => self.v[y],
+            false => self.v[x],
+        }
+    }
+
+    // Point I just past the last of the registers V0..=Vx transferred by FX55/FX65. The
+    // transfer itself was bounds checked, so this only wraps I to 0 when it ended on the last
+    // byte of memory.
+    fn increment_i_past(&mut self, x: usize) {
+        self.i = self.i.wrapping_add(x as u16 + 1) & self.address_mask();
+    }
+
+    // Let a DXYN stalled by the display wait quirk draw without waiting for the timers, for
+    // single stepping while they're frozen
+    pub fn release_vblank(&mut self) {
+        self.vblank = true;
+    }
+
+    // This function should be called at 60Hz
+    // Returns true if buzzer should sound
+    pub fn update_timers(&mut self) -> bool {
+        // The timers tick on the vertical blank, which also releases a waiting DXYN
+        self.vblank = true;
+        self.stats.timer_ticks += 1;
+
+        // The delay timer is active whenever the delay timer register (DT) is non-zero.
+        // This timer does nothing more than subtract 1 from the value of DT at a rate of 60Hz.
+        // When DT reaches 0, it deactivates.
+        if self.dt > 0 {
+            self.dt -= 1;
+        }
+
+        //The sound timer is active whenever the sound timer register (ST) is non-zero.
+        // This timer also decrements at a rate of 60Hz, however, as long as ST's value is greater than zero,
+        // the Chip-8 buzzer will sound. When ST reaches zero, the sound timer deactivates.
+        let beep = self.st > 0;
+        if beep {
+            self.st -= 1;
+        }
+        self.notify();
+        beep
+    }
+}
+
+// Registers from x to y inclusive, counting down when y is below x
+fn register_range(x: usize, y: usize) -> impl Iterator<Item = usize> {
+    let len = x.max(y) - x.min(y) + 1;
+    (0..len).map(move |k| if x <= y { x + k } else { x - k })
+}
+
+// Register dump for error reports and debugging, e.g.
+//   PC: 0x200  I: 0x000  SP: 0  DT: 0x00  ST: 0x00
+//   V0-V7: 00 00 00 00 00 00 00 00
+//   V8-VF: 00 00 00 00 00 00 00 00
+//   Stack: -
+//   Next:  0x200: 0x00E0  CLS
+impl<B: Bus> fmt::Display for CPU<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "PC: {:#05X}  I: {:#05X}  SP: {}  DT: {:#04X}  ST: {:#04X}",
+            self.pc,
+            self.i,
+            self.stack.len(),
+            self.dt,
+            self.st
+        )?;
+        for (half, regs) in self.v.chunks(8).enumerate() {
+            write!(f, "V{:X}-V{:X}:", half * 8, half * 8 + 7)?;
+            for reg in regs {
+                write!(f, " {:02X}", reg)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "Stack:")?;
+        if self.stack.is_empty() {
+            write!(f, " -")?;
+        }
+        for addr in &self.stack {
+            write!(f, " {:#05X}", addr)?;
+        }
+        writeln!(f)?;
+        write!(f, "Next:  {:#05X}: ", self.pc)?;
+        match self.peek_word(self.pc) {
+            Some(opcode) => write!(
+                f,
+                "{:#06X}  {}",
+                opcode,
+                disasm::disassemble_for(opcode, self.platform)
+            ),
+            None => write!(f, "(outside memory)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use alloc::string::{String, ToString};
+    use core::cell::RefCell;
+
+    // A CPU with the default quirks and `program` loaded at 0x200
+    fn cpu_with(dispatch: Dispatch, program: &[u16]) -> CPU {
+        let mut cpu = CPU::builder().dispatch(dispatch).build();
+        cpu.load_rom_bytes(&words(program)).unwrap();
+        cpu
+    }
+
+    fn words(program: &[u16]) -> Vec<u8> {
+        program.iter().flat_map(|op| op.to_be_bytes()).collect()
+    }
+
+    // (x, y) of every lit pixel, row by row
+    fn lit(cpu: &CPU) -> Vec<(usize, usize)> {
+        cpu.display.iter_pixels().map(|(x, y, _)| (x, y)).collect()
+    }
+
+    // Pixels a sprite drawn at the top left without wrapping lights up
+    fn sprite_pixels(sprite: &[u8]) -> Vec<(usize, usize)> {
+        (0..sprite.len())
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|&(x, y)| sprite[y] & 0x80 >> x != 0)
+            .collect()
+    }
+
+    fn opcode_at_end_of_64k_memory_wraps_pc(dispatch: Dispatch) {
+        let mut cpu = CPU::builder()
+            .dispatch(dispatch)
+            .memory_size(MemorySize::Large)
+            .build();
+        // JP 0xFFE
+        cpu.write_mem(0xFFFE, &[0x1F, 0xFE]).unwrap();
+        cpu.set_program_counter(0xFFFE).unwrap();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.pc, 0xFFE);
+
+        // DXYN waiting for the vertical blank and errors rewind pc to the instruction
+        cpu.pc = 0xFFFE;
+        cpu.quirks.display_wait = true;
+        cpu.write_mem(0xFFFE, &[0xD0, 0x01]).unwrap();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForVblank));
+        assert_eq!(cpu.pc, 0xFFFE);
+        // RET with nothing to return to
+        cpu.write_mem(0xFFFE, &[0x00, 0xEE]).unwrap();
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::StackUnderflow {
+                pc: 0xFFFE,
+                opcode: 0x00EE
+            })
+        );
+        assert_eq!(cpu.pc, 0xFFFE);
+    }
+
+    fn coverage_only_counts_instructions_that_run(dispatch: Dispatch) {
+        // LD V0, 1 / an opcode that doesn't decode
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0x5001]);
+        cpu.set_pre_exec_hook(|_, _| HookAction::Pause);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Paused));
+        assert!(!cpu.coverage()[0x200]);
+
+        cpu.set_pre_exec_hook(|_, _| HookAction::Continue);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert!(cpu.coverage()[0x200] && cpu.coverage()[0x201]);
+        assert!(cpu.exec_cycle().is_err());
+        assert!(!cpu.coverage()[0x202] && !cpu.coverage()[0x203]);
+    }
+
+    fn history_keeps_paused_instruction_once(dispatch: Dispatch) {
+        // LD V0, 1 / an opcode that doesn't decode
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0x5001]);
+        cpu.set_history(Some(History::new(8, 0)));
+        cpu.set_pre_exec_hook(|cpu, _| match cpu.pc {
+            0x200 => HookAction::Pause,
+            _ => HookAction::Continue,
+        });
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Paused));
+        assert_eq!(cpu.history().unwrap().entries().count(), 0);
+
+        cpu.set_pre_exec_hook(|_, _| HookAction::Continue);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert!(cpu.exec_cycle().is_err());
+        let entries: Vec<(u16, u16)> = cpu
+            .history()
+            .unwrap()
+            .entries()
+            .map(|entry| (entry.pc, entry.opcode))
+            .collect();
+        assert_eq!(entries, [(0x200, 0x6001), (0x202, 0x5001)]);
+    }
+
+    fn load_store_leaves_or_increments_i(dispatch: Dispatch) {
+        for (increments, x, expected_i) in [
+            (false, 0, 0x300),
+            (false, 0xF, 0x300),
+            (true, 0, 0x301),
+            (true, 0xF, 0x310),
+        ] {
+            for opcode in [0xF055 | (x << 8), 0xF065 | (x << 8)] {
+                let mut cpu = cpu_with(dispatch, &[0xA300, opcode]);
+                cpu.quirks.load_store_increments_i = increments;
+                cpu.v = [0x11; 16];
+                cpu.exec_cycles(2).unwrap();
+                assert_eq!(
+                    cpu.i, expected_i,
+                    "{:04X} incrementing {}",
+                    opcode, increments
+                );
+            }
+        }
+    }
+
+    fn load_store_ending_on_last_byte_wraps_i(dispatch: Dispatch) {
+        // LD [I], VF with I at 0xFF0 stores up to 0xFFF
+        let mut cpu = cpu_with(dispatch, &[0xAFF0, 0xFF55, 0xAFF0, 0xFF65]);
+        cpu.quirks.load_store_increments_i = true;
+        for x in 0..16 {
+            cpu.v[x] = x as u8;
+        }
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        assert_eq!(cpu.i, 0);
+        assert_eq!(cpu.read_mem(0xFF0..0x1000).unwrap(), &cpu.v);
+        cpu.v = [0; 16];
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        assert_eq!(cpu.i, 0);
+        assert_eq!(cpu.v[0xF], 0xF);
+    }
+
+    fn exec_cycles_stops_at_key_wait(dispatch: Dispatch) {
+        // Three loads, LD V3, K, then a load that mustn't run
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0x6102, 0x6203, 0xF30A, 0x6404]);
+        assert_eq!(cpu.exec_cycles(100), Ok(3));
+        assert_eq!(cpu.pc, 0x206);
+        // Still waiting, nothing more executes
+        assert_eq!(cpu.exec_cycles(100), Ok(0));
+        cpu.key_down(7).unwrap();
+        cpu.key_up(7).unwrap();
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        assert_eq!((cpu.v[3], cpu.v[4]), (7, 4));
+    }
+
+    fn exec_cycles_stops_after_halt_and_errors(dispatch: Dispatch) {
+        // LD V0, 1 / JP 0x202
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0x1202]);
+        assert_eq!(cpu.exec_cycles(10), Ok(2));
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0x00EE]);
+        assert!(matches!(
+            cpu.exec_cycles(10),
+            Err(Chip8Error::StackUnderflow { pc: 0x202, .. })
+        ));
+        assert_eq!(cpu.stats().instructions, 1);
+    }
+
+    fn draw_reads_exactly_n_bytes(dispatch: Dispatch) {
+        // Rows with a pattern unlike their neighbours, so an extra row shows up
+        let sprite: Vec<u8> = (0..16).map(|k| 0x81 | (k << 3)).collect();
+        for n in [1, 8, 15] {
+            // LD I, 0x300 / DRW V0, V1, n twice
+            let mut cpu = cpu_with(dispatch, &[0xA300, 0xD010 | n, 0xD010 | n]);
+            cpu.write_mem(0x300, &sprite).unwrap();
+            cpu.exec_cycles(2).unwrap();
+            assert_eq!(
+                lit(&cpu),
+                sprite_pixels(&sprite[..n as usize]),
+                "{} rows",
+                n
+            );
+            assert_eq!(cpu.v[0xF], 0);
+            // Drawing it again erases it and collides
+            cpu.exec_cycle().unwrap();
+            assert_eq!(lit(&cpu), []);
+            assert_eq!(cpu.v[0xF], 1);
+        }
+    }
+
+    fn draw_at_end_of_memory(dispatch: Dispatch) {
+        // LD I, 0xFFE / DRW V0, V1, 2
+        let mut cpu = cpu_with(dispatch, &[0xAFFE, 0xD012, 0xD013]);
+        cpu.write_mem(0xFFE, &[0xC0, 0x30]).unwrap();
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(lit(&cpu), [(0, 0), (1, 0), (2, 1), (3, 1)]);
+        // One row more runs past the last byte
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0x1000,
+                pc: 0x204,
+                opcode: 0xD013
+            })
+        );
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    fn jump_offset_adds_v0_or_vx(dispatch: Dispatch) {
+        for (jump_uses_vx, target) in [(false, 0x240), (true, 0x250)] {
+            // JP V0, 0x230, which reads as JP V2, 0x30 with the quirk
+            let mut cpu = cpu_with(dispatch, &[0xB230]);
+            cpu.quirks.jump_uses_vx = jump_uses_vx;
+            cpu.v[0] = 0x10;
+            cpu.v[2] = 0x20;
+            cpu.exec_cycle().unwrap();
+            assert_eq!(cpu.pc, target, "jump_uses_vx {}", jump_uses_vx);
+        }
+    }
+
+    fn jump_offset_past_memory_is_an_error(dispatch: Dispatch) {
+        // JP V0, 0xFFF
+        let mut cpu = cpu_with(dispatch, &[0xBFFF]);
+        cpu.v[0] = 0x01;
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::ProgramCounterOutOfBounds {
+                addr: 0x1000,
+                pc: 0x200
+            })
+        );
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    fn logic_ops_reset_vf_with_the_quirk(dispatch: Dispatch) {
+        for vf_reset in [false, true] {
+            for n in 1..=3 {
+                // OP V0, V1 / OP VF, V1 / OP V0, VF, each starting from VF = 1
+                for (opcode, x, y) in [
+                    (0x8010 | n, 0, 1),
+                    (0x8F10 | n, 0xF, 1),
+                    (0x80F0 | n, 0, 0xF),
+                ] {
+                    let mut cpu = cpu_with(dispatch, &[opcode]);
+                    cpu.quirks.vf_reset = vf_reset;
+                    cpu.v[0] = 0b1100;
+                    cpu.v[1] = 0b1010;
+                    cpu.v[0xF] = 1;
+                    let expected = match n {
+                        1 => cpu.v[x] | cpu.v[y],
+                        2 => cpu.v[x] & cpu.v[y],
+                        _ => cpu.v[x] ^ cpu.v[y],
+                    };
+                    cpu.exec_cycle().unwrap();
+                    let vf = match (vf_reset, x) {
+                        (true, _) => 0,
+                        (false, 0xF) => expected,
+                        (false, _) => 1,
+                    };
+                    assert_eq!(cpu.v[0xF], vf, "{:04X} vf_reset {}", opcode, vf_reset);
+                    if x != 0xF {
+                        assert_eq!(cpu.v[x], expected, "{:04X}", opcode);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vf_reset_follows_the_platform() {
+        assert!(Platform::Chip8.quirks().vf_reset);
+        assert!(!Platform::SuperChip.quirks().vf_reset);
+        assert!(!Platform::SuperChipLegacy.quirks().vf_reset);
+    }
+
+    fn sprite_at_edge_clips_or_wraps(dispatch: Dispatch) {
+        let columns = [0, 1, 2, 3, 4, 5, 62, 63];
+        for clipping in [false, true] {
+            // LD I, 0x300 / DRW V0, V1, 3 with V0 = 62, V1 = 30
+            let mut cpu = cpu_with(dispatch, &[0xA300, 0xD013]);
+            cpu.quirks.clipping = clipping;
+            cpu.write_mem(0x300, &[0xFF; 3]).unwrap();
+            cpu.v[0] = 62;
+            cpu.v[1] = 30;
+            // Only hit when the third row wraps to the top
+            cpu.display.set_pixel(0, 0, 1);
+            cpu.exec_cycles(2).unwrap();
+            let expected: Vec<_> = match clipping {
+                true => [(0, 0), (62, 30), (63, 30), (62, 31), (63, 31)].to_vec(),
+                false => [0, 30, 31]
+                    .iter()
+                    .flat_map(|&y| columns.iter().map(move |&x| (x, y)))
+                    .filter(|&p| p != (0, 0))
+                    .collect(),
+            };
+            assert_eq!(lit(&cpu), expected, "clipping {}", clipping);
+            assert_eq!(cpu.v[0xF], !clipping as u8);
+        }
+    }
+
+    fn sprite_start_wraps_in_both_modes(dispatch: Dispatch) {
+        for clipping in [false, true] {
+            // DRW V0, V1, 1 with V0 = 64 + 3, V1 = 32 + 2
+            let mut cpu = cpu_with(dispatch, &[0xA300, 0xD011]);
+            cpu.quirks.clipping = clipping;
+            cpu.write_mem(0x300, &[0x80]).unwrap();
+            cpu.v[0] = 67;
+            cpu.v[1] = 34;
+            cpu.exec_cycles(2).unwrap();
+            assert_eq!(lit(&cpu), [(3, 2)]);
+        }
+    }
+
+    fn add_i_wraps_and_optionally_sets_vf(dispatch: Dispatch) {
+        for (sets_vf, vf) in [(false, 0xAA), (true, 1)] {
+            // LD I, 0xFFF / ADD I, V0 with V0 = 1
+            let mut cpu = cpu_with(dispatch, &[0xAFFF, 0xF01E, 0xF01E]);
+            cpu.quirks.index_overflow_sets_vf = sets_vf;
+            cpu.v[0] = 1;
+            cpu.v[0xF] = 0xAA;
+            cpu.exec_cycles(2).unwrap();
+            assert_eq!((cpu.i, cpu.v[0xF]), (0, vf), "sets_vf {}", sets_vf);
+            // No overflow clears the flag
+            cpu.exec_cycle().unwrap();
+            assert_eq!(cpu.i, 1);
+            assert_eq!(cpu.v[0xF], if sets_vf { 0 } else { 0xAA });
+        }
+    }
+
+    fn load_font_uses_the_low_nibble(dispatch: Dispatch) {
+        for (vx, glyph) in [
+            (0x00, [0xF0, 0x90, 0x90, 0x90, 0xF0]),
+            (0x0F, [0xF0, 0x80, 0xF0, 0x80, 0x80]),
+            (0x1F, [0xF0, 0x80, 0xF0, 0x80, 0x80]),
+            (0xFF, [0xF0, 0x80, 0xF0, 0x80, 0x80]),
+        ] {
+            // LD F, V3
+            let mut cpu = cpu_with(dispatch, &[0xF329]);
+            cpu.v[3] = vx;
+            cpu.exec_cycle().unwrap();
+            assert_eq!(cpu.i, FONT_BASE + (vx as u16 & 0xF) * FONT_GLYPH_SIZE);
+            let i = cpu.i as usize;
+            assert_eq!(cpu.read_mem(i..i + 5).unwrap(), glyph, "V3 = {:02X}", vx);
+        }
+    }
+
+    fn ret_on_empty_stack_is_an_error(dispatch: Dispatch) {
+        let mut cpu = cpu_with(dispatch, &[0x00EE]);
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::StackUnderflow {
+                pc: 0x200,
+                opcode: 0x00EE
+            })
+        );
+    }
+
+    fn seventeenth_nested_call_overflows(dispatch: Dispatch) {
+        // Each CALL goes to the next instruction, another CALL
+        let program: Vec<u16> = (1..=17).map(|k| 0x2200 + 2 * k).collect();
+        let mut cpu = cpu_with(dispatch, &program);
+        assert_eq!(cpu.exec_cycles(16), Ok(16));
+        assert_eq!(cpu.stack_depth(), 16);
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::StackOverflow {
+                pc: 0x220,
+                opcode: 0x2222
+            })
+        );
+        assert_eq!(cpu.stack_depth(), 16);
+    }
+
+    fn pc_past_memory_errors_or_wraps(dispatch: Dispatch) {
+        // JP 0xFFF, the opcode there would end past the last byte
+        let mut cpu = cpu_with(dispatch, &[0x1FFF]);
+        cpu.exec_cycle().unwrap();
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::ProgramCounterOutOfBounds {
+                addr: 0x1000,
+                pc: 0xFFF
+            })
+        );
+        // Wrapping takes the second byte from 0x000
+        cpu.pc_wraps = true;
+        cpu.write_mem(0xFFF, &[0x60]).unwrap();
+        cpu.write_mem(0x000, &[0x42]).unwrap();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.v[0], 0x42);
+    }
+
+    fn running_off_the_end_errors_or_wraps(dispatch: Dispatch) {
+        for pc_wraps in [false, true] {
+            // LD V0, 1 at 0xFFE, then LD V1, 2 at 0x000
+            let mut cpu = cpu_with(dispatch, &[0x1FFE]);
+            cpu.pc_wraps = pc_wraps;
+            cpu.protect_interpreter_area = WriteProtection::Off;
+            cpu.write_mem(0xFFE, &[0x60, 0x01]).unwrap();
+            cpu.write_mem(0x000, &[0x61, 0x02]).unwrap();
+            cpu.exec_cycles(2).unwrap();
+            let next = cpu.exec_cycle();
+            match pc_wraps {
+                true => {
+                    assert_eq!(next, Ok(CycleState::Normal));
+                    assert_eq!((cpu.v[1], cpu.pc), (2, 0x002));
+                }
+                false => assert_eq!(
+                    next,
+                    Err(Chip8Error::ProgramCounterOutOfBounds {
+                        addr: 0x1000,
+                        pc: 0x1000
+                    })
+                ),
+            }
+        }
+    }
+
+    fn unknown_opcodes_leave_the_cpu_as_it_was(dispatch: Dispatch) {
+        for opcode in [0xE000, 0xF0FF, 0x5001] {
+            // LD V0, 1 / the opcode
+            let mut cpu = cpu_with(dispatch, &[0x6001, opcode]);
+            cpu.exec_cycle().unwrap();
+            let before = cpu.snapshot();
+            assert_eq!(
+                cpu.exec_cycle(),
+                Err(Chip8Error::UnknownOpcode { opcode, pc: 0x202 })
+            );
+            assert_eq!(cpu.snapshot(), before, "{:04X}", opcode);
+            assert_eq!(cpu.pc, 0x202);
+        }
+    }
+
+    fn seeded_cpus_agree_on_rnd(dispatch: Dispatch) {
+        // RND V0, 0xFF over and over
+        let run = |seed| {
+            let mut cpu = CPU::builder().dispatch(dispatch).seed(seed).build();
+            cpu.load_rom_bytes(&words(&[0xC0FF, 0x1200])).unwrap();
+            (0..64)
+                .map(|_| {
+                    cpu.exec_cycles(2).unwrap();
+                    cpu.v[0]
+                })
+                .collect::<Vec<u8>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        let mut cpu = cpu_with(dispatch, &[0xC0FF]);
+        cpu.seed_rng(7);
+        cpu.exec_cycle().unwrap();
+        assert_eq!(cpu.v[0], run(7)[0]);
+    }
+
+    fn sys_calls_are_skipped_or_rejected(dispatch: Dispatch) {
+        // SYS 0x123 / LD V0, 1
+        let mut cpu = cpu_with(dispatch, &[0x0123, 0x6001]);
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        assert_eq!((cpu.pc, cpu.v[0]), (0x204, 1));
+
+        let mut cpu = cpu_with(dispatch, &[0x0123, 0x6001]);
+        cpu.strict_sys_calls = true;
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::SysCall {
+                opcode: 0x0123,
+                pc: 0x200
+            })
+        );
+        assert_eq!((cpu.pc, cpu.v[0]), (0x200, 0));
+    }
+
+    fn cls_and_ret_are_not_sys_calls(dispatch: Dispatch) {
+        // CALL 0x204 / JP 0x202 / CLS / RET
+        let mut cpu = cpu_with(dispatch, &[0x2204, 0x1202, 0x00E0, 0x00EE]);
+        cpu.strict_sys_calls = true;
+        cpu.display.set_pixel(1, 1, 1);
+        assert_eq!(cpu.exec_cycles(3), Ok(3));
+        assert_eq!(lit(&cpu), []);
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    fn display_wait_draws_once_per_tick(dispatch: Dispatch) {
+        for (display_wait, per_tick) in [(true, 1), (false, 50)] {
+            // DRW V0, V1, 1 / JP 0x200
+            let mut cpu = cpu_with(dispatch, &[0xD011, 0x1200]);
+            cpu.quirks.display_wait = display_wait;
+            for tick in 1..=3 {
+                cpu.update_timers();
+                for _ in 0..100 {
+                    cpu.exec_cycle().unwrap();
+                }
+                assert_eq!(cpu.stats().draws, tick * per_tick);
+            }
+        }
+    }
+
+    fn display_wait_stalls_on_the_draw(dispatch: Dispatch) {
+        // DRW V0, V1, 1 twice
+        let mut cpu = cpu_with(dispatch, &[0xD011, 0xD011]);
+        cpu.quirks.display_wait = true;
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForVblank));
+        assert_eq!(cpu.pc, 0x200);
+        cpu.update_timers();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::DrewSprite));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForVblank));
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    fn vf_ends_up_holding_the_flag(dispatch: Dispatch) {
+        // (opcode, V1, VF before, VF after) with VF as the destination
+        for (opcode, v1, vf, flag) in [
+            (0x8F14, 0x02, 0xFF, 1),
+            (0x8F14, 0x02, 0x01, 0),
+            (0x8F15, 0x02, 0x01, 0),
+            (0x8F15, 0x02, 0x05, 1),
+            (0x8F16, 0x00, 0x03, 1),
+            (0x8F16, 0x00, 0x02, 0),
+            (0x8F17, 0x01, 0x02, 0),
+            (0x8F17, 0x05, 0x02, 1),
+            (0x8F1E, 0x00, 0x80, 1),
+            (0x8F1E, 0x00, 0x40, 0),
+        ] {
+            let mut cpu = cpu_with(dispatch, &[opcode]);
+            cpu.v[1] = v1;
+            cpu.v[0xF] = vf;
+            cpu.exec_cycle().unwrap();
+            assert_eq!(cpu.v[0xF], flag, "{:04X} VF = {:02X}", opcode, vf);
+        }
+    }
+
+    fn vf_as_operand_is_read_before_the_flag(dispatch: Dispatch) {
+        // (opcode, V0, VF, V0 after, VF after) with VF as the second operand
+        for (opcode, v0, vf, result, flag) in [
+            (0x80F4, 0xFF, 0x02, 0x01, 1),
+            (0x80F5, 0x01, 0x02, 0xFF, 0),
+            (0x80F7, 0x01, 0x03, 0x02, 1),
+        ] {
+            let mut cpu = cpu_with(dispatch, &[opcode]);
+            cpu.v[0] = v0;
+            cpu.v[0xF] = vf;
+            cpu.exec_cycle().unwrap();
+            assert_eq!((cpu.v[0], cpu.v[0xF]), (result, flag), "{:04X}", opcode);
+        }
+        // Shifting VF into V0 with the Vy quirk
+        for (opcode, vf, result, flag) in [(0x80F6, 0x03, 0x01, 1), (0x80FE, 0x81, 0x02, 1)] {
+            let mut cpu = cpu_with(dispatch, &[opcode]);
+            cpu.quirks.shift_uses_vy = true;
+            cpu.v[0xF] = vf;
+            cpu.exec_cycle().unwrap();
+            assert_eq!((cpu.v[0], cpu.v[0xF]), (result, flag), "{:04X}", opcode);
+        }
+    }
+
+    fn bcd_digits(dispatch: Dispatch) {
+        for (value, digits) in [
+            (0, [0, 0, 0]),
+            (9, [0, 0, 9]),
+            (10, [0, 1, 0]),
+            (99, [0, 9, 9]),
+            (100, [1, 0, 0]),
+            (255, [2, 5, 5]),
+        ] {
+            // LD I, 0x300 / LD B, V5
+            let mut cpu = cpu_with(dispatch, &[0xA300, 0xF533]);
+            cpu.v[5] = value;
+            cpu.exec_cycles(2).unwrap();
+            assert_eq!(cpu.read_mem(0x300..0x303).unwrap(), digits, "{}", value);
+        }
+    }
+
+    fn bcd_past_memory_is_an_error(dispatch: Dispatch) {
+        // LD I, 0xFFE / LD B, V0
+        let mut cpu = cpu_with(dispatch, &[0xAFFE, 0xF033]);
+        cpu.v[0] = 123;
+        cpu.exec_cycle().unwrap();
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0x1000,
+                pc: 0x202,
+                opcode: 0xF033
+            })
+        );
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    fn store_and_load_all_registers_round_trip(dispatch: Dispatch) {
+        // LD I, 0x300 / LD [I], VF / LD I, 0x300 / LD VF, [I]
+        let mut cpu = cpu_with(dispatch, &[0xA300, 0xFF55, 0xA300, 0xFF65]);
+        let regs: [u8; 16] = core::array::from_fn(|k| (k as u8) * 16 + 3);
+        cpu.v = regs;
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(cpu.read_mem(0x300..0x310).unwrap(), regs);
+        cpu.v = [0; 16];
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(cpu.v, regs);
+    }
+
+    fn store_and_load_past_memory_are_errors(dispatch: Dispatch) {
+        for opcode in [0xFF55, 0xFF65] {
+            // LD I, 0xFF8, then sixteen registers need up to 0x1007
+            let mut cpu = cpu_with(dispatch, &[0xAFF8, opcode]);
+            cpu.v = [0x5A; 16];
+            cpu.exec_cycle().unwrap();
+            assert_eq!(
+                cpu.exec_cycle(),
+                Err(Chip8Error::MemoryOutOfBounds {
+                    addr: 0x1000,
+                    pc: 0x202,
+                    opcode
+                })
+            );
+            assert_eq!(cpu.v, [0x5A; 16]);
+            assert_eq!(cpu.i, 0xFF8);
+        }
+    }
+
+    fn add_i_past_4k_wraps_to_the_start(dispatch: Dispatch) {
+        // LD I, 0xFFF / ADD I, V0 with V0 = 0x10 / DRW V1, V1, 1
+        let mut cpu = cpu_with(dispatch, &[0xAFFF, 0xF01E, 0xD111]);
+        cpu.v[0] = 0x10;
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(cpu.i, 0x00F);
+        // I points at the first row of the font's 3
+        cpu.exec_cycle().unwrap();
+        assert_eq!(lit(&cpu), [(0, 0), (1, 0), (2, 0), (3, 0)]);
+    }
+
+    fn exec_cycle_reports_what_happened(dispatch: Dispatch) {
+        // LD V0, 1 / DRW V0, V0, 1 / LD V1, K / JP 0x206
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0xD001, 0xF10A, 0x1206]);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::DrewSprite));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForKey));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForKey));
+        cpu.key_down(3).unwrap();
+        cpu.key_up(3).unwrap();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Halted));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Halted));
+    }
+
+    fn on_instruction_sees_each_pc_and_opcode(dispatch: Dispatch) {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        // CALL 0x206 / JP 0x204 / LD V0, 1 / RET
+        let mut cpu = cpu_with(dispatch, &[0x2206, 0x1202, 0x1204, 0x6001, 0x00EE]);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&seen);
+        cpu.on_instruction(move |pc, opcode| log.borrow_mut().push((pc, opcode)));
+        cpu.exec_cycles(4).unwrap();
+        assert_eq!(
+            *seen.borrow(),
+            [
+                (0x200, 0x2206),
+                (0x206, 0x6001),
+                (0x208, 0x00EE),
+                (0x202, 0x1202)
+            ]
+        );
+    }
+
+    fn execute_matches_exec_cycle(dispatch: Dispatch) {
+        // Arithmetic, a skip, a call and a store, then the same driven through execute
+        let program = [
+            0x6A05, 0x6B07, 0x8AB4, 0x8AB5, 0x3A05, 0x6C01, 0xA300, 0x2214, 0xFC55, 0x120A, 0x8AB6,
+            0x00EE,
+        ];
+        let mut by_cycle = cpu_with(dispatch, &program);
+        by_cycle.exec_cycles(10).unwrap();
+        let mut by_execute = cpu_with(dispatch, &program);
+        for _ in 0..10 {
+            let opcode = by_execute.read_word(by_execute.pc).unwrap();
+            by_execute.pc += 2;
+            by_execute
+                .execute(Instruction::decode(opcode).unwrap())
+                .unwrap();
+        }
+        // Only exec_cycle counts machine cycles
+        let by_cycle = CpuSnapshot {
+            machine_cycles: 0,
+            ..by_cycle.snapshot()
+        };
+        assert_eq!(by_execute.snapshot(), by_cycle);
+    }
+
+    fn machine_cycles_follow_the_vip_table(dispatch: Dispatch) {
+        // LD V0, 1 / ADD V0, 2 / LD I, 0x300 / DRW V0, V0, 3 / SE V0, 3 / skipped / ADD V0, V1
+        let mut cpu = cpu_with(
+            dispatch,
+            &[0x6001, 0x7002, 0xA300, 0xD003, 0x3003, 0x6105, 0x8014],
+        );
+        assert_eq!(cpu.exec_cycles(6), Ok(6));
+        assert_eq!(cpu.machine_cycles(), 6 + 10 + 12 + (68 + 3 * 46) + 10 + 44);
+    }
+
+    fn self_jump_halts(dispatch: Dispatch) {
+        // LD V0, 1 / JP 0x202
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0x1202]);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Halted));
+        assert_eq!(cpu.pc, 0x202);
+    }
+
+    fn delay_timer_loop_is_not_a_halt(dispatch: Dispatch) {
+        // LD V0, DT / SE V0, 0 / JP 0x200 / JP 0x206
+        let mut cpu = cpu_with(dispatch, &[0xF007, 0x3000, 0x1200, 0x1206]);
+        cpu.dt = 3;
+        let mut states = Vec::new();
+        while cpu.pc != 0x206 {
+            let state = cpu.exec_cycle().unwrap();
+            assert_ne!(state, CycleState::Halted);
+            states.push(state);
+            if state == CycleState::IdleUntilTimer {
+                cpu.update_timers();
+            }
+        }
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Halted));
+        assert_eq!(states.len(), 3 * 3 + 2);
+    }
+
+    fn stats_count_instructions_draws_and_ticks(dispatch: Dispatch) {
+        // LD V0, 3 / DRW V0, V0, 1 / ADD V0, -1 / SE V0, 0 / JP 0x202 / JP 0x20A
+        let mut cpu = cpu_with(dispatch, &[0x6003, 0xD001, 0x70FF, 0x3000, 0x1202, 0x120A]);
+        cpu.exec_cycles(100).unwrap();
+        cpu.update_timers();
+        cpu.update_timers();
+        let stats = cpu.stats();
+        assert_eq!(stats.instructions, 1 + 3 * 3 + 2 + 1);
+        assert_eq!(stats.draws, 3);
+        assert_eq!(stats.timer_ticks, 2);
+        let mut families = [0; 16];
+        families[0x1] = 3;
+        families[0x3] = 3;
+        families[0x6] = 1;
+        families[0x7] = 3;
+        families[0xD] = 3;
+        assert_eq!(stats.instructions_by_family, families);
+        cpu.reset_stats();
+        assert_eq!(*cpu.stats(), CpuStats::default());
+    }
+
+    fn stats_leave_out_stalled_cycles(dispatch: Dispatch) {
+        // LD V0, K / DRW V0, V0, 1 / JP 0x204
+        let mut cpu = cpu_on(dispatch, Platform::Chip8, &[0xF00A, 0xD001, 0x1204]);
+        for _ in 0..5 {
+            assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForKey));
+        }
+        cpu.key_down(1).unwrap();
+        cpu.key_up(1).unwrap();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        for _ in 0..3 {
+            assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForVblank));
+        }
+        assert_eq!((cpu.stats().instructions, cpu.stats().draws), (1, 0));
+        cpu.update_timers();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::DrewSprite));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Halted));
+
+        let stats = cpu.stats();
+        assert_eq!((stats.instructions, stats.draws), (3, 1));
+        let mut families = [0; 16];
+        families[0x1] = 1;
+        families[0xD] = 1;
+        families[0xF] = 1;
+        assert_eq!(stats.instructions_by_family, families);
+        let cycles: u64 = [0xF00A, 0xD001, 0x1204]
+            .iter()
+            .map(|&opcode| Instruction::decode(opcode).unwrap().vip_cycles() as u64)
+            .sum();
+        assert_eq!(cpu.machine_cycles(), cycles);
+        assert_eq!(
+            (cpu.profile().count(0x200), cpu.profile().count(0x202)),
+            (1, 1)
+        );
+    }
+
+    fn skips_step_over_long_loads_on_xo_chip(dispatch: Dispatch) {
+        // V0 = 0, V1 = 1, V2 = 0 and key 0 down, so every one of these skips
+        let skips = [0x3000, 0x4001, 0x5020, 0x9010, 0xE09E, 0xE1A1];
+        for (platform, next) in [(Platform::XoChip, 0x206), (Platform::Chip8, 0x204)] {
+            for skip in skips {
+                // the skip / LD I, long 0x1234
+                let mut cpu = CPU::builder().dispatch(dispatch).platform(platform).build();
+                cpu.load_rom_bytes(&words(&[skip, 0xF000, 0x1234])).unwrap();
+                cpu.v[1] = 1;
+                cpu.key_down(0).unwrap();
+                cpu.exec_cycle().unwrap();
+                assert_eq!(cpu.pc, next, "{:04X} on {:?}", skip, platform);
+            }
+        }
+    }
+
+    fn ret_at_reset_leaves_the_cpu_for_inspection(dispatch: Dispatch) {
+        let mut cpu = cpu_with(dispatch, &[0x00EE]);
+        let before = cpu.snapshot();
+        assert!(matches!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::StackUnderflow { pc: 0x200, .. })
+        ));
+        assert_eq!(cpu.snapshot(), before);
+    }
+
+    fn call_and_ret_pair_up(dispatch: Dispatch) {
+        // CALL 0x206 / LD V1, 2 / JP 0x204 / LD V0, 1 / RET
+        let mut cpu = cpu_with(dispatch, &[0x2206, 0x6102, 0x1204, 0x6001, 0x00EE]);
+        cpu.exec_cycle().unwrap();
+        assert_eq!((cpu.pc, cpu.stack_depth()), (0x206, 1));
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!((cpu.pc, cpu.stack_depth()), (0x202, 0));
+        assert_eq!(cpu.exec_cycles(10), Ok(2));
+        assert_eq!((cpu.v[0], cpu.v[1]), (1, 2));
+    }
+
+    fn deeper_stack_allows_deeper_nesting(dispatch: Dispatch) {
+        // 32 CALLs, each to the next instruction
+        let program: Vec<u16> = (1..=32).map(|k| 0x2200 + 2 * k).collect();
+        let mut cpu = cpu_with(dispatch, &program);
+        assert_eq!(cpu.max_stack_depth, 16);
+        cpu.max_stack_depth = 64;
+        assert_eq!(cpu.exec_cycles(32), Ok(32));
+        assert_eq!(cpu.stack_depth(), 32);
+    }
+
+    fn soft_reset_keeps_the_rom(dispatch: Dispatch) {
+        // LD V0, 5 / LD I, 0 / DRW V0, V0, 5 / CALL 0x20A / LD DT, V0
+        let program = [0x6005, 0xA000, 0xD005, 0x220A, 0xF015];
+        let mut cpu = cpu_with(dispatch, &program);
+        cpu.exec_cycles(5).unwrap();
+        cpu.write_mem(0x000, &[0xAA]).unwrap();
+        assert!(!lit(&cpu).is_empty());
+
+        cpu.soft_reset().unwrap();
+        assert_eq!(cpu.read_mem(0x200..0x20A).unwrap(), &words(&program)[..]);
+        assert_eq!((cpu.pc, cpu.i, cpu.v, cpu.dt), (0x200, 0, [0; 16], 0));
+        assert_eq!(cpu.stack_depth(), 0);
+        assert_eq!(lit(&cpu), []);
+        // The font is back
+        assert_eq!(cpu.read_mem(0x000..0x001).unwrap(), [0xF0]);
+        // and the ROM runs again
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.v[0], 5);
+    }
+
+    #[test]
+    fn soft_reset_needs_a_rom() {
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.soft_reset(), Err(Chip8Error::NoRomLoaded));
+        cpu.load_rom_bytes(&[0x60, 0x01]).unwrap();
+        cpu.reset();
+        assert_eq!(cpu.read_mem(0x200..0x202).unwrap(), [0, 0]);
+        assert_eq!(cpu.soft_reset(), Err(Chip8Error::NoRomLoaded));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn load_rom_reports_each_failure() {
+        let dir = std::env::temp_dir().join(format!("rusty_chip8_load_rom_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("empty"), []).unwrap();
+        fs::write(path("max"), vec![0x12; 0xE00]).unwrap();
+        fs::write(path("over"), vec![0x12; 0xE01]).unwrap();
+
+        let mut cpu = CPU::new();
+        assert!(matches!(
+            cpu.load_rom(&path("missing")),
+            Err(Chip8Error::RomLoad(RomLoadError::Io(_)))
+        ));
+        assert!(matches!(
+            cpu.load_rom(&path("empty")),
+            Err(Chip8Error::RomLoad(RomLoadError::Empty))
+        ));
+        assert_eq!(cpu.load_rom(&path("max")).unwrap(), 0xE00);
+        assert!(matches!(
+            cpu.load_rom(&path("over")),
+            Err(Chip8Error::RomLoad(RomLoadError::TooLarge {
+                size: 0xE01,
+                max: 0xE00
+            }))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn load_rom_bytes_runs_an_embedded_rom(dispatch: Dispatch) {
+        let maze = include_bytes!("../roms/MAZE");
+        let mut cpu = CPU::builder().dispatch(dispatch).seed(1).build();
+        assert_eq!(cpu.load_rom_bytes(maze), Ok(34));
+        assert_eq!(cpu.read_mem(0x200..0x222).unwrap(), maze);
+        // LD I, 0x21E / RND V2, 1
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        assert_eq!((cpu.pc, cpu.i), (0x204, 0x21E));
+        assert!(cpu.v[2] <= 1);
+    }
+
+    #[test]
+    fn load_rom_bytes_rejects_what_doesnt_fit() {
+        let mut cpu = CPU::new();
+        assert_eq!(
+            cpu.load_rom_bytes(&[0; 0xE01]),
+            Err(Chip8Error::RomLoad(RomLoadError::TooLarge {
+                size: 0xE01,
+                max: 0xE00
+            }))
+        );
+        assert_eq!(
+            cpu.load_rom_bytes(&[]),
+            Err(Chip8Error::RomLoad(RomLoadError::Empty))
+        );
+    }
+
+    #[test]
+    fn program_loaded_at_0x600_starts_there() {
+        // LD V0, 1 / JP 0x602
+        let program = words(&[0x6001, 0x1602]);
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.load_rom_at(&program, 0x600), Ok(4));
+        assert_eq!(cpu.pc, 0x600);
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        cpu.soft_reset().unwrap();
+        assert_eq!(cpu.pc, 0x600);
+        // The space left is what lies past 0x600
+        assert_eq!(
+            cpu.load_rom_at(&[0; 0xA01], 0x600),
+            Err(Chip8Error::RomLoad(RomLoadError::TooLarge {
+                size: 0xA01,
+                max: 0xA00
+            }))
+        );
+
+        let mut eti = CPU::with_platform(Platform::Eti660);
+        eti.load_rom_bytes(&program).unwrap();
+        assert_eq!(eti.pc, 0x600);
+        let mut configured = CPU::builder().load_address(0x600).build();
+        configured.load_rom_bytes(&program).unwrap();
+        assert_eq!(configured.read_mem(0x600..0x604).unwrap(), &program[..]);
+    }
+
+    #[test]
+    fn default_load_address_is_still_0x200() {
+        let mut cpu = CPU::new();
+        cpu.load_rom_bytes(&[0x60, 0x01]).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.read_mem(0x200..0x202).unwrap(), [0x60, 0x01]);
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn byte_access_at_the_edges_of_memory() {
+        let mut cpu = CPU::new();
+        for addr in [0x000, 0xFFF] {
+            cpu.write_byte(addr, 0x5A).unwrap();
+            assert_eq!(cpu.read_byte(addr), Ok(0x5A));
+        }
+        assert!(matches!(
+            cpu.read_byte(0x1000),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000, .. })
+        ));
+        assert!(matches!(
+            cpu.write_byte(0x1000, 1),
+            Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000, .. })
+        ));
+        cpu.write_word(0xFFE, 0x1234).unwrap();
+        assert_eq!(cpu.read_word(0xFFE), Ok(0x1234));
+        assert!(cpu.read_word(0xFFF).is_err());
+        assert!(cpu.write_word(0xFFF, 0).is_err());
+    }
+
+    fn memory_faults_name_the_instruction(dispatch: Dispatch) {
+        // LD I, 0xFFF / LD [I], V1
+        let mut cpu = cpu_with(dispatch, &[0xAFFF, 0xF155]);
+        cpu.exec_cycle().unwrap();
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0x1000,
+                pc: 0x202,
+                opcode: 0xF155
+            })
+        );
+    }
+
+    fn state_dump(dispatch: Dispatch) {
+        // CALL 0x206 / two unused words / ADD V1, V2
+        let mut cpu = cpu_with(dispatch, &[0x2206, 0x0000, 0x0000, 0x8124]);
+        cpu.exec_cycle().unwrap();
+        cpu.i = 0x2A0;
+        cpu.dt = 0x3C;
+        for x in 0..16 {
+            cpu.v[x] = (x as u8) << 4 | x as u8;
+        }
+        assert_eq!(
+            cpu.to_string(),
+            "PC: 0x206  I: 0x2A0  SP: 1  DT: 0x3C  ST: 0x00\n\
+             V0-V7: 00 11 22 33 44 55 66 77\n\
+             V8-VF: 88 99 AA BB CC DD EE FF\n\
+             Stack: 0x202\n\
+             Next:  0x206: 0x8124  ADD V1, V2"
+        );
+        cpu.stack.clear();
+        cpu.pc = 0xFFF;
+        assert!(cpu
+            .to_string()
+            .ends_with("Stack: -\nNext:  0xFFF: (outside memory)"));
+    }
+
+    #[test]
+    fn rom_may_fill_memory_to_the_last_byte() {
+        let max = 0x1000 - 0x200;
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.load_rom_bytes(&vec![0xAB; max - 1]), Ok(max - 1));
+        assert_eq!(cpu.load_rom_bytes(&vec![0xCD; max]), Ok(max));
+        assert_eq!(cpu.read_mem(0xFFF..0x1000).unwrap(), [0xCD]);
+        assert_eq!(
+            cpu.load_rom_bytes(&vec![0; max + 1]),
+            Err(Chip8Error::RomLoad(RomLoadError::TooLarge {
+                size: max + 1,
+                max
+            }))
+        );
+    }
+
+    fn interpreter_area_protection(dispatch: Dispatch) {
+        for protection in [
+            WriteProtection::Off,
+            WriteProtection::Skip,
+            WriteProtection::Error,
+        ] {
+            // LD I, 0x100 / LD [I], V1
+            let mut cpu = CPU::builder()
+                .dispatch(dispatch)
+                .protect_interpreter_area(protection)
+                .build();
+            cpu.load_rom_bytes(&words(&[0xA100, 0xF155])).unwrap();
+            cpu.v[0] = 0x11;
+            cpu.v[1] = 0x22;
+            cpu.exec_cycle().unwrap();
+            let result = cpu.exec_cycle();
+            let memory = cpu.read_mem(0x100..0x102).unwrap();
+            match protection {
+                WriteProtection::Off => {
+                    assert_eq!(result, Ok(CycleState::Normal));
+                    assert_eq!(memory, [0x11, 0x22]);
+                }
+                WriteProtection::Skip => {
+                    assert_eq!(result, Ok(CycleState::Normal));
+                    assert_eq!(memory, [0, 0]);
+                }
+                WriteProtection::Error => {
+                    assert_eq!(
+                        result,
+                        Err(Chip8Error::WriteProtected {
+                            addr: 0x100,
+                            pc: 0x202,
+                            opcode: 0xF155
+                        })
+                    );
+                    assert_eq!(memory, [0, 0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn protection_still_lets_reset_load_the_font() {
+        let mut cpu = CPU::builder()
+            .protect_interpreter_area(WriteProtection::Error)
+            .build();
+        cpu.set_font(FontSet::CosmacVip);
+        cpu.reset();
+        assert_eq!(cpu.read_mem(0x000..0x005).unwrap(), &fonts::COSMAC_VIP[..5]);
+        // The program area isn't protected
+        assert_eq!(cpu.write_byte(0x200, 1), Ok(()));
+    }
+
+    #[test]
+    fn random_memory_init_repeats_per_seed() {
+        let memory = |init| {
+            let mut cpu = CPU::builder().memory_init(init).build();
+            cpu.reset();
+            cpu.read_mem(0..0x1000).unwrap().to_vec()
+        };
+        let a = memory(MemoryInit::Random(5));
+        assert_eq!(a, memory(MemoryInit::Random(5)));
+        assert_ne!(a, memory(MemoryInit::Random(6)));
+        // The interpreter area only holds the fonts
+        assert_eq!(a[..0x200], memory(MemoryInit::Zero)[..0x200]);
+        assert!(a[0x200..].iter().any(|&b| b != a[0x200]));
+    }
+
+    #[test]
+    fn rom_loads_over_the_fill_pattern() {
+        let mut cpu = CPU::builder()
+            .memory_init(MemoryInit::Pattern(0xAA))
+            .build();
+        cpu.load_rom_bytes(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        assert_eq!(
+            cpu.read_mem(0x200..0x206).unwrap(),
+            [0x60, 0x01, 0x12, 0x02, 0xAA, 0xAA]
+        );
+        assert!(cpu
+            .read_mem(0x204..0x1000)
+            .unwrap()
+            .iter()
+            .all(|&b| b == 0xAA));
+    }
+
+    fn reads_of_unwritten_memory_are_reported_once(dispatch: Dispatch) {
+        // LD I, 0x300 / LD [I], V3 / LD I, 0x300 / LD V7, [I] twice
+        let mut cpu = CPU::builder()
+            .dispatch(dispatch)
+            .uninitialized_read_check(true)
+            .build();
+        cpu.load_rom_bytes(&words(&[0xA300, 0xF355, 0xA300, 0xF765, 0xF765]))
+            .unwrap();
+        cpu.exec_cycles(4).unwrap();
+        let expected: Vec<_> = (0x304..0x308)
+            .map(|addr| Chip8Error::UninitializedRead { addr, pc: 0x206 })
+            .collect();
+        assert_eq!(cpu.uninitialized_reads(), &expected[..]);
+        cpu.exec_cycle().unwrap();
+        assert_eq!(cpu.uninitialized_reads().len(), 4);
+        // The font counts as written
+        cpu.i = 0;
+        cpu.execute(Instruction::LoadRegs { x: 0xF }).unwrap();
+        assert_eq!(cpu.uninitialized_reads().len(), 4);
+    }
+
+    fn unwritten_reads_go_unreported_without_the_check(dispatch: Dispatch) {
+        let mut cpu = cpu_with(dispatch, &[0xA300, 0xF765]);
+        cpu.exec_cycles(2).unwrap();
+        assert!(cpu.uninitialized_reads().is_empty());
+    }
+
+    fn memory_observer_sees_each_byte_written(dispatch: Dispatch) {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        // LD I, 0x300 / LD [I], V2
+        let mut cpu = cpu_with(dispatch, &[0xA300, 0xF255]);
+        cpu.write_mem(0x301, &[0x77]).unwrap();
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&writes);
+        cpu.set_memory_observer(Box::new(move |addr, old, new| {
+            log.borrow_mut().push((addr, old, new))
+        }));
+        cpu.v[..3].copy_from_slice(&[1, 2, 3]);
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(
+            *writes.borrow(),
+            [(0x300, 0, 1), (0x301, 0x77, 2), (0x302, 0, 3)]
+        );
+        // Loading a ROM and resetting aren't program writes
+        cpu.load_rom_bytes(&[0x00, 0xE0]).unwrap();
+        cpu.reset();
+        assert_eq!(writes.borrow().len(), 3);
+    }
+
+    fn delay_timer_poll_is_idle(dispatch: Dispatch) {
+        // LD V0, DT / SE V0, 0 / JP 0x200
+        let mut cpu = cpu_with(dispatch, &[0xF007, 0x3000, 0x1200]);
+        cpu.dt = 5;
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::IdleUntilTimer));
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    fn loops_doing_more_than_reading_dt_are_not_idle(dispatch: Dispatch) {
+        for program in [
+            // LD V0, DT / SKP V1 / JP 0x200, also polls a key
+            &[0xF007, 0xE19E, 0x1200][..],
+            // LD V0, DT / LD [I], V0 / SE V0, 0 / JP 0x200, writes memory
+            &[0xF007, 0xF055, 0x3000, 0x1200],
+            // LD V0, DT / DRW V1, V1, 1 / SE V0, 0 / JP 0x200, draws
+            &[0xF007, 0xD111, 0x3000, 0x1200],
+            // SE V0, 0 / JP 0x200, never reads DT
+            &[0x3001, 0x1200],
+        ] {
+            let mut cpu = cpu_with(dispatch, program);
+            cpu.i = 0x300;
+            cpu.dt = 5;
+            for _ in 0..20 {
+                assert_ne!(
+                    cpu.exec_cycle(),
+                    Ok(CycleState::IdleUntilTimer),
+                    "{:04X?}",
+                    program
+                );
+            }
+        }
+    }
+
+    // The top left 4x5 pixels of the screen after drawing glyph 0 there with FX29 and DXYN
+    fn draw_zero(mut cpu: CPU) -> Vec<String> {
+        // LD F, V0 / DRW V0, V0, 5
+        cpu.load_rom_bytes(&words(&[0xF029, 0xD005])).unwrap();
+        cpu.exec_cycles(2).unwrap();
+        cpu.display
+            .to_ascii()
+            .lines()
+            .take(5)
+            .map(|line| line[..4].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn font_sets_draw_different_glyphs() {
+        let standard = draw_zero(CPU::builder().build());
+        assert_eq!(standard, ["####", "#..#", "#..#", "#..#", "####"]);
+        let dream = CPU::builder().font(FontSet::Dream6800).build();
+        assert_eq!(draw_zero(dream), ["###.", "#.#.", "#.#.", "#.#.", "###."]);
+        // Only takes effect on reset
+        let mut cpu = CPU::builder().build();
+        cpu.set_font(FontSet::Dream6800);
+        assert_eq!(draw_zero(cpu), standard);
+    }
+
+    #[test]
+    fn custom_font_replaces_the_glyphs() {
+        let font = include_bytes!("../tests/fixtures/font.bin");
+        let mut cpu = CPU::builder().custom_font(*font).build();
+        assert_eq!(cpu.read_mem(0x000..0x050).unwrap(), font);
+        // Still there after a soft reset
+        cpu.load_rom_bytes(&[0x00, 0xE0]).unwrap();
+        cpu.soft_reset().unwrap();
+        assert_eq!(draw_zero(cpu), ["....", "#..#", ".##.", "#..#", "...."]);
+    }
+
+    // Counts up from a start value, for deterministic RND results
+    struct Counter(u8);
+
+    impl RandomSource for Counter {
+        fn next_u8(&mut self) -> u8 {
+            self.0 = self.0.wrapping_add(37);
+            self.0
+        }
+    }
+
+    fn injected_random_source_gives_identical_traces(dispatch: Dispatch) {
+        let trace = |start| {
+            let mut cpu = CPU::with_random_source(Box::new(Counter(start)));
+            cpu.dispatch = dispatch;
+            // RND V0, FF / RND V1, 0F / ADD V0, V1 / JP 200
+            cpu.load_rom_bytes(&words(&[0xC0FF, 0xC10F, 0x8014, 0x1200]))
+                .unwrap();
+            (0..40)
+                .map(|_| {
+                    cpu.exec_cycle().unwrap();
+                    cpu.v
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(trace(5), trace(5));
+        assert_ne!(trace(5), trace(6));
+        assert_eq!(trace(5)[0][0], 42);
+        assert_eq!(trace(5)[1][1], 79 & 0x0F);
+    }
+
+    fn run_frame_reports_what_the_frame_did(dispatch: Dispatch) {
+        // LD V0, 5 / LD ST, V0 / DRW V0, V0, 5 / LD V1, K / JP 0x208
+        let mut cpu = cpu_with(dispatch, &[0x6005, 0xF018, 0xD005, 0xF10A, 0x1208]);
+        let frame = cpu.run_frame(10).unwrap();
+        assert_eq!(
+            frame,
+            FrameResult {
+                instructions: 3,
+                drew: true,
+                beep: true,
+                halted: false,
+                ended_by: Some(CycleState::WaitingForKey),
+            }
+        );
+        assert_eq!(cpu.st, 4);
+
+        // Still waiting for a key, nothing runs and nothing is drawn
+        assert_eq!(
+            cpu.run_frame(10).unwrap(),
+            FrameResult {
+                beep: true,
+                ended_by: Some(CycleState::WaitingForKey),
+                ..FrameResult::default()
+            }
+        );
+
+        cpu.key_down(1).unwrap();
+        cpu.key_up(1).unwrap();
+        let frame = cpu.run_frame(10).unwrap();
+        assert_eq!(frame.instructions, 2);
+        assert!(frame.halted && !frame.drew);
+    }
+
+    fn run_frame_stops_at_the_instruction_budget(dispatch: Dispatch) {
+        // ADD V0, 1 / JP 0x200
+        let mut cpu = cpu_with(dispatch, &[0x7001, 0x1200]);
+        assert_eq!(
+            cpu.run_frame(7).unwrap(),
+            FrameResult {
+                instructions: 7,
+                ..FrameResult::default()
+            }
+        );
+        assert_eq!(cpu.v[0], 4);
+    }
+
+    fn run_frame_stops_for_the_debugger_without_a_timer_tick(dispatch: Dispatch) {
+        // CLS / ADD V0, 1 / JP 0x202
+        let mut cpu = cpu_with(dispatch, &[0x00E0, 0x7001, 0x1202]);
+        cpu.dt = 5;
+        cpu.add_breakpoint(0x202);
+        assert_eq!(
+            cpu.run_frame(10).unwrap(),
+            FrameResult {
+                instructions: 1,
+                drew: true,
+                ended_by: Some(CycleState::BreakpointHit { addr: 0x202 }),
+                ..FrameResult::default()
+            }
+        );
+        assert_eq!(cpu.dt, 5);
+        // Going on from the breakpoint, the loop doesn't draw
+        cpu.remove_breakpoint(0x202);
+        let frame = cpu.run_frame(4).unwrap();
+        assert_eq!(
+            (frame.instructions, frame.drew, frame.ended_by),
+            (4, false, None)
+        );
+        assert_eq!(cpu.dt, 4);
+    }
+
+    fn hooks_see_each_instruction_before_and_after(dispatch: Dispatch) {
+        // LD V0, 2 / CALL 0x206 / JP 0x204 / ADD V0, 1 / RET
+        let mut cpu = cpu_with(dispatch, &[0x6002, 0x2206, 0x1204, 0x7001, 0x00EE]);
+        let pre = Rc::new(RefCell::new(Vec::new()));
+        let post = Rc::new(RefCell::new(Vec::new()));
+        let pre_seen = pre.clone();
+        cpu.set_pre_exec_hook(move |cpu, opcode| {
+            pre_seen.borrow_mut().push((cpu.pc, opcode));
+            HookAction::Continue
+        });
+        let post_seen = post.clone();
+        cpu.set_post_exec_hook(move |cpu, opcode| post_seen.borrow_mut().push((cpu.pc, opcode)));
+        cpu.exec_cycles(5).unwrap();
+
+        assert_eq!(
+            *pre.borrow(),
+            [
+                (0x200, 0x6002),
+                (0x202, 0x2206),
+                (0x206, 0x7001),
+                (0x208, 0x00EE),
+                (0x204, 0x1204),
+            ]
+        );
+        assert_eq!(
+            *post.borrow(),
+            [
+                (0x202, 0x6002),
+                (0x206, 0x2206),
+                (0x208, 0x7001),
+                (0x204, 0x00EE),
+                (0x204, 0x1204),
+            ]
+        );
+        assert_eq!(cpu.v[0], 3);
+    }
+
+    fn pause_skips_the_instruction(dispatch: Dispatch) {
+        // LD V0, 1
+        let mut cpu = cpu_with(dispatch, &[0x6001]);
+        let posts = Rc::new(RefCell::new(0));
+        let seen = posts.clone();
+        cpu.set_pre_exec_hook(|_, _| HookAction::Pause);
+        cpu.set_post_exec_hook(move |_, _| *seen.borrow_mut() += 1);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Paused));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Paused));
+        assert_eq!((cpu.pc, cpu.v[0], *posts.borrow()), (0x200, 0, 0));
+        assert_eq!(cpu.stats().instructions, 0);
+    }
+
+    // A CPU emulating `platform` with `program` loaded at 0x200
+    fn cpu_on(dispatch: Dispatch, platform: Platform, program: &[u16]) -> CPU {
+        let mut cpu = CPU::builder().dispatch(dispatch).platform(platform).build();
+        cpu.load_rom_bytes(&words(program)).unwrap();
+        cpu
+    }
+
+    fn hires_mode_draws_past_column_64(dispatch: Dispatch) {
+        // HIGH / LD V0, 100 / LD V1, 40 / LD I, 0x20C / DRW V0, V1, 1 / LOW / 0x80
+        let mut cpu = cpu_on(
+            dispatch,
+            Platform::SuperChip,
+            &[0x00FF, 0x6064, 0x6128, 0xA20C, 0xD011, 0x00FE, 0x8000],
+        );
+        cpu.exec_cycles(5).unwrap();
+        assert_eq!((cpu.display.width(), cpu.display.height()), (128, 64));
+        assert_eq!(lit(&cpu), [(100, 40)]);
+
+        // Back to low resolution, which clears the screen
+        cpu.exec_cycle().unwrap();
+        assert_eq!((cpu.display.width(), cpu.display.height()), (64, 32));
+        assert!(lit(&cpu).is_empty());
+    }
+
+    fn resolution_switch_can_keep_the_display(dispatch: Dispatch) {
+        // LD I, 0x208 / DRW V0, V0, 1 / HIGH / LOW / 0x80
+        let program = [0xA208, 0xD001, 0x00FF, 0x00FE, 0x8000];
+        let mut cpu = cpu_on(dispatch, Platform::SuperChip, &program);
+        cpu.quirks.resolution_switch_keeps_display = true;
+        cpu.exec_cycles(3).unwrap();
+        assert_eq!(lit(&cpu), [(0, 0)]);
+        cpu.exec_cycle().unwrap();
+        assert_eq!(lit(&cpu), [(0, 0)]);
+    }
+
+    fn lores_programs_draw_the_same_on_super_chip(dispatch: Dispatch) {
+        let maze = |platform| {
+            let mut cpu = CPU::builder()
+                .dispatch(dispatch)
+                .platform(platform)
+                .seed(7)
+                .build();
+            cpu.load_rom_bytes(include_bytes!("../roms/MAZE")).unwrap();
+            for _ in 0..200 {
+                cpu.run_frame(20).unwrap();
+            }
+            assert_eq!(cpu.display.width(), 64);
+            cpu.display.to_ascii()
+        };
+        let chip8 = maze(Platform::Chip8);
+        assert!(chip8.contains('#'));
+        assert_eq!(maze(Platform::SuperChip), chip8);
+    }
+
+    fn dxy0_draws_16x16_at_the_right_edge(dispatch: Dispatch) {
+        // HIGH / LD V0, 120 / LD I, 0x20A / DRW V0, V1, 0 / DRW V0, V1, 0 / a 16x16 block
+        let mut program = vec![0x00FF, 0x6078, 0xA20A, 0xD010, 0xD010];
+        program.extend([0xFFFF; 16]);
+        for clipping in [true, false] {
+            let mut cpu = cpu_on(dispatch, Platform::SuperChip, &program);
+            cpu.quirks.clipping = clipping;
+            cpu.exec_cycles(4).unwrap();
+            let columns: Vec<usize> = match clipping {
+                true => (120..128).collect(),
+                false => (0..8).chain(120..128).collect(),
+            };
+            let expected: Vec<(usize, usize)> = (0..16)
+                .flat_map(|y| columns.iter().map(move |&x| (x, y)))
+                .collect();
+            assert_eq!(lit(&cpu), expected);
+            assert_eq!(cpu.v[0xF], 0);
+
+            cpu.exec_cycle().unwrap();
+            assert!(lit(&cpu).is_empty());
+            assert_eq!(cpu.v[0xF], 1);
+        }
+    }
+
+    fn dxy0_on_chip8_follows_the_quirk(dispatch: Dispatch) {
+        // LD I, 0x204 / DRW V0, V0, 0 / a 16 row sprite
+        let mut program = vec![0xA204, 0xD000];
+        program.extend([0xF0F0; 8]);
+        let mut cpu = cpu_on(dispatch, Platform::Chip8, &program);
+        cpu.quirks.display_wait = false;
+        cpu.exec_cycles(2).unwrap();
+        assert!(lit(&cpu).is_empty());
+
+        let mut cpu = cpu_on(dispatch, Platform::Chip8, &program);
+        cpu.quirks.display_wait = false;
+        cpu.quirks.dxy0_draws_8x16 = true;
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(lit(&cpu), sprite_pixels(&[0xF0; 16]));
+    }
+
+    fn scrolls_move_the_screen(dispatch: Dispatch) {
+        // HIGH or LOW / LD V0, 10 / LD I, 0x20E / DRW V0, V0, 2 / SCD 3 / SCR / SCL / sprite
+        let scrolls = |platform, resolution| {
+            let mut cpu = cpu_on(
+                dispatch,
+                platform,
+                &[
+                    resolution, 0x600A, 0xA20E, 0xD002, 0x00C3, 0x00FB, 0x00FC, 0xC080,
+                ],
+            );
+            cpu.exec_cycles(4).unwrap();
+            (0..3)
+                .map(|_| {
+                    cpu.exec_cycle().unwrap();
+                    lit(&cpu)
+                })
+                .collect::<Vec<_>>()
+        };
+        let moved =
+            |dx: usize, dy: usize| vec![(10 + dx, 10 + dy), (11 + dx, 10 + dy), (10 + dx, 11 + dy)];
+        let whole = [moved(0, 3), moved(4, 3), moved(0, 3)];
+        assert_eq!(scrolls(Platform::SuperChip, 0x00FE), whole);
+        assert_eq!(scrolls(Platform::SuperChip, 0x00FF), whole);
+        assert_eq!(scrolls(Platform::SuperChipLegacy, 0x00FF), whole);
+        // SUPER-CHIP 1.1 scrolls by half as many low resolution pixels
+        assert_eq!(
+            scrolls(Platform::SuperChipLegacy, 0x00FE),
+            [moved(0, 1), moved(2, 1), moved(0, 1)]
+        );
+    }
+
+    fn scrolling_off_the_edge_blanks_the_pixels(dispatch: Dispatch) {
+        // LD V0, 58 / LD I, 0x20A / DRW V0, V0, 1 / SCR / SCD 15 / 0xF0
+        let program = [0x603A, 0xA20A, 0xD001, 0x00FB, 0x00CF, 0xF000];
+        let mut cpu = cpu_on(dispatch, Platform::SuperChip, &program);
+        cpu.exec_cycles(3).unwrap();
+        assert_eq!(lit(&cpu), [(58, 26), (59, 26), (60, 26), (61, 26)]);
+        cpu.exec_cycle().unwrap();
+        assert_eq!(lit(&cpu), [(62, 26), (63, 26)]);
+        cpu.exec_cycle().unwrap();
+        assert!(lit(&cpu).is_empty());
+    }
+
+    fn exit_halts_on_the_instruction(dispatch: Dispatch) {
+        // LD V0, 5 / EXIT
+        let mut cpu = cpu_on(dispatch, Platform::SuperChip, &[0x6005, 0x00FD]);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        for _ in 0..3 {
+            assert_eq!(cpu.exec_cycle(), Ok(CycleState::Halted));
+            assert_eq!((cpu.pc, cpu.v[0]), (0x202, 5));
+        }
+        assert_eq!(cpu.exec_cycles(10), Ok(1));
+        assert!(cpu.run_frame(10).unwrap().halted);
+    }
+
+    fn user_flags_round_trip_registers(dispatch: Dispatch) {
+        // LD R, VF / then clear V0 to VF / LD VF, R
+        let mut program = vec![0xFF75];
+        program.extend((0..16).map(|x| 0x6000 | x << 8));
+        program.push(0xFF85);
+        for (platform, kept) in [(Platform::SuperChip, 8), (Platform::XoChip, 16)] {
+            let mut cpu = cpu_on(dispatch, platform, &program);
+            for (x, v) in cpu.v.iter_mut().enumerate() {
+                *v = x as u8 + 1;
+            }
+            cpu.exec_cycles(18).unwrap();
+            assert_eq!(cpu.rpl_flags[..kept], cpu.v[..kept]);
+            assert!(cpu.rpl_flags[kept..].iter().all(|&flag| flag == 0));
+            assert!(cpu.v[kept..].iter().all(|&v| v == 0));
+            assert_eq!(cpu.v[..kept], (1..=kept as u8).collect::<Vec<_>>()[..]);
+        }
+    }
+
+    fn big_font_draws_ten_row_digits(dispatch: Dispatch) {
+        // LD V0, 0x21 / LD HF, V0 / DRW V1, V1, 10 / LD V0, 7 / LD V2, 10 / LD HF, V0 /
+        // DRW V2, V1, 10
+        let mut cpu = cpu_on(
+            dispatch,
+            Platform::SuperChip,
+            &[0x6021, 0xF030, 0xD11A, 0x6007, 0x620A, 0xF030, 0xD21A],
+        );
+        cpu.exec_cycles(2).unwrap();
+        // Only the low nibble picks the digit
+        assert_eq!(cpu.i, 80 + 10);
+        cpu.exec_cycles(5).unwrap();
+        let rows: Vec<String> = cpu
+            .display
+            .to_ascii()
+            .lines()
+            .take(11)
+            .map(|line| line[..18].to_string())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "...##.....########",
+                ".####.....########",
+                ".####...........##",
+                "...##...........##",
+                "...##..........##.",
+                "...##.........##..",
+                "...##........##...",
+                "...##........##...",
+                "########.....##...",
+                "########.....##...",
+                "..................",
+            ]
+        );
+    }
+
+    fn legacy_and_modern_super_chip_differ(dispatch: Dispatch) {
+        // LD I, 0x206 / DRW V0, V0, 0 / DRW V0, V0, 0 / a 16x16 block
+        let mut lores = vec![0xA206, 0xD000, 0xD000];
+        lores.extend([0xFFFF; 16]);
+        // HIGH / LD V1, 62 / LD I, 0x20A / DRW V0, V1, 3 / DRW V0, V1, 3 / 3 rows
+        let hires = [0x00FF, 0x613E, 0xA20A, 0xD013, 0xD013, 0xFFFF, 0xFF00];
+        let run = |platform| {
+            let mut cpu = cpu_on(dispatch, platform, &lores);
+            cpu.exec_cycle().unwrap();
+            cpu.exec_cycle().unwrap();
+            let drawn = lit(&cpu).len();
+            cpu.exec_cycle().unwrap();
+            let lores_vf = cpu.v[0xF];
+
+            let mut cpu = cpu_on(dispatch, platform, &hires);
+            cpu.exec_cycles(4).unwrap();
+            cpu.exec_cycle().unwrap();
+            (drawn, lores_vf, cpu.v[0xF])
+        };
+        // Modern draws 16x16 in low resolution and sets VF to 1 on a collision
+        assert_eq!(run(Platform::SuperChip), (256, 1, 1));
+        // 1.1 draws 8x16 and counts the rows that collide or fall off the bottom in hires
+        assert_eq!(run(Platform::SuperChipLegacy), (128, 1, 3));
+    }
+
+    fn hires_vf_counts_rows_off_the_bottom(dispatch: Dispatch) {
+        // HIGH or LOW / LD V1, y / LD I, 0x20A / DRW V0, V1, 4 / DRW V0, V1, 4 / 4 rows
+        let vf = |resolution: u16, y: u16| {
+            let mut cpu = cpu_on(
+                dispatch,
+                Platform::SuperChipLegacy,
+                &[
+                    resolution,
+                    0x6100 | y,
+                    0xA20A,
+                    0xD014,
+                    0xD014,
+                    0x00FF,
+                    0xFFFF,
+                ],
+            );
+            cpu.exec_cycles(4).unwrap();
+            let first = cpu.v[0xF];
+            cpu.exec_cycle().unwrap();
+            (first, cpu.v[0xF])
+        };
+        // Two rows fall off the bottom, then the one lit row left on screen collides as well
+        assert_eq!(vf(0x00FF, 62), (2, 3));
+        // The blank first row never collides
+        assert_eq!(vf(0x00FF, 61), (1, 3));
+        assert_eq!(vf(0x00FF, 10), (0, 3));
+        // Low resolution still only reports whether anything collided
+        assert_eq!(vf(0x00FE, 30), (0, 1));
+        assert_eq!(vf(0x00FE, 10), (0, 1));
+    }
+
+    fn planes_give_four_colors(dispatch: Dispatch) {
+        // LD I, 0x216 / LD V1, 4 / LD V2, 8 / PLANE 1 / DRW V0, V0, 1 / PLANE 2 /
+        // DRW V1, V0, 1 / PLANE 3 / DRW V2, V0, 1 / DRW V0, V0, 1 / JP 0x214 / sprite
+        let mut cpu = cpu_on(
+            dispatch,
+            Platform::XoChip,
+            &[
+                0xA216, 0x6104, 0x6208, 0xF101, 0xD001, 0xF201, 0xD101, 0xF301, 0xD201, 0xD001,
+                0x1214, 0x8080,
+            ],
+        );
+        let color = |cpu: &CPU, x| cpu.display.get_pixel(x, 0);
+        cpu.exec_cycles(5).unwrap();
+        assert_eq!((color(&cpu, 0), cpu.v[0xF]), (1, 0));
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!((color(&cpu, 4), cpu.v[0xF]), (2, 0));
+        // Both planes take a byte of the sprite each
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!((color(&cpu, 8), cpu.v[0xF]), (3, 0));
+        // Only the first plane collides, which is enough for VF
+        cpu.exec_cycle().unwrap();
+        assert_eq!((color(&cpu, 0), cpu.v[0xF]), (2, 1));
+        assert_eq!(lit(&cpu), [(0, 0), (4, 0), (8, 0)]);
+    }
+
+    fn chip8_draws_on_the_first_plane(dispatch: Dispatch) {
+        let mut cpu = cpu_on(dispatch, Platform::Chip8, &[0xF029, 0xD005, 0xF101]);
+        cpu.quirks.display_wait = false;
+        cpu.exec_cycles(2).unwrap();
+        assert!(cpu.display.iter_pixels().all(|(_, _, color)| color == 1));
+        assert_eq!(lit(&cpu).len(), 14);
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::UnknownOpcode {
+                opcode: 0xF101,
+                pc: 0x204
+            })
+        );
+    }
+
+    fn long_index_load_reads_the_next_word(dispatch: Dispatch) {
+        // LD I, long 0xABCD / LD V0, 1
+        let program = [0xF000, 0xABCD, 0x6001];
+        let mut cpu = cpu_on(dispatch, Platform::XoChip, &program);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!((cpu.i, cpu.pc), (0xABCD, 0x204));
+
+        let mut cpu = cpu_on(dispatch, Platform::SuperChip, &program);
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::UnknownOpcode {
+                opcode: 0xF000,
+                pc: 0x200
+            })
+        );
+    }
+
+    fn scroll_up_moves_the_selected_planes(dispatch: Dispatch) {
+        // HIGH or LOW / LD V0, 10 / LD V1, 20 / LD I, 0x218 / PLANE 1 / DRW V0, V0, 1 /
+        // PLANE 2 / DRW V1, V0, 1 / SCU 3 / PLANE 3 / SCU 3 / JP 0x216 / sprite
+        for resolution in [0x00FE, 0x00FF] {
+            let mut cpu = cpu_on(
+                dispatch,
+                Platform::XoChip,
+                &[
+                    resolution, 0x600A, 0x6114, 0xA218, 0xF101, 0xD001, 0xF201, 0xD101, 0x00D3,
+                    0xF301, 0x00D3, 0x1216, 0x8000,
+                ],
+            );
+            let pixels = |cpu: &CPU| cpu.display.iter_pixels().collect::<Vec<_>>();
+            cpu.exec_cycles(8).unwrap();
+            assert_eq!(pixels(&cpu), [(10, 10, 1), (20, 10, 2)]);
+            cpu.exec_cycle().unwrap();
+            assert_eq!(pixels(&cpu), [(20, 7, 2), (10, 10, 1)]);
+            cpu.exec_cycles(2).unwrap();
+            assert_eq!(pixels(&cpu), [(20, 4, 2), (10, 7, 1)]);
+        }
+    }
+
+    fn audio_opcodes_load_the_pattern_and_pitch(dispatch: Dispatch) {
+        // LD I, 0x208 / LD AUDIO, [I] / LD V0, 112 / LD PITCH, V0 / 16 byte pattern
+        let mut program = vec![0xA208, 0xF002, 0x6070, 0xF03A];
+        program.extend((0..8).map(|k| 0x0102 * k));
+        let mut cpu = cpu_on(dispatch, Platform::XoChip, &program);
+        assert_eq!(cpu.audio, Audio::new());
+        cpu.exec_cycles(4).unwrap();
+        let pattern: Vec<u8> = words(&program[4..]);
+        assert_eq!(cpu.audio.pattern.map(|p| p.to_vec()), Some(pattern));
+        assert_eq!(cpu.audio.pitch, 112);
+        assert_eq!(cpu.audio.bit_rate(), 8000.0);
+    }
+
+    fn register_ranges_go_either_direction(dispatch: Dispatch) {
+        // LD I, 0x300 / LD [I], V1-V3 / LD I, 0x310 / LD [I], V3-V1 / LD I, 0x320 /
+        // LD [I], V2-V2 / LD I, 0x300 / LD VA-V8, [I]
+        let mut cpu = cpu_on(
+            dispatch,
+            Platform::XoChip,
+            &[
+                0xA300, 0x5132, 0xA310, 0x5312, 0xA320, 0x5222, 0xA300, 0x5A83,
+            ],
+        );
+        cpu.v[1..4].copy_from_slice(&[0x11, 0x22, 0x33]);
+        cpu.exec_cycles(8).unwrap();
+        assert_eq!(cpu.read_mem(0x300..0x304).unwrap(), [0x11, 0x22, 0x33, 0]);
+        assert_eq!(cpu.read_mem(0x310..0x314).unwrap(), [0x33, 0x22, 0x11, 0]);
+        assert_eq!(cpu.read_mem(0x320..0x322).unwrap(), [0x22, 0]);
+        assert_eq!(cpu.v[8..11], [0x33, 0x22, 0x11]);
+        assert_eq!(cpu.i, 0x300);
+    }
+
+    fn register_ranges_past_memory_fault(dispatch: Dispatch) {
+        // LD I, long 0xFFFE / LD V0-V3, [I]
+        let mut cpu = cpu_on(dispatch, Platform::XoChip, &[0xF000, 0xFFFE, 0x5033]);
+        cpu.v[0] = 0x42;
+        cpu.exec_cycle().unwrap();
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0x10000,
+                pc: 0x204,
+                opcode: 0x5033
+            })
+        );
+        // Nothing was loaded
+        assert_eq!(cpu.v[0], 0x42);
+    }
+
+    fn register_ranges_are_xo_chip_only(dispatch: Dispatch) {
+        let mut cpu = cpu_on(dispatch, Platform::SuperChip, &[0x5132]);
+        assert_eq!(
+            cpu.exec_cycle(),
+            Err(Chip8Error::UnknownOpcode {
+                opcode: 0x5132,
+                pc: 0x200
+            })
+        );
+    }
+
+    fn xo_chip_runs_roms_past_4k(dispatch: Dispatch) {
+        // LD I, long 0x1400 / LD V2, [I] / JP 0x206, then data 4.5K into the ROM
+        let mut rom = words(&[0xF000, 0x1400, 0xF265, 0x1206]);
+        rom.resize(0x1800, 0);
+        rom[0x1200..0x1203].copy_from_slice(&[0xAA, 0xBB, 0xCC]);
+
+        let mut cpu = CPU::builder()
+            .dispatch(dispatch)
+            .platform(Platform::XoChip)
+            .build();
+        assert_eq!(cpu.load_rom_bytes(&rom), Ok(0x1800));
+        cpu.exec_cycles(3).unwrap();
+        assert_eq!(cpu.v[..3], [0xAA, 0xBB, 0xCC]);
+        assert_eq!(cpu.i, 0x1403);
+
+        let mut cpu = CPU::builder().dispatch(dispatch).build();
+        assert_eq!(
+            cpu.load_rom_bytes(&rom),
+            Err(Chip8Error::RomLoad(RomLoadError::TooLarge {
+                size: 0x1800,
+                max: 0xE00
+            }))
+        );
+    }
+
+    fn half_pixel_scroll_quirk_halves_lores_scrolls(dispatch: Dispatch) {
+        // LD I, 0x20A / DRW V0, V0, 2 / SCD 1 / SCD 2 / SCR / 0x80 0x40
+        let scroll = |half| {
+            let mut cpu = CPU::builder()
+                .dispatch(dispatch)
+                .platform(Platform::SuperChip)
+                .quirk(Quirk::ScrollHalfPixelLores, half)
+                .build();
+            cpu.load_rom_bytes(&words(&[0xA20A, 0xD002, 0x00C1, 0x00C2, 0x00FB, 0x8040]))
+                .unwrap();
+            cpu.exec_cycles(2).unwrap();
+            (0..3)
+                .map(|_| {
+                    cpu.exec_cycle().unwrap();
+                    lit(&cpu)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            scroll(false),
+            [
+                vec![(0, 1), (1, 2)],
+                vec![(0, 3), (1, 4)],
+                vec![(4, 3), (5, 4)],
+            ]
+        );
+        // Half of one low resolution pixel rounds down to nothing
+        assert_eq!(
+            scroll(true),
+            [
+                vec![(0, 0), (1, 1)],
+                vec![(0, 1), (1, 2)],
+                vec![(2, 1), (3, 2)],
+            ]
+        );
+    }
+
+    fn hires_chip8_draws_below_row_32(dispatch: Dispatch) {
+        // JP 0x260, then at 0x2C0: LD V0, 40 / LD V2, 100 / LD I, 0x2CC / DRW V1, V0, 1 /
+        // DRW V1, V2, 1 / JP 0x2CA / 0x80
+        let mut rom = words(&[0x1260]);
+        rom.resize(0xC0, 0);
+        rom.extend(words(&[
+            0x6028, 0x6264, 0xA2CC, 0xD101, 0xD121, 0x12CA, 0x8000,
+        ]));
+        for platform in [Platform::Chip8, Platform::HiresChip8] {
+            let mut cpu = CPU::builder().dispatch(dispatch).platform(platform).build();
+            cpu.load_rom_bytes(&rom).unwrap();
+            cpu.quirks.display_wait = false;
+            cpu.exec_cycle().unwrap();
+            assert_eq!(cpu.pc, 0x2C0);
+            assert_eq!((cpu.display.width(), cpu.display.height()), (64, 64));
+            assert_eq!(cpu.exec_cycles(10), Ok(6));
+            // y = 100 wraps at 64 rows, not 32
+            assert_eq!(lit(&cpu), [(0, 36), (0, 40)]);
+        }
+    }
+
+    fn eti660_has_48_rows_and_starts_at_0x600(dispatch: Dispatch) {
+        let mut cpu = CPU::builder()
+            .dispatch(dispatch)
+            .platform(Platform::Eti660)
+            .build();
+        assert_eq!(cpu.pc, 0x600);
+        assert_eq!((cpu.display.width(), cpu.display.height()), (64, 48));
+        // LD V0, 40 / LD V2, 60 / LD I, 0x60C / DRW V1, V0, 1 / DRW V1, V2, 1 / JP 0x60A / 0x80
+        let program = words(&[0x6028, 0x623C, 0xA60C, 0xD101, 0xD121, 0x160A, 0x8000]);
+        cpu.load_rom_bytes(&program).unwrap();
+        cpu.quirks.display_wait = false;
+        assert_eq!(cpu.exec_cycles(10), Ok(6));
+        // y = 60 wraps at 48 rows
+        assert_eq!(lit(&cpu), [(0, 12), (0, 40)]);
+        cpu.reset();
+        assert_eq!(cpu.pc, 0x600);
+    }
+
+    fn key_skips_follow_the_keyboard(dispatch: Dispatch) {
+        // SKP V0 / LD V1, 1 / SKNP V0 / LD V2, 1
+        let skips = |pressed: &[u8]| {
+            let mut cpu = cpu_with(dispatch, &[0xE09E, 0x6101, 0xE0A1, 0x6201]);
+            cpu.v[0] = 0xB;
+            cpu.keyboard.set_pressed(pressed).unwrap();
+            cpu.exec_cycles(3).unwrap();
+            (cpu.v[1], cpu.v[2])
+        };
+        assert_eq!(skips(&[0xB]), (0, 1));
+        assert_eq!(skips(&[0xA, 0xC]), (1, 0));
+        assert_eq!(skips(&[]), (1, 0));
+    }
+
+    fn builder_settings_reach_the_cpu(dispatch: Dispatch) {
+        let configured = || {
+            CPU::builder()
+                .dispatch(dispatch)
+                .platform(Platform::SuperChip)
+                .quirk(Quirk::JumpUsesVx, false)
+                .memory_size(MemorySize::Large)
+                .font(FontSet::Eti660)
+                .load_address(0x300)
+                .seed(42)
+                .build()
+        };
+        let mut cpu = configured();
+        assert_eq!(cpu.platform, Platform::SuperChip);
+        assert_eq!(
+            cpu.quirks,
+            Quirks {
+                jump_uses_vx: false,
+                ..Quirks::superchip_modern()
+            }
+        );
+        assert_eq!(cpu.memory_size(), MemorySize::Large);
+        assert_eq!(cpu.pc, 0x300);
+        assert_eq!(cpu.read_mem(0..80).unwrap(), fonts::ETI_660);
+
+        let plain = CPU::builder().dispatch(dispatch).build();
+        assert_eq!(plain.platform, Platform::Chip8);
+        assert_eq!(plain.quirks, Quirks::none());
+        assert_eq!(plain.memory_size(), MemorySize::Standard);
+        assert_eq!(plain.pc, 0x200);
+        assert_eq!(plain.read_mem(0..80).unwrap(), fonts::STANDARD);
+
+        // LD V3, 4 / JP V0, 0x310, which adds V3 instead of V0 with the jump quirk
+        let jump = words(&[0x6304, 0xB310]);
+        let mut schip = CPU::builder()
+            .dispatch(dispatch)
+            .platform(Platform::SuperChip)
+            .load_address(0x300)
+            .build();
+        for (cpu, target) in [(&mut cpu, 0x310), (&mut schip, 0x314)] {
+            cpu.load_rom_bytes(&jump).unwrap();
+            cpu.exec_cycles(2).unwrap();
+            assert_eq!(cpu.pc, target);
+        }
+
+        // The same seed gives the same RND results
+        let rnd = words(&[0xC0FF]);
+        cpu.load_rom_bytes(&rnd).unwrap();
+        cpu.exec_cycle().unwrap();
+        let mut again = configured();
+        again.load_rom_bytes(&rnd).unwrap();
+        again.exec_cycle().unwrap();
+        assert_eq!(cpu.v[0], again.v[0]);
+    }
+
+    #[test]
+    fn peek_and_poke_validate_their_arguments() {
+        let mut cpu = CPU::builder().build();
+        assert_eq!(cpu.register(16), Err(Chip8Error::InvalidRegister { x: 16 }));
+        assert_eq!(
+            cpu.set_register(20, 1),
+            Err(Chip8Error::InvalidRegister { x: 20 })
+        );
+        assert_eq!(
+            cpu.set_index(0x1000),
+            Err(Chip8Error::AddressOutOfRange {
+                addr: 0x1000,
+                len: 1
+            })
+        );
+        assert_eq!(
+            cpu.set_program_counter(0xFFF),
+            Err(Chip8Error::AddressOutOfRange {
+                addr: 0xFFF,
+                len: 2
+            })
+        );
+        assert_eq!(
+            cpu.read_mem(0xFF0..0x1001),
+            Err(Chip8Error::AddressOutOfRange {
+                addr: 0xFF0,
+                len: 0x11
+            })
+        );
+        // Nothing is written when part of the data doesn't fit
+        assert_eq!(
+            cpu.write_mem(0xFFE, &[1, 2, 3]),
+            Err(Chip8Error::AddressOutOfRange {
+                addr: 0xFFE,
+                len: 3
+            })
+        );
+        assert_eq!(cpu.read_mem(0xFFE..0x1000).unwrap(), [0, 0]);
+        assert_eq!((cpu.index(), cpu.program_counter()), (0, 0x200));
+    }
+
+    fn poke_an_opcode_and_peek_at_its_effect(dispatch: Dispatch) {
+        let mut cpu = CPU::builder().dispatch(dispatch).build();
+        // LD F, V3 / DRW V0, V0, 5
+        cpu.write_mem(0x400, &[0xF3, 0x29, 0xD0, 0x05]).unwrap();
+        cpu.set_program_counter(0x400).unwrap();
+        cpu.set_register(3, 0xA).unwrap();
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(cpu.index(), 50);
+        assert_eq!(cpu.program_counter(), 0x404);
+        assert_eq!(cpu.register(0xF), Ok(0));
+        let framebuffer = cpu.framebuffer();
+        // The top row of A is 0xF0
+        assert_eq!(framebuffer[..5], [1, 1, 1, 1, 0]);
+        assert_eq!(framebuffer.iter().filter(|&&color| color != 0).count(), 14);
+    }
+
+    fn sound_callback_fires_on_st_changes(dispatch: Dispatch) {
+        // LD V0, 2 / LD ST, V0 / LD ST, V0
+        let mut cpu = cpu_with(dispatch, &[0x6002, 0xF018, 0xF018]);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = events.clone();
+        cpu.on_sound(move |on| seen.borrow_mut().push(on));
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(*events.borrow(), [true]);
+        cpu.update_timers();
+        // Setting ST again while it's running isn't a change
+        cpu.exec_cycle().unwrap();
+        cpu.update_timers();
+        assert_eq!(*events.borrow(), [true]);
+        cpu.update_timers();
+        assert_eq!(*events.borrow(), [true, false]);
+        cpu.update_timers();
+        assert_eq!(*events.borrow(), [true, false]);
+    }
+
+    fn display_callback_fires_on_screen_changes(dispatch: Dispatch) {
+        // LD V0, 1 / DRW V0, V0, 1 with I on the top row of 0 / CLS / SCD 1
+        let mut cpu = cpu_on(
+            dispatch,
+            Platform::SuperChip,
+            &[0x6001, 0xD001, 0x00E0, 0x00C1],
+        );
+        let lit_counts = Rc::new(RefCell::new(Vec::new()));
+        let seen = lit_counts.clone();
+        cpu.on_display_updated(move |display| {
+            seen.borrow_mut().push(display.iter_pixels().count())
+        });
+        cpu.exec_cycle().unwrap();
+        assert!(lit_counts.borrow().is_empty());
+        cpu.exec_cycles(3).unwrap();
+        assert_eq!(*lit_counts.borrow(), [4, 0, 0]);
+    }
+
+    // Run BRIX for `n` cycles from cycle `start`, holding key 6 for a while and ticking the
+    // timers every 10 cycles
+    fn run_brix(cpu: &mut CPU, start: u32, n: u32) {
+        for cycle in start..start + n {
+            match cycle {
+                120 => cpu.key_down(6).unwrap(),
+                160 => cpu.key_up(6).unwrap(),
+                _ => {}
+            }
+            cpu.exec_cycle().unwrap();
+            if cycle % 10 == 9 {
+                cpu.update_timers();
+            }
+        }
+    }
+
+    #[test]
+    fn restore_replays_identically() {
+        let mut cpu = CPU::builder().seed(3).build();
+        cpu.load_rom_bytes(include_bytes!("../roms/BRIX")).unwrap();
+        run_brix(&mut cpu, 0, 100);
+        let snapshot = cpu.snapshot();
+        run_brix(&mut cpu, 100, 100);
+        let memory = cpu.read_mem(0..0x1000).unwrap().to_vec();
+        let framebuffer = cpu.framebuffer();
+        assert_ne!(cpu.snapshot(), snapshot);
+
+        cpu.restore(&snapshot);
+        assert_eq!(cpu.snapshot(), snapshot);
+        run_brix(&mut cpu, 100, 100);
+        assert_eq!(cpu.read_mem(0..0x1000).unwrap(), memory);
+        assert_eq!(cpu.framebuffer(), framebuffer);
+    }
+
+    #[test]
+    fn clones_leave_the_callbacks_behind() {
+        let mut cpu = CPU::builder().seed(3).build();
+        cpu.load_rom_bytes(include_bytes!("../roms/BRIX")).unwrap();
+        let calls = Rc::new(RefCell::new(0));
+        let seen = calls.clone();
+        cpu.on_instruction(move |_, _| *seen.borrow_mut() += 1);
+        run_brix(&mut cpu, 0, 100);
+        let mut clone = cpu.clone();
+        assert_eq!(clone.snapshot(), cpu.snapshot());
+        run_brix(&mut clone, 100, 100);
+        assert_eq!(*calls.borrow(), 100);
+        run_brix(&mut cpu, 100, 100);
+        assert_eq!(*calls.borrow(), 200);
+        assert_eq!(clone.snapshot(), cpu.snapshot());
+    }
+
+    fn scripted_keys_reach_skp_sknp_and_fx0a(dispatch: Dispatch) {
+        // SKP V0 / ADD V1, 1 / SKNP V0 / ADD V2, 1 / LD V3, K / SKP V0 / ADD V1, 1
+        let mut cpu = cpu_with(
+            dispatch,
+            &[0xE09E, 0x7101, 0xE0A1, 0x7201, 0xF30A, 0xE09E, 0x7101],
+        );
+        cpu.v[0] = 5;
+        cpu.key_down(5).unwrap();
+        assert_eq!(cpu.exec_cycles(3), Ok(3));
+        assert_eq!((cpu.v[1], cpu.v[2]), (0, 1));
+        // Holding the key doesn't complete LD V3, K
+        for _ in 0..3 {
+            assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForKey));
+        }
+        cpu.key_up(5).unwrap();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(cpu.v[3], 5);
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        assert_eq!(cpu.v[1], 1);
+
+        assert_eq!(
+            cpu.key_down(0x10),
+            Err(Chip8Error::InvalidKey { key: 0x10 })
+        );
+        assert_eq!(cpu.key_up(0xFF), Err(Chip8Error::InvalidKey { key: 0xFF }));
+    }
+
+    fn ascii_shows_the_glyph_a(dispatch: Dispatch) {
+        // LD V0, 0xA / LD F, V0 / LD V1, 2 / DRW V1, V1, 5
+        let mut cpu = cpu_with(dispatch, &[0x600A, 0xF029, 0x6102, 0xD115]);
+        cpu.exec_cycles(4).unwrap();
+        let ascii = cpu.display.to_ascii();
+        assert_eq!(ascii.lines().count(), 32);
+        assert!(ascii.lines().all(|line| line.len() == 64));
+        let corner: Vec<&str> = ascii.lines().take(8).map(|line| &line[..8]).collect();
+        assert_eq!(
+            corner.join("\n"),
+            "........\n\
+             ........\n\
+             ..####..\n\
+             ..#..#..\n\
+             ..####..\n\
+             ..#..#..\n\
+             ..#..#..\n\
+             ........"
+        );
+        assert_eq!(ascii.matches('#').count(), 14);
+    }
+
+    fn breakpoints_stop_before_the_instruction(dispatch: Dispatch) {
+        // LD V0, 1 / LD V1, 2 / LD V2, 3 / JP 0x206
+        let mut cpu = cpu_with(dispatch, &[0x6001, 0x6102, 0x6203, 0x1206]);
+        cpu.add_breakpoint(0x202);
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!(
+            cpu.exec_cycle(),
+            Ok(CycleState::BreakpointHit { addr: 0x202 })
+        );
+        assert_eq!((cpu.pc, cpu.v[0], cpu.v[1]), (0x202, 1, 0));
+        // Stepping off the breakpoint runs the instruction under it
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        assert_eq!((cpu.pc, cpu.v[1]), (0x204, 2));
+        cpu.remove_breakpoint(0x202);
+        assert_eq!(cpu.breakpoints().count(), 0);
+    }
+
+    fn breakpoints_stop_again_on_the_next_pass(dispatch: Dispatch) {
+        // ADD V0, 1 / JP 0x200
+        let mut cpu = cpu_with(dispatch, &[0x7001, 0x1200]);
+        cpu.add_breakpoint(0x200);
+        assert_eq!(
+            cpu.exec_cycle(),
+            Ok(CycleState::BreakpointHit { addr: 0x200 })
+        );
+        for pass in 1..4 {
+            // ADD and JP, then the breakpoint ends the batch
+            assert_eq!(cpu.exec_cycles(10), Ok(2));
+            assert_eq!((cpu.pc, cpu.v[0]), (0x200, pass));
+        }
+    }
+
+    fn fx55_over_a_watched_range_reports_the_first_write(dispatch: Dispatch) {
+        // LD I, 0x300 / LD V0, 1 / LD V1, 2 / LD V2, 3 / LD [I], V2
+        let mut cpu = cpu_with(dispatch, &[0xA300, 0x6001, 0x6102, 0x6203, 0xF255]);
+        cpu.add_watchpoint(0x301..=0x30F, WatchKind::Write);
+        cpu.add_watchpoint(0x302..=0x302, WatchKind::ReadWrite);
+        assert_eq!(cpu.exec_cycles(4), Ok(4));
+        let hit = WatchpointHit {
+            addr: 0x301,
+            kind: WatchKind::Write,
+            pc: 0x208,
+            old: 0,
+            new: 2,
+        };
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WatchpointHit(hit)));
+        // The instruction finished
+        assert_eq!(cpu.pc, 0x20A);
+        assert_eq!(cpu.read_mem(0x300..0x303).unwrap(), [1, 2, 3]);
+    }
+
+    fn dxyn_reading_a_watched_sprite_reports_a_read(dispatch: Dispatch) {
+        // LD I, 0x206 / DRW V0, V0, 1 / JP 0x204 / a sprite row
+        let mut cpu = cpu_with(dispatch, &[0xA206, 0xD001, 0x1204, 0xF000]);
+        cpu.quirks.display_wait = false;
+        // Writes alone don't catch reads, and the interpreter area can be watched too
+        cpu.add_watchpoint(0x206..=0x206, WatchKind::Write);
+        cpu.add_watchpoint(0x000..=0x1FF, WatchKind::Read);
+        assert_eq!(cpu.exec_cycles(2), Ok(2));
+        cpu.add_watchpoint(0x206..=0x207, WatchKind::Read);
+        cpu.pc = 0x202;
+        let hit = WatchpointHit {
+            addr: 0x206,
+            kind: WatchKind::Read,
+            pc: 0x202,
+            old: 0xF0,
+            new: 0xF0,
+        };
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WatchpointHit(hit)));
+        cpu.remove_watchpoint(0x206..=0x207);
+        cpu.pc = 0x202;
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::DrewSprite));
+        // LD F, V0 / DRW V0, V0, 5 reads the font
+        let mut cpu = cpu_with(dispatch, &[0xF029, 0xD005]);
+        cpu.quirks.display_wait = false;
+        cpu.add_watchpoint(0x000..=0x1FF, WatchKind::Read);
+        cpu.exec_cycle().unwrap();
+        assert!(matches!(cpu.exec_cycle(), Ok(CycleState::WatchpointHit(_))));
+    }
+
+    // The tests above that run instructions take the dispatch backend, and run once with each
+    macro_rules! on_both_backends {
+        ($($test:ident),* $(,)?) => {
+            mod match_backend {
+                $(
+                    #[test]
+                    fn $test() {
+                        super::$test(super::Dispatch::Match)
+                    }
+                )*
+            }
+            mod table_backend {
+                $(
+                    #[test]
+                    fn $test() {
+                        super::$test(super::Dispatch::Table)
+                    }
+                )*
+            }
+        };
+    }
+
+    on_both_backends! {
+        opcode_at_end_of_64k_memory_wraps_pc,
+        coverage_only_counts_instructions_that_run,
+        history_keeps_paused_instruction_once,
+        load_store_leaves_or_increments_i,
+        load_store_ending_on_last_byte_wraps_i,
+        exec_cycles_stops_at_key_wait,
+        exec_cycles_stops_after_halt_and_errors,
+        draw_reads_exactly_n_bytes,
+        draw_at_end_of_memory,
+        jump_offset_adds_v0_or_vx,
+        jump_offset_past_memory_is_an_error,
+        logic_ops_reset_vf_with_the_quirk,
+        sprite_at_edge_clips_or_wraps,
+        sprite_start_wraps_in_both_modes,
+        add_i_wraps_and_optionally_sets_vf,
+        load_font_uses_the_low_nibble,
+        ret_on_empty_stack_is_an_error,
+        seventeenth_nested_call_overflows,
+        pc_past_memory_errors_or_wraps,
+        running_off_the_end_errors_or_wraps,
+        unknown_opcodes_leave_the_cpu_as_it_was,
+        seeded_cpus_agree_on_rnd,
+        sys_calls_are_skipped_or_rejected,
+        cls_and_ret_are_not_sys_calls,
+        display_wait_draws_once_per_tick,
+        display_wait_stalls_on_the_draw,
+        vf_ends_up_holding_the_flag,
+        vf_as_operand_is_read_before_the_flag,
+        bcd_digits,
+        bcd_past_memory_is_an_error,
+        store_and_load_all_registers_round_trip,
+        store_and_load_past_memory_are_errors,
+        add_i_past_4k_wraps_to_the_start,
+        exec_cycle_reports_what_happened,
+        on_instruction_sees_each_pc_and_opcode,
+        execute_matches_exec_cycle,
+        machine_cycles_follow_the_vip_table,
+        self_jump_halts,
+        delay_timer_loop_is_not_a_halt,
+        stats_count_instructions_draws_and_ticks,
+        stats_leave_out_stalled_cycles,
+        skips_step_over_long_loads_on_xo_chip,
+        ret_at_reset_leaves_the_cpu_for_inspection,
+        call_and_ret_pair_up,
+        deeper_stack_allows_deeper_nesting,
+        soft_reset_keeps_the_rom,
+        load_rom_bytes_runs_an_embedded_rom,
+        memory_faults_name_the_instruction,
+        state_dump,
+        interpreter_area_protection,
+        reads_of_unwritten_memory_are_reported_once,
+        unwritten_reads_go_unreported_without_the_check,
+        memory_observer_sees_each_byte_written,
+        delay_timer_poll_is_idle,
+        loops_doing_more_than_reading_dt_are_not_idle,
+        injected_random_source_gives_identical_traces,
+        run_frame_reports_what_the_frame_did,
+        run_frame_stops_at_the_instruction_budget,
+        run_frame_stops_for_the_debugger_without_a_timer_tick,
+        hooks_see_each_instruction_before_and_after,
+        pause_skips_the_instruction,
+        hires_mode_draws_past_column_64,
+        resolution_switch_can_keep_the_display,
+        lores_programs_draw_the_same_on_super_chip,
+        dxy0_draws_16x16_at_the_right_edge,
+        dxy0_on_chip8_follows_the_quirk,
+        scrolls_move_the_screen,
+        scrolling_off_the_edge_blanks_the_pixels,
+        exit_halts_on_the_instruction,
+        user_flags_round_trip_registers,
+        big_font_draws_ten_row_digits,
+        legacy_and_modern_super_chip_differ,
+        hires_vf_counts_rows_off_the_bottom,
+        planes_give_four_colors,
+        chip8_draws_on_the_first_plane,
+        long_index_load_reads_the_next_word,
+        scroll_up_moves_the_selected_planes,
+        audio_opcodes_load_the_pattern_and_pitch,
+        register_ranges_go_either_direction,
+        register_ranges_past_memory_fault,
+        register_ranges_are_xo_chip_only,
+        xo_chip_runs_roms_past_4k,
+        half_pixel_scroll_quirk_halves_lores_scrolls,
+        hires_chip8_draws_below_row_32,
+        eti660_has_48_rows_and_starts_at_0x600,
+        key_skips_follow_the_keyboard,
+        builder_settings_reach_the_cpu,
+        poke_an_opcode_and_peek_at_its_effect,
+        sound_callback_fires_on_st_changes,
+        display_callback_fires_on_screen_changes,
+        scripted_keys_reach_skp_sknp_and_fx0a,
+        ascii_shows_the_glyph_a,
+        breakpoints_stop_before_the_instruction,
+        breakpoints_stop_again_on_the_next_pass,
+        fx55_over_a_watched_range_reports_the_first_write,
+        dxyn_reading_a_watched_sprite_reports_a_read,
+    }
+}
@@ -0,0 +1,333 @@
+use std::fmt::Write;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use crate::bus::Bus;
+use crate::condition::parse_number;
+use crate::disasm;
+use crate::emulator::Emulator;
+
+// Bytes per line of a hex dump
+const DUMP_WIDTH: usize = 16;
+
+// What `set` can change
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetTarget {
+    // V0 to VF
+    Register(u8),
+    Index,
+    ProgramCounter,
+    DelayTimer,
+    SoundTimer,
+}
+
+// A line typed at the debugger prompt, see DebugCommand::parse
+#[derive(Clone, Debug, PartialEq)]
+pub enum DebugCommand {
+    // s [N], run N instructions (default 1) and pause
+    Step(u32),
+    // c, resume running
+    Continue,
+    // b ADDR, add a breakpoint
+    Break(u16),
+    // p, print the registers
+    Print,
+    // x ADDR [LEN], hex dump LEN bytes (default 16)
+    Dump { addr: u16, len: u16 },
+    // set TARGET VALUE, where TARGET is v0-vf, i, pc, dt or st
+    Set { target: SetTarget, value: u16 },
+    // disasm ADDR [COUNT], disassemble COUNT instructions (default 16)
+    Disasm { addr: u16, count: u16 },
+    // q, quit the emulator
+    Quit,
+}
+
+impl DebugCommand {
+    // Numbers are decimal, or hex with 0x
+    pub fn parse(line: &str) -> Result<DebugCommand, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("empty command")?;
+        let args: Vec<&str> = words.collect();
+        let number = |k: usize, default: Option<u16>| match args.get(k) {
+            Some(arg) => parse_number(arg).ok_or_else(|| format!("{} isn't a number", arg)),
+            None => default.ok_or_else(|| format!("{} needs more arguments", name)),
+        };
+        let max_args = match name {
+            "s" | "step" | "b" | "break" => 1,
+            "x" | "set" | "disasm" => 2,
+            _ => 0,
+        };
+        if args.len() > max_args {
+            return Err(format!("too many arguments for {}", name));
+        }
+        let command = match name {
+            "s" | "step" => DebugCommand::Step(number(0, Some(1))? as u32),
+            "c" | "continue" => DebugCommand::Continue,
+            "b" | "break" => DebugCommand::Break(number(0, None)?),
+            "p" | "print" => DebugCommand::Print,
+            "x" => DebugCommand::Dump {
+                addr: number(0, None)?,
+                len: number(1, Some(DUMP_WIDTH as u16))?,
+            },
+            "set" => DebugCommand::Set {
+                target: parse_target(args.first().copied().unwrap_or_default())?,
+                value: number(1, None)?,
+            },
+            "disasm" => DebugCommand::Disasm {
+                addr: number(0, None)?,
+                count: number(1, Some(16))?,
+            },
+            "q" | "quit" => DebugCommand::Quit,
+            _ => return Err(format!("unknown command {}", name)),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_target(name: &str) -> Result<SetTarget, String> {
+    let lower = name.to_ascii_lowercase();
+    match lower.as_str() {
+        "i" => Ok(SetTarget::Index),
+        "pc" => Ok(SetTarget::ProgramCounter),
+        "dt" => Ok(SetTarget::DelayTimer),
+        "st" => Ok(SetTarget::SoundTimer),
+        _ if lower.len() == 2 && lower.starts_with('v') => u8::from_str_radix(&lower[1..], 16)
+            .map(SetTarget::Register)
+            .map_err(|_| format!("{} isn't v0-vf, i, pc, dt or st", name)),
+        _ => Err(format!("{} isn't v0-vf, i, pc, dt or st", name)),
+    }
+}
+
+// Parse the lines typed on stdin on a thread of their own, so the frontend keeps running while
+// it waits. Lines that don't parse are reported on stdout and dropped.
+pub fn read_commands() -> Receiver<DebugCommand> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match DebugCommand::parse(&line) {
+                Ok(command) => {
+                    if sender.send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => println!("error: {}", e),
+            }
+        }
+    });
+    receiver
+}
+
+// Carry out a command other than Quit and return what it prints, one or more lines
+pub fn execute<B: Bus>(command: &DebugCommand, emulator: &mut Emulator<B>) -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = run_command(command, emulator, &mut out);
+    out
+}
+
+fn run_command<B: Bus>(
+    command: &DebugCommand,
+    emulator: &mut Emulator<B>,
+    out: &mut String,
+) -> std::fmt::Result {
+    let cpu = &mut emulator.cpu;
+    match *command {
+        DebugCommand::Step(n) => {
+            emulator.pause();
+            for _ in 0..n {
+                emulator.step();
+            }
+            // The queued steps run right away, without any time passing for the timers
+            let output = emulator.tick(Duration::ZERO);
+            if let Some(addr) = output.breakpoint {
+                writeln!(out, "Breakpoint at {:#05X}", addr)?;
+            }
+            if let Some(hit) = output.watchpoint {
+                writeln!(out, "Watchpoint: {}", hit)?;
+            }
+            if let Some(e) = output.error {
+                writeln!(out, "error: {}", e)?;
+            }
+            writeln!(out, "{}", emulator.cpu)?;
+        }
+        DebugCommand::Continue => {
+            emulator.resume();
+            writeln!(out, "Continuing")?;
+        }
+        DebugCommand::Break(addr) => {
+            cpu.add_breakpoint(addr);
+            writeln!(out, "Breakpoint set at {:#05X}", addr)?;
+        }
+        DebugCommand::Print => writeln!(out, "{}", cpu)?,
+        DebugCommand::Dump { addr, len } => {
+            let start = addr as usize;
+            match cpu.read_mem(start..start + len as usize) {
+                Ok(bytes) => {
+                    for (line, chunk) in bytes.chunks(DUMP_WIDTH).enumerate() {
+                        write!(out, "{:#05X}:", start + line * DUMP_WIDTH)?;
+                        for byte in chunk {
+                            write!(out, " {:02X}", byte)?;
+                        }
+                        writeln!(out)?;
+                    }
+                }
+                Err(e) => writeln!(out, "error: {}", e)?,
+            }
+        }
+        DebugCommand::Set { target, value } => {
+            let result = match target {
+                SetTarget::Register(x) => cpu.set_register(x as usize, value as u8),
+                SetTarget::Index => cpu.set_index(value),
+                SetTarget::ProgramCounter => cpu.set_program_counter(value),
+                SetTarget::DelayTimer => {
+                    cpu.dt = value as u8;
+                    Ok(())
+                }
+                SetTarget::SoundTimer => {
+                    cpu.st = value as u8;
+                    Ok(())
+                }
+            };
+            match result {
+                Ok(()) => writeln!(out, "{}", cpu)?,
+                Err(e) => writeln!(out, "error: {}", e)?,
+            }
+        }
+        DebugCommand::Disasm { addr, count } => {
+            let lines =
+                disasm::disassemble_range_for(cpu.bus.ram(), addr, count as usize, cpu.platform);
+            for (at, opcode, text) in lines {
+                writeln!(out, "{:#05X}: {:#06X}  {}", at, opcode, text)?;
+            }
+        }
+        DebugCommand::Quit => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    fn emulator(program: &[u8]) -> Emulator {
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(program).unwrap();
+        Emulator::new(cpu, 600)
+    }
+
+    #[test]
+    fn parse_reads_every_command() {
+        let parse = |line| DebugCommand::parse(line).unwrap();
+        assert_eq!(parse("s"), DebugCommand::Step(1));
+        assert_eq!(parse("step 10"), DebugCommand::Step(10));
+        assert_eq!(parse("c"), DebugCommand::Continue);
+        assert_eq!(parse("b 0x2F0"), DebugCommand::Break(0x2F0));
+        assert_eq!(parse("p"), DebugCommand::Print);
+        assert_eq!(
+            parse("x 0x300 32"),
+            DebugCommand::Dump {
+                addr: 0x300,
+                len: 32
+            }
+        );
+        assert_eq!(
+            parse("x 0x300"),
+            DebugCommand::Dump {
+                addr: 0x300,
+                len: 16
+            }
+        );
+        assert_eq!(
+            parse("set V3 0x1f"),
+            DebugCommand::Set {
+                target: SetTarget::Register(3),
+                value: 0x1F
+            }
+        );
+        assert_eq!(
+            parse("set pc 0x200"),
+            DebugCommand::Set {
+                target: SetTarget::ProgramCounter,
+                value: 0x200
+            }
+        );
+        assert_eq!(
+            parse("  disasm 0x200 16 "),
+            DebugCommand::Disasm {
+                addr: 0x200,
+                count: 16
+            }
+        );
+        assert_eq!(parse("q"), DebugCommand::Quit);
+    }
+
+    #[test]
+    fn parse_explains_bad_lines() {
+        let error = |line| DebugCommand::parse(line).unwrap_err();
+        assert_eq!(error(""), "empty command");
+        assert_eq!(error("jump"), "unknown command jump");
+        assert_eq!(error("b"), "b needs more arguments");
+        assert_eq!(error("b 0xZZ"), "0xZZ isn't a number");
+        assert_eq!(error("p 1"), "too many arguments for p");
+        assert_eq!(error("set vg 1"), "vg isn't v0-vf, i, pc, dt or st");
+    }
+
+    #[test]
+    fn step_pauses_and_stops_at_breakpoints() {
+        // LD V0, 1 / LD V1, 2 / LD V2, 3 / JP 0x206
+        let mut emulator = emulator(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x06]);
+        let out = execute(&DebugCommand::Break(0x204), &mut emulator);
+        assert_eq!(out, "Breakpoint set at 0x204\n");
+        let out = execute(&DebugCommand::Step(5), &mut emulator);
+        assert!(emulator.is_paused());
+        assert!(out.starts_with("Breakpoint at 0x204\nPC: 0x204"));
+        assert_eq!((emulator.cpu.v[1], emulator.cpu.v[2]), (2, 0));
+        assert_eq!(
+            execute(&DebugCommand::Continue, &mut emulator),
+            "Continuing\n"
+        );
+        assert!(!emulator.is_paused());
+    }
+
+    #[test]
+    fn dump_set_and_disasm_print_stable_lines() {
+        let mut emulator = emulator(&[0x60, 0x01, 0x12, 0x02]);
+        let dump = DebugCommand::Dump {
+            addr: 0x1FE,
+            len: 20,
+        };
+        assert_eq!(
+            execute(&dump, &mut emulator),
+            "0x1FE: 00 00 60 01 12 02 00 00 00 00 00 00 00 00 00 00\n\
+             0x20E: 00 00 00 00\n"
+        );
+        let set = DebugCommand::Set {
+            target: SetTarget::Register(0xA),
+            value: 0x1F,
+        };
+        assert!(execute(&set, &mut emulator).contains("V8-VF: 00 00 1F"));
+        let set = DebugCommand::Set {
+            target: SetTarget::Index,
+            value: 0x1000,
+        };
+        assert!(execute(&set, &mut emulator).starts_with("error: "));
+        let disasm = DebugCommand::Disasm {
+            addr: 0x200,
+            count: 2,
+        };
+        assert_eq!(
+            execute(&disasm, &mut emulator),
+            "0x200: 0x6001  LD V0, 0x01\n0x202: 0x1202  JP 0x202\n"
+        );
+    }
+}
@@ -0,0 +1,494 @@
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::instruction::Instruction;
+use crate::quirks::Platform;
+
+// One opcode as assembly, e.g. "LD V3, 0x1F". Every SUPER-CHIP and XO-CHIP instruction is
+// recognized, words that aren't instructions show as "DW 0x1234".
+pub fn disassemble(opcode: u16) -> String {
+    match Instruction::decode(opcode) {
+        Some(instruction) => instruction.to_string(),
+        None => data_word(opcode),
+    }
+}
+
+// One opcode as the interpreters of platform run it: instructions it lacks are SYS calls in the
+// 0 family and data words elsewhere, and BXNN reads "JP Vx, addr" with the jump quirk
+pub fn disassemble_for(opcode: u16, platform: Platform) -> String {
+    match Instruction::decode(opcode) {
+        Some(Instruction::JumpOffset { x, nnn }) if platform.quirks().jump_uses_vx => {
+            format!("JP V{:X}, {:#05X}", x, nnn)
+        }
+        Some(instruction) if instruction.available_on(platform) => instruction.to_string(),
+        Some(_) if opcode & 0xF000 == 0 => Instruction::Sys(opcode).to_string(),
+        _ => data_word(opcode),
+    }
+}
+
+// count instructions from start as (address, opcode, assembly), stopping early at the end of
+// memory. XO-CHIP's F000 NNNN takes two words and shows its address, "LD I, LONG 0x1234".
+pub fn disassemble_range(memory: &[u8], start: u16, count: usize) -> Vec<(u16, u16, String)> {
+    disassemble_words(memory, start, count, disassemble)
+}
+
+// disassemble_range as the interpreters of platform see it, see disassemble_for
+pub fn disassemble_range_for(
+    memory: &[u8],
+    start: u16,
+    count: usize,
+    platform: Platform,
+) -> Vec<(u16, u16, String)> {
+    disassemble_words(memory, start, count, |opcode| {
+        disassemble_for(opcode, platform)
+    })
+}
+
+fn disassemble_words<F: Fn(u16) -> String>(
+    memory: &[u8],
+    start: u16,
+    count: usize,
+    disassemble: F,
+) -> Vec<(u16, u16, String)> {
+    let word = |addr: usize| {
+        memory
+            .get(addr..addr + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let mut lines = Vec::new();
+    let mut addr = start as usize;
+    while lines.len() < count {
+        let opcode = match word(addr) {
+            Some(opcode) => opcode,
+            None => break,
+        };
+        let mut text = disassemble(opcode);
+        let mut len = 2;
+        if text == Instruction::LoadLongI.to_string() {
+            if let Some(long) = word(addr + 2) {
+                text = format!("{} {:#06X}", text, long);
+                len = 4;
+            }
+        }
+        lines.push((addr as u16, opcode, text));
+        addr += len;
+    }
+    lines
+}
+
+fn data_word(opcode: u16) -> String {
+    format!("DW {:#06X}", opcode)
+}
+
+// A ROM split into code and data by following the control flow from its first instruction,
+// see analyze
+#[derive(Clone, Debug, PartialEq)]
+pub struct Listing {
+    pub base: u16,
+    // instructions and runs of data in address order, covering the whole ROM
+    pub items: Vec<ListingItem>,
+    // jump and call targets inside the code, each gets a label such as L_2F0
+    pub labels: BTreeSet<u16>,
+    // BNNN jumps, whose targets depend on a register. Only NNN itself is followed.
+    pub indirect_jumps: Vec<u16>,
+    // addresses reached in the middle of an instruction already found. The first decoding is
+    // kept and these aren't followed.
+    pub overlaps: Vec<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListingItem {
+    Code {
+        addr: u16,
+        opcode: u16,
+        text: String,
+    },
+    Data {
+        addr: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+// Bytes per db line
+const DATA_LINE: usize = 8;
+
+// Follow every path from base, the entry point: jumps, calls and the instruction after them,
+// both outcomes of skips and the NNN of BNNN. Words reached are code, the rest is data.
+// A path ends at RET, EXIT, a jump, or a word that isn't an instruction.
+pub fn analyze(rom: &[u8], base: u16) -> Listing {
+    let word = |addr: usize| {
+        let k = addr.checked_sub(base as usize)?;
+        rom.get(k..k + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    // Length of the instruction starting at each ROM byte, 0 for the rest
+    let mut starts = vec![0usize; rom.len()];
+    // Whether each byte belongs to an instruction
+    let mut covered = vec![false; rom.len()];
+    let mut labels = BTreeSet::new();
+    let mut indirect_jumps = Vec::new();
+    let mut overlaps = Vec::new();
+
+    let mut pending = vec![base as usize];
+    while let Some(addr) = pending.pop() {
+        let opcode = match word(addr) {
+            Some(opcode) => opcode,
+            // Outside the ROM, such as a jump into the interpreter area
+            None => continue,
+        };
+        let k = addr - base as usize;
+        if starts[k] > 0 {
+            continue;
+        }
+        let instruction = Instruction::decode(opcode);
+        let len = match instruction {
+            Some(Instruction::LoadLongI) if word(addr + 2).is_some() => 4,
+            _ => 2,
+        };
+        if covered[k..k + len].iter().any(|&byte| byte) {
+            overlaps.push(addr as u16);
+            continue;
+        }
+        starts[k] = len;
+        covered[k..k + len].iter_mut().for_each(|byte| *byte = true);
+
+        let next = addr + len;
+        // A skip passes over the whole next instruction, two words for F000 NNNN
+        let skipped = match word(next) {
+            Some(0xF000) => next + 4,
+            _ => next + 2,
+        };
+        match instruction {
+            Some(Instruction::Jump(nnn)) => {
+                labels.insert(nnn);
+                pending.push(nnn as usize);
+            }
+            Some(Instruction::Call(nnn)) => {
+                labels.insert(nnn);
+                pending.push(nnn as usize);
+                pending.push(next);
+            }
+            Some(Instruction::JumpOffset { nnn, .. }) => {
+                indirect_jumps.push(addr as u16);
+                labels.insert(nnn);
+                pending.push(nnn as usize);
+            }
+            Some(
+                Instruction::SkipEqByte { .. }
+                | Instruction::SkipNeByte { .. }
+                | Instruction::SkipEqReg { .. }
+                | Instruction::SkipNeReg { .. }
+                | Instruction::SkipKeyPressed { .. }
+                | Instruction::SkipKeyNotPressed { .. },
+            ) => {
+                pending.push(next);
+                pending.push(skipped);
+            }
+            Some(Instruction::Ret) | Some(Instruction::Exit) | None => {}
+            Some(_) => pending.push(next),
+        }
+    }
+    // Labels only make sense on instructions that made it into the listing
+    labels.retain(|&addr| {
+        (addr as usize)
+            .checked_sub(base as usize)
+            .is_some_and(|k| starts.get(k).is_some_and(|&len| len > 0))
+    });
+
+    let mut items = Vec::new();
+    let mut k = 0;
+    while k < rom.len() {
+        let addr = base as usize + k;
+        if starts[k] > 0 {
+            let opcode = word(addr).unwrap_or_default();
+            let text = match Instruction::decode(opcode) {
+                Some(Instruction::Jump(nnn)) if labels.contains(&nnn) => {
+                    format!("JP {}", label(nnn))
+                }
+                Some(Instruction::Call(nnn)) if labels.contains(&nnn) => {
+                    format!("CALL {}", label(nnn))
+                }
+                Some(Instruction::LoadLongI) if starts[k] == 4 => {
+                    format!("LD I, LONG {:#06X}", word(addr + 2).unwrap_or_default())
+                }
+                _ => disassemble(opcode),
+            };
+            items.push(ListingItem::Code {
+                addr: addr as u16,
+                opcode,
+                text,
+            });
+            k += starts[k];
+        } else {
+            let end = (k..rom.len())
+                .find(|&end| covered[end] || end - k == DATA_LINE)
+                .unwrap_or(rom.len());
+            items.push(ListingItem::Data {
+                addr: addr as u16,
+                bytes: rom[k..end].to_vec(),
+            });
+            k = end;
+        }
+    }
+
+    Listing {
+        base,
+        items,
+        labels,
+        indirect_jumps,
+        overlaps,
+    }
+}
+
+fn label(addr: u16) -> String {
+    format!("L_{:03X}", addr)
+}
+
+impl Listing {
+    // The lines of one item, its label if it has one and then the item itself after indent
+    pub(crate) fn write_item<W: fmt::Write>(
+        &self,
+        f: &mut W,
+        item: &ListingItem,
+        indent: &str,
+    ) -> fmt::Result {
+        match item {
+            ListingItem::Code { addr, opcode, text } => {
+                if self.labels.contains(addr) {
+                    writeln!(f, "{}:", label(*addr))?;
+                }
+                writeln!(f, "{}{:<23} ; {:#05X}: {:#06X}", indent, text, addr, opcode)
+            }
+            ListingItem::Data { addr, bytes } => {
+                let bytes: Vec<String> =
+                    bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+                writeln!(
+                    f,
+                    "{}{:<23} ; {:#05X}",
+                    indent,
+                    format!("db {}", bytes.join(", ")),
+                    addr
+                )
+            }
+        }
+    }
+}
+
+// One line per instruction or up to 8 bytes of data, labels on lines of their own and notes
+// about indirect jumps and overlaps at the end
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.items {
+            self.write_item(f, item, "    ")?;
+        }
+        for addr in &self.indirect_jumps {
+            writeln!(
+                f,
+                "; {:#05X}: indirect jump, only the base address is followed",
+                addr
+            )?;
+        }
+        for addr in &self.overlaps {
+            writeln!(
+                f,
+                "; {:#05X}: reached inside another instruction, not decoded",
+                addr
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_family_has_its_mnemonics() {
+        for (opcode, text) in [
+            (0x00E0, "CLS"),
+            (0x00EE, "RET"),
+            (0x0123, "SYS 0x123"),
+            (0x12F0, "JP 0x2F0"),
+            (0x22F0, "CALL 0x2F0"),
+            (0x3A12, "SE VA, 0x12"),
+            (0x4A12, "SNE VA, 0x12"),
+            (0x5240, "SE V2, V4"),
+            (0x631F, "LD V3, 0x1F"),
+            (0x7A01, "ADD VA, 0x01"),
+            (0x8AB0, "LD VA, VB"),
+            (0x8AB1, "OR VA, VB"),
+            (0x8AB2, "AND VA, VB"),
+            (0x8AB3, "XOR VA, VB"),
+            (0x8AB4, "ADD VA, VB"),
+            (0x8AB5, "SUB VA, VB"),
+            (0x8AB6, "SHR VA, VB"),
+            (0x8AB7, "SUBN VA, VB"),
+            (0x8ABE, "SHL VA, VB"),
+            (0x9AB0, "SNE VA, VB"),
+            (0xA300, "LD I, 0x300"),
+            (0xB300, "JP V0, 0x300"),
+            (0xC70F, "RND V7, 0x0F"),
+            (0xD015, "DRW V0, V1, 5"),
+            (0xE39E, "SKP V3"),
+            (0xE3A1, "SKNP V3"),
+        ] {
+            assert_eq!(disassemble(opcode), text, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn f_family_and_data_words() {
+        for (opcode, text) in [
+            (0xF007, "LD V0, DT"),
+            (0xF00A, "LD V0, K"),
+            (0xF015, "LD DT, V0"),
+            (0xF018, "LD ST, V0"),
+            (0xF01E, "ADD I, V0"),
+            (0xF029, "LD F, V0"),
+            (0xF033, "LD B, V0"),
+            (0xFF55, "LD [I], VF"),
+            (0xFF65, "LD VF, [I]"),
+            // Unknown words, including ones next to real F and 8 opcodes
+            (0xF0FF, "DW 0xF0FF"),
+            (0xF034, "DW 0xF034"),
+            (0x8AB8, "DW 0x8AB8"),
+            (0x5AB1, "DW 0x5AB1"),
+            (0xE3A2, "DW 0xE3A2"),
+        ] {
+            assert_eq!(disassemble(opcode), text, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn super_chip_and_xo_chip_mnemonics() {
+        for (opcode, text) in [
+            (0x00C4, "SCD 4"),
+            (0x00D4, "SCU 4"),
+            (0x00FB, "SCR"),
+            (0x00FC, "SCL"),
+            (0x00FD, "EXIT"),
+            (0x00FE, "LOW"),
+            (0x00FF, "HIGH"),
+            (0xD120, "DRW V1, V2, 0"),
+            (0xF330, "LD HF, V3"),
+            (0xF375, "LD R, V3"),
+            (0xF385, "LD V3, R"),
+            (0x5232, "LD [I], V2-V3"),
+            (0x5233, "LD V2-V3, [I]"),
+            (0xF201, "PLANE 2"),
+            (0xF002, "LD AUDIO, [I]"),
+            (0xF03A, "LD PITCH, V0"),
+        ] {
+            assert_eq!(disassemble(opcode), text, "{:04X}", opcode);
+        }
+        // Without the instructions, the 00NN ones are machine code calls and the rest data
+        assert_eq!(disassemble_for(0x00FF, Platform::Chip8), "SYS 0x0FF");
+        assert_eq!(disassemble_for(0xF330, Platform::Chip8), "DW 0xF330");
+        assert_eq!(disassemble_for(0xF330, Platform::SuperChip), "LD HF, V3");
+        assert_eq!(disassemble_for(0xB300, Platform::SuperChip), "JP V3, 0x300");
+        assert_eq!(disassemble_for(0xB300, Platform::Chip8), "JP V0, 0x300");
+    }
+
+    #[test]
+    fn ranges_take_long_index_loads_as_one_line() {
+        let memory = [0xF0, 0x00, 0x12, 0x34, 0x63, 0x1F, 0x00];
+        assert_eq!(
+            disassemble_range(&memory, 0, 10),
+            [
+                (0, 0xF000, "LD I, LONG 0x1234".to_string()),
+                (4, 0x631F, "LD V3, 0x1F".to_string()),
+            ]
+        );
+        assert_eq!(disassemble_range(&memory, 2, 1).len(), 1);
+        assert_eq!(
+            disassemble_range_for(&memory, 4, 1, Platform::Chip8),
+            [(4, 0x631F, "LD V3, 0x1F".to_string())]
+        );
+    }
+
+    fn code(addr: u16, opcode: u16, text: &str) -> ListingItem {
+        ListingItem::Code {
+            addr,
+            opcode,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn analyze_keeps_the_sprite_table_as_data() {
+        let rom = [
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD0, 0x15, // DRW V0, V1, 5
+            0x22, 0x08, // CALL 0x208
+            0x12, 0x06, // JP 0x206
+            0x00, 0xEE, // RET
+            0xF0, 0x90, 0xF0, 0x90, 0xF0, 0x00, // the sprite
+        ];
+        let listing = analyze(&rom, 0x200);
+        assert_eq!(
+            listing.items,
+            [
+                code(0x200, 0xA20A, "LD I, 0x20A"),
+                code(0x202, 0xD015, "DRW V0, V1, 5"),
+                code(0x204, 0x2208, "CALL L_208"),
+                code(0x206, 0x1206, "JP L_206"),
+                code(0x208, 0x00EE, "RET"),
+                ListingItem::Data {
+                    addr: 0x20A,
+                    bytes: vec![0xF0, 0x90, 0xF0, 0x90, 0xF0, 0x00]
+                },
+            ]
+        );
+        assert_eq!(
+            listing.labels.iter().copied().collect::<Vec<_>>(),
+            [0x206, 0x208]
+        );
+        let text = listing.to_string();
+        assert!(text.contains("L_208:\n    RET "));
+        assert!(text.contains("    db 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0x00 ; 0x20A\n"));
+    }
+
+    #[test]
+    fn analyze_follows_both_sides_of_skips() {
+        let rom = [
+            0x30, 0x01, // SE V0, 0x01
+            0x12, 0x06, // JP 0x206
+            0x00, 0xFD, // EXIT
+            0x00, 0xEE, // RET
+            0x12, 0x34, // never reached
+        ];
+        let listing = analyze(&rom, 0x200);
+        assert_eq!(listing.items.len(), 5);
+        assert_eq!(listing.items[2], code(0x204, 0x00FD, "EXIT"));
+        assert_eq!(
+            listing.items[4],
+            ListingItem::Data {
+                addr: 0x208,
+                bytes: vec![0x12, 0x34]
+            }
+        );
+    }
+
+    #[test]
+    fn analyze_notes_indirect_jumps_and_overlaps() {
+        let rom = [
+            0xB2, 0x04, // JP V0, 0x204
+            0xFF, 0xFF, // data
+            0x12, 0x05, // JP 0x205, into its own second byte
+            0x00,
+        ];
+        let listing = analyze(&rom, 0x200);
+        assert_eq!(listing.indirect_jumps, [0x200]);
+        assert_eq!(listing.overlaps, [0x205]);
+        assert_eq!(listing.labels.iter().copied().collect::<Vec<_>>(), [0x204]);
+        assert_eq!(listing.items[2], code(0x204, 0x1205, "JP 0x205"));
+        let text = listing.to_string();
+        assert!(text.contains("; 0x200: indirect jump, only the base address is followed\n"));
+        assert!(text.contains("; 0x205: reached inside another instruction, not decoded\n"));
+    }
+}
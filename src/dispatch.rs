@@ -0,0 +1,250 @@
+use core::marker::PhantomData;
+
+use crate::bus::Bus;
+use crate::cpu::{CycleState, CPU};
+use crate::error::Chip8Error;
+use crate::quirks::Platform;
+
+// How exec_cycle gets from an opcode to the code that runs it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Dispatch {
+    // Instruction::decode, one match over all the opcode nibbles, then CPU::execute
+    #[default]
+    Match,
+    // Function pointer tables indexed by the opcode nibbles, straight to the instruction's
+    // handler without building an Instruction, see lookup below
+    Table,
+}
+
+// Runs one instruction, with pc already past it
+pub(crate) type Handler<B> = fn(&mut CPU<B>, u16) -> Result<CycleState, Chip8Error>;
+
+// The handler of an opcode and what it costs in COSMAC VIP machine cycles
+pub(crate) type Op<B> = (Handler<B>, u32);
+
+type Lookup<B> = fn(u16, Platform) -> Option<Op<B>>;
+
+// Find the handler of an opcode through a table indexed by its top nibble, with secondary tables
+// for the 8, E and F families. None when the opcode isn't an instruction on `platform`. Always
+// agrees with Instruction::decode, available_on and vip_cycles.
+pub(crate) fn lookup<B: Bus>(opcode: u16, platform: Platform) -> Option<Op<B>> {
+    Tables::<B>::FAMILIES[(opcode >> 12) as usize](opcode, platform)
+}
+
+// The tables hold handlers for a CPU on bus B
+struct Tables<B>(PhantomData<B>);
+
+impl<B: Bus> Tables<B> {
+    const FAMILIES: [Lookup<B>; 16] = [
+        Self::family_0,
+        |_, _| Some((|cpu, op| cpu.op_jump(nnn(op)), 12)),
+        |_, _| Some((|cpu, op| cpu.op_call(nnn(op)), 26)),
+        |_, _| Some((|cpu, op| cpu.op_skip_eq_byte(x(op), kk(op)), 10)),
+        |_, _| Some((|cpu, op| cpu.op_skip_ne_byte(x(op), kk(op)), 10)),
+        Self::family_5,
+        |_, _| Some((|cpu, op| cpu.op_load_byte(x(op), kk(op)), 6)),
+        |_, _| Some((|cpu, op| cpu.op_add_vx_byte(x(op), kk(op)), 10)),
+        |op, _| Some((Self::ARITHMETIC[(op & 0xF) as usize]?, 44)),
+        |op, _| match op & 0xF {
+            0x0 => Some((|cpu, op| cpu.op_skip_ne_reg(x(op), y(op)), 14)),
+            _ => None,
+        },
+        |_, _| Some((|cpu, op| cpu.op_load_i(nnn(op)), 12)),
+        |_, _| Some((|cpu, op| cpu.op_jump_offset(x(op), nnn(op)), 22)),
+        |_, _| Some((|cpu, op| cpu.op_rnd(x(op), kk(op)), 36)),
+        |op, _| {
+            let n = (op & 0xF) as u8;
+            Some((
+                |cpu, op| cpu.op_draw(x(op), y(op), (op & 0xF) as u8),
+                68 + 46 * n as u32,
+            ))
+        },
+        |op, _| Some((Self::KEYS[kk(op) as usize]?, 14)),
+        |op, platform| Self::MISC[kk(op) as usize](op, platform),
+    ];
+
+    // 0NNN, where only 00CN, 00DN, 00E0, 00EE and 00FB to 00FF aren't machine code calls.
+    // Where they don't exist, the SUPER-CHIP 00NN instructions are machine code calls too.
+    fn family_0(op: u16, platform: Platform) -> Option<Op<B>> {
+        let superchip = platform.has_superchip_opcodes();
+        let op: Op<B> = match op {
+            0x00C0..=0x00CF if superchip => (|cpu, op| cpu.op_scroll_down((op & 0xF) as u8), 24),
+            0x00D0..=0x00DF if platform == Platform::XoChip => {
+                (|cpu, op| cpu.op_scroll_up((op & 0xF) as u8), 24)
+            }
+            0x00FB if superchip => (|cpu, _| cpu.op_scroll_right(), 24),
+            0x00FC if superchip => (|cpu, _| cpu.op_scroll_left(), 24),
+            0x00E0 => (|cpu, _| cpu.op_cls(), 24),
+            0x00EE => (|cpu, _| cpu.op_ret(), 10),
+            0x00FD if superchip => (|cpu, _| cpu.op_exit(), 0),
+            0x00FE if superchip => (|cpu, _| cpu.op_lores(), 24),
+            0x00FF if superchip => (|cpu, _| cpu.op_hires(), 24),
+            _ => (|cpu, op| cpu.op_sys(nnn(op)), 0),
+        };
+        Some(op)
+    }
+
+    // 5XYN, where 5XY2 and 5XY3 are XO-CHIP only
+    fn family_5(op: u16, platform: Platform) -> Option<Op<B>> {
+        let registers = (x(op).max(y(op)) - x(op).min(y(op)) + 1) as u32;
+        match op & 0xF {
+            0x0 => Some((|cpu, op| cpu.op_skip_eq_reg(x(op), y(op)), 14)),
+            0x2 if platform == Platform::XoChip => Some((
+                |cpu, op| cpu.op_store_range(x(op), y(op)),
+                14 + 14 * registers,
+            )),
+            0x3 if platform == Platform::XoChip => Some((
+                |cpu, op| cpu.op_load_range(x(op), y(op)),
+                14 + 14 * registers,
+            )),
+            _ => None,
+        }
+    }
+
+    // 8XYN, indexed by N
+    const ARITHMETIC: [Option<Handler<B>>; 16] = [
+        Some(|cpu, op| cpu.op_load_reg(x(op), y(op))),
+        Some(|cpu, op| cpu.op_or(x(op), y(op))),
+        Some(|cpu, op| cpu.op_and(x(op), y(op))),
+        Some(|cpu, op| cpu.op_xor(x(op), y(op))),
+        Some(|cpu, op| cpu.op_add_reg(x(op), y(op))),
+        Some(|cpu, op| cpu.op_sub(x(op), y(op))),
+        Some(|cpu, op| cpu.op_shr(x(op), y(op))),
+        Some(|cpu, op| cpu.op_subn(x(op), y(op))),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(|cpu, op| cpu.op_shl(x(op), y(op))),
+        None,
+    ];
+
+    // EXKK, indexed by KK
+    const KEYS: [Option<Handler<B>>; 256] = Self::keys_table();
+
+    const fn keys_table() -> [Option<Handler<B>>; 256] {
+        let mut table: [Option<Handler<B>>; 256] = [None; 256];
+        table[0x9E] = Some(|cpu, op| cpu.op_skip_key_pressed(x(op)));
+        table[0xA1] = Some(|cpu, op| cpu.op_skip_key_not_pressed(x(op)));
+        table
+    }
+
+    // FXKK, indexed by KK
+    const MISC: [Lookup<B>; 256] = Self::misc_table();
+
+    const fn misc_table() -> [Lookup<B>; 256] {
+        let mut table: [Lookup<B>; 256] = [|_, _| None; 256];
+        table[0x00] = |op, platform| match x(op) {
+            0 if platform == Platform::XoChip => Some((|cpu, _| cpu.op_load_long_i(), 24)),
+            _ => None,
+        };
+        table[0x01] = |_, platform| match platform {
+            Platform::XoChip => Some((|cpu, op| cpu.op_plane(x(op) as u8), 10)),
+            _ => None,
+        };
+        table[0x02] = |op, platform| match x(op) {
+            0 if platform == Platform::XoChip => Some((|cpu, _| cpu.op_load_audio(), 14 + 14 * 16)),
+            _ => None,
+        };
+        table[0x07] = |_, _| Some((|cpu, op| cpu.op_load_delay(x(op)), 10));
+        table[0x0A] = |_, _| Some((|cpu, op| cpu.op_wait_key(x(op)), 10));
+        table[0x15] = |_, _| Some((|cpu, op| cpu.op_set_delay(x(op)), 10));
+        table[0x18] = |_, _| Some((|cpu, op| cpu.op_set_sound(x(op)), 10));
+        table[0x1E] = |_, _| Some((|cpu, op| cpu.op_add_i(x(op)), 12));
+        table[0x29] = |_, _| Some((|cpu, op| cpu.op_load_font(x(op)), 20));
+        table[0x30] = |_, platform| match platform.has_superchip_opcodes() {
+            true => Some((|cpu, op| cpu.op_load_big_font(x(op)), 20)),
+            false => None,
+        };
+        table[0x3A] = |_, platform| match platform {
+            Platform::XoChip => Some((|cpu, op| cpu.op_set_pitch(x(op)), 10)),
+            _ => None,
+        };
+        table[0x33] = |_, _| Some((|cpu, op| cpu.op_store_bcd(x(op)), 80));
+        table[0x55] = |op, _| Some((|cpu, op| cpu.op_store_regs(x(op)), registers_cost(op)));
+        table[0x65] = |op, _| Some((|cpu, op| cpu.op_load_regs(x(op)), registers_cost(op)));
+        table[0x75] = |op, platform| match platform.has_superchip_opcodes() {
+            true => Some((|cpu, op| cpu.op_store_flags(x(op)), registers_cost(op))),
+            false => None,
+        };
+        table[0x85] = |op, platform| match platform.has_superchip_opcodes() {
+            true => Some((|cpu, op| cpu.op_load_flags(x(op)), registers_cost(op))),
+            false => None,
+        };
+        table
+    }
+}
+
+// FX55, FX65, FX75 and FX85 go through V0 to VX one register at a time
+const fn registers_cost(op: u16) -> u32 {
+    14 + 14 * (x(op) as u32 + 1)
+}
+
+const fn x(op: u16) -> usize {
+    ((op & 0x0F00) >> 8) as usize
+}
+
+const fn y(op: u16) -> usize {
+    ((op & 0x00F0) >> 4) as usize
+}
+
+const fn kk(op: u16) -> u8 {
+    (op & 0x00FF) as u8
+}
+
+const fn nnn(op: u16) -> u16 {
+    op & 0x0FFF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Ram;
+    use crate::instruction::Instruction;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn table_agrees_with_match_on_every_opcode() {
+        for platform in [
+            Platform::Chip8,
+            Platform::SuperChip,
+            Platform::XoChip,
+            Platform::Eti660,
+        ] {
+            for opcode in 0..=0xFFFF {
+                let decoded = match Instruction::decode(opcode) {
+                    Some(instruction) if instruction.available_on(platform) => Some(instruction),
+                    Some(_) if opcode & 0xF000 == 0 => Some(Instruction::Sys(opcode)),
+                    _ => None,
+                };
+                let cost = lookup::<Ram>(opcode, platform).map(|(_, cost)| cost);
+                assert_eq!(
+                    cost,
+                    decoded.map(|instruction| instruction.vip_cycles()),
+                    "opcode {:04X} on {:?}",
+                    opcode,
+                    platform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn roms_run_the_same_under_both() {
+        for rom in [
+            &include_bytes!("../roms/BRIX")[..],
+            include_bytes!("../roms/MAZE"),
+            include_bytes!("../roms/INVADERS"),
+        ] {
+            let run = |dispatch| {
+                let mut cpu = CPU::builder().dispatch(dispatch).seed(1).build();
+                cpu.load_rom_bytes(rom).unwrap();
+                let frames: Vec<_> = (0..300).map(|_| cpu.run_frame(15)).collect();
+                (frames, cpu.snapshot(), cpu.machine_cycles())
+            };
+            assert_eq!(run(Dispatch::Match), run(Dispatch::Table));
+        }
+    }
+}
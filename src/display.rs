@@ -1,52 +1,675 @@
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
+use alloc::string::String;
+use alloc::vec::Vec;
 
+use crate::palette::Palette;
+
+// The framebuffer is sized for the largest resolution, so switching resolution keeps the
+// top left of the picture where it was
+pub const MAX_WIDTH: usize = 128;
+pub const MAX_HEIGHT: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    // 64x32, the only mode of the original interpreter
+    Low,
+    // 128x64, SUPER-CHIP 00FF
+    High,
+    // 64x64, two-page HiRes CHIP-8
+    Tall,
+    // 64x48, ETI-660
+    Eti660,
+}
+
+impl Resolution {
+    pub fn width(self) -> usize {
+        match self {
+            Resolution::Low | Resolution::Tall | Resolution::Eti660 => 64,
+            Resolution::High => 128,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            Resolution::Low => 32,
+            Resolution::Eti660 => 48,
+            Resolution::High | Resolution::Tall => 64,
+        }
+    }
+}
+
+// What a sprite draw ran into, counted in sprite rows
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DrawResult {
+    // rows that turned off at least one pixel
+    pub collided_rows: usize,
+    // rows dropped at the bottom edge when clipping
+    pub clipped_rows: usize,
+}
+
+impl DrawResult {
+    pub fn collision(&self) -> bool {
+        self.collided_rows > 0
+    }
+}
+
+// Rows of the screen that changed, bit y set for row y
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DirtyRegion {
+    pub rows: u64,
+}
+
+// Every row of the largest screen
+const ALL_ROWS: u64 = u64::MAX >> (64 - MAX_HEIGHT);
+
+impl DirtyRegion {
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    pub fn contains(&self, y: usize) -> bool {
+        y < MAX_HEIGHT && self.rows >> y & 1 == 1
+    }
+
+    // Numbers of the changed rows, top to bottom
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let rows = self.rows;
+        (0..MAX_HEIGHT).filter(move |&y| rows >> y & 1 == 1)
+    }
+}
+
+// XO-CHIP has two bitplanes, so every pixel is a color index from 0 to 3
+pub const PLANES: usize = 2;
+// How Display::to_ascii shows each color index
+const ASCII_COLORS: [char; 4] = ['.', '#', '+', '@'];
+const ALL_PLANES: u8 = (1 << PLANES) - 1;
+
+// One row of one plane as words of 64 pixels, pixel x in bit 63 - x % 64 of word x / 64, so
+// the leftmost pixel of a word is its most significant bit. Lores and the other 64 pixel wide
+// modes only use the first word, hires uses both.
+const WORDS: usize = MAX_WIDTH / 64;
+type Row = [u64; WORDS];
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Display {
     pub need_redraw: bool,
-    pub fb: [bool; WIDTH*HEIGHT],
+    // Changed since CPU last reported it, see CPU::on_display_updated
+    pub(crate) updated: bool,
+    pub resolution: Resolution,
+    // Rows changed since take_dirty
+    dirty: DirtyRegion,
+    // Pixels of every plane, one Row per screen row
+    rows: [[Row; MAX_HEIGHT]; PLANES],
+    // Mask of the planes XO-CHIP FN01 selected for drawing, scrolling and clearing
+    pub planes: u8,
+    // Counts the changes to the screen, so CPU::run_frame can tell whether a frame drew
+    changes: u32,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Display {
     pub fn new() -> Self {
         Display {
             need_redraw: false,
-            fb: [false; WIDTH*HEIGHT],
+            updated: false,
+            resolution: Resolution::Low,
+            dirty: DirtyRegion { rows: ALL_ROWS },
+            rows: [[[0; WORDS]; MAX_HEIGHT]; PLANES],
+            planes: 1,
+            changes: 0,
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.resolution.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.resolution.height()
+    }
+
+    // Select the planes later draws, scrolls and clears apply to. Only the low 2 bits count.
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ALL_PLANES;
+    }
+
+    // Clear the selected planes
     pub fn clear(&mut self) {
+        self.mark_updated();
+        self.dirty.rows = ALL_ROWS;
+        for p in self.selected_planes() {
+            self.rows[p].fill([0; WORDS]);
+        }
+    }
+
+    // Switch resolution, clearing every plane unless `keep` is set
+    pub fn set_resolution(&mut self, resolution: Resolution, keep: bool) {
+        self.resolution = resolution;
+        if !keep {
+            self.rows = [[[0; WORDS]; MAX_HEIGHT]; PLANES];
+        }
+        self.mark_updated();
+        self.dirty.rows = ALL_ROWS;
+    }
+
+    // Move the selected planes down n rows, blanking the rows scrolled in at the top
+    pub fn scroll_down(&mut self, n: usize) {
+        for y in (0..self.height()).rev() {
+            let src = y.checked_sub(n);
+            self.scroll_row(y, |rows| src.map_or([0; WORDS], |src| rows[src]));
+        }
+        self.mark_updated();
+    }
+
+    // Move the selected planes up n rows, blanking the rows scrolled in at the bottom
+    pub fn scroll_up(&mut self, n: usize) {
+        let height = self.height();
+        for y in 0..height {
+            let src = y + n;
+            self.scroll_row(y, |rows| match src < height {
+                true => rows[src],
+                false => [0; WORDS],
+            });
+        }
+        self.mark_updated();
+    }
+
+    // Move the selected planes left n columns, blanking the columns scrolled in on the right
+    pub fn scroll_left(&mut self, n: usize) {
+        let visible = self.visible_columns();
+        for y in 0..self.height() {
+            // Column c of the result is column c + n of the row
+            self.scroll_row(y, |rows| shifted(&masked(rows[y], visible), n as isize));
+        }
+        self.mark_updated();
+    }
+
+    // Move the selected planes right n columns, blanking the columns scrolled in on the left
+    pub fn scroll_right(&mut self, n: usize) {
+        let visible = self.visible_columns();
+        for y in 0..self.height() {
+            self.scroll_row(y, |rows| shifted(&masked(rows[y], visible), -(n as isize)));
+        }
+        self.mark_updated();
+    }
+
+    fn mark_updated(&mut self) {
         self.need_redraw = true;
-        self.fb = [false; WIDTH*HEIGHT];
+        self.updated = true;
+        self.changes = self.changes.wrapping_add(1);
+    }
+
+    pub(crate) fn changes(&self) -> u32 {
+        self.changes
     }
 
-    pub fn set_pixel(&mut self, x: usize, y: usize, val: bool) {
-        self.fb[x + y * WIDTH] = val;
+    // Replace the visible part of row y in each selected plane with what `src` picks from
+    // that plane's rows. Off screen columns neither change nor scroll in.
+    fn scroll_row(&mut self, y: usize, src: impl Fn(&[Row; MAX_HEIGHT]) -> Row) {
+        let visible = self.visible_columns();
+        for p in self.selected_planes() {
+            let new = src(&self.rows[p]);
+            for ((word, new), mask) in self.rows[p][y].iter_mut().zip(new).zip(visible) {
+                *word = (*word & !mask) | (new & mask);
+            }
+        }
+        self.dirty.rows |= 1 << y;
     }
 
-    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
-        self.fb[x + y * WIDTH]
+    // Indexes of the planes FN01 selected
+    fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let planes = self.planes;
+        (0..PLANES).filter(move |p| planes >> p & 1 == 1)
     }
 
-    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8]) -> bool {
-        let rows = sprite.len();
-        let mut collision = false;
-        for j in 0..rows {
-            let row = sprite[j];
-            for i in 0..8 {
-                let new_value = row >> (7 - i) & 0x01;
-                if new_value == 1 {
-                    // Wraparound if goes out of bounds
-                    let xi = (x + i) % 64;
-                    let yj = (y + j) % 32;
-                    let old_value = self.get_pixel(xi, yj);
-                    if old_value {
-                        collision = true;
-                    }
-                    self.set_pixel(xi, yj, (new_value == 1) ^ old_value);
-                }
+    // Row bits of the columns on screen in the current resolution
+    fn visible_columns(&self) -> Row {
+        let mut visible = [0; WORDS];
+        for (k, word) in visible.iter_mut().enumerate() {
+            let columns = self.width().saturating_sub(64 * k).min(64);
+            *word = !u64::MAX.checked_shr(columns as u32).unwrap_or(0);
+        }
+        visible
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let (k, bit) = column_bit(x);
+        for (p, rows) in self.rows.iter_mut().enumerate() {
+            if color >> p & 1 == 1 {
+                rows[y][k] |= bit;
+            } else {
+                rows[y][k] &= !bit;
             }
         }
+        self.dirty.rows |= 1 << y;
+    }
+
+    // Rows changed since the last call, so a frontend can redraw only those
+    pub fn take_dirty(&mut self) -> DirtyRegion {
+        core::mem::take(&mut self.dirty)
+    }
+
+    // Have the whole screen redrawn, e.g. after the frontend changed palette
+    pub fn invalidate(&mut self) {
         self.need_redraw = true;
-        collision
-      }
-}
\ No newline at end of file
+        self.dirty.rows = ALL_ROWS;
+    }
+
+    // Color index of every pixel, MAX_WIDTH per row whatever the resolution
+    pub fn framebuffer(&self) -> Vec<u8> {
+        (0..MAX_WIDTH * MAX_HEIGHT)
+            .map(|i| self.get_pixel(i % MAX_WIDTH, i / MAX_WIDTH))
+            .collect()
+    }
+
+    // Color index of a pixel, bit 0 is plane 1 and bit 1 is plane 2. 0 when it's off in
+    // every plane.
+    pub fn get_pixel(&self, x: usize, y: usize) -> u8 {
+        let (k, shift) = (x / 64, 63 - x % 64);
+        self.rows.iter().enumerate().fold(0, |color, (p, rows)| {
+            color | ((rows[y][k] >> shift) as u8 & 1) << p
+        })
+    }
+
+    // (x, y, color index) of every pixel on the visible screen that isn't background
+    pub fn iter_pixels(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        let visible = self.visible_columns();
+        let words = (0..self.height()).flat_map(|y| (0..WORDS).map(move |k| (y, k)));
+        words.flat_map(move |(y, k)| {
+            let mut lit = self.rows.iter().fold(0, |lit, rows| lit | rows[y][k]) & visible[k];
+            core::iter::from_fn(move || {
+                if lit == 0 {
+                    return None;
+                }
+                let x = 64 * k + lit.leading_zeros() as usize;
+                lit &= !column_bit(x).1;
+                Some((x, y, self.get_pixel(x, y)))
+            })
+        })
+    }
+
+    // The visible screen as text, one line per row, for test failures and logs
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::with_capacity((self.width() + 1) * self.height());
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                out.push(ASCII_COLORS[self.get_pixel(x, y) as usize & 3]);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    // Convert the visible screen to packed RGBA bytes, row by row, through `palette`
+    pub fn to_rgba(&self, palette: &Palette, out: &mut Vec<u8>) {
+        out.clear();
+        out.reserve(self.width() * self.height() * 4);
+        for y in 0..self.height() {
+            self.push_row_rgba(y, palette, out);
+        }
+    }
+
+    // Convert one row of the visible screen to packed RGBA bytes through `palette`
+    pub fn row_to_rgba(&self, y: usize, palette: &Palette, out: &mut Vec<u8>) {
+        out.clear();
+        self.push_row_rgba(y, palette, out);
+    }
+
+    fn push_row_rgba(&self, y: usize, palette: &Palette, out: &mut Vec<u8>) {
+        for x in 0..self.width() {
+            let (r, g, b) = palette.rgb(self.get_pixel(x, y));
+            out.extend_from_slice(&[r, g, b, 0xFF]);
+        }
+    }
+
+    // Draw an 8 pixel wide sprite into every selected plane. The sprite holds the rows for each
+    // selected plane one after the other, plane 1 first.
+    // The starting coordinate always wraps around the screen.
+    // Pixels running off the right or bottom edge are clipped when `clip` is set, otherwise they wrap.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> DrawResult {
+        self.draw_planes(x, y, sprite, 1, clip)
+    }
+
+    // SUPER-CHIP 16x16 sprite, two bytes per row, otherwise the same as draw_sprite
+    pub fn draw_sprite16(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> DrawResult {
+        self.draw_planes(x, y, sprite, 2, clip)
+    }
+
+    // Split the sprite between the selected planes and draw each part.
+    // A plane collides on its own, the result adds up the collisions of all of them.
+    fn draw_planes(
+        &mut self,
+        x: usize,
+        y: usize,
+        sprite: &[u8],
+        bytes_per_row: usize,
+        clip: bool,
+    ) -> DrawResult {
+        let selected = self.planes.count_ones() as usize;
+        let mut result = DrawResult::default();
+        if selected == 0 {
+            return result;
+        }
+        let mut parts = sprite.chunks((sprite.len() / selected).max(1));
+        for plane in (0..PLANES).map(|p| 1u8 << p) {
+            if self.planes & plane == 0 {
+                continue;
+            }
+            let part = parts.next().unwrap_or(&[]);
+            let mut rows = [0u16; 16];
+            for (row, bytes) in rows.iter_mut().zip(part.chunks(bytes_per_row)) {
+                *row = bytes.iter().fold(0, |row, &byte| (row << 8) | byte as u16);
+                // A short last row still lines up on the left
+                *row <<= 8 * (bytes_per_row - bytes.len());
+            }
+            let len = (part.len() / bytes_per_row).min(16);
+            let drawn = self.draw_rows(x, y, &rows[..len], 8 * bytes_per_row, clip, plane);
+            result.collided_rows += drawn.collided_rows;
+            result.clipped_rows = result.clipped_rows.max(drawn.clipped_rows);
+        }
+        self.mark_updated();
+        result
+    }
+
+    // XOR rows of `bits` pixels, most significant bit leftmost, onto one plane of the screen
+    fn draw_rows(
+        &mut self,
+        x: usize,
+        y: usize,
+        rows: &[u16],
+        bits: usize,
+        clip: bool,
+        plane: u8,
+    ) -> DrawResult {
+        let (width, height) = (self.width(), self.height());
+        let x = x % width;
+        let y = y % height;
+        let visible = self.visible_columns();
+        let screen = &mut self.rows[plane.trailing_zeros() as usize];
+        let mut result = DrawResult::default();
+        for (j, &row) in rows.iter().enumerate() {
+            if clip && y + j >= height {
+                result.clipped_rows = rows.len() - j;
+                break;
+            }
+            // The sprite row as a word starting at column x, split over the row's words
+            let word = (row as u64) << (64 - bits);
+            let mut sprite = shifted(&[word], -(x as isize));
+            if !clip {
+                // What ran off the right edge comes back in on the left
+                let wrapped = shifted(&[word], (width - x) as isize);
+                for (part, wrapped) in sprite.iter_mut().zip(wrapped) {
+                    *part |= wrapped;
+                }
+            }
+            let sprite = masked(sprite, visible);
+            if sprite == [0; WORDS] {
+                continue;
+            }
+            let yj = (y + j) % height;
+            let screen_row = &mut screen[yj];
+            if screen_row
+                .iter()
+                .zip(sprite)
+                .any(|(&word, part)| word & part != 0)
+            {
+                result.collided_rows += 1;
+            }
+            for (word, part) in screen_row.iter_mut().zip(sprite) {
+                *word ^= part;
+            }
+            self.dirty.rows |= 1 << yj;
+        }
+        result
+    }
+}
+
+// Word of the row holding column x, and the bit in it
+fn column_bit(x: usize) -> (usize, u64) {
+    (x / 64, 1 << (63 - x % 64))
+}
+
+fn masked(row: Row, mask: Row) -> Row {
+    let mut out = row;
+    for (word, mask) in out.iter_mut().zip(mask) {
+        *word &= mask;
+    }
+    out
+}
+
+// A row of the words in `words` moved n columns to the left, or right for a negative n. Word k
+// of `words` starts at column 64 * k, columns moved past either end of the row are lost.
+fn shifted(words: &[u64], n: isize) -> Row {
+    let mut out = [0; WORDS];
+    for (k, word) in out.iter_mut().enumerate() {
+        // Column c of the result is column c + n of `words`
+        for (w, &src) in words.iter().enumerate() {
+            let left = 64 * k as isize + n - 64 * w as isize;
+            *word |= match left >= 0 {
+                true => src.checked_shl(left as u32).unwrap_or(0),
+                false => src.checked_shr(left.unsigned_abs() as u32).unwrap_or(0),
+            };
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn lit(display: &Display) -> Vec<(usize, usize)> {
+        display.iter_pixels().map(|(x, y, _)| (x, y)).collect()
+    }
+
+    #[test]
+    fn drawing_twice_erases_and_collides() {
+        let mut display = Display::new();
+        let first = display.draw_sprite(2, 3, &[0b1010_0000], false);
+        assert!(!first.collision());
+        assert_eq!(lit(&display), [(2, 3), (4, 3)]);
+        let second = display.draw_sprite(2, 3, &[0b1010_0000], false);
+        assert_eq!(second.collided_rows, 1);
+        assert!(lit(&display).is_empty());
+    }
+
+    #[test]
+    fn collision_counts_rows() {
+        let mut display = Display::new();
+        display.draw_sprite(0, 0, &[0x80, 0x80, 0x80], false);
+        // Overlaps only the first and last rows
+        let result = display.draw_sprite(0, 0, &[0x80, 0x40, 0x80], false);
+        assert_eq!(result.collided_rows, 2);
+        assert_eq!(lit(&display), [(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn lores_sprite_wraps_at_column_64() {
+        let mut display = Display::new();
+        display.draw_sprite(60, 30, &[0xFF, 0xFF, 0xFF], false);
+        assert_eq!(display.get_pixel(63, 30), 1);
+        assert_eq!(display.get_pixel(0, 30), 1);
+        assert_eq!(display.get_pixel(3, 31), 1);
+        assert_eq!(display.get_pixel(4, 30), 0);
+        // The third row wrapped to the top
+        assert_eq!(display.get_pixel(0, 0), 1);
+        // Nothing landed in the hidden right half of the framebuffer
+        assert_eq!(display.get_pixel(64, 30), 0);
+    }
+
+    #[test]
+    fn clipped_sprite_is_cut_at_the_edges() {
+        let mut display = Display::new();
+        let result = display.draw_sprite(60, 30, &[0xFF, 0xFF, 0xFF], true);
+        assert_eq!(result.clipped_rows, 1);
+        assert_eq!(lit(&display).len(), 8);
+        assert_eq!(display.get_pixel(0, 30), 0);
+        assert_eq!(display.get_pixel(0, 0), 0);
+        // The starting coordinate still wraps
+        let mut display = Display::new();
+        display.draw_sprite(66, 33, &[0x80], true);
+        assert_eq!(lit(&display), [(2, 1)]);
+    }
+
+    #[test]
+    fn hires_sprite_crosses_column_64() {
+        let mut display = Display::new();
+        display.set_resolution(Resolution::High, false);
+        display.draw_sprite16(56, 10, &[0xFF, 0xFF], false);
+        assert_eq!(lit(&display), (56..72).map(|x| (x, 10)).collect::<Vec<_>>());
+        let result = display.draw_sprite(60, 10, &[0xFF], false);
+        assert_eq!(result.collided_rows, 1);
+        assert_eq!(display.get_pixel(63, 10), 0);
+        assert_eq!(display.get_pixel(64, 10), 0);
+        assert_eq!(display.get_pixel(68, 10), 1);
+    }
+
+    #[test]
+    fn hires_sprite_wraps_at_column_128() {
+        let mut display = Display::new();
+        display.set_resolution(Resolution::High, false);
+        display.draw_sprite16(120, 63, &[0xFF, 0xFF, 0x80, 0x01], false);
+        // The second row wrapped to the top, its last pixel to column 7
+        let mut expected = vec![(7, 0), (120, 0)];
+        expected.extend((0..8).chain(120..128).map(|x| (x, 63)));
+        assert_eq!(lit(&display), expected);
+        let mut display = Display::new();
+        display.set_resolution(Resolution::High, false);
+        display.draw_sprite16(120, 63, &[0xFF, 0xFF, 0x80, 0x01], true);
+        assert_eq!(
+            lit(&display),
+            (120..128).map(|x| (x, 63)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn planes_draw_their_own_part_of_the_sprite() {
+        let mut display = Display::new();
+        display.select_planes(3);
+        display.draw_sprite(0, 0, &[0xC0, 0x60], false);
+        assert_eq!(display.to_ascii().lines().next().unwrap()[..4], *"#@+.");
+        display.select_planes(2);
+        display.clear();
+        assert_eq!(display.to_ascii().lines().next().unwrap()[..4], *"##..");
+    }
+
+    #[test]
+    fn scrolling_moves_pixels_and_blanks_the_rest() {
+        let mut display = Display::new();
+        display.set_pixel(10, 10, 1);
+        display.scroll_down(4);
+        assert_eq!(lit(&display), [(10, 14)]);
+        display.scroll_right(4);
+        assert_eq!(lit(&display), [(14, 14)]);
+        display.scroll_left(20);
+        assert!(lit(&display).is_empty());
+    }
+
+    #[test]
+    fn hires_scrolls_carry_pixels_across_column_64() {
+        let mut display = Display::new();
+        display.set_resolution(Resolution::High, false);
+        display.set_pixel(62, 5, 1);
+        display.scroll_right(4);
+        assert_eq!(lit(&display), [(66, 5)]);
+        display.scroll_left(4);
+        assert_eq!(lit(&display), [(62, 5)]);
+
+        // Back in lores, columns past 63 stay hidden and don't scroll in
+        display.set_pixel(66, 6, 1);
+        display.set_resolution(Resolution::Low, true);
+        display.scroll_left(4);
+        assert_eq!(lit(&display), [(58, 5)]);
+    }
+
+    #[test]
+    fn dirty_rows_follow_draws() {
+        let mut display = Display::new();
+        display.take_dirty();
+        display.draw_sprite(0, 30, &[0x80, 0x80, 0x80], false);
+        let dirty = display.take_dirty();
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), [0, 30, 31]);
+        assert!(display.take_dirty().is_empty());
+    }
+
+    #[test]
+    fn rgba_is_packed_row_by_row() {
+        let mut display = Display::new();
+        display.set_pixel(1, 0, 1);
+        display.set_pixel(63, 31, 1);
+        assert_eq!(
+            display.iter_pixels().collect::<Vec<_>>(),
+            [(1, 0, 1), (63, 31, 1)]
+        );
+        let palette = Palette {
+            colors: [0x102030, 0xA0B0C0, 0, 0],
+        };
+        let mut rgba = vec![0xEE; 3];
+        display.to_rgba(&palette, &mut rgba);
+        assert_eq!(rgba.len(), 64 * 32 * 4);
+        assert_eq!(rgba[..8], [0x10, 0x20, 0x30, 0xFF, 0xA0, 0xB0, 0xC0, 0xFF]);
+        assert_eq!(
+            rgba[rgba.len() - 8..],
+            [0x10, 0x20, 0x30, 0xFF, 0xA0, 0xB0, 0xC0, 0xFF]
+        );
+        let lit = rgba.chunks(4).filter(|&pixel| pixel[0] == 0xA0).count();
+        assert_eq!(lit, 2);
+
+        display.row_to_rgba(31, &palette, &mut rgba);
+        assert_eq!(rgba.len(), 64 * 4);
+        assert_eq!(rgba[63 * 4..], [0xA0, 0xB0, 0xC0, 0xFF]);
+    }
+
+    #[test]
+    fn dirty_region_covers_exactly_the_sprite_rows() {
+        let mut display = Display::new();
+        display.take_dirty();
+        display.draw_sprite(20, 10, &[0xF0, 0x90, 0x90, 0x90, 0xF0], false);
+        let dirty = display.take_dirty();
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), [10, 11, 12, 13, 14]);
+        assert!(dirty.contains(10) && !dirty.contains(9) && !dirty.contains(15));
+        assert!(display.take_dirty().is_empty());
+
+        // Blank sprite rows don't change the screen
+        display.draw_sprite(0, 0, &[0x00, 0x80], false);
+        assert_eq!(display.take_dirty().iter().collect::<Vec<_>>(), [1]);
+
+        // Clearing and scrolling touch the whole screen
+        display.clear();
+        assert_eq!(display.take_dirty().iter().count(), MAX_HEIGHT);
+        display.scroll_down(1);
+        assert!(display.take_dirty().iter().count() >= 32);
+    }
+
+    #[test]
+    fn rgba_fits_the_resolution_and_reuses_the_buffer() {
+        let palette = Palette {
+            colors: [0x000000, 0x33CC66, 0, 0],
+        };
+        let mut display = Display::new();
+        display.set_resolution(Resolution::High, false);
+        display.set_pixel(127, 0, 1);
+        let mut rgba = Vec::new();
+        display.to_rgba(&palette, &mut rgba);
+        assert_eq!(rgba.len(), 128 * 64 * 4);
+        assert_eq!(
+            rgba[126 * 4..128 * 4],
+            [0, 0, 0, 0xFF, 0x33, 0xCC, 0x66, 0xFF]
+        );
+
+        // Converting again into a buffer of the right size doesn't reallocate
+        let buffer = rgba.as_ptr();
+        display.to_rgba(&palette, &mut rgba);
+        assert_eq!(rgba.as_ptr(), buffer);
+
+        display.set_resolution(Resolution::Low, false);
+        display.to_rgba(&palette, &mut rgba);
+        assert_eq!(rgba.len(), 64 * 32 * 4);
+        assert!(rgba.chunks(4).all(|pixel| pixel == [0, 0, 0, 0xFF]));
+    }
+}
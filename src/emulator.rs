@@ -0,0 +1,594 @@
+use core::time::Duration;
+
+use crate::bus::{Bus, Ram};
+use crate::cpu::{CycleState, WatchpointHit, CPU};
+use crate::error::Chip8Error;
+use crate::instruction::VIP_MACHINE_CYCLES_PER_SECOND;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+// The delay and sound timers count down at 60Hz
+const TIMER_HZ: u64 = 60;
+// Longest time a single tick catches up on, so a stalled frontend doesn't cause a burst
+const MAX_TICK: Duration = Duration::from_millis(100);
+
+// How the emulator decides when to run the next instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    // Every instruction takes the same time, at clock_hz
+    Simple,
+    // Instructions take as long as they did on the COSMAC VIP, clock_hz is ignored
+    Original,
+}
+
+// What to do when the ROM executes a word that isn't a valid instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownOpcodePolicy {
+    Stop,
+    Skip,
+    Pause,
+}
+
+// What happened during one call to Emulator::tick
+#[derive(Debug, Default, PartialEq)]
+pub struct EmulatorOutput {
+    // instructions executed
+    pub instructions: u32,
+    // 60Hz timer updates
+    pub timer_ticks: u32,
+    // the display changed and needs redrawing
+    pub drew: bool,
+    // the sound timer is running, the buzzer should sound
+    pub beep: bool,
+    // the program finished during this tick
+    pub halted: bool,
+    // the error that paused or stopped emulation during this tick
+    pub error: Option<Chip8Error>,
+    // the breakpoint that paused emulation during this tick, see CPU::add_breakpoint
+    pub breakpoint: Option<u16>,
+    // the watched access that paused emulation during this tick, see CPU::add_watchpoint
+    pub watchpoint: Option<WatchpointHit>,
+}
+
+// A CPU running in real time: the frontend reports how much time passed and the emulator
+// runs the instructions and timer ticks that fit in it
+pub struct Emulator<B: Bus = Ram> {
+    pub cpu: CPU<B>,
+    pub timing: Timing,
+    // instructions per second with Timing::Simple
+    pub clock_hz: u32,
+    pub on_unknown_opcode: UnknownOpcodePolicy,
+    // what the CPU may still spend before it catches up with the clock: instructions with
+    // simple timing, machine cycles with original timing. Capped at one frame.
+    budget: u64,
+    // fraction of an instruction or machine cycle earned but not spent yet, in nanoseconds
+    // times the rate
+    budget_remainder: u64,
+    // time since the last timer tick, in nanoseconds times TIMER_HZ
+    timer_elapsed: u64,
+    paused: bool,
+    // instructions to run while paused, see step
+    steps: u32,
+    // frames to run while paused, see advance_frame
+    frames: u32,
+    halted: bool,
+    // an error stopped emulation for good, only reset() continues
+    stopped: bool,
+    waiting_for_key: bool,
+    // the ROM is polling the delay timer, don't run it again before the next tick
+    idle: bool,
+}
+
+impl<B: Bus> Emulator<B> {
+    pub fn new(cpu: CPU<B>, clock_hz: u32) -> Self {
+        Emulator {
+            cpu,
+            timing: Timing::Simple,
+            clock_hz,
+            on_unknown_opcode: UnknownOpcodePolicy::Stop,
+            budget: 0,
+            budget_remainder: 0,
+            timer_elapsed: 0,
+            paused: false,
+            steps: 0,
+            frames: 0,
+            halted: false,
+            stopped: false,
+            waiting_for_key: false,
+            idle: false,
+        }
+    }
+
+    // Load a ROM file, see CPU::load_rom
+    #[cfg(feature = "std")]
+    pub fn load_rom(&mut self, filename: &str) -> Result<usize, Chip8Error> {
+        self.cpu.load_rom(filename)
+    }
+
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<usize, Chip8Error> {
+        self.cpu.load_rom_bytes(data)
+    }
+
+    // Keys 0 to F currently held down
+    pub fn set_keys(&mut self, keys: &[u8]) -> Result<(), Chip8Error> {
+        self.cpu.keyboard.set_pressed(keys)
+    }
+
+    // Stop running instructions and timers until resume()
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.steps = 0;
+        self.frames = 0;
+    }
+
+    // Run one instruction on the next tick while paused, for single stepping. The timers stay
+    // frozen, so stepping through a delay loop never gets anywhere, but a DXYN waiting for the
+    // vertical blank draws right away.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.steps += 1;
+        }
+    }
+
+    // Run one 60Hz frame on the next tick while paused: the instructions a frame is worth at
+    // the clock rate, then a timer tick. Emulation stays paused afterwards, and a breakpoint
+    // or error ends the frame early.
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.frames += 1;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // The program finished, see CycleState::Halted
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    // Restart the loaded ROM, see CPU::soft_reset. Pausing is left as it was.
+    pub fn reset(&mut self) -> Result<(), Chip8Error> {
+        self.cpu.soft_reset()?;
+        self.halted = false;
+        self.stopped = false;
+        self.waiting_for_key = false;
+        self.idle = false;
+        self.budget = 0;
+        Ok(())
+    }
+
+    // Whether nothing will run before the next timer tick, so the frontend can sleep until then
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    pub fn time_to_next_timer(&self) -> Duration {
+        Duration::from_nanos(self.nanos_to_next_timer())
+    }
+
+    // Run everything that falls in the `elapsed` time since the last tick
+    pub fn tick(&mut self, elapsed: Duration) -> EmulatorOutput {
+        let mut output = EmulatorOutput::default();
+        while self.paused && self.steps > 0 && !self.halted && !self.stopped {
+            self.steps -= 1;
+            self.cpu.release_vblank();
+            self.exec_instruction(&mut output);
+            // Hitting a breakpoint or watchpoint drops the rest of the steps
+            if output.breakpoint.is_some() || output.watchpoint.is_some() {
+                self.steps = 0;
+            }
+        }
+        while self.paused && self.frames > 0 && !self.halted && !self.stopped {
+            self.frames -= 1;
+            self.paused = false;
+            self.run_frame(&mut output);
+            // Whatever paused emulation during the frame drops the rest of the frames
+            if self.paused {
+                self.frames = 0;
+            }
+            self.paused = true;
+        }
+        let mut remaining = elapsed.min(MAX_TICK).as_nanos() as u64;
+        // Step from timer tick to timer tick, so instructions see the timers change on time
+        while remaining > 0 && !self.stopped {
+            let step = remaining.min(self.nanos_to_next_timer());
+            remaining -= step;
+            self.run_instructions(step, &mut output);
+            if self.paused || self.stopped {
+                continue;
+            }
+            self.timer_elapsed += step * TIMER_HZ;
+            if self.timer_elapsed >= NANOS_PER_SECOND {
+                self.timer_elapsed -= NANOS_PER_SECOND;
+                self.tick_timers(&mut output);
+            }
+        }
+        output.drew = self.cpu.display.need_redraw;
+        output.beep = self.cpu.st > 0;
+        output
+    }
+
+    // Instructions or machine cycles per second
+    fn rate(&self) -> u64 {
+        match self.timing {
+            Timing::Simple => self.clock_hz as u64,
+            Timing::Original => VIP_MACHINE_CYCLES_PER_SECOND,
+        }
+    }
+
+    fn nanos_to_next_timer(&self) -> u64 {
+        (NANOS_PER_SECOND - self.timer_elapsed).div_ceil(TIMER_HZ)
+    }
+
+    fn run_instructions(&mut self, nanos: u64, output: &mut EmulatorOutput) {
+        let rate = self.rate();
+        self.budget_remainder += nanos * rate;
+        let earned = self.budget_remainder / NANOS_PER_SECOND;
+        self.budget_remainder %= NANOS_PER_SECOND;
+        self.budget = (self.budget + earned).min(self.frame_budget());
+        self.spend_budget(output);
+    }
+
+    // The most the CPU gets to spend in one 60Hz frame
+    fn frame_budget(&self) -> u64 {
+        (self.rate() / TIMER_HZ).max(1)
+    }
+
+    // One whole frame regardless of the time passed, see advance_frame and CPU::run_frame
+    fn run_frame(&mut self, output: &mut EmulatorOutput) {
+        let budget = self.frame_budget();
+        let timing = self.timing;
+        let (instructions, cycles) = (self.cpu.stats().instructions, self.cpu.machine_cycles());
+        // Skipped opcodes cost one each, as in exec_instruction
+        let mut skipped = 0;
+        loop {
+            let frame = self.cpu.run_frame_while(|cpu, _| {
+                let spent = match timing {
+                    Timing::Simple => cpu.stats().instructions - instructions,
+                    Timing::Original => cpu.machine_cycles() - cycles,
+                };
+                spent + skipped < budget
+            });
+            match frame {
+                Ok(frame) => {
+                    self.react(frame.ended_by.unwrap_or(CycleState::Normal), output);
+                    if !self.paused {
+                        output.timer_ticks += 1;
+                        self.idle = false;
+                    }
+                    break;
+                }
+                Err(e) => match self.skip(e, output) {
+                    true => skipped += 1,
+                    false => break,
+                },
+            }
+        }
+        output.instructions += (self.cpu.stats().instructions - instructions) as u32;
+    }
+
+    fn tick_timers(&mut self, output: &mut EmulatorOutput) {
+        self.cpu.update_timers();
+        output.timer_ticks += 1;
+        self.idle = false;
+    }
+
+    // Run instructions until the budget is spent or the CPU can't go on
+    fn spend_budget(&mut self, output: &mut EmulatorOutput) {
+        while self.budget > 0 {
+            // No point executing FX0A over and over until a key goes down and up
+            let keyboard = &self.cpu.keyboard;
+            let blocked = self.waiting_for_key
+                && keyboard.first_pressed().is_none()
+                && keyboard.recently_released().is_none();
+            if self.paused || self.halted || self.stopped || blocked || self.idle {
+                // Time spent waiting isn't owed to the program afterwards
+                self.budget = 0;
+                break;
+            }
+
+            let cost = self.exec_instruction(output);
+            self.budget = self.budget.saturating_sub(cost);
+        }
+    }
+
+    // Execute the instruction at pc and record what happened in output. Returns what it cost
+    // against the budget.
+    fn exec_instruction(&mut self, output: &mut EmulatorOutput) -> u64 {
+        let (instructions, cycles) = (self.cpu.stats().instructions, self.cpu.machine_cycles());
+        match self.cpu.exec_cycle() {
+            Ok(state) => self.react(state, output),
+            Err(e) => {
+                self.skip(e, output);
+            }
+        }
+        output.instructions += (self.cpu.stats().instructions - instructions) as u32;
+        // Skipped instructions still cost something, so the loop always makes progress
+        match self.timing {
+            Timing::Simple => 1,
+            Timing::Original => (self.cpu.machine_cycles() - cycles).max(1),
+        }
+    }
+
+    // Follow the state the CPU stopped in
+    fn react(&mut self, state: CycleState, output: &mut EmulatorOutput) {
+        self.waiting_for_key = state == CycleState::WaitingForKey;
+        match state {
+            CycleState::IdleUntilTimer => self.idle = true,
+            CycleState::Halted => {
+                output.halted = true;
+                self.halted = true;
+            }
+            // The pre-execution hook stops the emulator, e.g. on a breakpoint
+            CycleState::Paused => self.paused = true,
+            CycleState::WatchpointHit(hit) => {
+                self.paused = true;
+                output.watchpoint = Some(hit);
+            }
+            CycleState::BreakpointHit { addr } => {
+                self.paused = true;
+                output.breakpoint = Some(addr);
+            }
+            CycleState::Normal
+            | CycleState::DrewSprite
+            | CycleState::WaitingForVblank
+            | CycleState::WaitingForKey => {}
+        }
+    }
+
+    // Apply on_unknown_opcode to an error, true when the program goes on past it
+    fn skip(&mut self, e: Chip8Error, output: &mut EmulatorOutput) -> bool {
+        match (&e, self.on_unknown_opcode) {
+            (Chip8Error::UnknownOpcode { .. }, UnknownOpcodePolicy::Skip) => {
+                log!("Skipping {}", e);
+                self.cpu.pc = self.cpu.pc.wrapping_add(2) & self.cpu.address_mask();
+                return true;
+            }
+            (Chip8Error::UnknownOpcode { .. }, UnknownOpcodePolicy::Pause) => self.paused = true,
+            _ => self.stopped = true,
+        }
+        output.error = Some(e);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Platform;
+
+    // LD V0, 1 / an unknown opcode / LD V1, 2 / JP to itself
+    const BAD_OPCODE: [u8; 8] = [0x60, 0x01, 0xE0, 0x00, 0x61, 0x02, 0x12, 0x06];
+
+    fn emulator(program: &[u8]) -> Emulator {
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(program).unwrap();
+        Emulator::new(cpu, 600)
+    }
+
+    #[test]
+    fn unknown_opcode_stops_by_default() {
+        let mut emulator = emulator(&BAD_OPCODE);
+        let output = emulator.tick(Duration::from_millis(50));
+        assert_eq!(
+            output.error,
+            Some(Chip8Error::UnknownOpcode {
+                opcode: 0xE000,
+                pc: 0x202
+            })
+        );
+        assert_eq!(output.instructions, 1);
+        // Nothing runs any more
+        let output = emulator.tick(Duration::from_millis(50));
+        assert_eq!((output.instructions, output.timer_ticks), (0, 0));
+        assert_eq!(output.error, None);
+        assert_eq!(emulator.cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn unknown_opcode_can_be_skipped() {
+        let mut emulator = emulator(&BAD_OPCODE);
+        emulator.on_unknown_opcode = UnknownOpcodePolicy::Skip;
+        let output = emulator.tick(Duration::from_millis(50));
+        assert_eq!(output.error, None);
+        assert!(output.halted);
+        assert_eq!(emulator.cpu.v[1], 2);
+    }
+
+    #[test]
+    fn unknown_opcode_can_pause() {
+        let mut emulator = emulator(&BAD_OPCODE);
+        emulator.on_unknown_opcode = UnknownOpcodePolicy::Pause;
+        let output = emulator.tick(Duration::from_millis(50));
+        assert!(output.error.is_some());
+        assert!(emulator.is_paused());
+        assert_eq!(emulator.cpu.pc, 0x202);
+    }
+
+    #[test]
+    fn original_timing_spends_machine_cycles() {
+        // DRW V0, V0, 15 / JP 0x200, 68 + 15 * 46 + 12 = 770 machine cycles a loop
+        let mut original = emulator(&[0xD0, 0x0F, 0x12, 0x00]);
+        original.timing = Timing::Original;
+        let output = original.tick(Duration::from_millis(100));
+        // 100ms of a 220,080 Hz machine cycle clock is about 28 loops. Each of the six
+        // frames may start one instruction it can't quite afford.
+        let loops = VIP_MACHINE_CYCLES_PER_SECOND / 10 / 770;
+        assert!((2 * loops..=2 * loops + 6).contains(&(output.instructions as u64)));
+        // Simple timing runs clock_hz instructions a second whatever they are
+        let mut simple = emulator(&[0xD0, 0x0F, 0x12, 0x00]);
+        let output = simple.tick(Duration::from_millis(100));
+        assert_eq!(output.instructions, 60);
+    }
+
+    #[test]
+    fn idle_until_the_next_timer_tick() {
+        // LD V0, DT / SE V0, 0 / JP 0x200 / JP 0x206
+        let mut emulator = emulator(&[0xF0, 0x07, 0x30, 0x00, 0x12, 0x00, 0x12, 0x06]);
+        emulator.cpu.dt = 2;
+        // A 60Hz frame is worth 10 instructions at 600Hz, but the loop stops after one pass
+        let output = emulator.tick(Duration::from_millis(10));
+        assert_eq!(output.instructions, 3);
+        assert!(emulator.is_idle());
+        let output = emulator.tick(emulator.time_to_next_timer());
+        assert_eq!(output.timer_ticks, 1);
+        assert!(!emulator.is_idle());
+        emulator.tick(Duration::from_millis(50));
+        assert!(emulator.is_halted());
+    }
+
+    #[test]
+    fn tick_runs_at_the_configured_rates() {
+        // ADD V0, 1 / JP 0x200
+        let mut emulator = emulator(&[0x70, 0x01, 0x12, 0x00]);
+        let (mut instructions, mut timer_ticks) = (0, 0);
+        for _ in 0..100 {
+            let output = emulator.tick(Duration::from_millis(10));
+            instructions += output.instructions;
+            timer_ticks += output.timer_ticks;
+        }
+        assert_eq!((instructions, timer_ticks), (600, 60));
+
+        emulator.clock_hz = 1200;
+        let output = emulator.tick(Duration::from_millis(50));
+        assert_eq!((output.instructions, output.timer_ticks), (60, 3));
+        // A long stall doesn't make the emulator race to catch up
+        let output = emulator.tick(Duration::from_secs(5));
+        assert_eq!((output.instructions, output.timer_ticks), (120, 6));
+    }
+
+    #[test]
+    fn pause_step_and_reset() {
+        // ADD V0, 1 / JP 0x200
+        let mut emulator = emulator(&[0x70, 0x01, 0x12, 0x00]);
+        emulator.pause();
+        let output = emulator.tick(Duration::from_millis(100));
+        assert_eq!((output.instructions, output.timer_ticks), (0, 0));
+        emulator.step();
+        emulator.step();
+        assert_eq!(emulator.tick(Duration::from_millis(100)).instructions, 2);
+        assert_eq!((emulator.cpu.pc, emulator.cpu.v[0]), (0x200, 1));
+        emulator.advance_frame();
+        let output = emulator.tick(Duration::ZERO);
+        assert_eq!((output.instructions, output.timer_ticks), (10, 1));
+        assert!(emulator.is_paused());
+
+        emulator.resume();
+        assert_eq!(emulator.tick(Duration::from_millis(50)).instructions, 30);
+        emulator.reset().unwrap();
+        assert_eq!((emulator.cpu.pc, emulator.cpu.v[0]), (0x200, 0));
+    }
+
+    #[test]
+    fn pause_freezes_the_timers_and_the_beep() {
+        // JP to itself
+        let mut emulator = emulator(&[0x12, 0x00]);
+        emulator.cpu.st = 5;
+        emulator.cpu.dt = 5;
+        emulator.pause();
+        let output = emulator.tick(Duration::from_millis(100));
+        assert!(output.beep);
+        emulator.step();
+        emulator.tick(Duration::from_millis(100));
+        assert_eq!((emulator.cpu.st, emulator.cpu.dt), (5, 5));
+        emulator.resume();
+        let output = emulator.tick(Duration::from_millis(100));
+        assert_eq!(output.timer_ticks, 6);
+        assert!(!output.beep);
+    }
+
+    #[test]
+    fn keys_still_reach_a_paused_fx0a() {
+        // LD V0, K / ADD V1, 1
+        let mut emulator = emulator(&[0xF0, 0x0A, 0x71, 0x01]);
+        emulator.pause();
+        emulator.step();
+        emulator.tick(Duration::ZERO);
+        assert_eq!(emulator.cpu.pc, 0x200);
+        emulator.set_keys(&[0x5]).unwrap();
+        emulator.step();
+        emulator.tick(Duration::ZERO);
+        emulator.set_keys(&[]).unwrap();
+        emulator.step();
+        emulator.tick(Duration::ZERO);
+        assert_eq!((emulator.cpu.pc, emulator.cpu.v[0]), (0x202, 0x5));
+        emulator.step();
+        emulator.tick(Duration::ZERO);
+        assert_eq!((emulator.cpu.pc, emulator.cpu.v[1]), (0x204, 1));
+    }
+
+    #[test]
+    fn frame_advance_runs_one_frame_and_stays_paused() {
+        // ADD V0, 1 / JP 0x200
+        let mut emulator = emulator(&[0x70, 0x01, 0x12, 0x00]);
+        emulator.cpu.dt = 10;
+        emulator.pause();
+        emulator.advance_frame();
+        emulator.advance_frame();
+        let output = emulator.tick(Duration::from_millis(100));
+        assert_eq!((output.instructions, output.timer_ticks), (20, 2));
+        assert_eq!((emulator.cpu.v[0], emulator.cpu.dt), (10, 8));
+        assert!(emulator.is_paused());
+        assert_eq!(emulator.tick(Duration::from_millis(100)).instructions, 0);
+        // Without a pause there's nothing to advance
+        emulator.resume();
+        emulator.advance_frame();
+        emulator.pause();
+        assert_eq!(emulator.tick(Duration::ZERO).instructions, 0);
+    }
+
+    #[test]
+    fn breakpoint_ends_the_frame_early() {
+        // ADD V0, 1 / ADD V1, 1 / JP 0x200
+        let mut emulator = emulator(&[0x70, 0x01, 0x71, 0x01, 0x12, 0x00]);
+        emulator.cpu.add_breakpoint(0x202);
+        emulator.pause();
+        emulator.advance_frame();
+        emulator.advance_frame();
+        let output = emulator.tick(Duration::ZERO);
+        assert_eq!(output.breakpoint, Some(0x202));
+        assert_eq!((output.instructions, output.timer_ticks), (1, 0));
+        assert!(emulator.is_paused());
+        // The second frame was dropped too
+        assert_eq!(emulator.tick(Duration::ZERO).instructions, 0);
+    }
+
+    #[test]
+    fn frame_advance_goes_on_past_skipped_opcodes() {
+        let mut emulator = emulator(&BAD_OPCODE);
+        emulator.on_unknown_opcode = UnknownOpcodePolicy::Skip;
+        emulator.pause();
+        emulator.advance_frame();
+        let output = emulator.tick(Duration::ZERO);
+        assert_eq!(output.error, None);
+        // Through to the jump to itself, which ends the program
+        assert_eq!((output.instructions, output.timer_ticks), (3, 1));
+        assert!(output.halted);
+        assert_eq!(emulator.cpu.v[1], 2);
+    }
+
+    #[test]
+    fn steps_get_past_the_display_wait() {
+        // LD F, V0 / DRW V0, V0, 5 / DRW V0, V0, 5 / ADD V1, 1
+        let mut cpu = CPU::builder().platform(Platform::Chip8).build();
+        cpu.load_rom_bytes(&[0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0x71, 0x01])
+            .unwrap();
+        assert!(cpu.quirks.display_wait);
+        let mut emulator = Emulator::new(cpu, 600);
+        emulator.pause();
+        for pc in [0x202, 0x204, 0x206, 0x208].iter() {
+            emulator.step();
+            let output = emulator.tick(Duration::ZERO);
+            assert_eq!(output.instructions, 1);
+            assert_eq!(emulator.cpu.pc, *pc);
+        }
+        // The second draw erased the first
+        assert_eq!(emulator.cpu.display.iter_pixels().count(), 0);
+        assert_eq!((emulator.cpu.v[0xF], emulator.cpu.v[1]), (1, 1));
+    }
+}
@@ -0,0 +1,251 @@
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+use thiserror::Error;
+
+use crate::disasm::disassemble;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum Chip8Error {
+    // An instruction tried to access memory past the end of the address space
+    #[error(
+        "memory access out of bounds at {addr:#X} (opcode {opcode:#06X}: {}, at PC {pc:#05X})",
+        disassemble(*.opcode)
+    )]
+    MemoryOutOfBounds { addr: usize, pc: u16, opcode: u16 },
+    // The program counter reached addr, where a whole opcode can't be fetched from.
+    // pc is the instruction that got it there, or the fetch address itself when running off the end of memory.
+    #[error("program counter out of bounds at {addr:#X} (from PC {pc:#05X})")]
+    ProgramCounterOutOfBounds { addr: usize, pc: u16 },
+    // RET was executed with an empty call stack
+    #[error(
+        "stack underflow: return with an empty stack (opcode {opcode:#06X}: {}, at PC {pc:#05X})",
+        disassemble(*.opcode)
+    )]
+    StackUnderflow { pc: u16, opcode: u16 },
+    // CALL was executed with a full call stack
+    #[error(
+        "stack overflow: call with a full stack (opcode {opcode:#06X}: {}, at PC {pc:#05X})",
+        disassemble(*.opcode)
+    )]
+    StackOverflow { pc: u16, opcode: u16 },
+    // The word at pc doesn't decode to any instruction
+    #[error("unknown opcode {opcode:#06X} at PC {pc:#05X}")]
+    UnknownOpcode { opcode: u16, pc: u16 },
+    // A 0NNN call into COSMAC machine code was executed in strict mode
+    #[error(
+        "unsupported SYS call to machine code at {:#05X} (opcode {opcode:#06X}: {}, at PC {pc:#05X})",
+        .opcode & 0x0FFF,
+        disassemble(*.opcode)
+    )]
+    SysCall { opcode: u16, pc: u16 },
+    // An instruction wrote into the protected interpreter area
+    #[error(
+        "write to protected interpreter area at {addr:#05X} (opcode {opcode:#06X}: {}, at PC {pc:#05X})",
+        disassemble(*.opcode)
+    )]
+    WriteProtected { addr: u16, pc: u16, opcode: u16 },
+    // A byte that was never written since reset was read, with the uninitialized read check on
+    #[error("read of uninitialized memory at {addr:#05X} (from PC {pc:#05X})")]
+    UninitializedRead { addr: u16, pc: u16 },
+    // An operation needing a loaded ROM was attempted on an empty machine
+    #[error("no ROM is loaded")]
+    NoRomLoaded,
+    // A key outside 0 to F was pressed or released
+    #[error("no key {key:#04X}, keys are 0 to F")]
+    InvalidKey { key: u8 },
+    // A tool asked for a register past VF
+    #[error("no register {x}, registers are V0 to VF")]
+    InvalidRegister { x: usize },
+    // A tool accessed len bytes at addr, reaching past the end of memory
+    #[error("{len} bytes at {addr:#X} reach past the end of memory")]
+    AddressOutOfRange { addr: usize, len: usize },
+    // The ROM couldn't be loaded
+    #[error(transparent)]
+    RomLoad(#[from] RomLoadError),
+}
+
+#[derive(Debug, Error)]
+pub enum RomLoadError {
+    // The ROM file couldn't be read
+    #[cfg(feature = "std")]
+    #[error("couldn't read ROM: {0}")]
+    Io(#[from] io::Error),
+    // The ROM doesn't fit in program memory
+    #[error("ROM is {size} bytes but only {max} bytes fit in memory")]
+    TooLarge { size: usize, max: usize },
+    // The ROM file has no contents
+    #[error("ROM is empty")]
+    Empty,
+}
+
+// io::Error can't be cloned either, the copy keeps its kind and message
+impl Clone for RomLoadError {
+    fn clone(&self) -> Self {
+        match self {
+            #[cfg(feature = "std")]
+            RomLoadError::Io(e) => RomLoadError::Io(io::Error::new(e.kind(), e.to_string())),
+            RomLoadError::TooLarge { size, max } => RomLoadError::TooLarge {
+                size: *size,
+                max: *max,
+            },
+            RomLoadError::Empty => RomLoadError::Empty,
+        }
+    }
+}
+
+// io::Error can't be compared, so read errors are equal when their kinds are
+impl PartialEq for RomLoadError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            #[cfg(feature = "std")]
+            (RomLoadError::Io(a), RomLoadError::Io(b)) => a.kind() == b.kind(),
+            (
+                RomLoadError::TooLarge { size, max },
+                RomLoadError::TooLarge {
+                    size: other_size,
+                    max: other_max,
+                },
+            ) => size == other_size && max == other_max,
+            (RomLoadError::Empty, RomLoadError::Empty) => true,
+            _ => false,
+        }
+    }
+}
+
+// Only custom font files can fail to load
+#[cfg(feature = "std")]
+#[derive(Debug, Error)]
+pub enum FontLoadError {
+    // The font file couldn't be read
+    #[error("couldn't read font: {0}")]
+    Io(#[from] io::Error),
+    // The font file isn't exactly 16 glyphs of 5 bytes
+    #[error("font is {size} bytes but must be exactly 80 (16 glyphs of 5 rows)")]
+    WrongSize { size: usize },
+}
+
+// A breakpoint condition that doesn't parse, see Condition::parse
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum ConditionError {
+    // Nothing to compare on one side of && or ||, or no condition at all
+    #[error("empty condition")]
+    Empty,
+    // A part between && and || isn't two operands and a comparison
+    #[error("{term} isn't a comparison with ==, !=, <, <=, > or >=")]
+    MissingComparison { term: String },
+    // Not a register, I, DT, ST, mem[ADDR] or number
+    #[error("{operand} isn't v0 to vf, i, dt, st, mem[ADDR] or a number")]
+    BadOperand { operand: String },
+}
+
+// Why assembly was rejected, see asm::assemble. line counts from 1 and token is the part of it
+// at fault.
+// Display is written out below, its wording depends on the kind
+#[derive(Clone, Debug, Error, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub token: String,
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AsmErrorKind {
+    // Not an instruction or directive
+    UnknownMnemonic,
+    // The operands don't match any form of the instruction
+    BadOperands,
+    // A label used but never defined
+    UndefinedLabel,
+    // A label defined twice
+    DuplicateLabel,
+    // A number too big for its field, such as an address past 0xFFF
+    OutOfRange { max: u16 },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            AsmErrorKind::UnknownMnemonic => write!(f, "unknown instruction {}", self.token),
+            AsmErrorKind::BadOperands => write!(f, "bad operands {}", self.token),
+            AsmErrorKind::UndefinedLabel => write!(f, "undefined label {}", self.token),
+            AsmErrorKind::DuplicateLabel => write!(f, "label {} is already defined", self.token),
+            AsmErrorKind::OutOfRange { max } => {
+                write!(f, "{} is out of range, the most is {:#X}", self.token, max)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn messages_name_the_pc_and_opcode() {
+        let unknown = Chip8Error::UnknownOpcode {
+            opcode: 0x5AB1,
+            pc: 0x2F4,
+        };
+        assert_eq!(unknown.to_string(), "unknown opcode 0x5AB1 at PC 0x2F4");
+
+        let underflow = Chip8Error::StackUnderflow {
+            pc: 0x3A0,
+            opcode: 0x00EE,
+        }
+        .to_string();
+        assert!(underflow.starts_with("stack underflow"), "{}", underflow);
+        assert!(
+            underflow.contains("0x00EE") && underflow.contains("0x3A0"),
+            "{}",
+            underflow
+        );
+
+        let bounds = Chip8Error::MemoryOutOfBounds {
+            addr: 0x1000,
+            pc: 0x24E,
+            opcode: 0xF365,
+        }
+        .to_string();
+        assert!(
+            bounds.contains("0x1000") && bounds.contains("0xF365"),
+            "{}",
+            bounds
+        );
+        assert!(bounds.contains("0x24E"), "{}", bounds);
+    }
+
+    #[test]
+    fn rom_load_errors_explain_themselves() {
+        let too_large: Chip8Error = RomLoadError::TooLarge {
+            size: 4000,
+            max: 3584,
+        }
+        .into();
+        assert_eq!(
+            too_large.to_string(),
+            "ROM is 4000 bytes but only 3584 bytes fit in memory"
+        );
+        assert_eq!(
+            Chip8Error::from(RomLoadError::Empty).to_string(),
+            "ROM is empty"
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_errors_keep_the_io_error_as_source() {
+        use std::error::Error;
+
+        let e = Chip8Error::from(RomLoadError::from(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no such file",
+        )));
+        assert_eq!(e.to_string(), "couldn't read ROM: no such file");
+        assert_eq!(e.source().unwrap().to_string(), "no such file");
+    }
+}
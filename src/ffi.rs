@@ -0,0 +1,309 @@
+// C interface to the emulator core, declared in ffi/rusty_chip8.h.
+// Build a library for C with
+//   cargo rustc --lib --release --no-default-features --features ffi --crate-type staticlib
+// Every function catches panics, so none unwind into C. Functions returning int use 0 or a
+// positive result for success and one of the negative codes below for failure.
+// Safety: a handle passed in must be NULL or come from chip8_new and not be freed yet, and a
+// buffer with a length must have that many bytes to read or write.
+#![allow(clippy::missing_safety_doc)]
+
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::cpu::CPU;
+use crate::error::Chip8Error;
+
+pub const CHIP8_OK: c_int = 0;
+// The handle or a buffer was NULL
+pub const CHIP8_ERR_NULL: c_int = -1;
+// The emulator panicked, the handle shouldn't be used except to free it
+pub const CHIP8_ERR_PANIC: c_int = -2;
+// The output buffer can't hold the screen
+pub const CHIP8_ERR_BUFFER_TOO_SMALL: c_int = -3;
+// The Chip8Error variants, in declaration order
+pub const CHIP8_ERR_MEMORY_OUT_OF_BOUNDS: c_int = -10;
+pub const CHIP8_ERR_PC_OUT_OF_BOUNDS: c_int = -11;
+pub const CHIP8_ERR_STACK_UNDERFLOW: c_int = -12;
+pub const CHIP8_ERR_STACK_OVERFLOW: c_int = -13;
+pub const CHIP8_ERR_UNKNOWN_OPCODE: c_int = -14;
+pub const CHIP8_ERR_SYS_CALL: c_int = -15;
+pub const CHIP8_ERR_WRITE_PROTECTED: c_int = -16;
+pub const CHIP8_ERR_UNINITIALIZED_READ: c_int = -17;
+pub const CHIP8_ERR_NO_ROM_LOADED: c_int = -18;
+pub const CHIP8_ERR_INVALID_KEY: c_int = -19;
+pub const CHIP8_ERR_INVALID_REGISTER: c_int = -20;
+pub const CHIP8_ERR_ADDRESS_OUT_OF_RANGE: c_int = -21;
+pub const CHIP8_ERR_ROM_LOAD: c_int = -22;
+
+// The opaque handle C code holds
+pub struct Chip8 {
+    cpu: CPU,
+}
+
+pub fn error_code(e: &Chip8Error) -> c_int {
+    match e {
+        Chip8Error::MemoryOutOfBounds { .. } => CHIP8_ERR_MEMORY_OUT_OF_BOUNDS,
+        Chip8Error::ProgramCounterOutOfBounds { .. } => CHIP8_ERR_PC_OUT_OF_BOUNDS,
+        Chip8Error::StackUnderflow { .. } => CHIP8_ERR_STACK_UNDERFLOW,
+        Chip8Error::StackOverflow { .. } => CHIP8_ERR_STACK_OVERFLOW,
+        Chip8Error::UnknownOpcode { .. } => CHIP8_ERR_UNKNOWN_OPCODE,
+        Chip8Error::SysCall { .. } => CHIP8_ERR_SYS_CALL,
+        Chip8Error::WriteProtected { .. } => CHIP8_ERR_WRITE_PROTECTED,
+        Chip8Error::UninitializedRead { .. } => CHIP8_ERR_UNINITIALIZED_READ,
+        Chip8Error::NoRomLoaded => CHIP8_ERR_NO_ROM_LOADED,
+        Chip8Error::InvalidKey { .. } => CHIP8_ERR_INVALID_KEY,
+        Chip8Error::InvalidRegister { .. } => CHIP8_ERR_INVALID_REGISTER,
+        Chip8Error::AddressOutOfRange { .. } => CHIP8_ERR_ADDRESS_OUT_OF_RANGE,
+        Chip8Error::RomLoad(_) => CHIP8_ERR_ROM_LOAD,
+    }
+}
+
+// Run f on the emulator behind handle, turning a NULL handle and panics into error codes
+unsafe fn with_handle<F>(handle: *mut Chip8, f: F) -> c_int
+where
+    F: FnOnce(&mut Chip8) -> Result<c_int, Chip8Error>,
+{
+    let chip8 = match handle.as_mut() {
+        Some(chip8) => chip8,
+        None => return CHIP8_ERR_NULL,
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| f(chip8))) {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => error_code(&e),
+        Err(_) => CHIP8_ERR_PANIC,
+    }
+}
+
+// A reset CHIP-8 with the default quirks, or NULL on failure. Free it with chip8_free.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(Chip8 {
+            cpu: CPU::builder().build(),
+        }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+// Free a handle from chip8_new, NULL is ignored
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(handle: *mut Chip8) {
+    if !handle.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(handle))));
+    }
+}
+
+// Load len bytes of ROM at the program start. Returns the number of bytes loaded.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(handle: *mut Chip8, data: *const u8, len: usize) -> c_int {
+    if data.is_null() {
+        return CHIP8_ERR_NULL;
+    }
+    let rom = slice::from_raw_parts(data, len);
+    with_handle(handle, |chip8| {
+        chip8.cpu.load_rom_bytes(rom).map(|size| size as c_int)
+    })
+}
+
+// Run one 60Hz frame of up to `cycles` instructions, see CPU::run_frame.
+// Returns the number of instructions executed.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(handle: *mut Chip8, cycles: u32) -> c_int {
+    with_handle(handle, |chip8| {
+        let frame = chip8.cpu.run_frame(cycles)?;
+        Ok(frame.instructions.min(c_int::MAX as u32) as c_int)
+    })
+}
+
+// Keys held down, bit k set for key k
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys(handle: *mut Chip8, keys: u16) -> c_int {
+    with_handle(handle, |chip8| {
+        let held: Vec<u8> = (0..16).filter(|k| keys >> k & 1 == 1).collect();
+        chip8.cpu.keyboard.set_pressed(&held)?;
+        Ok(CHIP8_OK)
+    })
+}
+
+// Copy the color index of every pixel on the visible screen, row by row, into out.
+// Returns the number of bytes written, width times height of the current resolution.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(
+    handle: *mut Chip8,
+    out: *mut u8,
+    out_len: usize,
+) -> c_int {
+    if out.is_null() {
+        return CHIP8_ERR_NULL;
+    }
+    let out = slice::from_raw_parts_mut(out, out_len);
+    with_handle(handle, |chip8| {
+        let display = &chip8.cpu.display;
+        let (width, height) = (display.width(), display.height());
+        if out.len() < width * height {
+            return Ok(CHIP8_ERR_BUFFER_TOO_SMALL);
+        }
+        for y in 0..height {
+            for x in 0..width {
+                out[y * width + x] = display.get_pixel(x, y);
+            }
+        }
+        Ok((width * height) as c_int)
+    })
+}
+
+// Width and height of the current resolution in pixels
+#[no_mangle]
+pub unsafe extern "C" fn chip8_width(handle: *mut Chip8) -> c_int {
+    with_handle(handle, |chip8| Ok(chip8.cpu.display.width() as c_int))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn chip8_height(handle: *mut Chip8) -> c_int {
+    with_handle(handle, |chip8| Ok(chip8.cpu.display.height() as c_int))
+}
+
+// 1 while the sound timer runs and the buzzer should sound, 0 otherwise
+#[no_mangle]
+pub unsafe extern "C" fn chip8_beeping(handle: *mut Chip8) -> c_int {
+    with_handle(handle, |chip8| Ok((chip8.cpu.st > 0) as c_int))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draw the 0 glyph at (0, 0), start the sound timer, wait for key 5, then count in V0.
+    // The same program as ffi/test.c.
+    const ROM: [u8; 14] = [
+        0xA0, 0x00, 0xD0, 0x05, 0x60, 0x10, 0xF0, 0x18, 0xF1, 0x0A, 0x70, 0x01, 0x12, 0x0A,
+    ];
+
+    #[test]
+    fn runs_a_program_through_the_abi() {
+        unsafe {
+            let chip8 = chip8_new();
+            assert!(!chip8.is_null());
+            assert_eq!(
+                chip8_load_rom(chip8, ROM.as_ptr(), ROM.len()),
+                ROM.len() as c_int
+            );
+            // Up to the key wait
+            assert_eq!(chip8_run_frame(chip8, 10), 4);
+            assert_eq!(chip8_beeping(chip8), 1);
+            assert_eq!((chip8_width(chip8), chip8_height(chip8)), (64, 32));
+
+            let mut screen = [0u8; 64 * 32];
+            assert_eq!(
+                chip8_framebuffer(chip8, screen.as_mut_ptr(), screen.len()),
+                64 * 32
+            );
+            // Top row of the 0 glyph is 0xF0
+            assert_eq!(screen[..5], [1, 1, 1, 1, 0]);
+            assert_eq!(screen[64..69], [1, 0, 0, 1, 0]);
+
+            // FX0A finishes when the key is let go
+            assert_eq!(chip8_set_keys(chip8, 1 << 5), CHIP8_OK);
+            assert!((*chip8).cpu.keyboard.is_pressed(5));
+            assert_eq!(chip8_run_frame(chip8, 10), 0);
+            assert_eq!(chip8_set_keys(chip8, 0), CHIP8_OK);
+            assert_eq!(chip8_run_frame(chip8, 10), 10);
+            assert_eq!((*chip8).cpu.v[1], 5);
+            chip8_free(chip8);
+        }
+    }
+
+    #[test]
+    fn reports_error_codes() {
+        unsafe {
+            let null = ptr::null_mut();
+            let mut screen = [0u8; 64 * 32];
+            assert_eq!(
+                chip8_load_rom(null, ROM.as_ptr(), ROM.len()),
+                CHIP8_ERR_NULL
+            );
+            assert_eq!(chip8_run_frame(null, 10), CHIP8_ERR_NULL);
+            assert_eq!(chip8_set_keys(null, 0), CHIP8_ERR_NULL);
+            assert_eq!(
+                chip8_framebuffer(null, screen.as_mut_ptr(), screen.len()),
+                CHIP8_ERR_NULL
+            );
+            assert_eq!(chip8_width(null), CHIP8_ERR_NULL);
+            assert_eq!(chip8_height(null), CHIP8_ERR_NULL);
+            assert_eq!(chip8_beeping(null), CHIP8_ERR_NULL);
+            chip8_free(null);
+
+            let chip8 = chip8_new();
+            assert_eq!(chip8_load_rom(chip8, ptr::null(), 0), CHIP8_ERR_NULL);
+            assert_eq!(chip8_load_rom(chip8, ROM.as_ptr(), 0), CHIP8_ERR_ROM_LOAD);
+            assert_eq!(chip8_framebuffer(chip8, ptr::null_mut(), 0), CHIP8_ERR_NULL);
+            assert_eq!(
+                chip8_framebuffer(chip8, screen.as_mut_ptr(), screen.len() - 1),
+                CHIP8_ERR_BUFFER_TOO_SMALL
+            );
+            // RET with an empty stack
+            let ret = [0x00, 0xEE];
+            assert_eq!(chip8_load_rom(chip8, ret.as_ptr(), ret.len()), 2);
+            assert_eq!(chip8_run_frame(chip8, 10), CHIP8_ERR_STACK_UNDERFLOW);
+            let unknown = [0x50, 0x01];
+            assert_eq!(chip8_load_rom(chip8, unknown.as_ptr(), unknown.len()), 2);
+            assert_eq!(chip8_run_frame(chip8, 10), CHIP8_ERR_UNKNOWN_OPCODE);
+            chip8_free(chip8);
+        }
+    }
+
+    #[test]
+    fn header_matches() {
+        let header = include_str!("../ffi/rusty_chip8.h");
+        let codes = [
+            ("CHIP8_OK", CHIP8_OK),
+            ("CHIP8_ERR_NULL", CHIP8_ERR_NULL),
+            ("CHIP8_ERR_PANIC", CHIP8_ERR_PANIC),
+            ("CHIP8_ERR_BUFFER_TOO_SMALL", CHIP8_ERR_BUFFER_TOO_SMALL),
+            (
+                "CHIP8_ERR_MEMORY_OUT_OF_BOUNDS",
+                CHIP8_ERR_MEMORY_OUT_OF_BOUNDS,
+            ),
+            ("CHIP8_ERR_PC_OUT_OF_BOUNDS", CHIP8_ERR_PC_OUT_OF_BOUNDS),
+            ("CHIP8_ERR_STACK_UNDERFLOW", CHIP8_ERR_STACK_UNDERFLOW),
+            ("CHIP8_ERR_STACK_OVERFLOW", CHIP8_ERR_STACK_OVERFLOW),
+            ("CHIP8_ERR_UNKNOWN_OPCODE", CHIP8_ERR_UNKNOWN_OPCODE),
+            ("CHIP8_ERR_SYS_CALL", CHIP8_ERR_SYS_CALL),
+            ("CHIP8_ERR_WRITE_PROTECTED", CHIP8_ERR_WRITE_PROTECTED),
+            ("CHIP8_ERR_UNINITIALIZED_READ", CHIP8_ERR_UNINITIALIZED_READ),
+            ("CHIP8_ERR_NO_ROM_LOADED", CHIP8_ERR_NO_ROM_LOADED),
+            ("CHIP8_ERR_INVALID_KEY", CHIP8_ERR_INVALID_KEY),
+            ("CHIP8_ERR_INVALID_REGISTER", CHIP8_ERR_INVALID_REGISTER),
+            (
+                "CHIP8_ERR_ADDRESS_OUT_OF_RANGE",
+                CHIP8_ERR_ADDRESS_OUT_OF_RANGE,
+            ),
+            ("CHIP8_ERR_ROM_LOAD", CHIP8_ERR_ROM_LOAD),
+        ];
+        let defines: Vec<(&str, c_int)> = header
+            .lines()
+            .filter_map(|line| line.strip_prefix("#define CHIP8_"))
+            .filter_map(|line| line.split_once(' '))
+            .map(|(name, value)| (name, value.trim().parse().unwrap()))
+            .collect();
+        assert_eq!(defines.len(), codes.len());
+        for ((name, value), (define, define_value)) in codes.iter().zip(&defines) {
+            assert_eq!((&name["CHIP8_".len()..], *value), (*define, *define_value));
+        }
+        for function in [
+            "Chip8 *chip8_new(void);",
+            "void chip8_free(Chip8 *handle);",
+            "int chip8_load_rom(Chip8 *handle, const uint8_t *data, size_t len);",
+            "int chip8_run_frame(Chip8 *handle, uint32_t cycles);",
+            "int chip8_set_keys(Chip8 *handle, uint16_t keys);",
+            "int chip8_framebuffer(Chip8 *handle, uint8_t *out, size_t out_len);",
+            "int chip8_width(Chip8 *handle);",
+            "int chip8_height(Chip8 *handle);",
+            "int chip8_beeping(Chip8 *handle);",
+        ] {
+            assert!(header.contains(function), "{}", function);
+        }
+    }
+}
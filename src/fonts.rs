@@ -0,0 +1,213 @@
+#[cfg(feature = "std")]
+use std::convert::TryInto;
+#[cfg(feature = "std")]
+use std::fs;
+
+#[cfg(feature = "std")]
+use crate::error::FontLoadError;
+
+// Built-in hex fonts, 16 glyphs of 5 rows each, loaded into the interpreter area at reset.
+// Only the high nibble of each row is drawn.
+
+// The font used by most modern interpreters
+pub const STANDARD: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// The original COSMAC VIP interpreter
+pub const COSMAC_VIP: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// The DREAM 6800, three pixels wide
+pub const DREAM_6800: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// The ETI-660, three pixels wide with lowercase b and d
+pub const ETI_660: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// Fish'N'Chips, with rounded glyphs
+pub const FISH_N_CHIPS: [u8; 80] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+// SUPER-CHIP large font for FX30, the digits 0 to 9 as 8x10 sprites.
+// Every row is drawn, so these are twice as wide as the small glyphs.
+pub const BIG: [u8; 100] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FontSet {
+    #[default]
+    Standard,
+    CosmacVip,
+    Dream6800,
+    Eti660,
+    FishNChips,
+}
+
+impl FontSet {
+    pub fn from_name(name: &str) -> Option<FontSet> {
+        match name {
+            "standard" => Some(FontSet::Standard),
+            "vip" => Some(FontSet::CosmacVip),
+            "dream6800" => Some(FontSet::Dream6800),
+            "eti660" => Some(FontSet::Eti660),
+            "fishnchips" => Some(FontSet::FishNChips),
+            _ => None,
+        }
+    }
+
+    pub fn glyphs(self) -> &'static [u8; 80] {
+        match self {
+            FontSet::Standard => &STANDARD,
+            FontSet::CosmacVip => &COSMAC_VIP,
+            FontSet::Dream6800 => &DREAM_6800,
+            FontSet::Eti660 => &ETI_660,
+            FontSet::FishNChips => &FISH_N_CHIPS,
+        }
+    }
+}
+
+// Read a custom font: a binary file of exactly 80 bytes, 5 rows for each of the glyphs 0 to F
+#[cfg(feature = "std")]
+pub fn read_font_file(filename: &str) -> Result<[u8; 80], FontLoadError> {
+    let contents = fs::read(filename)?;
+    contents
+        .as_slice()
+        .try_into()
+        .map_err(|_| FontLoadError::WrongSize {
+            size: contents.len(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_pick_the_sets() {
+        for (name, font) in [
+            ("standard", FontSet::Standard),
+            ("vip", FontSet::CosmacVip),
+            ("dream6800", FontSet::Dream6800),
+            ("eti660", FontSet::Eti660),
+            ("fishnchips", FontSet::FishNChips),
+        ] {
+            assert_eq!(FontSet::from_name(name), Some(font));
+        }
+        assert_eq!(FontSet::from_name("Standard"), None);
+        assert_eq!(FontSet::Dream6800.glyphs(), &DREAM_6800);
+    }
+
+    #[test]
+    fn glyphs_only_use_the_high_nibble() {
+        for font in [STANDARD, COSMAC_VIP, DREAM_6800, ETI_660, FISH_N_CHIPS] {
+            assert!(font.iter().all(|row| row & 0x0F == 0));
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn font_files_must_be_80_bytes() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let font = read_font_file(&format!("{}/tests/fixtures/font.bin", dir)).unwrap();
+        assert_eq!(font[..5], [0x00, 0x90, 0x60, 0x90, 0x00]);
+        assert!(matches!(
+            read_font_file(&format!("{}/roms/MAZE", dir)),
+            Err(FontLoadError::WrongSize { size: 34 })
+        ));
+        assert!(matches!(
+            read_font_file(&format!("{}/tests/fixtures/missing.bin", dir)),
+            Err(FontLoadError::Io(_))
+        ));
+    }
+}
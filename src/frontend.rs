@@ -0,0 +1,456 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audio::Audio;
+use crate::bus::Bus;
+use crate::debugger::{self, DebugCommand};
+use crate::display::Display;
+use crate::emulator::Emulator;
+use crate::error::Chip8Error;
+use crate::overlay::{self, MemoryView, MEMORY_VIEW_WIDTH};
+use crate::palette::Palette;
+
+// Requests from the player that go to the emulator rather than the ROM
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Quit,
+    // Pause when running, resume when paused
+    Pause,
+    // Run one instruction while paused, see Emulator::step
+    Step,
+    // Run one 60Hz frame while paused, see Emulator::advance_frame
+    AdvanceFrame,
+    // Restart the ROM, see Emulator::reset
+    Reset,
+    // Switch to the next built-in palette
+    NextPalette,
+    // Show or hide the registers over the screen
+    ToggleOverlay,
+    // Show or hide the hex viewer
+    ToggleMemoryView,
+    // Move the hex viewer this many lines down, or up when negative, while paused
+    ScrollMemoryView(isize),
+    // Make the hex viewer follow I again
+    MemoryViewHome,
+    // Print the profile so far to stdout, see CPU::profile
+    PrintProfile,
+}
+
+// What the player is doing, see Frontend::poll_input
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputState {
+    // CHIP-8 keys 0 to F held down right now
+    pub keys: Vec<u8>,
+    // commands given since the last poll, in order
+    pub commands: Vec<Command>,
+}
+
+// Whatever shows the screen, plays the buzzer and reads the keys, driven by run
+pub trait Frontend {
+    fn poll_input(&mut self) -> InputState;
+    // Show the screen after it changed. display.take_dirty tells which rows did.
+    fn present(&mut self, display: &mut Display, palette: &Palette) -> Result<(), String>;
+    // Start or stop the buzzer
+    fn set_beep(&mut self, on: bool);
+    // What the buzzer plays from now on, for frontends that support XO-CHIP sound
+    fn set_audio(&mut self, _audio: &Audio) {}
+    // The program finished, or was restarted after finishing
+    fn set_halted(&mut self, _halted: bool) {}
+    // Emulation was paused or resumed, by the player or on an error
+    fn set_paused(&mut self, _paused: bool) {}
+    // Lines of text to draw over the screen from the next present on, None hides them.
+    // See overlay::text_pixels.
+    fn set_overlay(&mut self, _lines: Option<&[String]>) {}
+    // The hex viewer to draw over the screen from the next present on, None hides it
+    fn set_memory_view(&mut self, _view: Option<&MemoryView>) {}
+}
+
+// A frontend without any output, for headless runs and tests
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NullFrontend {
+    // keys reported as held down
+    pub keys: Vec<u8>,
+    // ask run to quit on this poll, counting from 1
+    pub quit_after_polls: Option<u64>,
+    pub polls: u64,
+    // times present was called
+    pub frames: u64,
+    pub beeping: bool,
+    pub halted: bool,
+    pub paused: bool,
+    pub overlay: Option<Vec<String>>,
+    pub memory_view: Option<MemoryView>,
+}
+
+impl NullFrontend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Frontend for NullFrontend {
+    fn poll_input(&mut self) -> InputState {
+        self.polls += 1;
+        let mut commands = Vec::new();
+        if self
+            .quit_after_polls
+            .is_some_and(|polls| self.polls >= polls)
+        {
+            commands.push(Command::Quit);
+        }
+        InputState {
+            keys: self.keys.clone(),
+            commands,
+        }
+    }
+
+    fn present(&mut self, display: &mut Display, _palette: &Palette) -> Result<(), String> {
+        display.take_dirty();
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn set_beep(&mut self, on: bool) {
+        self.beeping = on;
+    }
+
+    fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    fn set_overlay(&mut self, lines: Option<&[String]>) {
+        self.overlay = lines.map(<[String]>::to_vec);
+    }
+
+    fn set_memory_view(&mut self, view: Option<&MemoryView>) {
+        self.memory_view = view.cloned();
+    }
+}
+
+// How often the overlay is updated, also the window its instruction rate is measured over
+const OVERLAY_REFRESH: Duration = Duration::from_millis(100);
+
+// The hex viewer while it's shown
+struct HexViewer {
+    // first address shown, None to follow I
+    start: Option<usize>,
+    // None to update on this loop
+    updated: Option<Instant>,
+}
+
+// How run drives the emulator
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    // colors of the screen, Command::NextPalette moves on from here
+    pub palette: Palette,
+    // debugger commands to carry out between ticks, their output goes to stdout.
+    // See debugger::read_commands.
+    pub debug_commands: Option<Receiver<DebugCommand>>,
+}
+
+// Run the emulator in real time until the player quits or an error stops it.
+// Returns the error that stopped emulation, None when the player quit.
+pub fn run<F: Frontend, B: Bus>(
+    frontend: &mut F,
+    emulator: &mut Emulator<B>,
+    options: &RunOptions,
+) -> Result<Option<Chip8Error>, String> {
+    let mut palette = options.palette;
+    // What the frontend was last told to play
+    let mut audio = Audio::new();
+    let mut beeping = false;
+    let mut paused = emulator.is_paused();
+    frontend.set_paused(paused);
+    let mut last_tick = Instant::now();
+    // While the overlay is shown, when its rate window started and the instructions since
+    let mut overlay_rate: Option<(Instant, u64)> = None;
+    let mut hex_viewer: Option<HexViewer> = None;
+    // Addresses written while the hex viewer is shown, recorded by a memory observer set up the
+    // first time it's opened
+    let written: Rc<RefCell<Option<Vec<u16>>>> = Rc::default();
+    let mut observing = false;
+
+    loop {
+        // Present even if the screen didn't change, to update the overlay
+        let mut redraw = false;
+        let input = frontend.poll_input();
+        for command in input.commands {
+            match command {
+                Command::Quit => return Ok(None),
+                Command::Pause if emulator.is_paused() => emulator.resume(),
+                Command::Pause => emulator.pause(),
+                Command::Step => emulator.step(),
+                Command::AdvanceFrame => emulator.advance_frame(),
+                Command::Reset => {
+                    emulator.reset().map_err(|e| e.to_string())?;
+                    frontend.set_halted(false);
+                }
+                Command::NextPalette => {
+                    palette = palette.next_named();
+                    emulator.cpu.display.invalidate();
+                }
+                Command::ToggleOverlay if overlay_rate.is_some() => {
+                    overlay_rate = None;
+                    frontend.set_overlay(None);
+                    redraw = true;
+                }
+                Command::ToggleOverlay => {
+                    // Shown once the first rate window ends
+                    overlay_rate = Some((Instant::now(), 0));
+                }
+                Command::ToggleMemoryView if hex_viewer.is_some() => {
+                    hex_viewer = None;
+                    *written.borrow_mut() = None;
+                    frontend.set_memory_view(None);
+                    redraw = true;
+                }
+                Command::ToggleMemoryView => {
+                    if !observing {
+                        observing = true;
+                        // Keep calling the observer already there, such as the --log-writes one
+                        let mut next = emulator.cpu.take_memory_observer();
+                        let written = Rc::clone(&written);
+                        emulator
+                            .cpu
+                            .set_memory_observer(Box::new(move |addr, old, new| {
+                                if let Some(written) = written.borrow_mut().as_mut() {
+                                    written.push(addr);
+                                }
+                                if let Some(next) = next.as_mut() {
+                                    next(addr, old, new);
+                                }
+                            }));
+                    }
+                    *written.borrow_mut() = Some(Vec::new());
+                    hex_viewer = Some(HexViewer {
+                        start: None,
+                        updated: None,
+                    });
+                }
+                Command::ScrollMemoryView(lines) if emulator.is_paused() => {
+                    if let Some(viewer) = &mut hex_viewer {
+                        let start = viewer
+                            .start
+                            .unwrap_or_else(|| MemoryView::start_around(emulator.cpu.i as usize));
+                        let start = start.saturating_add_signed(lines * MEMORY_VIEW_WIDTH as isize);
+                        let memory_len = emulator.cpu.bus.ram().len();
+                        viewer.start = Some(MemoryView::clamp_start(memory_len, start));
+                        viewer.updated = None;
+                    }
+                }
+                Command::ScrollMemoryView(_) => {}
+                Command::PrintProfile => {
+                    let cpu = &emulator.cpu;
+                    print!("{}", cpu.profile().report(cpu.bus.ram(), cpu.platform));
+                }
+                Command::MemoryViewHome => {
+                    if let Some(viewer) = &mut hex_viewer {
+                        viewer.start = None;
+                        viewer.updated = None;
+                    }
+                }
+            }
+        }
+        if let Some(commands) = &options.debug_commands {
+            while let Ok(command) = commands.try_recv() {
+                if command == DebugCommand::Quit {
+                    return Ok(None);
+                }
+                print!("{}", debugger::execute(&command, emulator));
+            }
+        }
+        emulator.set_keys(&input.keys).map_err(|e| e.to_string())?;
+
+        let now = Instant::now();
+        let output = emulator.tick(now - last_tick);
+        last_tick = now;
+
+        if output.halted {
+            // The final frame stays up, Command::Reset restarts
+            frontend.set_halted(true);
+        }
+        if let Some(addr) = output.breakpoint {
+            eprintln!("Breakpoint at {:#05X}\n{}", addr, emulator.cpu);
+        }
+        if let Some(hit) = output.watchpoint {
+            eprintln!("Watchpoint: {}\n{}", hit, emulator.cpu);
+        }
+        if let Some(e) = output.error {
+            if emulator.is_paused() {
+                eprintln!("Paused on {}\n{}", e, emulator.cpu);
+            } else {
+                eprintln!("Emulation stopped: {}\n{}", e, emulator.cpu);
+                frontend.set_beep(false);
+                return Ok(Some(e));
+            }
+        }
+
+        if emulator.is_paused() != paused {
+            paused = emulator.is_paused();
+            frontend.set_paused(paused);
+        }
+
+        // Follow XO-CHIP pattern and pitch changes
+        if emulator.cpu.audio != audio {
+            audio = emulator.cpu.audio;
+            frontend.set_audio(&audio);
+        }
+        let beep = output.beep && !emulator.is_paused();
+        if beep != beeping {
+            beeping = beep;
+            frontend.set_beep(beep);
+        }
+
+        if let Some((since, instructions)) = &mut overlay_rate {
+            *instructions += output.instructions as u64;
+            let window = now - *since;
+            if window >= OVERLAY_REFRESH {
+                let rate = (*instructions as f64 / window.as_secs_f64()) as u64;
+                frontend.set_overlay(Some(&overlay::state_lines(&emulator.cpu, rate)));
+                *since = now;
+                *instructions = 0;
+                redraw = true;
+            }
+        }
+
+        if let Some(viewer) = &mut hex_viewer {
+            if viewer
+                .updated
+                .is_none_or(|updated| now - updated >= OVERLAY_REFRESH)
+            {
+                let start = viewer
+                    .start
+                    .unwrap_or_else(|| MemoryView::start_around(emulator.cpu.i as usize));
+                let written = written.borrow_mut().replace(Vec::new()).unwrap_or_default();
+                let view = MemoryView::new(emulator.cpu.bus.ram(), start, &written);
+                frontend.set_memory_view(Some(&view));
+                viewer.updated = Some(now);
+                redraw = true;
+            }
+        }
+
+        if output.drew || redraw {
+            frontend.present(&mut emulator.cpu.display, &palette)?;
+            emulator.cpu.display.need_redraw = false;
+        }
+
+        let sleep_time = match emulator.is_idle() {
+            // Nothing to do until the next tick
+            true => emulator.time_to_next_timer(),
+            false => Duration::from_micros(100),
+        };
+        thread::sleep(sleep_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use std::sync::mpsc;
+
+    fn emulator(program: &[u8]) -> Emulator {
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(program).unwrap();
+        Emulator::new(cpu, 600)
+    }
+
+    #[test]
+    fn run_quits_when_asked() {
+        // LD F, V0 / DRW V0, V0, 5 / JP 0x204
+        let mut emulator = emulator(&[0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04]);
+        let mut frontend = NullFrontend {
+            quit_after_polls: Some(50),
+            ..NullFrontend::new()
+        };
+        assert_eq!(
+            run(&mut frontend, &mut emulator, &RunOptions::default()),
+            Ok(None)
+        );
+        assert_eq!(frontend.polls, 50);
+        assert!(frontend.frames >= 1);
+        assert!(frontend.halted);
+        assert!(!emulator.cpu.display.need_redraw);
+    }
+
+    #[test]
+    fn run_returns_the_error_that_stopped_it() {
+        // LD V0, 1 / an unknown opcode
+        let mut emulator = emulator(&[0x60, 0x01, 0xE0, 0x00]);
+        let mut frontend = NullFrontend {
+            quit_after_polls: Some(1000),
+            ..NullFrontend::new()
+        };
+        let result = run(&mut frontend, &mut emulator, &RunOptions::default());
+        assert_eq!(
+            result,
+            Ok(Some(Chip8Error::UnknownOpcode {
+                opcode: 0xE000,
+                pc: 0x202
+            }))
+        );
+        assert!(frontend.polls < 1000);
+    }
+
+    #[test]
+    fn keys_reach_the_program() {
+        // LD V0, K / JP 0x202
+        let mut emulator = emulator(&[0xF0, 0x0A, 0x12, 0x02]);
+        let mut frontend = NullFrontend {
+            keys: vec![0x7],
+            quit_after_polls: Some(20),
+            ..NullFrontend::new()
+        };
+        run(&mut frontend, &mut emulator, &RunOptions::default()).unwrap();
+        assert!(emulator.cpu.keyboard.is_pressed(0x7));
+        // LD V0, K still waits for the key to go up
+        assert_eq!(emulator.cpu.pc, 0x200);
+        frontend.keys.clear();
+        frontend.polls = 0;
+        run(&mut frontend, &mut emulator, &RunOptions::default()).unwrap();
+        assert_eq!(emulator.cpu.v[0], 0x7);
+        assert!(frontend.halted);
+    }
+
+    #[test]
+    fn debug_commands_drive_the_emulator() {
+        // LD V0, 1 / LD V1, 2 / LD V2, 3 / JP 0x206
+        let mut emulator = emulator(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x12, 0x06]);
+        let (sender, receiver) = mpsc::channel();
+        for command in ["s 2", "set v5 0x1f", "q"].iter() {
+            sender.send(DebugCommand::parse(command).unwrap()).unwrap();
+        }
+        let options = RunOptions {
+            debug_commands: Some(receiver),
+            ..RunOptions::default()
+        };
+        let mut frontend = NullFrontend::new();
+        assert_eq!(run(&mut frontend, &mut emulator, &options), Ok(None));
+        assert!(emulator.is_paused());
+        let cpu = &emulator.cpu;
+        assert_eq!((cpu.pc, cpu.v[1], cpu.v[2], cpu.v[5]), (0x204, 2, 0, 0x1F));
+    }
+
+    #[test]
+    fn paused_emulation_stays_silent() {
+        // JP to itself
+        let mut emulator = emulator(&[0x12, 0x00]);
+        emulator.cpu.st = 30;
+        emulator.pause();
+        let mut frontend = NullFrontend {
+            quit_after_polls: Some(20),
+            ..NullFrontend::new()
+        };
+        run(&mut frontend, &mut emulator, &RunOptions::default()).unwrap();
+        assert!(frontend.paused);
+        assert!(!frontend.beeping);
+        assert_eq!(emulator.cpu.st, 30);
+    }
+}
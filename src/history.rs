@@ -0,0 +1,218 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use core::fmt::Write;
+
+use crate::disasm;
+use crate::quirks::Platform;
+
+// Entries kept when nothing else is asked for
+pub const DEFAULT_HISTORY_LEN: usize = 64;
+
+// V0 to VF and I before an instruction executed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+}
+
+// One instruction that ran or failed, oldest first from History::entries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    // for every snapshot_every-th entry, see History::new
+    pub registers: Option<Registers>,
+}
+
+// The last instructions the CPU ran, to see how it got to an error. See CPU::set_history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct History {
+    // (pc, opcode) pairs, a ring with the oldest at next once it's full
+    entries: Box<[(u16, u16)]>,
+    next: usize,
+    full: bool,
+    // registers for the entry at the same index, when it got a snapshot
+    registers: Box<[Option<Registers>]>,
+    snapshot_every: usize,
+    // entries to go until the next snapshot
+    until_snapshot: usize,
+}
+
+impl History {
+    // Keep the last len instructions, at least 1, and the registers before every
+    // snapshot_every-th of them, never with 0
+    pub fn new(len: usize, snapshot_every: usize) -> Self {
+        let len = len.max(1);
+        History {
+            entries: vec![(0, 0); len].into_boxed_slice(),
+            next: 0,
+            full: false,
+            registers: vec![None; len].into_boxed_slice(),
+            snapshot_every,
+            until_snapshot: 0,
+        }
+    }
+
+    // Add the instruction at pc, registers as they are before it executes
+    pub fn record(&mut self, pc: u16, opcode: u16, v: &[u8; 16], i: u16) {
+        self.entries[self.next] = (pc, opcode);
+        if self.snapshot_every > 0 {
+            self.registers[self.next] = match self.until_snapshot {
+                0 => {
+                    self.until_snapshot = self.snapshot_every;
+                    Some(Registers { v: *v, i })
+                }
+                _ => None,
+            };
+            self.until_snapshot -= 1;
+        }
+        self.next += 1;
+        if self.next == self.entries.len() {
+            self.next = 0;
+            self.full = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.full = false;
+        self.until_snapshot = 0;
+    }
+
+    // The instructions kept, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = HistoryEntry> + '_ {
+        let (older, newer) = match self.full {
+            true => (self.next..self.entries.len(), 0..self.next),
+            false => (0..0, 0..self.next),
+        };
+        older.chain(newer).map(move |k| HistoryEntry {
+            pc: self.entries[k].0,
+            opcode: self.entries[k].1,
+            registers: self.registers[k],
+        })
+    }
+
+    // One line per instruction as the interpreters of platform run it, oldest first, each
+    // snapshot on a line under its instruction
+    pub fn listing(&self, platform: Platform) -> String {
+        let mut out = String::new();
+        for entry in self.entries() {
+            // Writing to a String can't fail
+            let _ = writeln!(
+                out,
+                "    {:#05X}: {:#06X}  {}",
+                entry.pc,
+                entry.opcode,
+                disasm::disassemble_for(entry.opcode, platform)
+            );
+            if let Some(registers) = entry.registers {
+                out.push_str("           ");
+                for (x, value) in registers.v.iter().enumerate() {
+                    let _ = write!(out, " V{:X}={:02X}", x, value);
+                }
+                let _ = writeln!(out, " I={:04X}", registers.i);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::error::Chip8Error;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    fn pcs(history: &History) -> Vec<u16> {
+        history.entries().map(|entry| entry.pc).collect()
+    }
+
+    #[test]
+    fn ring_keeps_the_newest_in_order() {
+        let mut history = History::new(3, 0);
+        assert!(pcs(&history).is_empty());
+        for pc in [0x200, 0x202, 0x204, 0x206, 0x208].iter() {
+            history.record(*pc, 0x00E0, &[0; 16], 0);
+        }
+        assert_eq!(pcs(&history), [0x204, 0x206, 0x208]);
+        assert!(history.entries().all(|entry| entry.registers.is_none()));
+        history.clear();
+        assert!(pcs(&history).is_empty());
+    }
+
+    #[test]
+    fn snapshots_come_every_nth_entry() {
+        let mut history = History::new(8, 2);
+        for k in 0..5 {
+            history.record(0x200 + 2 * k, 0x7001, &[k as u8; 16], 0x300);
+        }
+        let snapshots: Vec<Option<u8>> = history
+            .entries()
+            .map(|entry| entry.registers.map(|registers| registers.v[0]))
+            .collect();
+        assert_eq!(snapshots, [Some(0), None, Some(2), None, Some(4)]);
+    }
+
+    #[test]
+    fn a_fault_leaves_the_instructions_that_led_to_it() {
+        // LD V0, 1 / CALL 0x206 / (unused) / ADD V0, 1 / an unknown opcode
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(&[0x60, 0x01, 0x22, 0x06, 0x00, 0x00, 0x70, 0x01, 0xE0, 0x00])
+            .unwrap();
+        cpu.set_history(Some(History::new(DEFAULT_HISTORY_LEN, 0)));
+        assert_eq!(
+            cpu.exec_cycles(10),
+            Err(Chip8Error::UnknownOpcode {
+                opcode: 0xE000,
+                pc: 0x208
+            })
+        );
+        let history = cpu.history().unwrap();
+        assert_eq!(pcs(history), [0x200, 0x202, 0x206, 0x208]);
+        let listing = history.listing(cpu.platform);
+        assert_eq!(
+            listing.lines().collect::<Vec<_>>(),
+            [
+                "    0x200: 0x6001  LD V0, 0x01",
+                "    0x202: 0x2206  CALL 0x206",
+                "    0x206: 0x7001  ADD V0, 0x01",
+                "    0x208: 0xE000  DW 0xE000",
+            ]
+        );
+    }
+
+    #[test]
+    fn stalled_instructions_are_recorded_once() {
+        // LD V0, K / DRW V0, V0, 1 / an unknown opcode
+        let mut cpu = CPU::builder().platform(Platform::Chip8).build();
+        cpu.load_rom_bytes(&[0xF0, 0x0A, 0xD0, 0x01, 0xE0, 0x00])
+            .unwrap();
+        cpu.set_history(Some(History::new(DEFAULT_HISTORY_LEN, 1)));
+        let traced = Rc::new(RefCell::new(Vec::new()));
+        let log = traced.clone();
+        cpu.on_instruction(move |pc, _| log.borrow_mut().push(pc));
+        for _ in 0..10 {
+            cpu.exec_cycle().unwrap();
+        }
+        cpu.key_down(1).unwrap();
+        cpu.key_up(1).unwrap();
+        for _ in 0..10 {
+            cpu.exec_cycle().unwrap();
+        }
+        cpu.update_timers();
+        assert!(cpu.exec_cycles(10).is_err());
+        let history = cpu.history().unwrap();
+        assert_eq!(pcs(history), [0x200, 0x202, 0x204]);
+        assert_eq!(*traced.borrow(), [0x200, 0x202, 0x204]);
+        // The snapshot is from before the instruction, not from when it first stalled
+        let registers: Vec<u8> = history
+            .entries()
+            .map(|entry| entry.registers.unwrap().v[0])
+            .collect();
+        assert_eq!(registers, [0, 1, 1]);
+    }
+}
@@ -0,0 +1,464 @@
+use core::fmt;
+
+use crate::quirks::Platform;
+
+// The COSMAC VIP ran at 1.76 MHz with 8 clock periods per machine cycle
+pub const VIP_MACHINE_CYCLES_PER_SECOND: u64 = 1_760_640 / 8;
+
+// A decoded CHIP-8 instruction.
+// Register operands are indices into V, addresses are 12-bit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    // 0NNN - SYS addr
+    Sys(u16),
+    // 00E0 - CLS
+    Cls,
+    // 00EE - RET
+    Ret,
+    // 00CN - SCD nibble, SUPER-CHIP scroll down N pixels
+    ScrollDown(u8),
+    // 00FB - SCR, SUPER-CHIP scroll right 4 pixels
+    ScrollRight,
+    // 00FC - SCL, SUPER-CHIP scroll left 4 pixels
+    ScrollLeft,
+    // 00FD - EXIT, SUPER-CHIP end of program
+    Exit,
+    // 00FE - LOW, SUPER-CHIP 64x32 mode
+    Lores,
+    // 00FF - HIGH, SUPER-CHIP 128x64 mode
+    Hires,
+    // FN01 - PLANE n, XO-CHIP select the bitplanes to draw on
+    Plane(u8),
+    // 00DN - SCU n, XO-CHIP scroll up n pixels
+    ScrollUp(u8),
+    // 1NNN - JP addr
+    Jump(u16),
+    // 2NNN - CALL addr
+    Call(u16),
+    // 3XKK - SE Vx, byte
+    SkipEqByte { x: usize, kk: u8 },
+    // 4XKK - SNE Vx, byte
+    SkipNeByte { x: usize, kk: u8 },
+    // 5XY0 - SE Vx, Vy
+    SkipEqReg { x: usize, y: usize },
+    // 5XY2 - LD [I], Vx-Vy, XO-CHIP store Vx to Vy, in either direction
+    StoreRange { x: usize, y: usize },
+    // 5XY3 - LD Vx-Vy, [I], XO-CHIP load Vx to Vy, in either direction
+    LoadRange { x: usize, y: usize },
+    // 6XKK - LD Vx, byte
+    LoadByte { x: usize, kk: u8 },
+    // 7XKK - ADD Vx, byte
+    AddVxByte { x: usize, kk: u8 },
+    // 8XY0 - LD Vx, Vy
+    LoadReg { x: usize, y: usize },
+    // 8XY1 - OR Vx, Vy
+    Or { x: usize, y: usize },
+    // 8XY2 - AND Vx, Vy
+    And { x: usize, y: usize },
+    // 8XY3 - XOR Vx, Vy
+    Xor { x: usize, y: usize },
+    // 8XY4 - ADD Vx, Vy
+    AddReg { x: usize, y: usize },
+    // 8XY5 - SUB Vx, Vy
+    Sub { x: usize, y: usize },
+    // 8XY6 - SHR Vx {, Vy}
+    Shr { x: usize, y: usize },
+    // 8XY7 - SUBN Vx, Vy
+    SubN { x: usize, y: usize },
+    // 8XYE - SHL Vx {, Vy}
+    Shl { x: usize, y: usize },
+    // 9XY0 - SNE Vx, Vy
+    SkipNeReg { x: usize, y: usize },
+    // ANNN - LD I, addr
+    LoadI(u16),
+    // F000 NNNN - LD I, long NNNN, XO-CHIP load a 16-bit address from the next word into I
+    LoadLongI,
+    // BNNN - JP V0, addr (BXNN - JP Vx, addr with the jump quirk)
+    JumpOffset { x: usize, nnn: u16 },
+    // CXKK - RND Vx, byte
+    Rnd { x: usize, kk: u8 },
+    // DXYN - DRW Vx, Vy, nibble
+    Draw { x: usize, y: usize, n: u8 },
+    // EX9E - SKP Vx
+    SkipKeyPressed { x: usize },
+    // EXA1 - SKNP Vx
+    SkipKeyNotPressed { x: usize },
+    // FX07 - LD Vx, DT
+    LoadDelay { x: usize },
+    // F002 - LD AUDIO, [I], XO-CHIP load the 16 byte sound pattern
+    LoadAudio,
+    // FX0A - LD Vx, K
+    WaitKey { x: usize },
+    // FX15 - LD DT, Vx
+    SetDelay { x: usize },
+    // FX18 - LD ST, Vx
+    SetSound { x: usize },
+    // FX1E - ADD I, Vx
+    AddI { x: usize },
+    // FX3A - LD PITCH, Vx, XO-CHIP set the sound pattern playback rate
+    SetPitch { x: usize },
+    // FX29 - LD F, Vx
+    LoadFont { x: usize },
+    // FX30 - LD HF, Vx, SUPER-CHIP large font
+    LoadBigFont { x: usize },
+    // FX33 - LD B, Vx
+    StoreBcd { x: usize },
+    // FX55 - LD [I], Vx
+    StoreRegs { x: usize },
+    // FX65 - LD Vx, [I]
+    LoadRegs { x: usize },
+    // FX75 - LD R, Vx, SUPER-CHIP store V0..Vx in the RPL user flags
+    StoreFlags { x: usize },
+    // FX85 - LD Vx, R, SUPER-CHIP load V0..Vx from the RPL user flags
+    LoadFlags { x: usize },
+}
+
+impl Instruction {
+    // Decode a raw opcode, returning None for words that aren't valid instructions
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        // Break apart opcode for decoding
+        let op_4 = (opcode & 0xF000) >> 12;
+        let op_3 = (opcode & 0x0F00) >> 8;
+        let op_2 = (opcode & 0x00F0) >> 4;
+        let op_1 = opcode & 0x000F;
+
+        let nnn = opcode & 0x0FFF;
+        let x = op_3 as usize;
+        let y = op_2 as usize;
+        let n = op_1 as u8;
+        let kk = (opcode & 0x00FF) as u8;
+
+        let instruction = match (op_4, op_3, op_2, op_1) {
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Ret,
+            (0x0, 0x0, 0xC, _) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xD, _) => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::Lores,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::Hires,
+            (0x0, _, _, _) => Instruction::Sys(nnn),
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SkipEqByte { x, kk },
+            (0x4, _, _, _) => Instruction::SkipNeByte { x, kk },
+            (0x5, _, _, 0x0) => Instruction::SkipEqReg { x, y },
+            (0x5, _, _, 0x2) => Instruction::StoreRange { x, y },
+            (0x5, _, _, 0x3) => Instruction::LoadRange { x, y },
+            (0x6, _, _, _) => Instruction::LoadByte { x, kk },
+            (0x7, _, _, _) => Instruction::AddVxByte { x, kk },
+            (0x8, _, _, 0x0) => Instruction::LoadReg { x, y },
+            (0x8, _, _, 0x1) => Instruction::Or { x, y },
+            (0x8, _, _, 0x2) => Instruction::And { x, y },
+            (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+            (0x8, _, _, 0x4) => Instruction::AddReg { x, y },
+            (0x8, _, _, 0x5) => Instruction::Sub { x, y },
+            (0x8, _, _, 0x6) => Instruction::Shr { x, y },
+            (0x8, _, _, 0x7) => Instruction::SubN { x, y },
+            (0x8, _, _, 0xE) => Instruction::Shl { x, y },
+            (0x9, _, _, 0x0) => Instruction::SkipNeReg { x, y },
+            (0xA, _, _, _) => Instruction::LoadI(nnn),
+            (0xB, _, _, _) => Instruction::JumpOffset { x, nnn },
+            (0xC, _, _, _) => Instruction::Rnd { x, kk },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 0x9, 0xE) => Instruction::SkipKeyPressed { x },
+            (0xE, _, 0xA, 0x1) => Instruction::SkipKeyNotPressed { x },
+            (0xF, _, 0x0, 0x7) => Instruction::LoadDelay { x },
+            (0xF, _, 0x0, 0xA) => Instruction::WaitKey { x },
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelay { x },
+            (0xF, _, 0x1, 0x8) => Instruction::SetSound { x },
+            (0xF, _, 0x1, 0xE) => Instruction::AddI { x },
+            (0xF, 0x0, 0x0, 0x0) => Instruction::LoadLongI,
+            (0xF, _, 0x0, 0x1) => Instruction::Plane(x as u8),
+            (0xF, 0x0, 0x0, 0x2) => Instruction::LoadAudio,
+            (0xF, _, 0x2, 0x9) => Instruction::LoadFont { x },
+            (0xF, _, 0x3, 0x0) => Instruction::LoadBigFont { x },
+            (0xF, _, 0x3, 0xA) => Instruction::SetPitch { x },
+            (0xF, _, 0x3, 0x3) => Instruction::StoreBcd { x },
+            (0xF, _, 0x5, 0x5) => Instruction::StoreRegs { x },
+            (0xF, _, 0x6, 0x5) => Instruction::LoadRegs { x },
+            (0xF, _, 0x7, 0x5) => Instruction::StoreFlags { x },
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags { x },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    // Whether the instruction exists on an interpreter family. The SUPER-CHIP additions are
+    // machine code calls or invalid on plain CHIP-8, the XO-CHIP ones only exist on XO-CHIP.
+    pub fn available_on(&self, platform: Platform) -> bool {
+        match *self {
+            Instruction::ScrollUp(_)
+            | Instruction::Plane(_)
+            | Instruction::LoadLongI
+            | Instruction::LoadAudio
+            | Instruction::SetPitch { .. }
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. } => platform == Platform::XoChip,
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::Lores
+            | Instruction::Hires
+            | Instruction::LoadBigFont { .. }
+            | Instruction::StoreFlags { .. }
+            | Instruction::LoadFlags { .. } => platform.has_superchip_opcodes(),
+            _ => true,
+        }
+    }
+
+    // Encode back into the raw opcode, the inverse of decode
+    pub fn encode(&self) -> u16 {
+        let xy = |x: usize, y: usize| ((x as u16) << 8) | ((y as u16) << 4);
+        let xkk = |x: usize, kk: u8| ((x as u16) << 8) | kk as u16;
+        let x_ = |x: usize| (x as u16) << 8;
+
+        match *self {
+            Instruction::Sys(nnn) => nnn,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | n as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::ScrollUp(n) => 0x00D0 | n as u16,
+            Instruction::Exit => 0x00FD,
+            Instruction::Lores => 0x00FE,
+            Instruction::Hires => 0x00FF,
+            Instruction::Plane(n) => 0xF001 | (n as u16) << 8,
+            Instruction::Jump(nnn) => 0x1000 | nnn,
+            Instruction::Call(nnn) => 0x2000 | nnn,
+            Instruction::SkipEqByte { x, kk } => 0x3000 | xkk(x, kk),
+            Instruction::SkipNeByte { x, kk } => 0x4000 | xkk(x, kk),
+            Instruction::SkipEqReg { x, y } => 0x5000 | xy(x, y),
+            Instruction::StoreRange { x, y } => 0x5002 | xy(x, y),
+            Instruction::LoadRange { x, y } => 0x5003 | xy(x, y),
+            Instruction::LoadByte { x, kk } => 0x6000 | xkk(x, kk),
+            Instruction::AddVxByte { x, kk } => 0x7000 | xkk(x, kk),
+            Instruction::LoadReg { x, y } => 0x8000 | xy(x, y),
+            Instruction::Or { x, y } => 0x8001 | xy(x, y),
+            Instruction::And { x, y } => 0x8002 | xy(x, y),
+            Instruction::Xor { x, y } => 0x8003 | xy(x, y),
+            Instruction::AddReg { x, y } => 0x8004 | xy(x, y),
+            Instruction::Sub { x, y } => 0x8005 | xy(x, y),
+            Instruction::Shr { x, y } => 0x8006 | xy(x, y),
+            Instruction::SubN { x, y } => 0x8007 | xy(x, y),
+            Instruction::Shl { x, y } => 0x800E | xy(x, y),
+            Instruction::SkipNeReg { x, y } => 0x9000 | xy(x, y),
+            Instruction::LoadI(nnn) => 0xA000 | nnn,
+            Instruction::LoadLongI => 0xF000,
+            Instruction::JumpOffset { nnn, .. } => 0xB000 | nnn,
+            Instruction::Rnd { x, kk } => 0xC000 | xkk(x, kk),
+            Instruction::Draw { x, y, n } => 0xD000 | xy(x, y) | n as u16,
+            Instruction::SkipKeyPressed { x } => 0xE09E | x_(x),
+            Instruction::SkipKeyNotPressed { x } => 0xE0A1 | x_(x),
+            Instruction::LoadDelay { x } => 0xF007 | x_(x),
+            Instruction::WaitKey { x } => 0xF00A | x_(x),
+            Instruction::SetDelay { x } => 0xF015 | x_(x),
+            Instruction::SetSound { x } => 0xF018 | x_(x),
+            Instruction::AddI { x } => 0xF01E | x_(x),
+            Instruction::LoadFont { x } => 0xF029 | x_(x),
+            Instruction::LoadBigFont { x } => 0xF030 | x_(x),
+            Instruction::SetPitch { x } => 0xF03A | x_(x),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::StoreBcd { x } => 0xF033 | x_(x),
+            Instruction::StoreRegs { x } => 0xF055 | x_(x),
+            Instruction::LoadRegs { x } => 0xF065 | x_(x),
+            Instruction::StoreFlags { x } => 0xF075 | x_(x),
+            Instruction::LoadFlags { x } => 0xF085 | x_(x),
+        }
+    }
+
+    // Approximate time the COSMAC VIP interpreter took to execute this instruction, in machine cycles.
+    // Only relative costs matter here: DXYN dwarfs a register move, and costs scale with the
+    // number of sprite rows or registers transferred.
+    pub fn vip_cycles(&self) -> u32 {
+        match *self {
+            Instruction::Sys(_) | Instruction::Exit => 0,
+            Instruction::Cls | Instruction::Lores | Instruction::Hires => 24,
+            // Not on the VIP, priced like clearing the screen
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollUp(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft => 24,
+            Instruction::Plane(_) => 10,
+            Instruction::Ret => 10,
+            Instruction::Jump(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::SkipEqByte { .. } | Instruction::SkipNeByte { .. } => 10,
+            Instruction::SkipEqReg { .. } | Instruction::SkipNeReg { .. } => 14,
+            Instruction::StoreRange { x, y } | Instruction::LoadRange { x, y } => {
+                14 + 14 * (x.max(y) - x.min(y) + 1) as u32
+            }
+            Instruction::LoadByte { .. } => 6,
+            Instruction::AddVxByte { .. } => 10,
+            Instruction::LoadReg { .. }
+            | Instruction::Or { .. }
+            | Instruction::And { .. }
+            | Instruction::Xor { .. }
+            | Instruction::AddReg { .. }
+            | Instruction::Sub { .. }
+            | Instruction::Shr { .. }
+            | Instruction::SubN { .. }
+            | Instruction::Shl { .. } => 44,
+            Instruction::LoadI(_) => 12,
+            Instruction::LoadLongI => 24,
+            Instruction::JumpOffset { .. } => 22,
+            Instruction::Rnd { .. } => 36,
+            Instruction::Draw { n, .. } => 68 + 46 * n as u32,
+            Instruction::SkipKeyPressed { .. } | Instruction::SkipKeyNotPressed { .. } => 14,
+            Instruction::LoadDelay { .. } => 10,
+            Instruction::WaitKey { .. } => 10,
+            Instruction::SetDelay { .. }
+            | Instruction::SetSound { .. }
+            | Instruction::SetPitch { .. } => 10,
+            Instruction::LoadAudio => 14 + 14 * 16,
+            Instruction::AddI { .. } => 12,
+            Instruction::LoadFont { .. } | Instruction::LoadBigFont { .. } => 20,
+            Instruction::StoreBcd { .. } => 80,
+            Instruction::StoreRegs { x }
+            | Instruction::LoadRegs { x }
+            | Instruction::StoreFlags { x }
+            | Instruction::LoadFlags { x } => 14 + 14 * (x as u32 + 1),
+        }
+    }
+}
+
+// Assembly in the mnemonics of Cowgod's technical reference, e.g. "LD V1, 0x2A"
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS {:#05X}", nnn),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Lores => write!(f, "LOW"),
+            Instruction::Hires => write!(f, "HIGH"),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::Jump(nnn) => write!(f, "JP {:#05X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SkipEqByte { x, kk } => write!(f, "SE V{:X}, {:#04X}", x, kk),
+            Instruction::SkipNeByte { x, kk } => write!(f, "SNE V{:X}, {:#04X}", x, kk),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            Instruction::LoadByte { x, kk } => write!(f, "LD V{:X}, {:#04X}", x, kk),
+            Instruction::AddVxByte { x, kk } => write!(f, "ADD V{:X}, {:#04X}", x, kk),
+            Instruction::LoadReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::LoadLongI => write!(f, "LD I, LONG"),
+            Instruction::JumpOffset { nnn, .. } => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::Rnd { x, kk } => write!(f, "RND V{:X}, {:#04X}", x, kk),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::SetPitch { x } => write!(f, "LD PITCH, V{:X}", x),
+            Instruction::LoadAudio => write!(f, "LD AUDIO, [I]"),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegs { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegs { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_each_opcode_pattern() {
+        for (opcode, instruction) in [
+            (0x00E0, Instruction::Cls),
+            (0x00EE, Instruction::Ret),
+            (0x0123, Instruction::Sys(0x123)),
+            (0x1ABC, Instruction::Jump(0xABC)),
+            (0x2ABC, Instruction::Call(0xABC)),
+            (0x3A12, Instruction::SkipEqByte { x: 0xA, kk: 0x12 }),
+            (0x4A12, Instruction::SkipNeByte { x: 0xA, kk: 0x12 }),
+            (0x5AB0, Instruction::SkipEqReg { x: 0xA, y: 0xB }),
+            (0x6A12, Instruction::LoadByte { x: 0xA, kk: 0x12 }),
+            (0x7A12, Instruction::AddVxByte { x: 0xA, kk: 0x12 }),
+            (0x8AB0, Instruction::LoadReg { x: 0xA, y: 0xB }),
+            (0x8AB1, Instruction::Or { x: 0xA, y: 0xB }),
+            (0x8AB2, Instruction::And { x: 0xA, y: 0xB }),
+            (0x8AB3, Instruction::Xor { x: 0xA, y: 0xB }),
+            (0x8AB4, Instruction::AddReg { x: 0xA, y: 0xB }),
+            (0x8AB5, Instruction::Sub { x: 0xA, y: 0xB }),
+            (0x8AB6, Instruction::Shr { x: 0xA, y: 0xB }),
+            (0x8AB7, Instruction::SubN { x: 0xA, y: 0xB }),
+            (0x8ABE, Instruction::Shl { x: 0xA, y: 0xB }),
+            (0x9AB0, Instruction::SkipNeReg { x: 0xA, y: 0xB }),
+            (0xAABC, Instruction::LoadI(0xABC)),
+            (0xBABC, Instruction::JumpOffset { x: 0xA, nnn: 0xABC }),
+            (0xCA12, Instruction::Rnd { x: 0xA, kk: 0x12 }),
+            (
+                0xDAB5,
+                Instruction::Draw {
+                    x: 0xA,
+                    y: 0xB,
+                    n: 5,
+                },
+            ),
+            (0xEA9E, Instruction::SkipKeyPressed { x: 0xA }),
+            (0xEAA1, Instruction::SkipKeyNotPressed { x: 0xA }),
+            (0xFA07, Instruction::LoadDelay { x: 0xA }),
+            (0xFA0A, Instruction::WaitKey { x: 0xA }),
+            (0xFA15, Instruction::SetDelay { x: 0xA }),
+            (0xFA18, Instruction::SetSound { x: 0xA }),
+            (0xFA1E, Instruction::AddI { x: 0xA }),
+            (0xFA29, Instruction::LoadFont { x: 0xA }),
+            (0xFA33, Instruction::StoreBcd { x: 0xA }),
+            (0xFA55, Instruction::StoreRegs { x: 0xA }),
+            (0xFA65, Instruction::LoadRegs { x: 0xA }),
+        ] {
+            assert_eq!(
+                Instruction::decode(opcode),
+                Some(instruction),
+                "{:04X}",
+                opcode
+            );
+            assert_eq!(instruction.encode(), opcode);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_opcodes() {
+        for opcode in [
+            0x5AB1, 0x5AB4, 0x8AB8, 0x8ABF, 0x9AB1, 0xEA00, 0xEA9F, 0xFAFF, 0xFA00, 0xF0FF,
+        ] {
+            assert_eq!(Instruction::decode(opcode), None, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn encode_inverts_decode() {
+        for opcode in 0..=0xFFFF {
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+            }
+        }
+    }
+}
@@ -1,61 +1,146 @@
-use std::collections::HashMap;
-use std::collections::HashSet;
-
-use sdl2::keyboard::Keycode;
+use crate::error::Chip8Error;
 
+// State of the 16 key hex keypad. Frontends translate their own key events into keys 0 to F.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Keyboard {
-    pub keymap: HashMap<Keycode, u8>,
-    pub keys: HashSet<u8>,
+    keys: [bool; 16],
+    // last key to go up, for FX0A which completes on release
+    released: Option<u8>,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Keyboard {
     pub fn new() -> Self {
         Keyboard {
-            keymap: Keyboard::gen_keymap(),
-            keys:  HashSet::new(),
+            keys: [false; 16],
+            released: None,
         }
     }
 
+    // Forget all keys, including the last release
     pub fn clear(&mut self) {
-        self.keys.clear();
-    } 
-
-    fn gen_keymap() -> HashMap<Keycode, u8> {
-        let mut keymap = HashMap::new();
-    
-        keymap.insert(Keycode::Num1, 0);
-        keymap.insert(Keycode::Num2, 1);
-        keymap.insert(Keycode::Num3, 2);
-        keymap.insert(Keycode::Num4, 3);
-        keymap.insert(Keycode::Q, 4);
-        keymap.insert(Keycode::W, 5);
-        keymap.insert(Keycode::E, 6);
-        keymap.insert(Keycode::R, 7);
-        keymap.insert(Keycode::A, 8);
-        keymap.insert(Keycode::S, 9);
-        keymap.insert(Keycode::D, 10);
-        keymap.insert(Keycode::F, 11);
-        keymap.insert(Keycode::Z, 12);
-        keymap.insert(Keycode::X, 13);
-        keymap.insert(Keycode::C, 14);
-        keymap.insert(Keycode::V, 15);
-    
-        keymap
-    }
-
-    pub fn update_keys(&mut self, keys_pressed: HashSet<Keycode>) {
-        // Map over the keys, only considering chip8 keys
-        self.keys.clear();
-
-        for x in keys_pressed.iter() {
-            if self.keymap.contains_key(x) {
-                self.keys.insert(*self.keymap.get(x).unwrap());
+        self.keys = [false; 16];
+        self.released = None;
+    }
+
+    // Keys outside 0 to F are errors for all of these
+    pub fn press(&mut self, key: u8) -> Result<(), Chip8Error> {
+        *self.key_mut(key)? = true;
+        Ok(())
+    }
+
+    pub fn release(&mut self, key: u8) -> Result<(), Chip8Error> {
+        let pressed = self.key_mut(key)?;
+        if *pressed {
+            *pressed = false;
+            self.released = Some(key);
+        }
+        Ok(())
+    }
+
+    pub fn release_all(&mut self) {
+        for key in 0..16 {
+            let _ = self.release(key);
+        }
+    }
+
+    // Replace the pressed keys, for frontends that poll the whole keyboard.
+    // Keys missing from `keys` are released.
+    pub fn set_pressed(&mut self, keys: &[u8]) -> Result<(), Chip8Error> {
+        if let Some(&key) = keys.iter().find(|&&key| key >= 16) {
+            return Err(Chip8Error::InvalidKey { key });
+        }
+        for key in 0..16 {
+            match keys.contains(&key) {
+                true => self.press(key)?,
+                false => self.release(key)?,
             }
         }
+        Ok(())
     }
 
     pub fn is_pressed(&self, key: u8) -> bool {
-        self.keys.contains(&key)
+        self.keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    // Lowest key held down, if any
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys
+            .iter()
+            .position(|&pressed| pressed)
+            .map(|key| key as u8)
+    }
+
+    // Last key released and not yet taken by take_released
+    pub fn recently_released(&self) -> Option<u8> {
+        self.released
+    }
+
+    pub fn take_released(&mut self) -> Option<u8> {
+        self.released.take()
+    }
+
+    fn key_mut(&mut self, key: u8) -> Result<&mut bool, Chip8Error> {
+        self.keys
+            .get_mut(key as usize)
+            .ok_or(Chip8Error::InvalidKey { key })
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_go_down_and_up() {
+        let mut keyboard = Keyboard::new();
+        keyboard.press(0xA).unwrap();
+        keyboard.press(0x3).unwrap();
+        assert!(keyboard.is_pressed(0xA) && keyboard.is_pressed(0x3));
+        assert_eq!(keyboard.first_pressed(), Some(0x3));
+        keyboard.release(0x3).unwrap();
+        assert!(!keyboard.is_pressed(0x3));
+        assert_eq!(keyboard.first_pressed(), Some(0xA));
+        keyboard.clear();
+        assert_eq!(keyboard.first_pressed(), None);
+    }
+
+    #[test]
+    fn keys_past_f_are_errors() {
+        let mut keyboard = Keyboard::new();
+        assert_eq!(
+            keyboard.press(0x10),
+            Err(Chip8Error::InvalidKey { key: 0x10 })
+        );
+        assert_eq!(
+            keyboard.release(0xFF),
+            Err(Chip8Error::InvalidKey { key: 0xFF })
+        );
+        assert!(!keyboard.is_pressed(0x10));
+        assert_eq!(keyboard, Keyboard::new());
+    }
+
+    #[test]
+    fn releases_are_remembered_until_taken() {
+        let mut keyboard = Keyboard::new();
+        // Releasing a key that isn't down doesn't count
+        keyboard.release(0x5).unwrap();
+        assert_eq!(keyboard.recently_released(), None);
+        keyboard.set_pressed(&[0x5, 0x7]).unwrap();
+        keyboard.set_pressed(&[0x7]).unwrap();
+        assert_eq!(keyboard.take_released(), Some(0x5));
+        assert_eq!(keyboard.take_released(), None);
+        keyboard.release_all();
+        assert_eq!(keyboard.take_released(), Some(0x7));
+        assert_eq!(
+            keyboard.set_pressed(&[0x1, 0x12]),
+            Err(Chip8Error::InvalidKey { key: 0x12 })
+        );
+        assert!(!keyboard.is_pressed(0x1));
+    }
+}
@@ -0,0 +1,51 @@
+// The emulator core, free of any frontend. The SDL2 frontend lives in main.rs and plugs into
+// frontend::run.
+// Without the std feature it's no_std, check with
+//   cargo check --lib --no-default-features --target thumbv7em-none-eabihf
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// Messages from the core go to stdout, and nowhere without std
+macro_rules! log {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "std")]
+        println!($($arg)*);
+        #[cfg(not(feature = "std"))]
+        let _ = format_args!($($arg)*);
+    };
+}
+
+pub mod asm;
+pub mod audio;
+pub mod bus;
+pub mod condition;
+pub mod coverage;
+pub mod cpu;
+#[cfg(feature = "std")]
+pub mod debugger;
+pub mod disasm;
+pub mod dispatch;
+pub mod display;
+pub mod emulator;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fonts;
+#[cfg(feature = "std")]
+pub mod frontend;
+pub mod history;
+pub mod instruction;
+pub mod keyboard;
+pub mod octo;
+pub mod overlay;
+pub mod palette;
+pub mod profile;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod quirks;
+pub mod rng;
+pub mod rom_db;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod user_flags;
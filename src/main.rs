@@ -1,63 +1,870 @@
-pub mod cpu;
-pub mod display;
-pub mod keyboard;
-
 extern crate sdl2;
 
-use std::collections::HashSet;
 use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::time::{Duration, Instant};
 
-use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
+use sdl2::render::{BlendMode, Canvas, Texture};
 use sdl2::video::Window;
+use sdl2::EventPump;
+
+use rusty_chip8::asm;
+use rusty_chip8::audio::{Audio, PatternWave};
+use rusty_chip8::bus::Bus;
+use rusty_chip8::condition::Condition;
+use rusty_chip8::coverage;
+use rusty_chip8::cpu::{self, MemoryInit, MemorySize, WatchKind, WriteProtection};
+use rusty_chip8::debugger;
+use rusty_chip8::disasm;
+use rusty_chip8::dispatch::Dispatch;
+use rusty_chip8::display::{Display, MAX_HEIGHT, MAX_WIDTH};
+use rusty_chip8::emulator::{Emulator, Timing, UnknownOpcodePolicy};
+use rusty_chip8::fonts::{self, FontSet};
+use rusty_chip8::frontend::{self, Command, Frontend, InputState, RunOptions};
+use rusty_chip8::history::{History, DEFAULT_HISTORY_LEN};
+use rusty_chip8::octo;
+use rusty_chip8::overlay::{self, MemoryView, MEMORY_VIEW_LINES};
+use rusty_chip8::palette::Palette;
+use rusty_chip8::quirks::Platform;
+use rusty_chip8::rng::Rng;
+use rusty_chip8::trace;
+use rusty_chip8::user_flags;
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
+struct Speaker {
+    wave: PatternWave,
 }
 
-impl AudioCallback for SquareWave {
+impl AudioCallback for Speaker {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+        self.wave.fill(out);
+    }
+}
+
+const WINDOW_TITLE: &str = "Rusty CHIP8";
+// Window pixels per overlay font pixel, and the gap around the overlay text
+const OVERLAY_SCALE: usize = 2;
+const OVERLAY_MARGIN: usize = 4;
+// Holding period advances a frame after this long, then every FRAME_ADVANCE_REPEAT
+const FRAME_ADVANCE_DELAY: Duration = Duration::from_millis(400);
+const FRAME_ADVANCE_REPEAT: Duration = Duration::from_millis(100);
+
+struct Options {
+    rom_path: String,
+    clock_hz: u32,
+    on_unknown_opcode: UnknownOpcodePolicy,
+    seed: Option<u64>,
+    strict_sys_calls: bool,
+    trace: bool,
+    trace_file: Option<String>,
+    timing: Timing,
+    platform: Option<Platform>,
+    stats: bool,
+    // entries and the snapshot interval, no history with 0 entries
+    history: (usize, usize),
+    coverage: Option<String>,
+    profile: bool,
+    dump_screen: bool,
+    load_address: Option<u16>,
+    protect_interpreter_area: WriteProtection,
+    memory_init: MemoryInit,
+    memory_size: Option<MemorySize>,
+    warn_uninitialized: bool,
+    log_writes: Option<(u16, u16)>,
+    scroll_half_pixel_lores: Option<bool>,
+    font: Option<FontSet>,
+    font_file: Option<String>,
+    dispatch: Dispatch,
+    palette: Palette,
+    breakpoints: Vec<(u16, Option<Condition>)>,
+    watchpoints: Vec<(u16, u16, WatchKind)>,
+    debug: bool,
+    disasm: bool,
+    analyze: bool,
+}
+
+const USAGE: &str = "Usage: rusty_chip8 <ROM path> <CPU speed in HZ> [options]
+       rusty_chip8 --disasm|--analyze <ROM path> [options]
+       rusty_chip8 asm <source path> [-o <ROM path>]    .8o sources are Octo assembly
+
+Options:
+    --analyze                             print a listing of the ROM instead of running it that
+                                          follows jumps, calls and skips from the start address,
+                                          with labels on their targets and the rest as db data
+    --break ADDR[:if=COND]                pause before executing the instruction at this hex
+                                          address and print the registers, can be repeated.
+                                          With a condition such as v3==0x1f&&i>0x300 only when it
+                                          holds, comparing v0-vf, i, dt, st, mem[ADDR] and numbers
+    --coverage PATH                       write which ROM bytes executed to a file on exit, with
+                                          the --analyze listing marked + executed or - not
+    --debug                               start paused and read debugger commands from stdin:
+                                          s [N] step, c continue, b ADDR break, p registers,
+                                          x ADDR [LEN] hex dump, set v0-vf|i|pc|dt|st VALUE,
+                                          disasm ADDR [COUNT], q quit
+    --disasm                              print a listing of the ROM instead of running it, with
+                                          the mnemonics of the platform
+    --dispatch match|table                decode opcodes with a match (default), or run them
+                                          straight from handler tables
+    --dump-screen                         print the screen as text on exit
+    --font standard|vip|dream6800|eti660|fishnchips
+                                          hex font of a specific machine (default standard,
+                                          eti660 with --platform eti660)
+    --font-file PATH                      load the hex font from an 80 byte file instead
+    --history N[:regs=EVERY]              keep the last N instructions (default 64, 0 for none) and
+                                          the registers before every EVERY-th, printed when an
+                                          error stops emulation
+    --load-address ADDR                   load and start the ROM at this hex address, e.g. 600
+                                          (default 200, 600 with --platform eti660)
+    --log-writes START-END                print every write the ROM makes to the hex address range,
+                                          e.g. 200-300 to spot self-modifying code
+    --lores-scroll half|whole             scroll by half or whole pixels in low resolution,
+                                          overriding the platform (half for schip-legacy)
+    --mem-init zero|random[:N]|pattern:XX fill memory above 0x200 with zeros (default), random
+                                          bytes from seed N, or the hex byte XX before loading
+    --memory 4k|64k                       size of the address space (default 64k for xochip,
+                                          4k otherwise)
+    --on-unknown-opcode stop|skip|pause   what to do when an invalid instruction is executed
+    --palette NAME|C0,C1,C2,C3            display colors: mono (default), octo, lcd, hotdog, grey,
+                                          cga0, cga1, or four hex RGB colors for the background,
+                                          plane 1, plane 2 and both planes
+    --platform chip8|schip|schip-legacy|xochip|hires|eti660
+                                          emulate the quirks of a specific interpreter
+    --profile                             print the most executed addresses and opcode families
+                                          on exit
+    --protect-interpreter-area skip|stop  drop writes below 0x200 with a warning, or stop on them
+    --seed N                              seed the RND instruction for reproducible runs
+    --stats                               print instruction and timer counters on exit
+    --strict-sys                          stop on 0NNN machine code calls instead of ignoring them
+    --trace                               print the address and opcode of every executed instruction
+    --trace-file PATH                     log every executed instruction and the registers it
+                                          changed to a file, see trace::TraceLog for the format
+    --timing simple|original              run at a flat CPU speed (default), or with COSMAC VIP
+                                          instruction timings, ignoring the CPU speed
+    --warn-uninitialized                  warn when the ROM reads memory it never wrote
+    --watch START-END:r|w|rw              pause after an instruction reads or writes the hex
+                                          address range, e.g. 300-30F:w, can be repeated
+
+Keys:
+    F1                                    show or hide the registers and instruction rate
+    F2                                    print the profile so far, see --profile
+    F3                                    show or hide the hex viewer, following I
+    PgUp, PgDn                            scroll the hex viewer while paused
+    Home                                  make the hex viewer follow I again
+    F5                                    restart the ROM
+    F6                                    switch to the next built-in palette
+    N                                     run one instruction while paused
+    .                                     run one 60Hz frame while paused, repeats when held
+    P, Space                              pause or resume
+    Esc                                   quit";
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut positional = Vec::new();
+    let mut on_unknown_opcode = UnknownOpcodePolicy::Stop;
+    let mut seed = None;
+    let mut strict_sys_calls = false;
+    let mut trace = false;
+    let mut trace_file = None;
+    let mut timing = Timing::Simple;
+    let mut platform = None;
+    let mut stats = false;
+    let mut history = (DEFAULT_HISTORY_LEN, 0);
+    let mut coverage = None;
+    let mut profile = false;
+    let mut dump_screen = false;
+    let mut load_address = None;
+    let mut protect_interpreter_area = WriteProtection::Off;
+    let mut memory_init = MemoryInit::Zero;
+    let mut memory_size = None;
+    let mut warn_uninitialized = false;
+    let mut log_writes = None;
+    let mut font = None;
+    let mut font_file = None;
+    let mut dispatch = Dispatch::Match;
+    let mut palette = Palette::default();
+    let mut debug = false;
+    let mut disasm = false;
+    let mut analyze = false;
+    let mut scroll_half_pixel_lores = None;
+    let mut breakpoints = Vec::new();
+    let mut watchpoints = Vec::new();
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--on-unknown-opcode" => {
+                on_unknown_opcode = match iter.next().map(String::as_str) {
+                    Some("stop") => UnknownOpcodePolicy::Stop,
+                    Some("skip") => UnknownOpcodePolicy::Skip,
+                    Some("pause") => UnknownOpcodePolicy::Pause,
+                    _ => {
+                        return Err(format!(
+                            "--on-unknown-opcode expects stop, skip or pause\n{}",
+                            USAGE
+                        ))
+                    }
+                }
+            }
+            "--break" => match iter.next().map(|s| parse_breakpoint(s)) {
+                Some(Ok(breakpoint)) => breakpoints.push(breakpoint),
+                Some(Err(msg)) => return Err(format!("--break: {}\n{}", msg, USAGE)),
+                None => return Err(format!("--break expects a hex address\n{}", USAGE)),
+            },
+            "--watch" => match iter.next().and_then(|s| parse_watchpoint(s)) {
+                Some(watchpoint) => watchpoints.push(watchpoint),
+                None => {
+                    return Err(format!(
+                        "--watch expects a hex address range and r, w or rw such as 300-30F:w\n{}",
+                        USAGE
+                    ))
+                }
+            },
+            "--lores-scroll" => {
+                scroll_half_pixel_lores = match iter.next().map(String::as_str) {
+                    Some("half") => Some(true),
+                    Some("whole") => Some(false),
+                    _ => return Err(format!("--lores-scroll expects half or whole\n{}", USAGE)),
+                }
+            }
+            "--palette" => {
+                palette = match iter.next().and_then(|arg| Palette::parse(arg)) {
+                    Some(palette) => palette,
+                    None => {
+                        return Err(format!(
+                            "--palette expects a palette name or four hex colors\n{}",
+                            USAGE
+                        ))
+                    }
+                }
+            }
+            "--memory" => {
+                memory_size = match iter.next().map(String::as_str) {
+                    Some("4k") => Some(MemorySize::Standard),
+                    Some("64k") => Some(MemorySize::Large),
+                    _ => return Err(format!("--memory expects 4k or 64k\n{}", USAGE)),
+                }
+            }
+            "--dispatch" => {
+                dispatch = match iter.next().map(String::as_str) {
+                    Some("match") => Dispatch::Match,
+                    Some("table") => Dispatch::Table,
+                    _ => return Err(format!("--dispatch expects match or table\n{}", USAGE)),
+                }
+            }
+            "--font" => {
+                font = match iter.next().and_then(|name| FontSet::from_name(name)) {
+                    Some(font) => Some(font),
+                    None => {
+                        return Err(format!(
+                            "--font expects standard, vip, dream6800, eti660 or fishnchips\n{}",
+                            USAGE
+                        ))
+                    }
+                }
+            }
+            "--font-file" => {
+                font_file = match iter.next() {
+                    Some(path) => Some(path.clone()),
+                    None => return Err(format!("--font-file expects a path\n{}", USAGE)),
+                }
+            }
+            "--coverage" => {
+                coverage = match iter.next() {
+                    Some(path) => Some(path.clone()),
+                    None => return Err(format!("--coverage expects a path\n{}", USAGE)),
+                }
+            }
+            "--history" => {
+                history = match iter.next().and_then(|s| parse_history(s)) {
+                    Some(history) => history,
+                    None => {
+                        return Err(format!(
+                        "--history expects a number of instructions such as 64 or 64:regs=8\n{}",
+                        USAGE
+                    ))
+                    }
+                }
+            }
+            "--trace-file" => {
+                trace_file = match iter.next() {
+                    Some(path) => Some(path.clone()),
+                    None => return Err(format!("--trace-file expects a path\n{}", USAGE)),
+                }
+            }
+            "--log-writes" => {
+                log_writes = match iter.next().and_then(|s| parse_address_range(s)) {
+                    Some(range) => Some(range),
+                    None => {
+                        return Err(format!(
+                            "--log-writes expects a hex address range such as 200-300\n{}",
+                            USAGE
+                        ))
+                    }
+                }
+            }
+            "--mem-init" => {
+                memory_init = match iter.next().map(|s| parse_memory_init(s)) {
+                    Some(Some(init)) => init,
+                    _ => {
+                        return Err(format!(
+                            "--mem-init expects zero, random, random:N or pattern:XX\n{}",
+                            USAGE
+                        ))
+                    }
+                }
+            }
+            "--seed" => {
+                seed = match iter.next().map(|s| s.parse::<u64>()) {
+                    Some(Ok(seed)) => Some(seed),
+                    _ => return Err(format!("--seed expects an unsigned integer\n{}", USAGE)),
+                }
+            }
+            "--strict-sys" => strict_sys_calls = true,
+            "--trace" => trace = true,
+            "--debug" => debug = true,
+            "--disasm" => disasm = true,
+            "--analyze" => analyze = true,
+            "--stats" => stats = true,
+            "--profile" => profile = true,
+            "--dump-screen" => dump_screen = true,
+            "--warn-uninitialized" => warn_uninitialized = true,
+            "--platform" => {
+                platform = match iter.next().and_then(|name| Platform::from_name(name)) {
+                    Some(platform) => Some(platform),
+                    None => {
+                        return Err(format!(
+                    "--platform expects chip8, schip, schip-legacy, xochip, hires or eti660\n{}",
+                    USAGE
+                ))
+                    }
+                }
+            }
+            "--load-address" => {
+                let addr = iter
+                    .next()
+                    .map(|s| u16::from_str_radix(s.trim_start_matches("0x"), 16));
+                load_address = match addr {
+                    Some(Ok(addr)) => Some(addr),
+                    _ => return Err(format!("--load-address expects a hex address\n{}", USAGE)),
+                }
+            }
+            "--protect-interpreter-area" => {
+                protect_interpreter_area = match iter.next().map(String::as_str) {
+                    Some("skip") => WriteProtection::Skip,
+                    Some("stop") => WriteProtection::Error,
+                    _ => {
+                        return Err(format!(
+                            "--protect-interpreter-area expects skip or stop\n{}",
+                            USAGE
+                        ))
+                    }
+                }
+            }
+            "--timing" => {
+                timing = match iter.next().map(String::as_str) {
+                    Some("simple") => Timing::Simple,
+                    Some("original") => Timing::Original,
+                    _ => return Err(format!("--timing expects simple or original\n{}", USAGE)),
+                }
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}\n{}", arg, USAGE)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    // The ROM has to start within memory, which is only known once all options are read
+    let memory = memory_size.unwrap_or(match platform {
+        Some(Platform::XoChip) => MemorySize::Large,
+        _ => MemorySize::Standard,
+    });
+    if let Some(addr) = load_address {
+        if addr as usize >= memory.bytes() {
+            return Err(format!(
+                "--load-address {:X} is past the end of memory at {:X}\n{}",
+                addr,
+                memory.bytes() - 1,
+                USAGE
+            ));
+        }
+    }
+
+    // A listing doesn't need a CPU speed
+    let clock_hz = match (positional.len(), disasm || analyze) {
+        (1, true) => 0,
+        (2, _) => positional[1]
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid CPU speed {}\n{}", positional[1], USAGE))?,
+        _ => return Err(USAGE.to_string()),
+    };
+
+    Ok(Options {
+        rom_path: positional[0].clone(),
+        clock_hz,
+        on_unknown_opcode,
+        seed,
+        strict_sys_calls,
+        trace,
+        trace_file,
+        timing,
+        platform,
+        stats,
+        history,
+        coverage,
+        profile,
+        dump_screen,
+        load_address,
+        protect_interpreter_area,
+        memory_init,
+        memory_size,
+        warn_uninitialized,
+        log_writes,
+        scroll_half_pixel_lores,
+        font,
+        font_file,
+        dispatch,
+        palette,
+        breakpoints,
+        watchpoints,
+        debug,
+        disasm,
+        analyze,
+    })
+}
+
+// Parse an inclusive hex range such as "200-2FF"
+fn parse_address_range(arg: &str) -> Option<(u16, u16)> {
+    let mut parts = arg.splitn(2, '-');
+    let start = u16::from_str_radix(parts.next()?.trim_start_matches("0x"), 16).ok()?;
+    let end = u16::from_str_radix(parts.next()?.trim_start_matches("0x"), 16).ok()?;
+    match start <= end {
+        true => Some((start, end)),
+        false => None,
+    }
+}
+
+// Parse a hex address with an optional condition such as "2A0:if=v3==0x1f"
+fn parse_breakpoint(arg: &str) -> Result<(u16, Option<Condition>), String> {
+    let mut parts = arg.splitn(2, ":if=");
+    let addr = parts.next().unwrap_or_default().trim_start_matches("0x");
+    let addr = u16::from_str_radix(addr, 16).map_err(|_| "expects a hex address".to_string())?;
+    let condition = match parts.next() {
+        Some(text) => Some(Condition::parse(text).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    Ok((addr, condition))
+}
+
+// Parse an inclusive hex range and access kind such as "300-30F:w"
+fn parse_watchpoint(arg: &str) -> Option<(u16, u16, WatchKind)> {
+    let mut parts = arg.splitn(2, ':');
+    let (start, end) = parse_address_range(parts.next()?)?;
+    let kind = match parts.next()? {
+        "r" => WatchKind::Read,
+        "w" => WatchKind::Write,
+        "rw" => WatchKind::ReadWrite,
+        _ => return None,
+    };
+    Some((start, end, kind))
+}
+
+// Parse a history length and optional snapshot interval such as "64:regs=8"
+fn parse_history(arg: &str) -> Option<(usize, usize)> {
+    let mut parts = arg.splitn(2, ":regs=");
+    let len = parts.next()?.parse().ok()?;
+    let snapshot_every = match parts.next() {
+        Some(every) => every.parse().ok()?,
+        None => 0,
+    };
+    Some((len, snapshot_every))
+}
+
+fn parse_memory_init(arg: &str) -> Option<MemoryInit> {
+    let mut parts = arg.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("zero"), None) => Some(MemoryInit::Zero),
+        (Some("random"), None) => Some(MemoryInit::Random(Rng::from_clock().next_u64())),
+        (Some("random"), Some(seed)) => seed.parse().ok().map(MemoryInit::Random),
+        (Some("pattern"), Some(val)) => u8::from_str_radix(val.trim_start_matches("0x"), 16)
+            .ok()
+            .map(MemoryInit::Pattern),
+        _ => None,
+    }
+}
+
+// rusty_chip8 asm SOURCE [-o ROM], sources ending in .8o are Octo
+fn assemble_file(args: &[String]) -> Result<(), String> {
+    let (source_path, rom_path) = match args {
+        [source] => (source, Path::new(source).with_extension("ch8")),
+        [source, flag, rom] if flag == "-o" => (source, PathBuf::from(rom)),
+        _ => return Err(USAGE.to_string()),
+    };
+    let source = fs::read_to_string(source_path)
+        .map_err(|e| format!("Failed to read {}: {}", source_path, e))?;
+    let rom = match Path::new(source_path).extension() {
+        Some(extension) if extension == "8o" => octo::assemble(&source, 0x200),
+        _ => asm::assemble(&source, 0x200),
+    }
+    .map_err(|e| format!("{}: {}", source_path, e))?;
+    fs::write(&rom_path, &rom)
+        .map_err(|e| format!("Failed to write {}: {}", rom_path.display(), e))?;
+    println!("Assembled {} bytes into {}", rom.len(), rom_path.display());
+    Ok(())
+}
+
+// The ROM as the CPU would load it, one instruction per line, or analyzed with --analyze
+fn print_listing(options: &Options) -> Result<(), String> {
+    let mut builder = cpu::CPU::builder().use_rom_database(options.platform.is_none());
+    if let Some(platform) = options.platform {
+        builder = builder.platform(platform);
+    }
+    if let Some(size) = options.memory_size {
+        builder = builder.memory_size(size);
+    }
+    if let Some(addr) = options.load_address {
+        builder = builder.load_address(addr);
+    }
+    let mut chip8_cpu = builder.build();
+    let size = chip8_cpu
+        .load_rom(&options.rom_path)
+        .map_err(|e| format!("Failed to load {}: {}", options.rom_path, e))?;
+    let start = chip8_cpu.program_start;
+    let end = start as usize + size;
+    let rom = &chip8_cpu.bus.ram()[..end];
+    if options.analyze {
+        print!("{}", disasm::analyze(&rom[start as usize..], start));
+        return Ok(());
+    }
+    let mut out = BufWriter::new(io::stdout());
+    for (addr, opcode, text) in disasm::disassemble_range_for(rom, start, size, chip8_cpu.platform)
+    {
+        let _ = writeln!(out, "{:#05X}: {:#06X}  {}", addr, opcode, text);
+    }
+    // A ROM of odd length ends in half a word, which the listing stops short of
+    if size % 2 == 1 {
+        let last = end - 1;
+        let _ = writeln!(
+            out,
+            "{:#05X}: {:#04X}    DB {:#04X}",
+            last, rom[last], rom[last]
+        );
+    }
+    Ok(())
+}
+
+fn print_stats(stats: &cpu::CpuStats) {
+    println!("Instructions executed: {}", stats.instructions);
+    for (family, count) in stats.instructions_by_family.iter().enumerate() {
+        if *count > 0 {
+            println!("    {:X}xxx: {}", family, count);
         }
     }
+    println!("Sprites drawn: {}", stats.draws);
+    println!("Timer ticks: {}", stats.timer_ticks);
 }
 
-pub fn update_canvas(canvas: &mut Canvas<Window>, chip8_cpu: &cpu::CPU) {
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
+// The CHIP-8 key a PC key stands for, laid out as the 4x4 block from 1 to V:
+//   1 2 3 4       0 1 2 3
+//   Q W E R  ->   4 5 6 7
+//   A S D F       8 9 A B
+//   Z X C V       C D E F
+fn chip8_key(keycode: Keycode) -> Option<u8> {
+    let key = match keycode {
+        Keycode::Num1 => 0,
+        Keycode::Num2 => 1,
+        Keycode::Num3 => 2,
+        Keycode::Num4 => 3,
+        Keycode::Q => 4,
+        Keycode::W => 5,
+        Keycode::E => 6,
+        Keycode::R => 7,
+        Keycode::A => 8,
+        Keycode::S => 9,
+        Keycode::D => 10,
+        Keycode::F => 11,
+        Keycode::Z => 12,
+        Keycode::X => 13,
+        Keycode::C => 14,
+        Keycode::V => 15,
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn palette_color(palette: &Palette, color: u8) -> Color {
+    let (r, g, b) = palette.rgb(color);
+    Color::RGB(r, g, b)
+}
+
+struct SdlFrontend<'a> {
+    event_pump: EventPump,
+    canvas: Canvas<Window>,
+    // The picture at one texel per pixel, big enough for the largest resolution.
+    // Smaller ones use its top left corner.
+    screen: Texture<'a>,
+    audio_device: AudioDevice<Speaker>,
+    // shown in the window title
+    halted: bool,
+    paused: bool,
+    // drawn over the top left corner of the window, see Frontend::set_overlay
+    overlay: Option<Vec<String>>,
+    memory_view: Option<MemoryView>,
+    // while period is held, when it advances another frame
+    next_frame_advance: Option<Instant>,
+}
+
+impl SdlFrontend<'_> {
+    fn update_title(&mut self) {
+        let mut title = WINDOW_TITLE.to_string();
+        if self.halted {
+            title.push_str(" - program finished");
+        }
+        if self.paused {
+            title.push_str(" - PAUSED");
+        }
+        // Only fails on a title containing a NUL byte
+        let _ = self.canvas.window_mut().set_title(&title);
+    }
+
+    // The register overlay at the top left and the hex viewer at the bottom left
+    fn draw_overlays(&mut self) -> Result<(), String> {
+        if let Some(lines) = &self.overlay {
+            draw_text(&mut self.canvas, lines, &[], 0)?;
+        }
+        if let Some(view) = &self.memory_view {
+            let lines = view.lines();
+            let (_, height) = overlay::text_size(&lines);
+            let (_, window_height) = self.canvas.output_size()?;
+            let top = window_height as i32 - (height * OVERLAY_SCALE + 2 * OVERLAY_MARGIN) as i32;
+            draw_text(&mut self.canvas, &lines, &view.written_cells(), top)?;
+        }
+        Ok(())
+    }
+}
+
+// White text on a translucent black box at the left edge, straight onto the canvas so the screen
+// texture isn't touched. The characters at the (row, column) cells in highlighted get a red
+// background.
+fn draw_text(
+    canvas: &mut Canvas<Window>,
+    lines: &[String],
+    highlighted: &[(usize, usize)],
+    top: i32,
+) -> Result<(), String> {
+    let scaled = |x: usize| (OVERLAY_MARGIN + x * OVERLAY_SCALE) as i32;
+    let (width, height) = overlay::text_size(lines);
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0xC0));
+    canvas.fill_rect(Rect::new(
+        0,
+        top,
+        (width * OVERLAY_SCALE + 2 * OVERLAY_MARGIN) as u32,
+        (height * OVERLAY_SCALE + 2 * OVERLAY_MARGIN) as u32,
+    ))?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    let cells: Vec<Rect> = highlighted
+        .iter()
+        .map(|&(row, column)| {
+            let (x, y) = overlay::cell_origin(row, column);
+            Rect::new(
+                scaled(x),
+                top + scaled(y),
+                (overlay::GLYPH_WIDTH * OVERLAY_SCALE) as u32,
+                (overlay::GLYPH_HEIGHT * OVERLAY_SCALE) as u32,
+            )
+        })
+        .collect();
+    canvas.set_draw_color(Color::RGB(0xC0, 0, 0));
+    canvas.fill_rects(&cells)?;
+
+    let pixels: Vec<Rect> = overlay::text_pixels(lines)
+        .map(|(x, y)| {
+            Rect::new(
+                scaled(x),
+                top + scaled(y),
+                OVERLAY_SCALE as u32,
+                OVERLAY_SCALE as u32,
+            )
+        })
+        .collect();
+    canvas.set_draw_color(Color::RGB(0xFF, 0xFF, 0xFF));
+    canvas.fill_rects(&pixels)
+}
+
+impl Frontend for SdlFrontend<'_> {
+    fn poll_input(&mut self) -> InputState {
+        let mut commands = Vec::new();
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => commands.push(Command::Quit),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => commands.push(Command::ToggleOverlay),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => commands.push(Command::PrintProfile),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => commands.push(Command::ToggleMemoryView),
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageUp),
+                    ..
+                } => commands.push(Command::ScrollMemoryView(-(MEMORY_VIEW_LINES as isize))),
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageDown),
+                    ..
+                } => commands.push(Command::ScrollMemoryView(MEMORY_VIEW_LINES as isize)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Home),
+                    ..
+                } => commands.push(Command::MemoryViewHome),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => commands.push(Command::Reset),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => commands.push(Command::NextPalette),
+                Event::KeyDown {
+                    keycode: Some(Keycode::P | Keycode::Space),
+                    ..
+                } => commands.push(Command::Pause),
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } => commands.push(Command::Step),
+                // Holding period repeats at our own rate rather than the system's
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    repeat: false,
+                    ..
+                } => {
+                    commands.push(Command::AdvanceFrame);
+                    self.next_frame_advance = Some(Instant::now() + FRAME_ADVANCE_DELAY);
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Period),
+                    ..
+                } => self.next_frame_advance = None,
+                _ => {}
+            }
+        }
+        if let Some(due) = &mut self.next_frame_advance {
+            if Instant::now() >= *due {
+                commands.push(Command::AdvanceFrame);
+                *due += FRAME_ADVANCE_REPEAT;
+            }
+        }
+
+        let keys = self
+            .event_pump
+            .keyboard_state()
+            .pressed_scancodes()
+            .filter_map(Keycode::from_scancode)
+            .filter_map(chip8_key)
+            .collect();
+        InputState { keys, commands }
+    }
 
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    for i in 0..chip8_cpu.display.fb.len() {
-        let x = i % 64;
-        let y = i / 64;
-        if chip8_cpu.display.get_pixel(x, y) {
-            canvas
-                .fill_rect(Rect::new((x * 12) as i32, (y * 12) as i32, 12, 12))
-                .unwrap();
+    // Only the rows that changed since the last call are converted again, then the whole
+    // picture is scaled onto the canvas
+    fn present(&mut self, display: &mut Display, palette: &Palette) -> Result<(), String> {
+        let (width, height) = (display.width(), display.height());
+        let mut row = Vec::with_capacity(width * 4);
+        for y in display.take_dirty().iter().take_while(|&y| y < height) {
+            display.row_to_rgba(y, palette, &mut row);
+            self.screen
+                .update(Rect::new(0, y as i32, width as u32, 1), &row, width * 4)
+                .map_err(|e| e.to_string())?;
         }
+
+        self.canvas.set_draw_color(palette_color(palette, 0));
+        self.canvas.clear();
+
+        // Scale the current resolution to fit the window, 12px cells in lores and 6px in hires.
+        // The square 64x64 screen is centered.
+        let (window_width, window_height) = self.canvas.output_size()?;
+        let cell = (window_width as usize / width).min(window_height as usize / height);
+        let left = (window_width as usize - cell * width) / 2;
+        self.canvas.copy(
+            &self.screen,
+            Rect::new(0, 0, width as u32, height as u32),
+            Rect::new(
+                left as i32,
+                0,
+                (cell * width) as u32,
+                (cell * height) as u32,
+            ),
+        )?;
+        self.draw_overlays()?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn set_beep(&mut self, on: bool) {
+        match on {
+            true => self.audio_device.resume(),
+            false => self.audio_device.pause(),
+        }
+    }
+
+    fn set_audio(&mut self, audio: &Audio) {
+        self.audio_device.lock().wave.set_audio(audio);
+    }
+
+    fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+        self.update_title();
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.update_title();
+    }
+
+    fn set_overlay(&mut self, lines: Option<&[String]>) {
+        self.overlay = lines.map(<[String]>::to_vec);
+    }
+
+    fn set_memory_view(&mut self, view: Option<&MemoryView>) {
+        self.memory_view = view.cloned();
     }
 }
 
 pub fn main() -> Result<(), String> {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        panic!("Expected path to Chip8 ROM as first argument, CPU speed in HZ as second argument");
+    if args.get(1).map(String::as_str) == Some("asm") {
+        if let Err(msg) = assemble_file(&args[2..]) {
+            eprintln!("{}", msg);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            process::exit(1);
+        }
+    };
+    if options.disasm || options.analyze {
+        return print_listing(&options);
     }
 
     let sdl_context = sdl2::init()?;
@@ -65,7 +872,7 @@ pub fn main() -> Result<(), String> {
     let video_subsystem = sdl_context.video()?;
 
     let window = video_subsystem
-        .window("Rusty CHIP8", 768, 384)
+        .window(WINDOW_TITLE, 768, 384)
         .position_centered()
         .build()
         .map_err(|e| e.to_string())?;
@@ -78,81 +885,182 @@ pub fn main() -> Result<(), String> {
 
     let audio_device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
         // initialize the audio callback
-        SquareWave {
-            phase_inc: 440.0 / spec.freq as f32,
-            phase: 0.0,
-            volume: 0.25,
+        Speaker {
+            wave: PatternWave::new(spec.freq as f32, 0.25),
         }
     })?;
 
-    let mut canvas: Canvas<Window> = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let canvas: Canvas<Window> = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let screen = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, MAX_WIDTH as u32, MAX_HEIGHT as u32)
+        .map_err(|e| e.to_string())?;
 
-    let mut event_pump = sdl_context.event_pump()?;
+    let mut frontend = SdlFrontend {
+        event_pump: sdl_context.event_pump()?,
+        canvas,
+        screen,
+        audio_device,
+        halted: false,
+        paused: false,
+        overlay: None,
+        memory_view: None,
+        next_frame_advance: None,
+    };
 
     // Initialize chip8 CPU
-    let mut chip8_cpu = cpu::CPU::new();
-    chip8_cpu.reset();
-    chip8_cpu.load_rom(&args[1]);
+    let mut builder = cpu::CPU::builder()
+        // An explicit platform always wins over the ROM database
+        .use_rom_database(options.platform.is_none())
+        .strict_sys_calls(options.strict_sys_calls)
+        .dispatch(options.dispatch)
+        .protect_interpreter_area(options.protect_interpreter_area)
+        .memory_init(options.memory_init)
+        .uninitialized_read_check(options.warn_uninitialized)
+        .seed(options.seed.unwrap_or_else(|| Rng::from_clock().next_u64()));
+    if let Some(platform) = options.platform {
+        builder = builder.platform(platform);
+    }
+    if let Some(size) = options.memory_size {
+        builder = builder.memory_size(size);
+    }
+    if let Some(font) = options.font {
+        builder = builder.font(font);
+    }
+    if let Some(path) = &options.font_file {
+        match fonts::read_font_file(path) {
+            Ok(font) => builder = builder.custom_font(font),
+            Err(e) => {
+                eprintln!("Failed to load {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(addr) = options.load_address {
+        builder = builder.load_address(addr);
+    }
+    let mut chip8_cpu = builder.build();
+    let rom_size = match chip8_cpu.load_rom(&options.rom_path) {
+        Ok(size) => {
+            println!("Loaded {} bytes at {:#05X}", size, chip8_cpu.program_start);
+            size
+        }
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", options.rom_path, e);
+            process::exit(1);
+        }
+    };
+    // As loaded, for --coverage once the program may have changed it
+    let start = chip8_cpu.program_start as usize;
+    let rom = chip8_cpu.bus.ram()[start..start + rom_size].to_vec();
+    // After loading, so it also wins over quirks from the ROM database
+    if let Some(half) = options.scroll_half_pixel_lores {
+        chip8_cpu.quirks.scroll_half_pixel_lores = half;
+    }
+    // Restore the high scores and such the ROM saved with FX75 on earlier runs
+    let flags_dir = user_flags::default_dir();
+    let rom_crc = chip8_cpu.rom_crc().unwrap_or_default();
+    if let Some(dir) = &flags_dir {
+        chip8_cpu.rpl_flags = user_flags::load(dir, rom_crc);
+    }
+    let loaded_flags = chip8_cpu.rpl_flags;
 
-    // Calculate how often we need to run a cpu cycle
-    const US_IN_S: u32 = 1000000;
-    let exec_time = US_IN_S / args[2].parse::<u32>().unwrap();
+    if options.trace {
+        // Buffer the trace, one write per instruction would dominate the run time
+        let mut out = BufWriter::new(io::stdout());
+        let platform = chip8_cpu.platform;
+        chip8_cpu.on_instruction(move |pc, opcode| {
+            let text = disasm::disassemble_for(opcode, platform);
+            let _ = writeln!(out, "{:#05X}: {:#06X}  {}", pc, opcode, text);
+        });
+    }
 
-    let mut cpu_exec_clk = Instant::now();
-    let mut delay_timer_clk = Instant::now();
-    let mut beep_timer = Instant::now();
+    if let (len @ 1.., snapshot_every) = options.history {
+        chip8_cpu.set_history(Some(History::new(len, snapshot_every)));
+    }
 
-    'main_loop: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'main_loop,
-                _ => {}
+    if let Some(path) = &options.trace_file {
+        match fs::File::create(path) {
+            Ok(file) => trace::install(&mut chip8_cpu, BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("Failed to create {}: {}", path, e);
+                process::exit(1);
             }
         }
+    }
 
-        if beep_timer.elapsed().as_millis() > 20 {
-            audio_device.pause();
-        }
+    if let Some((start, end)) = options.log_writes {
+        chip8_cpu.set_memory_observer(Box::new(move |addr, old, new| {
+            if (start..=end).contains(&addr) {
+                println!("Write {:#05X}: {:#04X} -> {:#04X}", addr, old, new);
+            }
+        }));
+    }
 
-        // Create a set of pressed Keys.
-        let keys: HashSet<Keycode> = event_pump
-            .keyboard_state()
-            .pressed_scancodes()
-            .filter_map(Keycode::from_scancode)
-            .collect();
+    for (addr, condition) in &options.breakpoints {
+        match condition {
+            Some(condition) => chip8_cpu.add_conditional_breakpoint(*addr, condition.clone()),
+            None => chip8_cpu.add_breakpoint(*addr),
+        }
+    }
+    for &(start, end, kind) in &options.watchpoints {
+        chip8_cpu.add_watchpoint(start..=end, kind);
+    }
 
-        // Update the key state in the chip8 CPU
-        // This is not optimal, make it a reference eventually
-        chip8_cpu.keyboard.update_keys(keys.clone());
+    let mut emulator = Emulator::new(chip8_cpu, options.clock_hz);
+    emulator.timing = options.timing;
+    emulator.on_unknown_opcode = options.on_unknown_opcode;
 
-        if cpu_exec_clk.elapsed().as_micros() >= exec_time.into() {
-            chip8_cpu.exec_cycle();
-            cpu_exec_clk = Instant::now();
-        }
+    let mut run_options = RunOptions {
+        palette: options.palette,
+        ..RunOptions::default()
+    };
+    if options.debug {
+        emulator.pause();
+        run_options.debug_commands = Some(debugger::read_commands());
+    }
+    // Emulation stopped on an error, exit with a failure status
+    let stopped_by_error = frontend::run(&mut frontend, &mut emulator, &run_options)?.is_some();
 
-        let mut output_beep = false;
-        if delay_timer_clk.elapsed().as_micros() >= (US_IN_S / 60).into() {
-            output_beep = chip8_cpu.update_timers();
-            delay_timer_clk = Instant::now();
+    let chip8_cpu = emulator.cpu;
+    if options.stats {
+        print_stats(chip8_cpu.stats());
+    }
+    if let Some(path) = &options.coverage {
+        let report = coverage::report(&rom, start as u16, chip8_cpu.coverage());
+        if let Err(e) = fs::write(path, report) {
+            eprintln!("Failed to write {}: {}", path, e);
         }
+    }
+    if options.profile {
+        let profile = chip8_cpu.profile();
+        print!(
+            "{}",
+            profile.report(chip8_cpu.bus.ram(), chip8_cpu.platform)
+        );
+    }
+    if options.dump_screen {
+        print!("{}", chip8_cpu.display.to_ascii());
+    }
 
-        if output_beep {
-            beep_timer = Instant::now();
-            audio_device.resume();
+    if let Some(dir) = &flags_dir {
+        if chip8_cpu.rpl_flags != loaded_flags {
+            if let Err(e) = user_flags::save(dir, rom_crc, &chip8_cpu.rpl_flags) {
+                eprintln!("Failed to save user flags to {}: {}", dir.display(), e);
+            }
         }
+    }
 
-        if chip8_cpu.display.need_redraw {
-            update_canvas(&mut canvas, &chip8_cpu);
-            chip8_cpu.display.need_redraw = false;
+    if stopped_by_error {
+        if let Some(history) = chip8_cpu.history() {
+            eprint!(
+                "Last instructions, oldest first:\n{}",
+                history.listing(chip8_cpu.platform)
+            );
         }
-
-        canvas.present();
-        ::std::thread::sleep(Duration::from_micros(100));
+        // Exiting skips the drop that writes out the end of --trace-file
+        drop(chip8_cpu);
+        process::exit(1);
     }
-
     Ok(())
-}
\ No newline at end of file
+}
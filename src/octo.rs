@@ -0,0 +1,754 @@
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use crate::error::{AsmError, AsmErrorKind};
+use crate::instruction::Instruction;
+
+// The Octo assembly language, the subset without macros:
+//
+//   :const SPEED 2          # constants, defined before use
+//   :alias x v3             # other names for registers
+//   : main                  # labels, main is where the program starts
+//     x := 0
+//     i := face             # labels can be used before they're defined
+//     loop
+//       sprite x x 3
+//       x += SPEED
+//       if x == 60 then x := 0
+//       if v0 key begin clear else v1 := delay end
+//       while x != 50
+//     again
+//   : face 0x3C 0x42 0x81   # bare numbers are raw bytes
+//
+// Both sides of == and != in conditions can be registers or numbers, and key and -key test
+// the keypad. Statements assemble to the same instructions as the classic mnemonics in asm.
+
+// Assemble Octo source into a ROM to be loaded at base
+pub fn assemble(source: &str, base: u16) -> Result<Vec<u8>, AsmError> {
+    let tokens = tokenize(source);
+    let compiler = compile(&tokens, base, false)?;
+    // Execution starts at main, like Octo. A jump to it goes first when anything comes before.
+    match compiler.labels.get("main") {
+        Some(&main) if main != base => Ok(compile(&tokens, base, true)?.rom),
+        _ => Ok(compiler.rom),
+    }
+}
+
+fn compile<'a>(
+    tokens: &[Token<'a>],
+    base: u16,
+    jump_to_main: bool,
+) -> Result<Compiler<'a>, AsmError> {
+    let mut compiler = Compiler {
+        tokens: tokens.to_vec(),
+        pos: 0,
+        base,
+        rom: Vec::new(),
+        labels: BTreeMap::new(),
+        constants: BTreeMap::new(),
+        aliases: BTreeMap::new(),
+        fixups: Vec::new(),
+        loops: Vec::new(),
+        blocks: Vec::new(),
+    };
+    if jump_to_main {
+        let main = Token {
+            text: "main",
+            line: 1,
+        };
+        compiler.fixup(0, FixupKind::Nnn, main);
+        compiler.emit(Instruction::Jump(0));
+    }
+    compiler.compile()?;
+    Ok(compiler)
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+// Whitespace separated words, # starts a comment
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for (k, text) in source.lines().enumerate() {
+        let text = text.split('#').next().unwrap_or_default();
+        for word in text.split_whitespace() {
+            tokens.push(Token {
+                text: word,
+                line: k + 1,
+            });
+        }
+    }
+    tokens
+}
+
+// Where a label goes once it's defined
+#[derive(Clone, Copy)]
+enum FixupKind {
+    // the low 12 bits of the word at the offset
+    Nnn,
+    // the whole word at the offset
+    Word,
+}
+
+struct Fixup<'a> {
+    at: usize,
+    kind: FixupKind,
+    label: Token<'a>,
+}
+
+struct Compiler<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    base: u16,
+    rom: Vec<u8>,
+    labels: BTreeMap<&'a str, u16>,
+    constants: BTreeMap<&'a str, u16>,
+    aliases: BTreeMap<&'a str, usize>,
+    // label references waiting for their definition
+    fixups: Vec<Fixup<'a>>,
+    // the open loops, with where each starts and the offsets of its while jumps
+    loops: Vec<(u16, Vec<usize>)>,
+    // the open if ... begin blocks, with the offset of the jump to their else or end
+    blocks: Vec<usize>,
+}
+
+impl<'a> Compiler<'a> {
+    fn compile(&mut self) -> Result<(), AsmError> {
+        while self.pos < self.tokens.len() {
+            self.statement()?;
+        }
+        // A loop without again or a begin without end
+        if !self.loops.is_empty() {
+            return Err(error(self.last_line(), "loop", AsmErrorKind::BadOperands));
+        }
+        if !self.blocks.is_empty() {
+            return Err(error(self.last_line(), "begin", AsmErrorKind::BadOperands));
+        }
+        for fixup in &self.fixups {
+            let addr = match self.labels.get(fixup.label.text) {
+                Some(&addr) => addr,
+                None => return Err(undefined(fixup.label)),
+            };
+            let word = u16::from_be_bytes([self.rom[fixup.at], self.rom[fixup.at + 1]]);
+            let word = match fixup.kind {
+                FixupKind::Nnn => (word & 0xF000) | check(fixup.label, addr, 0xFFF)?,
+                FixupKind::Word => addr,
+            };
+            self.rom[fixup.at..fixup.at + 2].copy_from_slice(&word.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.text {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(name)?;
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value(0xFFFF)?;
+                if self.constants.insert(name.text, value).is_some() {
+                    return Err(error(name.line, name.text, AsmErrorKind::DuplicateLabel));
+                }
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+            }
+            ":call" => {
+                let target = self.address()?;
+                self.emit(Instruction::Call(target));
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.rom.push(byte);
+            }
+            "clear" => self.emit(Instruction::Cls),
+            "return" | ";" => self.emit(Instruction::Ret),
+            "exit" => self.emit(Instruction::Exit),
+            "lores" => self.emit(Instruction::Lores),
+            "hires" => self.emit(Instruction::Hires),
+            "scroll-left" => self.emit(Instruction::ScrollLeft),
+            "scroll-right" => self.emit(Instruction::ScrollRight),
+            "audio" => self.emit(Instruction::LoadAudio),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown(n));
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp(n));
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::Plane(n));
+            }
+            "native" => {
+                let target = self.address()?;
+                self.emit(Instruction::Sys(target));
+            }
+            "jump" => {
+                let target = self.address()?;
+                self.emit(Instruction::Jump(target));
+            }
+            "jump0" => {
+                let nnn = self.address()?;
+                self.emit(Instruction::JumpOffset { x: 0, nnn });
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, n });
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let instruction = match self.peek() {
+                    Some("-") => {
+                        self.pos += 1;
+                        let y = self.register()?;
+                        match token.text {
+                            "save" => Instruction::StoreRange { x, y },
+                            _ => Instruction::LoadRange { x, y },
+                        }
+                    }
+                    _ => match token.text {
+                        "save" => Instruction::StoreRegs { x },
+                        _ => Instruction::LoadRegs { x },
+                    },
+                };
+                self.emit(instruction);
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreFlags { x });
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags { x });
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreBcd { x });
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.text {
+                    "delay" => Instruction::SetDelay { x },
+                    "buzzer" => Instruction::SetSound { x },
+                    _ => Instruction::SetPitch { x },
+                });
+            }
+            "i" => self.index_statement()?,
+            "if" => self.if_statement()?,
+            "else" => {
+                let jump = self.blocks.pop().ok_or_else(|| unexpected(token))?;
+                let end = self.rom.len();
+                self.emit(Instruction::Jump(0));
+                self.patch_jump(jump, token)?;
+                self.blocks.push(end);
+            }
+            "end" => {
+                let jump = self.blocks.pop().ok_or_else(|| unexpected(token))?;
+                self.patch_jump(jump, token)?;
+            }
+            "loop" => {
+                let start = self.here(token)?;
+                self.loops.push((start, Vec::new()));
+            }
+            "while" => {
+                // Leave the loop when the condition doesn't hold
+                self.condition(true)?;
+                let jump = self.rom.len();
+                self.emit(Instruction::Jump(0));
+                match self.loops.last_mut() {
+                    Some((_, breaks)) => breaks.push(jump),
+                    None => return Err(unexpected(token)),
+                }
+            }
+            "again" => {
+                let (start, breaks) = self.loops.pop().ok_or_else(|| unexpected(token))?;
+                self.emit(Instruction::Jump(check(token, start, 0xFFF)?));
+                for jump in breaks {
+                    self.patch_jump(jump, token)?;
+                }
+            }
+            _ if self.register_of(token).is_some() => self.register_statement(token)?,
+            _ if parse_number(token.text).is_some() => {
+                self.pos -= 1;
+                let byte = self.byte()?;
+                self.rom.push(byte);
+            }
+            // A label on its own is a call
+            _ if is_identifier(token.text) => {
+                self.pos -= 1;
+                let target = self.address()?;
+                self.emit(Instruction::Call(target));
+            }
+            _ => return Err(error(token.line, token.text, AsmErrorKind::UnknownMnemonic)),
+        }
+        Ok(())
+    }
+
+    // i := addr, i := long addr, i := hex vx, i := bighex vx, i += vx
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        match op.text {
+            ":=" => match self.peek() {
+                Some("long") => {
+                    self.pos += 1;
+                    self.emit(Instruction::LoadLongI);
+                    let at = self.rom.len();
+                    self.rom.extend_from_slice(&[0, 0]);
+                    let target = self.next()?;
+                    match self.resolve(target, 0xFFFF)? {
+                        Some(addr) => self.rom[at..].copy_from_slice(&addr.to_be_bytes()),
+                        None => self.fixup(at, FixupKind::Word, target),
+                    }
+                }
+                Some("hex") => {
+                    self.pos += 1;
+                    let x = self.register()?;
+                    self.emit(Instruction::LoadFont { x });
+                }
+                Some("bighex") => {
+                    self.pos += 1;
+                    let x = self.register()?;
+                    self.emit(Instruction::LoadBigFont { x });
+                }
+                _ => {
+                    let addr = self.address()?;
+                    self.emit(Instruction::LoadI(addr));
+                }
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddI { x });
+            }
+            _ => return Err(unexpected(op)),
+        }
+        Ok(())
+    }
+
+    // vx := ..., vx += ... and the other assignments to a register
+    fn register_statement(&mut self, target: Token<'a>) -> Result<(), AsmError> {
+        let x = self.register_of(target).unwrap_or_default();
+        let op = self.next()?;
+        let source = self.next()?;
+        let y = self.register_of(source);
+        let instruction = match (op.text, source.text, y) {
+            (":=", _, Some(y)) => Instruction::LoadReg { x, y },
+            (":=", "delay", _) => Instruction::LoadDelay { x },
+            (":=", "key", _) => Instruction::WaitKey { x },
+            (":=", "random", _) => {
+                let kk = self.byte()?;
+                Instruction::Rnd { x, kk }
+            }
+            (":=", _, None) => Instruction::LoadByte {
+                x,
+                kk: self.byte_of(source)?,
+            },
+            ("+=", _, Some(y)) => Instruction::AddReg { x, y },
+            ("+=", _, None) => Instruction::AddVxByte {
+                x,
+                kk: self.byte_of(source)?,
+            },
+            ("-=", _, Some(y)) => Instruction::Sub { x, y },
+            // Adding the two's complement
+            ("-=", _, None) => Instruction::AddVxByte {
+                x,
+                kk: self.byte_of(source)?.wrapping_neg(),
+            },
+            ("=-", _, Some(y)) => Instruction::SubN { x, y },
+            ("|=", _, Some(y)) => Instruction::Or { x, y },
+            ("&=", _, Some(y)) => Instruction::And { x, y },
+            ("^=", _, Some(y)) => Instruction::Xor { x, y },
+            (">>=", _, Some(y)) => Instruction::Shr { x, y },
+            ("<<=", _, Some(y)) => Instruction::Shl { x, y },
+            _ => return Err(unexpected(op)),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    // if COND then STATEMENT, or if COND begin ... [else ...] end
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let keyword = self
+            .tokens
+            .iter()
+            .skip(self.pos)
+            .map(|token| token.text)
+            .find(|&text| text == "then" || text == "begin");
+        match keyword {
+            Some("then") => {
+                self.condition(false)?;
+                self.expect("then")?;
+                self.statement()
+            }
+            _ => {
+                self.condition(true)?;
+                self.expect("begin")?;
+                self.blocks.push(self.rom.len());
+                self.emit(Instruction::Jump(0));
+                Ok(())
+            }
+        }
+    }
+
+    // The skip for a condition. It passes over the next instruction when the condition is false,
+    // or when it holds if skip_when_true.
+    fn condition(&mut self, skip_when_true: bool) -> Result<(), AsmError> {
+        let left = self.next()?;
+        let x = self.register_of(left).ok_or_else(|| unexpected(left))?;
+        let op = self.next()?;
+        let instruction = match op.text {
+            "key" | "-key" => {
+                // SKP skips when the key is held
+                match (op.text == "key") == skip_when_true {
+                    true => Instruction::SkipKeyPressed { x },
+                    false => Instruction::SkipKeyNotPressed { x },
+                }
+            }
+            "==" | "!=" => {
+                let equal_skips = (op.text == "==") == skip_when_true;
+                let right = self.next()?;
+                match (self.register_of(right), equal_skips) {
+                    (Some(y), true) => Instruction::SkipEqReg { x, y },
+                    (Some(y), false) => Instruction::SkipNeReg { x, y },
+                    (None, true) => Instruction::SkipEqByte {
+                        x,
+                        kk: self.byte_of(right)?,
+                    },
+                    (None, false) => Instruction::SkipNeByte {
+                        x,
+                        kk: self.byte_of(right)?,
+                    },
+                }
+            }
+            _ => return Err(unexpected(op)),
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: Token<'a>) -> Result<(), AsmError> {
+        if !is_identifier(name.text) {
+            return Err(unexpected(name));
+        }
+        let addr = self.here(name)?;
+        if self.labels.insert(name.text, addr).is_some() {
+            return Err(error(name.line, name.text, AsmErrorKind::DuplicateLabel));
+        }
+        Ok(())
+    }
+
+    // The address the next byte goes to
+    fn here(&self, token: Token) -> Result<u16, AsmError> {
+        let addr = self.base as usize + self.rom.len();
+        u16::try_from(addr).map_err(|_| {
+            error(
+                token.line,
+                token.text,
+                AsmErrorKind::OutOfRange { max: 0xFFFF },
+            )
+        })
+    }
+
+    // Point the jump at offset jump to the next instruction
+    fn patch_jump(&mut self, jump: usize, token: Token) -> Result<(), AsmError> {
+        let target = check(token, self.here(token)?, 0xFFF)?;
+        let word = Instruction::Jump(target).encode();
+        self.rom[jump..jump + 2].copy_from_slice(&word.to_be_bytes());
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.rom
+            .extend_from_slice(&instruction.encode().to_be_bytes());
+    }
+
+    fn fixup(&mut self, at: usize, kind: FixupKind, label: Token<'a>) {
+        self.fixups.push(Fixup { at, kind, label });
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, AsmError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| error(self.last_line(), "end of file", AsmErrorKind::BadOperands))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|token| token.text)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.text == text {
+            true => Ok(()),
+            false => Err(unexpected(token)),
+        }
+    }
+
+    fn last_line(&self) -> usize {
+        self.tokens.last().map_or(1, |token| token.line)
+    }
+
+    fn register(&mut self) -> Result<usize, AsmError> {
+        let token = self.next()?;
+        self.register_of(token).ok_or_else(|| unexpected(token))
+    }
+
+    fn register_of(&self, token: Token) -> Option<usize> {
+        if let Some(&x) = self.aliases.get(token.text) {
+            return Some(x);
+        }
+        let digit = token.text.strip_prefix(['v', 'V'])?;
+        match digit.len() {
+            1 => usize::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    // A number or constant, or a label already defined. None for a label that isn't yet.
+    fn resolve(&self, token: Token, max: u16) -> Result<Option<u16>, AsmError> {
+        let value = match parse_number(token.text) {
+            Some(n) => n,
+            None => match self
+                .constants
+                .get(token.text)
+                .or_else(|| self.labels.get(token.text))
+            {
+                Some(&value) => value as i64,
+                None if is_identifier(token.text) => return Ok(None),
+                None => return Err(unexpected(token)),
+            },
+        };
+        // Negative numbers count down from the top, -1 is 0xFF as a byte
+        let value = match value {
+            n if n < 0 && -n <= max as i64 / 2 + 1 => n + max as i64 + 1,
+            n => n,
+        };
+        match u16::try_from(value) {
+            Ok(value) if value <= max => Ok(Some(value)),
+            _ => Err(error(
+                token.line,
+                token.text,
+                AsmErrorKind::OutOfRange { max },
+            )),
+        }
+    }
+
+    // A value that has to be known now
+    fn value_of(&self, token: Token, max: u16) -> Result<u16, AsmError> {
+        self.resolve(token, max)?.ok_or_else(|| undefined(token))
+    }
+
+    fn value(&mut self, max: u16) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        self.value_of(token, max)
+    }
+
+    fn byte_of(&self, token: Token) -> Result<u8, AsmError> {
+        self.value_of(token, 0xFF).map(|n| n as u8)
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        self.value(0xFF).map(|n| n as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        self.value(0xF).map(|n| n as u8)
+    }
+
+    // A 12-bit address. Labels not defined yet are filled in at the end, so the instruction
+    // using it has to be the next one emitted.
+    fn address(&mut self) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        match self.resolve(token, 0xFFF)? {
+            Some(addr) => Ok(addr),
+            None => {
+                self.fixup(self.rom.len(), FixupKind::Nnn, token);
+                Ok(0)
+            }
+        }
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Decimal, 0x hex or 0b binary, possibly negative
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let n = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -n } else { n })
+}
+
+fn check(token: Token, value: u16, max: u16) -> Result<u16, AsmError> {
+    match value <= max {
+        true => Ok(value),
+        false => Err(error(
+            token.line,
+            token.text,
+            AsmErrorKind::OutOfRange { max },
+        )),
+    }
+}
+
+fn error(line: usize, token: &str, kind: AsmErrorKind) -> AsmError {
+    AsmError {
+        line,
+        token: token.to_string(),
+        kind,
+    }
+}
+
+fn unexpected(token: Token) -> AsmError {
+    error(token.line, token.text, AsmErrorKind::BadOperands)
+}
+
+fn undefined(token: Token) -> AsmError {
+    error(token.line, token.text, AsmErrorKind::UndefinedLabel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    // The example at the top of the file
+    const EXAMPLE: &str = "
+        :const SPEED 2
+        :alias x v3
+        : main
+          x := 0
+          i := face
+          loop
+            sprite x x 3
+            x += SPEED
+            if x == 60 then x := 0
+            if v0 key begin clear else v1 := delay end
+            while x != 50
+          again
+        : face 0x3C 0x42 0x81
+    ";
+
+    #[test]
+    fn example_matches_octo() {
+        let rom = assemble(EXAMPLE, 0x200).unwrap();
+        assert_eq!(
+            rom,
+            [
+                0x63, 0x00, 0xA2, 0x1C, 0xD3, 0x33, 0x73, 0x02, 0x43, 0x3C, 0x63, 0x00, 0xE0, 0x9E,
+                0x12, 0x14, 0x00, 0xE0, 0x12, 0x16, 0xF1, 0x07, 0x43, 0x32, 0x12, 0x1C, 0x12, 0x04,
+                0x3C, 0x42, 0x81,
+            ]
+        );
+    }
+
+    #[test]
+    fn example_matches_classic_assembler() {
+        let classic = "
+            main:   LD V3, 0
+                    LD I, face
+            top:    DRW V3, V3, 3
+                    ADD V3, 2
+                    SNE V3, 60
+                    LD V3, 0
+                    SKP V0
+                    JP other
+                    CLS
+                    JP done
+            other:  LD V1, DT
+            done:   SNE V3, 50
+                    JP face
+                    JP top
+            face:   db 0x3C, 0x42, 0x81
+        ";
+        assert_eq!(
+            assemble(EXAMPLE, 0x200).unwrap(),
+            asm::assemble(classic, 0x200).unwrap()
+        );
+    }
+
+    #[test]
+    fn forward_main_gets_a_jump() {
+        let rom = assemble(": data 0xAA : main jump main", 0x200).unwrap();
+        // JP main / data / JP main at 0x203
+        assert_eq!(rom, [0x12, 0x03, 0xAA, 0x12, 0x03]);
+    }
+
+    #[test]
+    fn statements_encode_like_octo() {
+        let cases: [(&str, u16); 12] = [
+            ("v1 := v2", 0x8120),
+            ("v1 -= 1", 0x71FF),
+            ("v1 =- v2", 0x8127),
+            ("v1 >>= v2", 0x8126),
+            ("v1 := random 0x0F", 0xC10F),
+            ("v1 := key", 0xF10A),
+            ("i += v4", 0xF41E),
+            ("i := hex v4", 0xF429),
+            ("buzzer := v2", 0xF218),
+            ("bcd v5", 0xF533),
+            ("save v5", 0xF555),
+            ("load v2 - v5", 0x5253),
+        ];
+        for (source, opcode) in cases {
+            assert_eq!(
+                assemble(source, 0x200).unwrap(),
+                opcode.to_be_bytes(),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn loop_past_4k_is_out_of_range() {
+        let err = assemble("loop again", 0x1000).unwrap_err();
+        assert_eq!(err.line, 1);
+        assert_eq!(err.token, "again");
+        assert_eq!(err.kind, AsmErrorKind::OutOfRange { max: 0xFFF });
+    }
+
+    #[test]
+    fn errors_name_line_and_token() {
+        let err = assemble("v0 := 1\njump nowhere", 0x200).unwrap_err();
+        assert_eq!((err.line, err.token.as_str()), (2, "nowhere"));
+        assert_eq!(err.kind, AsmErrorKind::UndefinedLabel);
+        let err = assemble("loop\nv0 += 1", 0x200).unwrap_err();
+        assert_eq!(err.kind, AsmErrorKind::BadOperands);
+        let err = assemble("v0 := 256", 0x200).unwrap_err();
+        assert_eq!(err.kind, AsmErrorKind::OutOfRange { max: 0xFF });
+    }
+}
@@ -0,0 +1,294 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+
+// Text drawn over the screen by frontends, such as the register overlay
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+// Space between characters and between lines
+pub const GLYPH_SPACING: usize = 1;
+
+// The first character in TEXT_FONT
+const FIRST_CHAR: u8 = b' ';
+
+// A 5x7 font for ASCII space to underscore, 7 rows per glyph.
+// Only the high 5 bits of each row are drawn.
+const TEXT_FONT: [u8; 448] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // space
+    0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0x20, // !
+    0x50, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, // "
+    0x50, 0x50, 0xF8, 0x50, 0xF8, 0x50, 0x50, // #
+    0x20, 0x78, 0xA0, 0x70, 0x28, 0xF0, 0x20, // $
+    0xC0, 0xC8, 0x10, 0x20, 0x40, 0x98, 0x18, // %
+    0x60, 0x90, 0xA0, 0x40, 0xA8, 0x90, 0x68, // &
+    0x20, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, // '
+    0x10, 0x20, 0x40, 0x40, 0x40, 0x20, 0x10, // (
+    0x40, 0x20, 0x10, 0x10, 0x10, 0x20, 0x40, // )
+    0x00, 0x20, 0xA8, 0x70, 0xA8, 0x20, 0x00, // *
+    0x00, 0x20, 0x20, 0xF8, 0x20, 0x20, 0x00, // +
+    0x00, 0x00, 0x00, 0x00, 0x60, 0x20, 0x40, // ,
+    0x00, 0x00, 0x00, 0xF8, 0x00, 0x00, 0x00, // -
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x60, // .
+    0x00, 0x08, 0x10, 0x20, 0x40, 0x80, 0x00, // /
+    0x70, 0x88, 0x98, 0xA8, 0xC8, 0x88, 0x70, // 0
+    0x20, 0x60, 0x20, 0x20, 0x20, 0x20, 0x70, // 1
+    0x70, 0x88, 0x08, 0x10, 0x20, 0x40, 0xF8, // 2
+    0xF8, 0x10, 0x20, 0x10, 0x08, 0x88, 0x70, // 3
+    0x10, 0x30, 0x50, 0x90, 0xF8, 0x10, 0x10, // 4
+    0xF8, 0x80, 0xF0, 0x08, 0x08, 0x88, 0x70, // 5
+    0x30, 0x40, 0x80, 0xF0, 0x88, 0x88, 0x70, // 6
+    0xF8, 0x08, 0x10, 0x20, 0x40, 0x40, 0x40, // 7
+    0x70, 0x88, 0x88, 0x70, 0x88, 0x88, 0x70, // 8
+    0x70, 0x88, 0x88, 0x78, 0x08, 0x10, 0x60, // 9
+    0x00, 0x60, 0x60, 0x00, 0x60, 0x60, 0x00, // :
+    0x00, 0x60, 0x60, 0x00, 0x60, 0x20, 0x40, // ;
+    0x10, 0x20, 0x40, 0x80, 0x40, 0x20, 0x10, // <
+    0x00, 0x00, 0xF8, 0x00, 0xF8, 0x00, 0x00, // =
+    0x40, 0x20, 0x10, 0x08, 0x10, 0x20, 0x40, // >
+    0x70, 0x88, 0x08, 0x10, 0x20, 0x00, 0x20, // ?
+    0x70, 0x88, 0x08, 0x68, 0xA8, 0xA8, 0x70, // @
+    0x70, 0x88, 0x88, 0xF8, 0x88, 0x88, 0x88, // A
+    0xF0, 0x88, 0x88, 0xF0, 0x88, 0x88, 0xF0, // B
+    0x70, 0x88, 0x80, 0x80, 0x80, 0x88, 0x70, // C
+    0xE0, 0x90, 0x88, 0x88, 0x88, 0x90, 0xE0, // D
+    0xF8, 0x80, 0x80, 0xF0, 0x80, 0x80, 0xF8, // E
+    0xF8, 0x80, 0x80, 0xF0, 0x80, 0x80, 0x80, // F
+    0x70, 0x88, 0x80, 0xB8, 0x88, 0x88, 0x78, // G
+    0x88, 0x88, 0x88, 0xF8, 0x88, 0x88, 0x88, // H
+    0x70, 0x20, 0x20, 0x20, 0x20, 0x20, 0x70, // I
+    0x38, 0x10, 0x10, 0x10, 0x10, 0x90, 0x60, // J
+    0x88, 0x90, 0xA0, 0xC0, 0xA0, 0x90, 0x88, // K
+    0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0xF8, // L
+    0x88, 0xD8, 0xA8, 0xA8, 0x88, 0x88, 0x88, // M
+    0x88, 0x88, 0xC8, 0xA8, 0x98, 0x88, 0x88, // N
+    0x70, 0x88, 0x88, 0x88, 0x88, 0x88, 0x70, // O
+    0xF0, 0x88, 0x88, 0xF0, 0x80, 0x80, 0x80, // P
+    0x70, 0x88, 0x88, 0x88, 0xA8, 0x90, 0x68, // Q
+    0xF0, 0x88, 0x88, 0xF0, 0xA0, 0x90, 0x88, // R
+    0x78, 0x80, 0x80, 0x70, 0x08, 0x08, 0xF0, // S
+    0xF8, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, // T
+    0x88, 0x88, 0x88, 0x88, 0x88, 0x88, 0x70, // U
+    0x88, 0x88, 0x88, 0x88, 0x88, 0x50, 0x20, // V
+    0x88, 0x88, 0x88, 0xA8, 0xA8, 0xA8, 0x50, // W
+    0x88, 0x88, 0x50, 0x20, 0x50, 0x88, 0x88, // X
+    0x88, 0x88, 0x50, 0x20, 0x20, 0x20, 0x20, // Y
+    0xF8, 0x08, 0x10, 0x20, 0x40, 0x80, 0xF8, // Z
+    0x70, 0x40, 0x40, 0x40, 0x40, 0x40, 0x70, // [
+    0x00, 0x80, 0x40, 0x20, 0x10, 0x08, 0x00, // backslash
+    0x70, 0x10, 0x10, 0x10, 0x10, 0x10, 0x70, // ]
+    0x20, 0x50, 0x88, 0x00, 0x00, 0x00, 0x00, // ^
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, // _
+];
+
+// The rows of the glyph for c. Lowercase letters use the uppercase glyphs, characters without a
+// glyph show as a question mark.
+pub fn glyph(c: char) -> &'static [u8] {
+    let code = match c.to_ascii_uppercase() {
+        c @ ' '..='_' => c as u8,
+        _ => b'?',
+    };
+    let start = (code - FIRST_CHAR) as usize * GLYPH_HEIGHT;
+    &TEXT_FONT[start..start + GLYPH_HEIGHT]
+}
+
+// Top left corner in font pixels of the character at row and column
+pub fn cell_origin(row: usize, column: usize) -> (usize, usize) {
+    (
+        column * (GLYPH_WIDTH + GLYPH_SPACING),
+        row * (GLYPH_HEIGHT + GLYPH_SPACING),
+    )
+}
+
+// Width and height in font pixels of lines of text drawn by text_pixels
+pub fn text_size(lines: &[String]) -> (usize, usize) {
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let cell_width = GLYPH_WIDTH + GLYPH_SPACING;
+    let cell_height = GLYPH_HEIGHT + GLYPH_SPACING;
+    (
+        (columns * cell_width).saturating_sub(GLYPH_SPACING),
+        (lines.len() * cell_height).saturating_sub(GLYPH_SPACING),
+    )
+}
+
+// The lit pixels of lines of text, top left at (0, 0)
+pub fn text_pixels(lines: &[String]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    lines.iter().enumerate().flat_map(|(row, line)| {
+        line.chars().enumerate().flat_map(move |(column, c)| {
+            let (left, top) = cell_origin(row, column);
+            glyph(c).iter().enumerate().flat_map(move |(y, bits)| {
+                (0..GLYPH_WIDTH)
+                    .filter(move |x| bits & (0x80 >> x) != 0)
+                    .map(move |x| (left + x, top + y))
+            })
+        })
+    })
+}
+
+// The registers, the next instruction and the instruction rate, one line each
+pub fn state_lines<B: Bus>(cpu: &CPU<B>, instructions_per_second: u64) -> Vec<String> {
+    let mut lines: Vec<String> = format!("{}", cpu).lines().map(String::from).collect();
+    lines.push(format!("IPS:   {}", instructions_per_second));
+    lines
+}
+
+// Bytes per line and lines of the hex viewer
+pub const MEMORY_VIEW_WIDTH: usize = 16;
+pub const MEMORY_VIEW_LINES: usize = 16;
+
+// A page of memory for the hex viewer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryView {
+    // address of the first byte, a multiple of MEMORY_VIEW_WIDTH
+    pub start: usize,
+    pub bytes: Vec<u8>,
+    // the bytes written since the last view, same length as bytes
+    pub written: Vec<bool>,
+}
+
+impl MemoryView {
+    // The page starting at the line holding start, moved back to fit when it runs past the end
+    // of memory. written lists addresses written since the last view.
+    pub fn new(ram: &[u8], start: usize, written: &[u16]) -> Self {
+        let start = Self::clamp_start(ram.len(), start);
+        let end = (start + MEMORY_VIEW_WIDTH * MEMORY_VIEW_LINES).min(ram.len());
+        let mut flags = vec![false; end - start];
+        for &addr in written {
+            if let Some(flag) = (addr as usize)
+                .checked_sub(start)
+                .and_then(|k| flags.get_mut(k))
+            {
+                *flag = true;
+            }
+        }
+        MemoryView {
+            start,
+            bytes: ram[start..end].to_vec(),
+            written: flags,
+        }
+    }
+
+    // The first address of a page showing addr on its fifth line
+    pub fn start_around(addr: usize) -> usize {
+        (addr - addr % MEMORY_VIEW_WIDTH).saturating_sub(4 * MEMORY_VIEW_WIDTH)
+    }
+
+    // start rounded down to a line and moved back so a whole page of memory_len bytes fits
+    pub fn clamp_start(memory_len: usize, start: usize) -> usize {
+        let last = memory_len.saturating_sub(MEMORY_VIEW_WIDTH * MEMORY_VIEW_LINES);
+        let start = start.min(last);
+        start - start % MEMORY_VIEW_WIDTH
+    }
+
+    // The page as text, such as 0x300: 00 11 22 ...
+    pub fn lines(&self) -> Vec<String> {
+        self.bytes
+            .chunks(MEMORY_VIEW_WIDTH)
+            .enumerate()
+            .map(|(line, chunk)| {
+                let mut text = format!("{:#05X}:", self.start + line * MEMORY_VIEW_WIDTH);
+                for byte in chunk {
+                    text.push_str(&format!(" {:02X}", byte));
+                }
+                text
+            })
+            .collect()
+    }
+
+    // Row and column in lines() of each character of the written bytes
+    pub fn written_cells(&self) -> Vec<(usize, usize)> {
+        let mut cells = Vec::new();
+        for (k, _) in self
+            .written
+            .iter()
+            .enumerate()
+            .filter(|(_, &written)| written)
+        {
+            let (line, column) = (k / MEMORY_VIEW_WIDTH, k % MEMORY_VIEW_WIDTH);
+            let addr = self.start + line * MEMORY_VIEW_WIDTH;
+            // The address and colon, then a space before each byte
+            let first = format!("{:#05X}:", addr).len() + 3 * column + 1;
+            cells.push((line, first));
+            cells.push((line, first + 1));
+        }
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn glyphs_fall_back_to_a_question_mark() {
+        assert_eq!(glyph('A'), [0x70, 0x88, 0x88, 0xF8, 0x88, 0x88, 0x88]);
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('~'), glyph('?'));
+        assert_eq!(glyph('é'), glyph('?'));
+        assert_eq!(glyph('_'), [0, 0, 0, 0, 0, 0, 0xF8]);
+    }
+
+    #[test]
+    fn text_is_laid_out_in_cells() {
+        let lines = ["AB".to_string(), "-".to_string()];
+        assert_eq!(text_size(&lines), (11, 15));
+        assert_eq!(text_size(&[]), (0, 0));
+        let pixels: Vec<_> = text_pixels(&lines).collect();
+        // The top row of A, B starting one column after A ends, then the dash in the next line
+        assert_eq!(pixels[..3], [(1, 0), (2, 0), (3, 0)]);
+        assert!(pixels.contains(&(6, 0)));
+        assert_eq!(
+            pixels[pixels.len() - 5..],
+            [(0, 11), (1, 11), (2, 11), (3, 11), (4, 11)]
+        );
+    }
+
+    #[test]
+    fn state_lines_end_with_the_instruction_rate() {
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(&[0x63, 0x1F]).unwrap();
+        cpu.exec_cycle().unwrap();
+        let lines = state_lines(&cpu, 700);
+        assert!(lines[0].starts_with("PC: 0x202  I: 0x000"));
+        assert_eq!(lines[1], "V0-V7: 00 00 00 1F 00 00 00 00");
+        assert_eq!(lines.last().unwrap(), "IPS:   700");
+    }
+
+    #[test]
+    fn memory_view_pages_fit_4k_and_64k() {
+        let ram = vec![0; 0x1000];
+        assert_eq!(MemoryView::new(&ram, 0x305, &[]).start, 0x300);
+        assert_eq!(MemoryView::new(&ram, 0xFFF, &[]).start, 0xF00);
+        assert_eq!(MemoryView::new(&ram, 0xFFF, &[]).bytes.len(), 256);
+        let ram = vec![0; 0x10000];
+        assert_eq!(MemoryView::new(&ram, 0xFFFF, &[]).start, 0xFF00);
+        assert_eq!(MemoryView::start_around(0x345), 0x300);
+        assert_eq!(MemoryView::start_around(0x20), 0);
+    }
+
+    #[test]
+    fn memory_view_highlights_written_bytes() {
+        let mut ram = vec![0; 0x1000];
+        ram[0x311] = 0xAB;
+        // Writes outside the page are ignored
+        let view = MemoryView::new(&ram, 0x300, &[0x311, 0x2FF, 0x400]);
+        let lines = view.lines();
+        assert_eq!(lines.len(), 16);
+        assert_eq!(
+            lines[1],
+            "0x310: 00 AB 00 00 00 00 00 00 00 00 00 00 00 00 00 00"
+        );
+        assert_eq!(view.written.iter().filter(|&&written| written).count(), 1);
+        assert_eq!(view.written_cells(), [(1, 10), (1, 11)]);
+        assert_eq!(&lines[1][10..12], "AB");
+    }
+}
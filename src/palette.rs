@@ -0,0 +1,149 @@
+// Colors of the four XO-CHIP color indices: background, plane 1, plane 2 and both planes.
+// CHIP-8 and SUPER-CHIP only ever use the first two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    // 0xRRGGBB
+    pub colors: [u32; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        MONO
+    }
+}
+
+// White on black, what this emulator has always shown
+pub const MONO: Palette = Palette {
+    colors: [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+};
+
+// Built-in palettes, most of them from Octo, in the order the palette hotkey cycles through them
+pub const NAMED: [(&str, Palette); 7] = [
+    ("mono", MONO),
+    (
+        "octo",
+        Palette {
+            colors: [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+        },
+    ),
+    (
+        "lcd",
+        Palette {
+            colors: [0xF9FFB3, 0x3D8026, 0xABCC47, 0x00131A],
+        },
+    ),
+    (
+        "hotdog",
+        Palette {
+            colors: [0x000000, 0xFF0000, 0xFFFF00, 0xFFFFFF],
+        },
+    ),
+    (
+        "grey",
+        Palette {
+            colors: [0xAAAAAA, 0x000000, 0xFFFFFF, 0x666666],
+        },
+    ),
+    (
+        "cga0",
+        Palette {
+            colors: [0x000000, 0x00FF00, 0xFF0000, 0xFFFF00],
+        },
+    ),
+    (
+        "cga1",
+        Palette {
+            colors: [0x000000, 0xFF00FF, 0x00FFFF, 0xFFFFFF],
+        },
+    ),
+];
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Palette> {
+        NAMED
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    // A palette name, or four hex colors separated by commas, e.g. "000000,FFFFFF,FF0000,00FF00"
+    pub fn parse(arg: &str) -> Option<Palette> {
+        if let Some(palette) = Palette::from_name(arg) {
+            return Some(palette);
+        }
+        let mut colors = [0; 4];
+        let mut parts = arg.split(',');
+        for color in colors.iter_mut() {
+            let part = parts.next()?.trim_start_matches('#');
+            if part.len() != 6 {
+                return None;
+            }
+            *color = u32::from_str_radix(part, 16).ok()?;
+        }
+        match parts.next() {
+            Some(_) => None,
+            None => Some(Palette { colors }),
+        }
+    }
+
+    // The built-in palette after this one, wrapping around. Custom palettes go to the first.
+    pub fn next_named(&self) -> Palette {
+        let index = NAMED.iter().position(|(_, palette)| palette == self);
+        let next = index.map_or(0, |i| (i + 1) % NAMED.len());
+        NAMED[next].1
+    }
+
+    // Red, green and blue of a color index
+    pub fn rgb(&self, color: u8) -> (u8, u8, u8) {
+        let c = self.colors[color as usize & 3];
+        ((c >> 16) as u8, (c >> 8) as u8, c as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Display;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn parse_takes_names_and_hex_colors() {
+        assert_eq!(Palette::parse("hotdog"), Some(NAMED[3].1));
+        assert_eq!(
+            Palette::parse("#000000,FFFFFF,ff0000,00FF00"),
+            Some(Palette {
+                colors: [0x000000, 0xFFFFFF, 0xFF0000, 0x00FF00]
+            })
+        );
+        assert_eq!(Palette::parse("000000,FFFFFF,FF0000"), None);
+        assert_eq!(Palette::parse("000000,FFFFFF,FF0000,00FF00,0000FF"), None);
+        assert_eq!(Palette::parse("000000,FFFFFF,FF0000,0F0"), None);
+        assert_eq!(Palette::parse("sepia"), None);
+    }
+
+    #[test]
+    fn next_named_cycles_through_the_list() {
+        assert_eq!(MONO.next_named(), NAMED[1].1);
+        assert_eq!(NAMED[6].1.next_named(), MONO);
+        let custom = Palette {
+            colors: [1, 2, 3, 4],
+        };
+        assert_eq!(custom.next_named(), MONO);
+    }
+
+    #[test]
+    fn rgba_uses_the_color_of_each_plane_combination() {
+        let mut display = Display::new();
+        display.select_planes(3);
+        // Plane 1 gets 0b0101, plane 2 gets 0b0011: colors 0, 1, 2 and 3 along the top row
+        display.draw_sprite(0, 0, &[0x50, 0x30], false);
+        let mut rgba = Vec::new();
+        display.to_rgba(&Palette::from_name("hotdog").unwrap(), &mut rgba);
+        assert_eq!(rgba.len(), 64 * 32 * 4);
+        assert_eq!(
+            rgba[..16],
+            [0, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+        assert!(rgba[16..].chunks(4).all(|pixel| pixel == [0, 0, 0, 0xFF]));
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use crate::disasm;
+use crate::quirks::Platform;
+
+// Addresses listed by ProfileData::report
+pub const PROFILE_TOP: usize = 20;
+
+// Instructions executed per address and per opcode family, see CPU::profile
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileData {
+    // indexed by the address of the instruction, grown to 4K or 64K the first time an address
+    // past the end executes
+    by_address: Box<[u32]>,
+    // indexed by the high nibble of the opcode
+    pub by_family: [u64; 16],
+    pub total: u64,
+}
+
+impl ProfileData {
+    // Count one execution of opcode at pc
+    pub fn record(&mut self, pc: u16, opcode: u16) {
+        match self.by_address.get_mut(pc as usize) {
+            Some(count) => *count = count.saturating_add(1),
+            None => self.grow(pc),
+        }
+        self.by_family[(opcode >> 12) as usize] += 1;
+        self.total += 1;
+    }
+
+    #[cold]
+    fn grow(&mut self, pc: u16) {
+        let len = if pc < 0x1000 { 0x1000 } else { 0x10000 };
+        let mut by_address = vec![0; len].into_boxed_slice();
+        by_address[..self.by_address.len()].copy_from_slice(&self.by_address);
+        by_address[pc as usize] = 1;
+        self.by_address = by_address;
+    }
+
+    // Times the instruction at addr executed
+    pub fn count(&self, addr: u16) -> u32 {
+        self.by_address.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    // The n addresses executed most as (address, count), most first, ties by address
+    pub fn hottest(&self, n: usize) -> Vec<(u16, u32)> {
+        let mut hottest: Vec<(u16, u32)> = self
+            .by_address
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(addr, &count)| (addr as u16, count))
+            .collect();
+        hottest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hottest.truncate(n);
+        hottest
+    }
+
+    // The hottest addresses with their share of the total and disassembly as memory holds it
+    // now, then how often each opcode family executed
+    pub fn report(&self, memory: &[u8], platform: Platform) -> String {
+        let mut out = String::new();
+        // Writing to a String can't fail
+        let _ = self.write_report(&mut out, memory, platform);
+        out
+    }
+
+    fn write_report(
+        &self,
+        out: &mut String,
+        memory: &[u8],
+        platform: Platform,
+    ) -> core::fmt::Result {
+        let percent = |count: u64| match self.total {
+            0 => 0.0,
+            total => count as f64 * 100.0 / total as f64,
+        };
+        writeln!(out, "Instructions executed: {}", self.total)?;
+        writeln!(out, "Hottest addresses:")?;
+        for (addr, count) in self.hottest(PROFILE_TOP) {
+            let text = match memory.get(addr as usize..addr as usize + 2) {
+                Some(bytes) => {
+                    disasm::disassemble_for(u16::from_be_bytes([bytes[0], bytes[1]]), platform)
+                }
+                None => String::new(),
+            };
+            writeln!(
+                out,
+                "    {:#05X} {:>6.2}% {:>10}  {}",
+                addr,
+                percent(count as u64),
+                count,
+                text
+            )?;
+        }
+        writeln!(out, "Opcode families:")?;
+        for (family, &count) in self.by_family.iter().enumerate() {
+            if count > 0 {
+                writeln!(
+                    out,
+                    "    {:X}xxx  {:>6.2}% {:>10}",
+                    family,
+                    percent(count),
+                    count
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+
+    #[test]
+    fn counts_a_loop_run_1000_times() {
+        // ADD V0, 1 / JP 0x200
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        cpu.exec_cycles(2000).unwrap();
+        let profile = cpu.profile();
+        assert_eq!((profile.count(0x200), profile.count(0x202)), (1000, 1000));
+        assert_eq!(profile.count(0x204), 0);
+        assert_eq!(profile.total, 2000);
+        assert_eq!(
+            (profile.by_family[0x7], profile.by_family[0x1]),
+            (1000, 1000)
+        );
+        assert_eq!(profile.hottest(20), [(0x200, 1000), (0x202, 1000)]);
+
+        let report = profile.report(cpu.bus.ram(), cpu.platform);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "Instructions executed: 2000");
+        assert_eq!(lines[2], "    0x200  50.00%       1000  ADD V0, 0x01");
+        assert_eq!(lines[5], "    1xxx   50.00%       1000");
+    }
+
+    #[test]
+    fn hottest_orders_by_count_then_address() {
+        let mut profile = ProfileData::default();
+        for &(pc, times) in [(0x300, 1), (0x208, 3), (0x200, 1), (0x1234, 2)].iter() {
+            for _ in 0..times {
+                profile.record(pc, 0x6000);
+            }
+        }
+        // Past 4K the counts move to a 64K table
+        assert_eq!(profile.count(0x1234), 2);
+        assert_eq!(profile.hottest(3), [(0x208, 3), (0x1234, 2), (0x200, 1)]);
+        assert_eq!(profile.by_family[6], 7);
+    }
+
+    #[test]
+    fn waiting_on_a_key_is_one_hit() {
+        // LD V0, K / JP 0x200
+        let mut cpu = CPU::builder().build();
+        cpu.load_rom_bytes(&[0xF0, 0x0A, 0x12, 0x00]).unwrap();
+        assert_eq!(cpu.exec_cycles(1000), Ok(0));
+        assert_eq!(cpu.profile().total, 0);
+        cpu.key_down(2).unwrap();
+        cpu.key_up(2).unwrap();
+        cpu.exec_cycles(2).unwrap();
+        assert_eq!(cpu.profile().hottest(20), [(0x200, 1), (0x202, 1)]);
+    }
+}
@@ -0,0 +1,158 @@
+// Python bindings to the emulator core, a `rusty_chip8` module with a Chip8 class. Build and
+// install it into the current virtualenv with
+//   maturin develop --release
+// See pyproject.toml, and examples/test_chip8.py for a pytest script using it.
+// Errors from the core raise rusty_chip8.Chip8Error with the Chip8Error message.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::cpu::CPU;
+use crate::error;
+
+// Instructions a frame runs unless run_frame is told otherwise, 600 a second at 60Hz
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+create_exception!(
+    rusty_chip8,
+    Chip8Error,
+    PyException,
+    "An error from the emulator core."
+);
+
+fn to_py(e: error::Chip8Error) -> PyErr {
+    Chip8Error::new_err(e.to_string())
+}
+
+// A reset CHIP-8 with the default quirks. The CPU keeps Rust callbacks, so the object stays on
+// the thread that made it.
+#[pyclass(unsendable)]
+pub struct Chip8 {
+    cpu: CPU,
+}
+
+#[pymethods]
+impl Chip8 {
+    #[new]
+    pub fn new() -> Self {
+        Chip8 {
+            cpu: CPU::builder().build(),
+        }
+    }
+
+    // Load a ROM at the program start, returns the number of bytes loaded
+    pub fn load_rom(&mut self, rom: &[u8]) -> PyResult<usize> {
+        self.cpu.load_rom_bytes(rom).map_err(to_py)
+    }
+
+    // Execute up to n instructions, see CPU::exec_cycles. Returns how many executed.
+    #[pyo3(signature = (n = 1))]
+    pub fn step(&mut self, n: u32) -> PyResult<u32> {
+        self.cpu.exec_cycles(n).map_err(to_py)
+    }
+
+    // Run one 60Hz frame, see CPU::run_frame. Returns the number of instructions executed.
+    #[pyo3(signature = (instructions = DEFAULT_INSTRUCTIONS_PER_FRAME))]
+    pub fn run_frame(&mut self, instructions: u32) -> PyResult<u32> {
+        let frame = self.cpu.run_frame(instructions).map_err(to_py)?;
+        Ok(frame.instructions)
+    }
+
+    pub fn press_key(&mut self, key: u8) -> PyResult<()> {
+        self.cpu.key_down(key).map_err(to_py)
+    }
+
+    pub fn release_key(&mut self, key: u8) -> PyResult<()> {
+        self.cpu.key_up(key).map_err(to_py)
+    }
+
+    // The color index of every pixel of the visible screen, row by row. Wrap it with
+    // numpy.frombuffer(..., dtype=numpy.uint8).reshape(height, width) for an array.
+    pub fn framebuffer<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let display = &self.cpu.display;
+        let (width, height) = (display.width(), display.height());
+        let pixels: Vec<u8> = (0..width * height)
+            .map(|k| display.get_pixel(k % width, k / width))
+            .collect();
+        PyBytes::new(py, &pixels)
+    }
+
+    // Width and height of the current resolution in pixels
+    pub fn size(&self) -> (usize, usize) {
+        (self.cpu.display.width(), self.cpu.display.height())
+    }
+
+    // V0 to VF
+    pub fn registers(&self) -> Vec<u8> {
+        self.cpu.v.to_vec()
+    }
+
+    pub fn beeping(&self) -> bool {
+        self.cpu.st > 0
+    }
+
+    // The screen as text, see Display::to_ascii
+    pub fn screenshot_ascii(&self) -> String {
+        self.cpu.display.to_ascii()
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[pymodule]
+fn rusty_chip8(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Chip8>()?;
+    m.add("Chip8Error", m.py().get_type::<Chip8Error>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 0xA / LD F, V0 / DRW V1, V1, 5 / JP 0x206
+    const DRAW_A: [u8; 8] = [0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+
+    #[test]
+    fn chip8_class_runs_a_rom() {
+        Python::initialize();
+        Python::attach(|py| {
+            let chip8 = Bound::new(py, Chip8::new()).unwrap();
+            let bytes = PyBytes::new(py, &DRAW_A);
+            let loaded: usize = chip8
+                .call_method1("load_rom", (bytes,))
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(loaded, 8);
+            let executed: u32 = chip8.call_method1("step", (3,)).unwrap().extract().unwrap();
+            assert_eq!(executed, 3);
+            let registers: Vec<u8> = chip8.call_method0("registers").unwrap().extract().unwrap();
+            assert_eq!(registers[0], 0xA);
+
+            let chip8 = chip8.borrow();
+            assert_eq!(chip8.framebuffer(py).as_bytes()[..5], [1, 1, 1, 1, 0]);
+            assert!(chip8.screenshot_ascii().starts_with("####"));
+        });
+    }
+
+    #[test]
+    fn errors_raise_chip8_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut chip8 = Chip8::new();
+            let e = chip8.press_key(16).unwrap_err();
+            assert!(e.is_instance_of::<Chip8Error>(py));
+            assert_eq!(e.value(py).to_string(), "no key 0x10, keys are 0 to F");
+            chip8.load_rom(&[0xE0, 0x00]).unwrap();
+            let e = chip8.run_frame(10).unwrap_err();
+            assert_eq!(e.value(py).to_string(), "unknown opcode 0xE000 at PC 0x200");
+        });
+    }
+}
@@ -0,0 +1,301 @@
+// Behaviors that differ between CHIP-8 interpreters.
+// The defaults match what this emulator has always done.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // FX55/FX65 leave I pointing just past the last register stored or loaded (I = I + x + 1),
+    // as the original COSMAC VIP interpreter did
+    pub load_store_increments_i: bool,
+    // BNNN is treated as BXNN and jumps to XNN + Vx instead of NNN + V0, as SUPER-CHIP does
+    pub jump_uses_vx: bool,
+    // 8XY1/8XY2/8XY3 zero VF after the logic operation, as the original interpreter did
+    pub vf_reset: bool,
+    // DXYN clips sprites at the right and bottom edges of the screen instead of wrapping them
+    pub clipping: bool,
+    // FX1E sets VF to 1 when I overflows past 0xFFF and to 0 otherwise, as the Amiga interpreter did.
+    // Off by default since most interpreters leave VF alone.
+    pub index_overflow_sets_vf: bool,
+    // DXYN waits for the vertical blank, so at most one sprite is drawn per 60Hz frame
+    pub display_wait: bool,
+    // 8XY6/8XYE shift Vy and store the result in Vx, as the original interpreter did,
+    // instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    // 00FE/00FF leave the picture alone instead of clearing the screen, as SUPER-CHIP 1.1 did.
+    // Off by default since most interpreters clear.
+    pub resolution_switch_keeps_display: bool,
+    // DXY0 draws an 8x16 sprite on plain CHIP-8, as some later interpreters did, instead of
+    // nothing. SUPER-CHIP and XO-CHIP always draw a 16x16 sprite.
+    pub dxy0_draws_8x16: bool,
+    // DXY0 draws an 8x16 sprite in low resolution, as SUPER-CHIP 1.1 did, instead of 16x16
+    pub lores_dxy0_draws_8x16: bool,
+    // 00CN/00FB/00FC scroll by half as many pixels in low resolution, as SUPER-CHIP 1.1 did since
+    // its screen was 128x64 underneath. Odd row counts round down.
+    pub scroll_half_pixel_lores: bool,
+    // DXYN in high resolution sets VF to the number of sprite rows that collided or were clipped
+    // at the bottom edge, as SUPER-CHIP 1.1 did, instead of 0 or 1
+    pub hires_collision_counts_rows: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::none()
+    }
+}
+
+// Names for the individual Quirks fields, to toggle them one at a time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quirk {
+    LoadStoreIncrementsI,
+    JumpUsesVx,
+    VfReset,
+    Clipping,
+    IndexOverflowSetsVf,
+    DisplayWait,
+    ShiftUsesVy,
+    ResolutionSwitchKeepsDisplay,
+    Dxy0Draws8x16,
+    LoresDxy0Draws8x16,
+    ScrollHalfPixelLores,
+    HiresCollisionCountsRows,
+}
+
+// Interpreter families with a well known combination of quirks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform {
+    Chip8,
+    // SUPER-CHIP as most modern interpreters implement it
+    SuperChip,
+    // SUPER-CHIP 1.1 on the HP48, low resolution quirks included
+    SuperChipLegacy,
+    XoChip,
+    // Two-page HiRes CHIP-8: the original instruction set on a 64x64 screen
+    HiresChip8,
+    // ETI-660: the original instruction set on a 64x48 screen, programs start at 0x600
+    Eti660,
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "schip" => Some(Platform::SuperChip),
+            "schip-legacy" => Some(Platform::SuperChipLegacy),
+            "xochip" => Some(Platform::XoChip),
+            "hires" => Some(Platform::HiresChip8),
+            "eti660" => Some(Platform::Eti660),
+            _ => None,
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Eti660 => Quirks::original_chip8(),
+            Platform::SuperChip => Quirks::superchip_modern(),
+            Platform::SuperChipLegacy => Quirks::superchip_legacy(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    // Whether the SUPER-CHIP instructions exist, XO-CHIP builds on them
+    pub fn has_superchip_opcodes(self) -> bool {
+        match self {
+            Platform::SuperChip | Platform::SuperChipLegacy | Platform::XoChip => true,
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Eti660 => false,
+        }
+    }
+}
+
+impl Quirks {
+    pub fn get(&self, quirk: Quirk) -> bool {
+        *self.field(quirk)
+    }
+
+    pub fn set(&mut self, quirk: Quirk, on: bool) {
+        *self.field_mut(quirk) = on;
+    }
+
+    fn field(&self, quirk: Quirk) -> &bool {
+        match quirk {
+            Quirk::LoadStoreIncrementsI => &self.load_store_increments_i,
+            Quirk::JumpUsesVx => &self.jump_uses_vx,
+            Quirk::VfReset => &self.vf_reset,
+            Quirk::Clipping => &self.clipping,
+            Quirk::IndexOverflowSetsVf => &self.index_overflow_sets_vf,
+            Quirk::DisplayWait => &self.display_wait,
+            Quirk::ShiftUsesVy => &self.shift_uses_vy,
+            Quirk::ResolutionSwitchKeepsDisplay => &self.resolution_switch_keeps_display,
+            Quirk::Dxy0Draws8x16 => &self.dxy0_draws_8x16,
+            Quirk::LoresDxy0Draws8x16 => &self.lores_dxy0_draws_8x16,
+            Quirk::ScrollHalfPixelLores => &self.scroll_half_pixel_lores,
+            Quirk::HiresCollisionCountsRows => &self.hires_collision_counts_rows,
+        }
+    }
+
+    fn field_mut(&mut self, quirk: Quirk) -> &mut bool {
+        match quirk {
+            Quirk::LoadStoreIncrementsI => &mut self.load_store_increments_i,
+            Quirk::JumpUsesVx => &mut self.jump_uses_vx,
+            Quirk::VfReset => &mut self.vf_reset,
+            Quirk::Clipping => &mut self.clipping,
+            Quirk::IndexOverflowSetsVf => &mut self.index_overflow_sets_vf,
+            Quirk::DisplayWait => &mut self.display_wait,
+            Quirk::ShiftUsesVy => &mut self.shift_uses_vy,
+            Quirk::ResolutionSwitchKeepsDisplay => &mut self.resolution_switch_keeps_display,
+            Quirk::Dxy0Draws8x16 => &mut self.dxy0_draws_8x16,
+            Quirk::LoresDxy0Draws8x16 => &mut self.lores_dxy0_draws_8x16,
+            Quirk::ScrollHalfPixelLores => &mut self.scroll_half_pixel_lores,
+            Quirk::HiresCollisionCountsRows => &mut self.hires_collision_counts_rows,
+        }
+    }
+
+    // Every quirk off, the default. Usable in constants unlike Default::default.
+    pub const fn none() -> Self {
+        Quirks {
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clipping: false,
+            index_overflow_sets_vf: false,
+            display_wait: false,
+            shift_uses_vy: false,
+            resolution_switch_keeps_display: false,
+            dxy0_draws_8x16: false,
+            lores_dxy0_draws_8x16: false,
+            scroll_half_pixel_lores: false,
+            hires_collision_counts_rows: false,
+        }
+    }
+
+    // Behavior of the original COSMAC VIP interpreter
+    pub const fn original_chip8() -> Self {
+        Quirks {
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clipping: true,
+            index_overflow_sets_vf: false,
+            display_wait: true,
+            shift_uses_vy: true,
+            resolution_switch_keeps_display: false,
+            dxy0_draws_8x16: false,
+            lores_dxy0_draws_8x16: false,
+            scroll_half_pixel_lores: false,
+            hires_collision_counts_rows: false,
+        }
+    }
+
+    // Behavior of SUPER-CHIP 1.1 on the HP48
+    pub const fn superchip_legacy() -> Self {
+        Quirks {
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clipping: true,
+            index_overflow_sets_vf: false,
+            display_wait: false,
+            shift_uses_vy: false,
+            resolution_switch_keeps_display: false,
+            dxy0_draws_8x16: false,
+            lores_dxy0_draws_8x16: true,
+            scroll_half_pixel_lores: true,
+            hires_collision_counts_rows: true,
+        }
+    }
+
+    // Behavior of what Octo calls modern SUPER-CHIP: 1.1 with whole low resolution pixels
+    pub const fn superchip_modern() -> Self {
+        Quirks {
+            lores_dxy0_draws_8x16: false,
+            scroll_half_pixel_lores: false,
+            hires_collision_counts_rows: false,
+            ..Quirks::superchip_legacy()
+        }
+    }
+
+    // Behavior of XO-CHIP as implemented by Octo
+    pub const fn xo_chip() -> Self {
+        Quirks {
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clipping: false,
+            index_overflow_sets_vf: false,
+            display_wait: false,
+            shift_uses_vy: true,
+            resolution_switch_keeps_display: false,
+            dxy0_draws_8x16: false,
+            lores_dxy0_draws_8x16: false,
+            scroll_half_pixel_lores: false,
+            hires_collision_counts_rows: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    #[test]
+    fn presets_set_their_flags() {
+        let chip8 = Quirks::original_chip8();
+        assert!(chip8.load_store_increments_i && chip8.vf_reset && chip8.shift_uses_vy);
+        assert!(chip8.clipping && chip8.display_wait);
+        assert!(!chip8.jump_uses_vx);
+
+        let schip = Quirks::superchip_modern();
+        assert!(schip.jump_uses_vx && schip.clipping);
+        assert!(!schip.load_store_increments_i && !schip.vf_reset && !schip.shift_uses_vy);
+        assert!(!schip.display_wait && !schip.hires_collision_counts_rows);
+
+        let legacy = Quirks::superchip_legacy();
+        assert_eq!(
+            legacy,
+            Quirks {
+                lores_dxy0_draws_8x16: true,
+                scroll_half_pixel_lores: true,
+                hires_collision_counts_rows: true,
+                ..schip
+            }
+        );
+
+        let xo = Quirks::xo_chip();
+        assert!(xo.load_store_increments_i && xo.shift_uses_vy);
+        assert!(!xo.clipping && !xo.vf_reset && !xo.jump_uses_vx && !xo.display_wait);
+
+        assert_eq!(Quirks::default(), Quirks::none());
+        assert_eq!(Platform::Eti660.quirks(), chip8);
+    }
+
+    #[test]
+    fn get_and_set_name_the_fields() {
+        let mut quirks = Quirks::none();
+        quirks.set(Quirk::JumpUsesVx, true);
+        assert!(quirks.jump_uses_vx && quirks.get(Quirk::JumpUsesVx));
+        assert_eq!(
+            quirks,
+            Quirks {
+                jump_uses_vx: true,
+                ..Quirks::none()
+            }
+        );
+        assert_eq!(
+            Platform::from_name("schip-legacy"),
+            Some(Platform::SuperChipLegacy)
+        );
+        assert_eq!(Platform::from_name("chip-48"), None);
+    }
+
+    #[test]
+    fn presets_run_the_same_program_differently() {
+        // LD V1, 0x81 / SHR V0, V1 / LD I, 0x300 / LD [I], V0
+        let program = [0x61, 0x81, 0x80, 0x16, 0xA3, 0x00, 0xF0, 0x55];
+        let run = |platform| {
+            let mut cpu = CPU::builder().platform(platform).build();
+            cpu.load_rom_bytes(&program).unwrap();
+            cpu.exec_cycles(4).unwrap();
+            (cpu.v[0], cpu.i)
+        };
+        assert_eq!(run(Platform::Chip8), (0x40, 0x301));
+        assert_eq!(run(Platform::SuperChip), (0x00, 0x300));
+    }
+}
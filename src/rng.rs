@@ -0,0 +1,100 @@
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Where RND gets its bytes from. Tests and headless runs can plug in a predictable source.
+pub trait RandomSource {
+    fn next_u8(&mut self) -> u8;
+
+    // Internal state for CPU snapshots, None for sources that can't be saved
+    fn state(&self) -> Option<u64> {
+        None
+    }
+
+    fn set_state(&mut self, _state: u64) {}
+}
+
+// xorshift64* pseudo random number generator.
+// Cheap, well distributed over the bytes RND needs, and reproducible from a seed.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The generator gets stuck on a zero state, so mix the seed with a constant first
+        let mut state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        if state == 0 {
+            state = 0x9E37_79B9_7F4A_7C15;
+        }
+        Rng { state }
+    }
+
+    // Seed from the system clock for runs that don't need to be reproducible
+    #[cfg(feature = "std")]
+    pub fn from_clock() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Rng::new(now.as_secs() ^ ((now.subsec_nanos() as u64) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        // The high bits of xorshift64* are the best distributed
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+impl RandomSource for Rng {
+    fn next_u8(&mut self) -> u8 {
+        Rng::next_u8(self)
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.state)
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn bytes_cover_the_whole_range_evenly() {
+        let mut rng = Rng::new(0);
+        let mut counts = [0u32; 256];
+        for _ in 0..256 * 100 {
+            counts[rng.next_u8() as usize] += 1;
+        }
+        // 100 expected per value, a stuck or biased generator lands far outside this
+        assert!(
+            counts.iter().all(|&n| (50..=150).contains(&n)),
+            "{:?}",
+            counts
+        );
+        // Low bits too, RND masks with kk
+        let odd = (0..1000).filter(|_| rng.next_u8() & 1 == 1).count();
+        assert!((400..=600).contains(&odd));
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let (mut a, mut b, mut c) = (Rng::new(42), Rng::new(42), Rng::new(43));
+        let a: Vec<u8> = (0..32).map(|_| a.next_u8()).collect();
+        let b: Vec<u8> = (0..32).map(|_| b.next_u8()).collect();
+        let c: Vec<u8> = (0..32).map(|_| c.next_u8()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::quirks::Quirk;
+
+// A ROM known to need specific quirks, identified by the CRC-32 of its bytes. Only the quirks
+// listed change, the rest keep the value the platform preset gave them.
+pub struct RomProfile {
+    pub crc32: u32,
+    pub name: &'static str,
+    pub quirks: &'static [(Quirk, bool)],
+}
+
+static PROFILES: &[RomProfile] = &[
+    // Sprites run off the bottom of the screen and must be clipped rather than wrapped
+    RomProfile {
+        crc32: 0xD106_C808,
+        name: "Blitz",
+        quirks: &[(Quirk::Clipping, true)],
+    },
+    // Written for SUPER-CHIP style shifts and FX55/FX65 leaving I alone
+    RomProfile {
+        crc32: 0x9D30_7E90,
+        name: "Blinky",
+        quirks: &[
+            (Quirk::ShiftUsesVy, false),
+            (Quirk::LoadStoreIncrementsI, false),
+        ],
+    },
+    // Written for SUPER-CHIP style shifts
+    RomProfile {
+        crc32: 0xEAD6_25B8,
+        name: "Space Invaders",
+        quirks: &[(Quirk::ShiftUsesVy, false)],
+    },
+];
+
+// Find the profile of a ROM from its CPU::rom_hash
+pub fn lookup(crc32: u32) -> Option<&'static RomProfile> {
+    PROFILES.iter().find(|profile| profile.crc32 == crc32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::quirks::{Platform, Quirks};
+
+    #[test]
+    fn known_roms_get_their_quirks() {
+        let blitz = include_bytes!("../roms/BLITZ");
+        assert_eq!(lookup(<CPU>::rom_hash(blitz)).unwrap().name, "Blitz");
+
+        let mut cpu = CPU::new();
+        cpu.load_rom_bytes(blitz).unwrap();
+        assert_eq!(
+            cpu.quirks,
+            Quirks {
+                clipping: true,
+                ..Quirks::none()
+            }
+        );
+    }
+
+    #[test]
+    fn profiles_change_only_their_quirks_of_the_preset() {
+        let mut cpu = CPU::builder().platform(Platform::Chip8).build();
+        cpu.load_rom_bytes(include_bytes!("../roms/INVADERS"))
+            .unwrap();
+        assert_eq!(
+            cpu.quirks,
+            Quirks {
+                shift_uses_vy: false,
+                ..Quirks::original_chip8()
+            }
+        );
+
+        // The next ROM starts again from the preset
+        cpu.load_rom_bytes(include_bytes!("../roms/BLITZ")).unwrap();
+        assert_eq!(
+            cpu.quirks,
+            Quirks {
+                clipping: true,
+                ..Quirks::original_chip8()
+            }
+        );
+        cpu.load_rom_bytes(include_bytes!("../roms/PONG")).unwrap();
+        assert_eq!(cpu.quirks, Quirks::original_chip8());
+    }
+
+    #[test]
+    fn builder_quirks_win_over_profiles() {
+        let mut cpu = CPU::builder()
+            .platform(Platform::SuperChip)
+            .quirk(Quirk::LoadStoreIncrementsI, true)
+            .build();
+        cpu.load_rom_bytes(include_bytes!("../roms/BLINKY"))
+            .unwrap();
+        assert!(cpu.quirks.load_store_increments_i);
+        assert!(!cpu.quirks.shift_uses_vy);
+        assert!(cpu.quirks.clipping);
+    }
+
+    #[test]
+    fn unknown_roms_and_disabled_database_keep_quirks() {
+        let mut cpu = CPU::with_platform(Platform::SuperChip);
+        cpu.load_rom_bytes(include_bytes!("../roms/PONG")).unwrap();
+        assert_eq!(cpu.quirks, Quirks::superchip_modern());
+
+        let mut cpu = CPU::builder()
+            .platform(Platform::Chip8)
+            .use_rom_database(false)
+            .build();
+        cpu.load_rom_bytes(include_bytes!("../roms/BLITZ")).unwrap();
+        assert_eq!(cpu.quirks, Quirks::original_chip8());
+    }
+
+    #[test]
+    fn rom_hash_is_crc32() {
+        assert_eq!(<CPU>::rom_hash(b"123456789"), 0xCBF4_3926);
+        assert_eq!(<CPU>::rom_hash(&[]), 0);
+    }
+}
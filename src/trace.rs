@@ -0,0 +1,240 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::disasm;
+use crate::quirks::Platform;
+
+// Lines formatted before they're written out together
+const TRACE_BUFFER_LINES: usize = 4096;
+
+// A log of every executed instruction, one line each:
+//
+//   00000001 0200 6005 LD V0, 0x05 | V0:00->05
+//   00000002 0202 A20A LD I, 0x20A | I:0000->020A
+//   00000003 0204 D013 DRW V0, V1, 3 | VF:0->1
+//   00000004 0206 1206 JP 0x206
+//
+// That's the instruction count from 1, the address and opcode in hex, the disassembly as the
+// platform runs it, and after " | " the registers the instruction changed: V0 to VE as two hex
+// digits, VF as one or two, then I as four. Lines without changes end after the disassembly.
+// The format stays put so traces of different versions can be diffed.
+//
+// The address and changes come from comparing with the registers after the previous
+// instruction, so changes made in between, such as by the debugger, show up on the next line.
+// DXYN waiting for the vertical blank and FX0A waiting for a key don't execute and get no line
+// until they do.
+pub struct TraceLog<W: Write> {
+    out: W,
+    platform: Platform,
+    // filled in order and written out all at once when full, the strings are reused
+    lines: Vec<String>,
+    pending: usize,
+    count: u64,
+    // pc, V0 to VF and I after the previous instruction
+    pc: u16,
+    v: [u8; 16],
+    i: u16,
+    // tracing stops on a write error
+    failed: bool,
+}
+
+impl<W: Write> TraceLog<W> {
+    // Start from the state of cpu, which the next instruction it executes gets compared with
+    pub fn new<B: Bus>(out: W, cpu: &CPU<B>) -> Self {
+        TraceLog {
+            out,
+            platform: cpu.platform,
+            lines: vec![String::new(); TRACE_BUFFER_LINES],
+            pending: 0,
+            count: 0,
+            pc: cpu.pc,
+            v: cpu.v,
+            i: cpu.i,
+            failed: false,
+        }
+    }
+
+    // Log the instruction cpu just executed
+    pub fn record<B: Bus>(&mut self, cpu: &CPU<B>, opcode: u16) {
+        if self.failed {
+            return;
+        }
+        self.count += 1;
+        let line = &mut self.lines[self.pending];
+        line.clear();
+        // Writing to a String can't fail
+        let _ = write!(
+            line,
+            "{:08} {:04X} {:04X} {}",
+            self.count,
+            self.pc,
+            opcode,
+            disasm::disassemble_for(opcode, self.platform)
+        );
+        if cpu.v != self.v || cpu.i != self.i {
+            line.push_str(" |");
+            for (x, (old, new)) in self.v.iter().zip(&cpu.v).enumerate() {
+                match (old != new, x) {
+                    (false, _) => {}
+                    (true, 0xF) => {
+                        let _ = write!(line, " VF:{:X}->{:X}", old, new);
+                    }
+                    (true, _) => {
+                        let _ = write!(line, " V{:X}:{:02X}->{:02X}", x, old, new);
+                    }
+                }
+            }
+            if cpu.i != self.i {
+                let _ = write!(line, " I:{:04X}->{:04X}", self.i, cpu.i);
+            }
+        }
+        line.push('\n');
+        self.pc = cpu.pc;
+        self.v = cpu.v;
+        self.i = cpu.i;
+
+        self.pending += 1;
+        if self.pending == self.lines.len() {
+            self.write_pending();
+        }
+    }
+
+    // Write out the lines so far
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_pending();
+        self.out.flush()
+    }
+
+    fn write_pending(&mut self) {
+        let out = &mut self.out;
+        let result = self.lines[..self.pending]
+            .iter()
+            .try_for_each(|line| out.write_all(line.as_bytes()));
+        self.pending = 0;
+        if let Err(e) = result {
+            log!("Trace stopped: {}", e);
+            self.failed = true;
+        }
+    }
+}
+
+impl<W: Write> Drop for TraceLog<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+// Log every instruction cpu executes to out from now on. A post-execution hook already set
+// keeps being called after the log. The log is written out when the hook is replaced or the
+// CPU dropped.
+pub fn install<B: Bus + 'static, W: Write + 'static>(cpu: &mut CPU<B>, out: W) {
+    let mut log = TraceLog::new(out, cpu);
+    let mut next = cpu.take_post_exec_hook();
+    cpu.set_post_exec_hook(move |cpu, opcode| {
+        log.record(cpu, opcode);
+        if let Some(next) = next.as_mut() {
+            next(cpu, opcode);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CycleState;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Output the test can read while the CPU owns the log
+    #[derive(Clone, Default)]
+    struct SharedOut(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOut {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOut {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    fn cpu_with(program: &[u16]) -> CPU {
+        let mut cpu = CPU::builder().build();
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        cpu.load_rom_bytes(&rom).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn traces_ten_instructions() {
+        let mut cpu = cpu_with(&[
+            0x6005, 0x6103, 0x8014, 0x7001, 0xA214, 0xD011, 0xD011, 0x8106, 0xF11E, 0x1212, 0x8000,
+        ]);
+        let out = SharedOut::default();
+        install(&mut cpu, out.clone());
+        for _ in 0..10 {
+            cpu.exec_cycle().unwrap();
+        }
+        // Written out when the hook goes away
+        drop(cpu);
+        assert_eq!(
+            out.text(),
+            "00000001 0200 6005 LD V0, 0x05 | V0:00->05\n\
+             00000002 0202 6103 LD V1, 0x03 | V1:00->03\n\
+             00000003 0204 8014 ADD V0, V1 | V0:05->08\n\
+             00000004 0206 7001 ADD V0, 0x01 | V0:08->09\n\
+             00000005 0208 A214 LD I, 0x214 | I:0000->0214\n\
+             00000006 020A D011 DRW V0, V1, 1\n\
+             00000007 020C D011 DRW V0, V1, 1 | VF:0->1\n\
+             00000008 020E 8106 SHR V1, V0 | V1:03->01\n\
+             00000009 0210 F11E ADD I, V1 | I:0214->0215\n\
+             00000010 0212 1212 JP 0x212\n"
+        );
+    }
+
+    #[test]
+    fn stalled_instructions_get_no_line() {
+        // DRW V0, V0, 1 / LD V1, K
+        let mut cpu = cpu_with(&[0xD001, 0xF10A]);
+        cpu.quirks.display_wait = true;
+        let out = SharedOut::default();
+        install(&mut cpu, out.clone());
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForVblank));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForVblank));
+        cpu.update_timers();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::DrewSprite));
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::WaitingForKey));
+        cpu.key_down(0xA).unwrap();
+        cpu.key_up(0xA).unwrap();
+        assert_eq!(cpu.exec_cycle(), Ok(CycleState::Normal));
+        drop(cpu);
+        assert_eq!(
+            out.text(),
+            "00000001 0200 D001 DRW V0, V0, 1\n\
+             00000002 0202 F10A LD V1, K | V1:00->0A\n"
+        );
+    }
+
+    #[test]
+    fn keeps_calling_the_previous_hook() {
+        let mut cpu = cpu_with(&[0x6005, 0x6103]);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let hook_seen = Rc::clone(&seen);
+        cpu.set_post_exec_hook(move |cpu, opcode| hook_seen.borrow_mut().push((cpu.pc, opcode)));
+        let out = SharedOut::default();
+        install(&mut cpu, out.clone());
+        cpu.exec_cycles(2).unwrap();
+        drop(cpu);
+        assert_eq!(*seen.borrow(), [(0x202, 0x6005), (0x204, 0x6103)]);
+        assert_eq!(out.text().lines().count(), 2);
+    }
+}
@@ -0,0 +1,68 @@
+// Saving the flags needs a file system, so only the size is available without std
+#[cfg(feature = "std")]
+use std::env;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+// SUPER-CHIP has 8 RPL user flags, XO-CHIP extends them to 16
+pub const USER_FLAGS: usize = 16;
+
+// Where the RPL user flags of every ROM are kept between runs, one file per ROM
+#[cfg(feature = "std")]
+pub fn default_dir() -> Option<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::var_os("APPDATA") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+        },
+    };
+    Some(data_dir.join("rusty_chip8").join("flags"))
+}
+
+#[cfg(feature = "std")]
+fn flags_path(dir: &Path, rom_crc: u32) -> PathBuf {
+    dir.join(format!("{:08X}.bin", rom_crc))
+}
+
+// Flags saved for the ROM with this CPU::rom_hash. A missing or damaged file reads as all zeros.
+#[cfg(feature = "std")]
+pub fn load(dir: &Path, rom_crc: u32) -> [u8; USER_FLAGS] {
+    let mut flags = [0; USER_FLAGS];
+    if let Ok(contents) = fs::read(flags_path(dir, rom_crc)) {
+        if contents.len() == USER_FLAGS {
+            flags.copy_from_slice(&contents);
+        }
+    }
+    flags
+}
+
+#[cfg(feature = "std")]
+pub fn save(dir: &Path, rom_crc: u32, flags: &[u8; USER_FLAGS]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(flags_path(dir, rom_crc), flags)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_survive_a_save_and_load() {
+        let dir = env::temp_dir().join(format!("rusty_chip8_flags_{}", std::process::id()));
+        let flags: [u8; USER_FLAGS] = core::array::from_fn(|k| k as u8 * 3);
+        save(&dir, 0xDEADBEEF, &flags).unwrap();
+        assert_eq!(load(&dir, 0xDEADBEEF), flags);
+        // Other ROMs keep their own flags
+        assert_eq!(load(&dir, 0x12345678), [0; USER_FLAGS]);
+
+        // A damaged file reads as zeros
+        fs::write(flags_path(&dir, 0xDEADBEEF), [1, 2, 3]).unwrap();
+        assert_eq!(load(&dir, 0xDEADBEEF), [0; USER_FLAGS]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}